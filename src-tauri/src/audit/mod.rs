@@ -0,0 +1,181 @@
+use crate::models::{AuditLogEntry, AuditLogFilter};
+use crate::session::Session;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqliteConnection;
+
+/// Serialize a record for the `before`/`after` side of an audit entry
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// Append an entry to the audit log.
+///
+/// Call this on the same connection or transaction as the change itself, so
+/// the change and its audit entry are committed (or rolled back) together.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    actor: &Session,
+    action: &str,
+    entity_type: &str,
+    entity_id: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (id, actor_id, actor_username, branch_id, action, entity_type,
+                               entity_id, before_json, after_json, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&actor.user_id)
+    .bind(&actor.username)
+    .bind(&actor.branch_id)
+    .bind(action)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(before.map(|v| v.to_string()))
+    .bind(after.map(|v| v.to_string()))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Audit entries matching `filter`, newest first
+pub async fn query(
+    conn: &mut SqliteConnection,
+    filter: &AuditLogFilter,
+    limit: Option<i64>,
+) -> Result<Vec<AuditLogEntry>, String> {
+    let mut query = String::from(
+        r#"
+        SELECT id, actor_id, actor_username, branch_id, action, entity_type, entity_id,
+               before_json, after_json, created_at
+        FROM audit_log WHERE 1=1
+        "#,
+    );
+    let mut params: Vec<&str> = Vec::new();
+
+    for (clause, value) in [
+        (" AND entity_type = ?", &filter.entity_type),
+        (" AND entity_id = ?", &filter.entity_id),
+        (" AND actor_id = ?", &filter.actor_id),
+        (" AND action = ?", &filter.action),
+        (" AND DATE(created_at) >= ?", &filter.date_from),
+        (" AND DATE(created_at) <= ?", &filter.date_to),
+    ] {
+        if let Some(value) = value {
+            query.push_str(clause);
+            params.push(value);
+        }
+    }
+
+    query.push_str(" ORDER BY created_at DESC");
+    if let Some(limit) = limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    let mut q = sqlx::query_as::<_, AuditLogEntry>(&query);
+    for param in params {
+        q = q.bind(param);
+    }
+
+    q.fetch_all(&mut *conn).await.map_err(|e| e.to_string())
+}
+
+/// Render audit entries as CSV with a header row
+pub fn to_csv(entries: &[AuditLogEntry]) -> String {
+    let mut csv = String::from(
+        "created_at,actor_username,actor_id,branch_id,action,entity_type,entity_id,before,after\n",
+    );
+
+    for entry in entries {
+        let fields = [
+            entry.created_at.as_str(),
+            &entry.actor_username,
+            &entry.actor_id,
+            &entry.branch_id,
+            &entry.action,
+            &entry.entity_type,
+            &entry.entity_id,
+            entry.before_json.as_deref().unwrap_or(""),
+            entry.after_json.as_deref().unwrap_or(""),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Role;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_audit_log_is_append_only() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+
+        let actor = Session {
+            user_id: "u1".to_string(),
+            username: "owner".to_string(),
+            full_name: "Owner".to_string(),
+            branch_id: "default".to_string(),
+            role: Role::Owner,
+        };
+        record(
+            &mut conn,
+            &actor,
+            "update",
+            "gold_price",
+            "p1",
+            Some(serde_json::json!({ "sell_price": 1000 })),
+            Some(serde_json::json!({ "sell_price": 1100 })),
+        )
+        .await
+        .unwrap();
+
+        let filter = AuditLogFilter {
+            entity_type: Some("gold_price".to_string()),
+            ..Default::default()
+        };
+        let entries = query(&mut conn, &filter, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor_username, "owner");
+
+        assert!(sqlx::query("UPDATE audit_log SET action = 'nothing'")
+            .execute(&mut *conn)
+            .await
+            .is_err());
+        assert!(sqlx::query("DELETE FROM audit_log")
+            .execute(&mut *conn)
+            .await
+            .is_err());
+
+        // Snapshots contain commas and quotes, so they must be quoted
+        let csv = to_csv(&entries);
+        assert!(csv.ends_with(",\"{\"\"sell_price\"\":1000}\",\"{\"\"sell_price\"\":1100}\"\n"));
+    }
+}
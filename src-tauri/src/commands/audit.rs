@@ -0,0 +1,38 @@
+use super::{ApiResponse, DbPool};
+use crate::audit;
+use crate::models::{AuditLogEntry, AuditLogFilter};
+use crate::session::{Role, SessionStore};
+use tauri::State;
+
+#[tauri::command]
+pub async fn get_audit_log(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    filter: Option<AuditLogFilter>,
+    limit: Option<i64>,
+) -> Result<ApiResponse<Vec<AuditLogEntry>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let limit = limit.unwrap_or(200).clamp(1, 1000);
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let entries = audit::query(&mut conn, &filter.unwrap_or_default(), Some(limit)).await?;
+
+    Ok(ApiResponse::success(entries))
+}
+
+/// Export every matching audit entry as CSV text
+#[tauri::command]
+pub async fn export_audit_log(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    filter: Option<AuditLogFilter>,
+) -> Result<ApiResponse<String>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let entries = audit::query(&mut conn, &filter.unwrap_or_default(), None).await?;
+
+    Ok(ApiResponse::success(audit::to_csv(&entries)))
+}
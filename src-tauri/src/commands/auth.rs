@@ -1,10 +1,13 @@
 use super::{ApiResponse, DbPool};
+use crate::audit;
 use crate::models::{LoginRequest, LoginResponse, User, UserResponse};
+use crate::session::{Role, SessionStore};
 use tauri::State;
 
 #[tauri::command]
 pub async fn login(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
     request: LoginRequest,
 ) -> Result<ApiResponse<LoginResponse>, String> {
     let user: Option<User> = sqlx::query_as::<_, User>(
@@ -28,8 +31,7 @@ pub async fn login(
 
             if valid {
                 // Update last login
-                let now = chrono::Utc::now();
-                let now_str = now.to_rfc3339();
+                let now_str = chrono::Utc::now().to_rfc3339();
                 sqlx::query("UPDATE users SET last_login = ? WHERE id = ?")
                     .bind(&now_str)
                     .bind(&user.id)
@@ -37,11 +39,8 @@ pub async fn login(
                     .await
                     .map_err(|e| e.to_string())?;
 
-                // Generate token (simple for now, should use JWT in production)
-                let token = format!("token-{}-{}", user.id, now.timestamp());
-
-                // Token expires in 24 hours
-                let expires_at = (now + chrono::Duration::hours(24)).to_rfc3339();
+                sessions.cleanup().await?;
+                let (token, expires_at) = sessions.create(&user.id).await?;
 
                 Ok(ApiResponse::success(LoginResponse {
                     user: UserResponse::from(user),
@@ -57,7 +56,23 @@ pub async fn login(
 }
 
 #[tauri::command]
-pub async fn get_users(pool: State<'_, DbPool>) -> Result<ApiResponse<Vec<UserResponse>>, String> {
+pub async fn logout(
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<bool>, String> {
+    sessions.revoke(&token).await?;
+
+    Ok(ApiResponse::success(true))
+}
+
+#[tauri::command]
+pub async fn get_users(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<UserResponse>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
     let users: Vec<User> = sqlx::query_as::<_, User>(
         r#"
         SELECT id, branch_id, username, password_hash, full_name, role,
@@ -75,17 +90,28 @@ pub async fn get_users(pool: State<'_, DbPool>) -> Result<ApiResponse<Vec<UserRe
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_user(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     username: String,
     password: String,
     full_name: String,
     role: String,
     branch_id: String,
 ) -> Result<ApiResponse<UserResponse>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    if Role::parse(&role).is_none() {
+        return Ok(ApiResponse::error("Role must be 'owner' or 'kasir'"));
+    }
+
     let id = uuid::Uuid::new_v4().to_string();
     let password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST).map_err(|e| e.to_string())?;
 
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
     sqlx::query(
         r#"
         INSERT INTO users (id, branch_id, username, password_hash, full_name, role, is_active)
@@ -98,7 +124,7 @@ pub async fn create_user(
     .bind(&password_hash)
     .bind(&full_name)
     .bind(&role)
-    .execute(&pool.0)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
@@ -110,42 +136,100 @@ pub async fn create_user(
         "#,
     )
     .bind(&id)
-    .fetch_one(&pool.0)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
-    Ok(ApiResponse::success(UserResponse::from(user)))
+    let user = UserResponse::from(user);
+    audit::record(&mut tx, &session, "create", "user", &id, None, audit::snapshot(&user)).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(user))
 }
 
 #[tauri::command]
 pub async fn change_password(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     user_id: String,
     new_password: String,
 ) -> Result<ApiResponse<bool>, String> {
+    // Users may change their own password; only owners may change others'
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+    if session.user_id != user_id && !session.is_owner() {
+        return Ok(ApiResponse::error("You can only change your own password"));
+    }
+
     let password_hash =
         bcrypt::hash(&new_password, bcrypt::DEFAULT_COST).map_err(|e| e.to_string())?;
 
-    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let updated = sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
         .bind(&password_hash)
         .bind(&user_id)
-        .execute(&pool.0)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    if updated.rows_affected() == 0 {
+        return Ok(ApiResponse::error("User not found"));
+    }
+
+    // Hashes are never logged, only the fact that the password changed
+    audit::record(&mut tx, &session, "change_password", "user", &user_id, None, None).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    // Sign the user out everywhere else
+    sessions.revoke_user(&user_id, Some(&token)).await?;
+
     Ok(ApiResponse::success(true))
 }
 
 #[tauri::command]
 pub async fn toggle_user_status(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     user_id: String,
 ) -> Result<ApiResponse<bool>, String> {
-    sqlx::query("UPDATE users SET is_active = NOT is_active WHERE id = ?")
-        .bind(&user_id)
-        .execute(&pool.0)
-        .await
-        .map_err(|e| e.to_string())?;
+    let session = sessions.authorize(&token, Role::Owner).await?;
+    if session.user_id == user_id {
+        return Ok(ApiResponse::error("You cannot deactivate your own account"));
+    }
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let toggled: Option<(bool,)> =
+        sqlx::query_as("UPDATE users SET is_active = NOT is_active WHERE id = ? RETURNING is_active")
+            .bind(&user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let Some((is_active,)) = toggled else {
+        return Ok(ApiResponse::error("User not found"));
+    };
+
+    audit::record(
+        &mut tx,
+        &session,
+        if is_active { "activate" } else { "deactivate" },
+        "user",
+        &user_id,
+        Some(serde_json::json!({ "is_active": !is_active })),
+        Some(serde_json::json!({ "is_active": is_active })),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    // Deactivated users are already locked out by `resolve`; drop their
+    // sessions too so reactivating them does not revive old tokens.
+    sessions.revoke_user(&user_id, None).await?;
 
     Ok(ApiResponse::success(true))
 }
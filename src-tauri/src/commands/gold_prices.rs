@@ -1,11 +1,18 @@
 use super::{ApiResponse, DbPool};
-use crate::models::{GoldPrice, SetGoldPriceRequest};
+use crate::audit;
+use crate::models::{GoldPrice, PricingConfig, SavePricingConfigRequest, SetGoldPriceRequest};
+use crate::pricing::{self, PriceQuote, PriceSide};
+use crate::session::{Role, SessionStore};
 use tauri::State;
 
 #[tauri::command]
 pub async fn get_today_prices(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<Vec<GoldPrice>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
 
     let prices: Vec<GoldPrice> = sqlx::query_as::<_, GoldPrice>(
@@ -27,10 +34,30 @@ pub async fn get_today_prices(
 #[tauri::command]
 pub async fn set_gold_price(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     request: SetGoldPriceRequest,
 ) -> Result<ApiResponse<GoldPrice>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
     let id = uuid::Uuid::new_v4().to_string();
+    let select = r#"
+        SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at
+        FROM gold_prices
+        WHERE date = ? AND gold_type = ? AND purity = ?
+        "#;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    // Today's price may already be set; keep it for the audit log
+    let previous: Option<GoldPrice> = sqlx::query_as::<_, GoldPrice>(select)
+        .bind(&today)
+        .bind(&request.gold_type)
+        .bind(request.purity)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
 
     // Upsert price
     sqlx::query(
@@ -47,23 +74,30 @@ pub async fn set_gold_price(
     .bind(request.purity)
     .bind(request.buy_price)
     .bind(request.sell_price)
-    .execute(&pool.0)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
-    let price: GoldPrice = sqlx::query_as::<_, GoldPrice>(
-        r#"
-        SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at
-        FROM gold_prices
-        WHERE date = ? AND gold_type = ? AND purity = ?
-        "#,
+    let price: GoldPrice = sqlx::query_as::<_, GoldPrice>(select)
+        .bind(&today)
+        .bind(&request.gold_type)
+        .bind(request.purity)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        if previous.is_some() { "update" } else { "create" },
+        "gold_price",
+        &price.id,
+        previous.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&price),
     )
-    .bind(&today)
-    .bind(&request.gold_type)
-    .bind(request.purity)
-    .fetch_one(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
 
     Ok(ApiResponse::success(price))
 }
@@ -71,10 +105,14 @@ pub async fn set_gold_price(
 #[tauri::command]
 pub async fn get_price_history(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     gold_type: String,
     purity: i32,
     days: i32,
 ) -> Result<ApiResponse<Vec<GoldPrice>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let prices: Vec<GoldPrice> = sqlx::query_as::<_, GoldPrice>(
         r#"
         SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at
@@ -97,8 +135,12 @@ pub async fn get_price_history(
 #[tauri::command]
 pub async fn get_all_prices_for_date(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     date: String,
 ) -> Result<ApiResponse<Vec<GoldPrice>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let prices: Vec<GoldPrice> = sqlx::query_as::<_, GoldPrice>(
         r#"
         SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at
@@ -118,9 +160,13 @@ pub async fn get_all_prices_for_date(
 #[tauri::command]
 pub async fn get_price_for_calculation(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     gold_type: String,
     purity: i32,
 ) -> Result<ApiResponse<Option<GoldPrice>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
 
     let price: Option<GoldPrice> = sqlx::query_as::<_, GoldPrice>(
@@ -139,3 +185,103 @@ pub async fn get_price_for_calculation(
 
     Ok(ApiResponse::success(price))
 }
+
+/// Quote an inventory item at today's gold price for the given transaction type
+#[tauri::command]
+pub async fn quote_item_price(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    inventory_id: String,
+    transaction_type: String,
+) -> Result<ApiResponse<PriceQuote>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
+    let side = match PriceSide::for_transaction_type(&transaction_type) {
+        Some(side) => side,
+        None => {
+            return Ok(ApiResponse::error(&format!(
+                "Unknown transaction type: {}",
+                transaction_type
+            )))
+        }
+    };
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    match pricing::quote_inventory_item(&mut conn, &inventory_id, side, &today).await {
+        Ok(quote) => Ok(ApiResponse::success(quote)),
+        Err(e) => Ok(ApiResponse::error(&e)),
+    }
+}
+
+#[tauri::command]
+pub async fn get_pricing_config(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<PricingConfig>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let config = pricing::get_config(&mut conn).await?;
+
+    Ok(ApiResponse::success(config))
+}
+
+#[tauri::command]
+pub async fn save_pricing_config(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    request: SavePricingConfigRequest,
+) -> Result<ApiResponse<PricingConfig>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let tolerance = request.override_tolerance_percent;
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Ok(ApiResponse::error("Tolerance must be a percentage of zero or more"));
+    }
+    if !matches!(request.override_policy.as_str(), "reject" | "flag") {
+        return Ok(ApiResponse::error("Override policy must be 'reject' or 'flag'"));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let previous = pricing::get_config(&mut tx).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO pricing_config (id, override_tolerance_percent, override_policy, updated_at)
+        VALUES ('default', ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            override_tolerance_percent = excluded.override_tolerance_percent,
+            override_policy = excluded.override_policy,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(request.override_tolerance_percent)
+    .bind(&request.override_policy)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let config = pricing::get_config(&mut tx).await?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "pricing_config",
+        &config.id,
+        audit::snapshot(&previous),
+        audit::snapshot(&config),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(config))
+}
@@ -1,11 +1,17 @@
 use super::{ApiResponse, DbPool};
+use crate::audit;
 use crate::models::{Category, CreateInventoryRequest, Inventory, Product};
+use crate::session::{Role, SessionStore};
 use tauri::State;
 
 #[tauri::command]
 pub async fn get_categories(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<Vec<Category>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let categories: Vec<Category> = sqlx::query_as::<_, Category>(
         "SELECT id, name, description, created_at FROM categories ORDER BY name",
     )
@@ -17,13 +23,19 @@ pub async fn get_categories(
 }
 
 #[tauri::command]
-pub async fn get_products(pool: State<'_, DbPool>) -> Result<ApiResponse<Vec<Product>>, String> {
+pub async fn get_products(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<Product>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let products: Vec<Product> = sqlx::query_as::<_, Product>(
         r#"
         SELECT id, category_id, sku, name, description, gold_type, gold_purity,
                weight_gram, labor_cost, images, is_active, created_at
         FROM products
-        WHERE is_active = 1
+        WHERE is_active = 1 AND deleted_at IS NULL
         ORDER BY name
         "#,
     )
@@ -35,8 +47,11 @@ pub async fn get_products(pool: State<'_, DbPool>) -> Result<ApiResponse<Vec<Pro
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_product(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     category_id: String,
     name: String,
     gold_type: String,
@@ -46,6 +61,8 @@ pub async fn create_product(
     sku: Option<String>,
     description: Option<String>,
 ) -> Result<ApiResponse<Product>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
     let id = uuid::Uuid::new_v4().to_string();
 
     sqlx::query(
@@ -85,21 +102,26 @@ pub async fn create_product(
 #[tauri::command]
 pub async fn get_inventory(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     status: Option<String>,
 ) -> Result<ApiResponse<Vec<Inventory>>, String> {
-    // First get all inventory items
-    let inv_query = match &status {
-        Some(s) => format!(
-            "SELECT id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, created_at FROM inventory WHERE status = '{}' ORDER BY created_at DESC",
-            s
-        ),
-        None => "SELECT id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, created_at FROM inventory ORDER BY created_at DESC".to_string(),
-    };
+    sessions.authorize(&token, Role::Kasir).await?;
 
-    let inventory_items: Vec<Inventory> = sqlx::query_as::<_, Inventory>(&inv_query)
-        .fetch_all(&pool.0)
-        .await
-        .map_err(|e| e.to_string())?;
+    // First get all inventory items
+    let inventory_items: Vec<Inventory> = sqlx::query_as::<_, Inventory>(
+        r#"
+        SELECT id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, created_at
+        FROM inventory
+        WHERE (? IS NULL OR status = ?) AND deleted_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(&status)
+    .bind(&status)
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
 
     // Get all products for joining
     let products: Vec<Product> = sqlx::query_as::<_, Product>(
@@ -130,14 +152,18 @@ pub async fn get_inventory(
 #[tauri::command]
 pub async fn scan_barcode(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     barcode: String,
 ) -> Result<ApiResponse<Option<Inventory>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let inventory: Option<Inventory> = sqlx::query_as::<_, Inventory>(
         r#"
         SELECT id, product_id, branch_id, barcode, status, location,
                purchase_price, purchase_date, supplier, notes, sold_at, created_at
         FROM inventory
-        WHERE barcode = ?
+        WHERE barcode = ? AND deleted_at IS NULL
         "#,
     )
     .bind(&barcode)
@@ -151,9 +177,12 @@ pub async fn scan_barcode(
 #[tauri::command]
 pub async fn add_inventory(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     request: CreateInventoryRequest,
-    branch_id: String,
 ) -> Result<ApiResponse<Inventory>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
     let id = uuid::Uuid::new_v4().to_string();
 
     sqlx::query(
@@ -164,7 +193,7 @@ pub async fn add_inventory(
     )
     .bind(&id)
     .bind(&request.product_id)
-    .bind(&branch_id)
+    .bind(&session.branch_id)
     .bind(&request.barcode)
     .bind(&request.location)
     .bind(request.purchase_price)
@@ -193,38 +222,82 @@ pub async fn add_inventory(
 #[tauri::command]
 pub async fn update_inventory_location(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     inventory_id: String,
     location: String,
 ) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let select = r#"
+        SELECT id, product_id, branch_id, barcode, status, location,
+               purchase_price, purchase_date, supplier, notes, sold_at, created_at
+        FROM inventory WHERE id = ? AND deleted_at IS NULL
+        "#;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let before: Option<Inventory> = sqlx::query_as::<_, Inventory>(select)
+        .bind(&inventory_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(before) = before else {
+        return Ok(ApiResponse::error("Inventory not found"));
+    };
+
     sqlx::query("UPDATE inventory SET location = ? WHERE id = ?")
         .bind(&location)
         .bind(&inventory_id)
-        .execute(&pool.0)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let inventory: Inventory = sqlx::query_as::<_, Inventory>(select)
+        .bind(&inventory_id)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "inventory",
+        &inventory_id,
+        audit::snapshot(&before),
+        audit::snapshot(&inventory),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(ApiResponse::success(true))
 }
 
 #[tauri::command]
 pub async fn get_inventory_stats(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<
     ApiResponse<(i64, i64, i64, f64, i64)>,
     String,
 > {
-    let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM inventory")
+    sessions.authorize(&token, Role::Kasir).await?;
+
+    let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE deleted_at IS NULL")
         .fetch_one(&pool.0)
         .await
         .map_err(|e| e.to_string())?;
 
     let available: (i64,) =
-        sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'available'")
+        sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'available' AND deleted_at IS NULL")
             .fetch_one(&pool.0)
             .await
             .map_err(|e| e.to_string())?;
 
-    let sold: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'sold'")
+    let sold: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'sold' AND deleted_at IS NULL")
         .fetch_one(&pool.0)
         .await
         .map_err(|e| e.to_string())?;
@@ -234,7 +307,7 @@ pub async fn get_inventory_stats(
         SELECT SUM(p.weight_gram)
         FROM inventory i
         JOIN products p ON i.product_id = p.id
-        WHERE i.status = 'available'
+        WHERE i.status = 'available' AND i.deleted_at IS NULL
         "#,
     )
     .fetch_one(&pool.0)
@@ -242,7 +315,7 @@ pub async fn get_inventory_stats(
     .map_err(|e| e.to_string())?;
 
     let value: (Option<i64>,) = sqlx::query_as(
-        "SELECT SUM(purchase_price) FROM inventory WHERE status = 'available'",
+        "SELECT SUM(purchase_price) FROM inventory WHERE status = 'available' AND deleted_at IS NULL",
     )
     .fetch_one(&pool.0)
     .await
@@ -258,14 +331,19 @@ pub async fn get_inventory_stats(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_inventory(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     inventory_id: String,
     location: Option<String>,
     purchase_price: Option<i32>,
     supplier: Option<String>,
     notes: Option<String>,
 ) -> Result<ApiResponse<Inventory>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
     // Build dynamic update query
     let mut updates = Vec::new();
     let mut params: Vec<String> = Vec::new();
@@ -296,26 +374,50 @@ pub async fn update_inventory(
         updates.join(", ")
     );
 
+    let select = r#"
+        SELECT id, product_id, branch_id, barcode, status, location,
+               purchase_price, purchase_date, supplier, notes, sold_at, created_at
+        FROM inventory WHERE id = ? AND deleted_at IS NULL
+        "#;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let before: Option<Inventory> = sqlx::query_as::<_, Inventory>(select)
+        .bind(&inventory_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(before) = before else {
+        return Ok(ApiResponse::error("Inventory not found"));
+    };
+
     let mut q = sqlx::query(&query);
     for param in &params {
         q = q.bind(param);
     }
     q = q.bind(&inventory_id);
 
-    q.execute(&pool.0).await.map_err(|e| e.to_string())?;
+    q.execute(&mut *tx).await.map_err(|e| e.to_string())?;
 
     // Fetch updated inventory
-    let inventory: Inventory = sqlx::query_as::<_, Inventory>(
-        r#"
-        SELECT id, product_id, branch_id, barcode, status, location,
-               purchase_price, purchase_date, supplier, notes, sold_at, created_at
-        FROM inventory WHERE id = ?
-        "#,
+    let inventory: Inventory = sqlx::query_as::<_, Inventory>(select)
+        .bind(&inventory_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "inventory",
+        &inventory_id,
+        audit::snapshot(&before),
+        audit::snapshot(&inventory),
     )
-    .bind(&inventory_id)
-    .fetch_one(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
 
     Ok(ApiResponse::success(inventory))
 }
@@ -323,18 +425,24 @@ pub async fn update_inventory(
 #[tauri::command]
 pub async fn delete_inventory(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     inventory_id: String,
 ) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
     // Check if inventory exists and is available (not sold)
     let inventory: Option<Inventory> = sqlx::query_as::<_, Inventory>(
         r#"
         SELECT id, product_id, branch_id, barcode, status, location,
                purchase_price, purchase_date, supplier, notes, sold_at, created_at
-        FROM inventory WHERE id = ?
+        FROM inventory WHERE id = ? AND deleted_at IS NULL
         "#,
     )
     .bind(&inventory_id)
-    .fetch_optional(&pool.0)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
@@ -344,12 +452,27 @@ pub async fn delete_inventory(
                 return Ok(ApiResponse::error("Cannot delete inventory that is not available"));
             }
 
-            sqlx::query("DELETE FROM inventory WHERE id = ?")
+            // Only marked deleted: the row keeps its barcode, and sync
+            // pushes the delete to Salesforce
+            sqlx::query("UPDATE inventory SET deleted_at = datetime('now') WHERE id = ?")
                 .bind(&inventory_id)
-                .execute(&pool.0)
+                .execute(&mut *tx)
                 .await
                 .map_err(|e| e.to_string())?;
 
+            audit::record(
+                &mut tx,
+                &session,
+                "delete",
+                "inventory",
+                &inventory_id,
+                audit::snapshot(&inv),
+                None,
+            )
+            .await?;
+
+            tx.commit().await.map_err(|e| e.to_string())?;
+
             Ok(ApiResponse::success(true))
         }
         None => Ok(ApiResponse::error("Inventory not found")),
@@ -361,8 +484,12 @@ pub async fn delete_inventory(
 #[tauri::command]
 pub async fn generate_barcode(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     category_code: String,
 ) -> Result<ApiResponse<String>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     // Get next sequence number for this category
     let count: (i64,) = sqlx::query_as(
         "SELECT COUNT(*) FROM inventory WHERE barcode LIKE ?",
@@ -1,3 +1,4 @@
+use crate::sync::SyncError;
 use sqlx::SqlitePool;
 
 pub mod auth;
@@ -6,6 +7,9 @@ pub mod transactions;
 pub mod gold_prices;
 pub mod reports;
 pub mod sync;
+pub mod stock_opname;
+pub mod transfers;
+pub mod audit;
 
 // Re-export all commands
 pub use auth::*;
@@ -14,6 +18,9 @@ pub use transactions::*;
 pub use gold_prices::*;
 pub use reports::*;
 pub use sync::*;
+pub use stock_opname::*;
+pub use transfers::*;
+pub use audit::*;
 
 // Database state wrapper
 pub struct DbPool(pub SqlitePool);
@@ -24,6 +31,9 @@ pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    /// Kind of sync error, see `SyncError::code`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -32,6 +42,7 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            error_code: None,
         }
     }
 
@@ -40,6 +51,16 @@ impl<T> ApiResponse<T> {
             success: false,
             data: None,
             error: Some(message.to_string()),
+            error_code: None,
+        }
+    }
+
+    pub fn sync_error(error: &SyncError) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(error.to_string()),
+            error_code: Some(error.code().to_string()),
         }
     }
 }
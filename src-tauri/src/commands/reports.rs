@@ -1,11 +1,16 @@
 use super::{ApiResponse, DbPool};
 use crate::models::{DashboardSummary, SalesReport};
+use crate::session::{Role, SessionStore};
 use tauri::State;
 
 #[tauri::command]
 pub async fn get_dashboard_summary(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<DashboardSummary>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let now = chrono::Local::now();
     let today = now.format("%Y-%m-%d").to_string();
     let yesterday = (now - chrono::Duration::days(1)).format("%Y-%m-%d").to_string();
@@ -64,7 +69,7 @@ pub async fn get_dashboard_summary(
 
     // Available stock count
     let stock_count: (i64,) =
-        sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'available'")
+        sqlx::query_as("SELECT COUNT(*) FROM inventory WHERE status = 'available' AND deleted_at IS NULL")
             .fetch_one(&pool.0)
             .await
             .map_err(|e| e.to_string())?;
@@ -75,7 +80,7 @@ pub async fn get_dashboard_summary(
         SELECT COALESCE(SUM(p.weight_gram), 0)
         FROM inventory i
         JOIN products p ON i.product_id = p.id
-        WHERE i.status = 'available'
+        WHERE i.status = 'available' AND i.deleted_at IS NULL
         "#,
     )
     .fetch_one(&pool.0)
@@ -116,9 +121,13 @@ pub async fn get_dashboard_summary(
 #[tauri::command]
 pub async fn get_sales_report(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     date_from: String,
     date_to: String,
 ) -> Result<ApiResponse<Vec<SalesReport>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
     let rows = sqlx::query_as::<_, (String, Option<i64>, Option<i64>, i64)>(
         r#"
         SELECT
@@ -154,8 +163,12 @@ pub async fn get_sales_report(
 #[tauri::command]
 pub async fn get_daily_summary(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     date: String,
 ) -> Result<ApiResponse<serde_json::Value>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     // Sales summary
     let sales: (Option<i64>, i64) = sqlx::query_as(
         r#"
@@ -247,7 +260,11 @@ pub async fn get_daily_summary(
 #[tauri::command]
 pub async fn get_stock_report(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<Vec<serde_json::Value>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
     let rows = sqlx::query_as::<_, (String, i64, i64, i64, Option<f64>, Option<i64>)>(
         r#"
         SELECT
@@ -260,6 +277,7 @@ pub async fn get_stock_report(
         FROM inventory i
         JOIN products p ON i.product_id = p.id
         JOIN categories c ON p.category_id = c.id
+        WHERE i.deleted_at IS NULL
         GROUP BY c.id, c.name
         ORDER BY c.name
         "#,
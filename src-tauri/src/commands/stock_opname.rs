@@ -0,0 +1,671 @@
+use super::{ApiResponse, DbPool};
+use crate::models::{
+    Inventory, OpnameScanResponse, StockOpnameResult, StockOpnameScan, StockOpnameSession,
+};
+use crate::session::{Role, Session, SessionStore};
+use sqlx::SqliteConnection;
+use tauri::State;
+
+/// Statuses of stock that should physically be on the shelf
+const ON_HAND_STATUSES: [&str; 3] = ["available", "reserved", "reprocess"];
+
+#[tauri::command]
+pub async fn open_stock_opname(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    location: Option<String>,
+    notes: Option<String>,
+) -> Result<ApiResponse<StockOpnameSession>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let location = location.filter(|l| !l.trim().is_empty());
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO stock_opname_sessions (id, branch_id, location, notes, opened_by)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&session.branch_id)
+    .bind(&location)
+    .bind(&notes)
+    .bind(&session.user_id)
+    .execute(&pool.0)
+    .await;
+
+    if let Err(e) = inserted {
+        if e.as_database_error()
+            .is_some_and(|db| db.is_unique_violation())
+        {
+            return Ok(ApiResponse::error(
+                "A stock opname is already open for this location",
+            ));
+        }
+        return Err(e.to_string());
+    }
+
+    let opname = get_session(&pool.0, &id).await?;
+    Ok(ApiResponse::success(opname))
+}
+
+#[tauri::command]
+pub async fn record_opname_scan(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    session_id: String,
+    barcode: String,
+    location: Option<String>,
+) -> Result<ApiResponse<OpnameScanResponse>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let opname = get_session(&pool.0, &session_id).await?;
+    if !same_branch(&session, &opname) {
+        return Ok(ApiResponse::error("This stock opname belongs to another branch"));
+    }
+    if opname.status != "open" {
+        return Ok(ApiResponse::error("This stock opname is already closed"));
+    }
+
+    let inventory: Option<Inventory> = sqlx::query_as::<_, Inventory>(
+        r#"
+        SELECT id, product_id, branch_id, barcode, status, location,
+               purchase_price, purchase_date, supplier, notes, sold_at, created_at
+        FROM inventory
+        WHERE barcode = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(&barcode)
+    .fetch_optional(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+    // Another branch's item is not linked, so it reconciles as unexpected
+    let inventory = inventory.filter(|i| i.branch_id == opname.branch_id);
+
+    // A location-scoped count records every scan at that location
+    let location = opname
+        .location
+        .clone()
+        .or(location.filter(|l| !l.trim().is_empty()));
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO stock_opname_scans (id, session_id, barcode, inventory_id, location, scanned_by)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(session_id, barcode) DO NOTHING
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&session_id)
+    .bind(&barcode)
+    .bind(inventory.as_ref().map(|i| i.id.clone()))
+    .bind(&location)
+    .bind(&session.user_id)
+    .execute(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let scan: StockOpnameScan = sqlx::query_as(
+        r#"
+        SELECT id, session_id, barcode, inventory_id, location, scanned_by, scanned_at
+        FROM stock_opname_scans WHERE session_id = ? AND barcode = ?
+        "#,
+    )
+    .bind(&session_id)
+    .bind(&barcode)
+    .fetch_one(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(OpnameScanResponse {
+        scan,
+        duplicate: inserted.rows_affected() == 0,
+        inventory,
+    }))
+}
+
+/// Close a stock opname and reconcile the scans against the inventory
+#[tauri::command]
+pub async fn close_stock_opname(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    session_id: String,
+) -> Result<ApiResponse<Vec<StockOpnameResult>>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    // Closing first takes the write lock and stops further scans
+    let closed = sqlx::query(
+        r#"
+        UPDATE stock_opname_sessions SET status = 'closed', closed_by = ?, closed_at = datetime('now')
+        WHERE id = ? AND status = 'open' AND (branch_id = ? OR ?)
+        "#,
+    )
+    .bind(&session.user_id)
+    .bind(&session_id)
+    .bind(&session.branch_id)
+    .bind(session.is_owner())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if closed.rows_affected() == 0 {
+        return Ok(ApiResponse::error("This stock opname is not open"));
+    }
+
+    let opname: StockOpnameSession = sqlx::query_as(
+        r#"
+        SELECT id, branch_id, location, status, notes, opened_by, opened_at,
+               closed_by, closed_at, approved_by, approved_at
+        FROM stock_opname_sessions WHERE id = ?
+        "#,
+    )
+    .bind(&session_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let expected: Vec<StockItem> = sqlx::query_as(
+        r#"
+        SELECT id, barcode, branch_id, status, location FROM inventory
+        WHERE branch_id = ? AND status IN (?, ?, ?) AND (? IS NULL OR location = ?)
+          AND deleted_at IS NULL
+        "#,
+    )
+    .bind(&opname.branch_id)
+    .bind(ON_HAND_STATUSES[0])
+    .bind(ON_HAND_STATUSES[1])
+    .bind(ON_HAND_STATUSES[2])
+    .bind(&opname.location)
+    .bind(&opname.location)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let scans: Vec<ScanRow> = sqlx::query_as(
+        r#"
+        SELECT s.barcode, s.location AS scanned_location,
+               i.id, i.branch_id, i.status, i.location
+        FROM stock_opname_scans s
+        LEFT JOIN inventory i ON s.inventory_id = i.id
+        WHERE s.session_id = ?
+        "#,
+    )
+    .bind(&session_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let scans: Vec<ScannedItem> = scans.into_iter().map(ScannedItem::from).collect();
+
+    for finding in reconcile(
+        &opname.branch_id,
+        opname.location.as_deref(),
+        &expected,
+        &scans,
+    ) {
+        sqlx::query(
+            r#"
+            INSERT INTO stock_opname_results (id, session_id, kind, barcode, inventory_id,
+                                              status_before, expected_location, scanned_location)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&session_id)
+        .bind(finding.kind)
+        .bind(&finding.barcode)
+        .bind(&finding.inventory_id)
+        .bind(&finding.status_before)
+        .bind(&finding.expected_location)
+        .bind(&finding.scanned_location)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    let results = get_results(&mut tx, &session_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(results))
+}
+
+#[tauri::command]
+pub async fn get_stock_opname_sessions(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<StockOpnameSession>>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let opnames: Vec<StockOpnameSession> = sqlx::query_as(
+        r#"
+        SELECT id, branch_id, location, status, notes, opened_by, opened_at,
+               closed_by, closed_at, approved_by, approved_at
+        FROM stock_opname_sessions
+        WHERE branch_id = ?
+        ORDER BY opened_at DESC
+        LIMIT 100
+        "#,
+    )
+    .bind(&session.branch_id)
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(opnames))
+}
+
+#[tauri::command]
+pub async fn get_stock_opname_results(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    session_id: String,
+) -> Result<ApiResponse<Vec<StockOpnameResult>>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let opname = get_session(&pool.0, &session_id).await?;
+    if !same_branch(&session, &opname) {
+        return Ok(ApiResponse::error("This stock opname belongs to another branch"));
+    }
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let results = get_results(&mut conn, &session_id).await?;
+
+    Ok(ApiResponse::success(results))
+}
+
+/// Apply the selected reconciliation results to the inventory.
+///
+/// Every applied change is written to `inventory_adjustments`; results that
+/// are not selected are marked ignored.
+#[tauri::command]
+pub async fn approve_stock_opname(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    session_id: String,
+    adjust_result_ids: Vec<String>,
+) -> Result<ApiResponse<Vec<StockOpnameResult>>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let approved = sqlx::query(
+        r#"
+        UPDATE stock_opname_sessions SET status = 'approved', approved_by = ?, approved_at = datetime('now')
+        WHERE id = ? AND status = 'closed'
+        "#,
+    )
+    .bind(&session.user_id)
+    .bind(&session_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if approved.rows_affected() == 0 {
+        return Ok(ApiResponse::error(
+            "Only a closed stock opname can be approved",
+        ));
+    }
+
+    let results = get_results(&mut tx, &session_id).await?;
+
+    for result_id in &adjust_result_ids {
+        let result = match results.iter().find(|r| &r.id == result_id) {
+            Some(result) => result,
+            None => {
+                return Ok(ApiResponse::error(&format!(
+                    "Result {} does not belong to this stock opname",
+                    result_id
+                )))
+            }
+        };
+        let inventory_id = match &result.inventory_id {
+            Some(id) if result.kind != "unexpected" => id,
+            _ => {
+                return Ok(ApiResponse::error(&format!(
+                    "Item {} is not in this branch's stock and cannot be adjusted",
+                    result.barcode
+                )))
+            }
+        };
+
+        let (field, old_value, new_value) = match result.kind.as_str() {
+            "missing" => (
+                "status",
+                result.status_before.clone(),
+                Some("missing".to_string()),
+            ),
+            "found" | "sold_but_scanned" => (
+                "status",
+                result.status_before.clone(),
+                Some("available".to_string()),
+            ),
+            "wrong_location" => (
+                "location",
+                result.expected_location.clone(),
+                result.scanned_location.clone(),
+            ),
+            other => return Err(format!("Unknown stock opname result: {}", other)),
+        };
+
+        // Only apply the change if the item is still as it was counted
+        let updated = if field == "status" {
+            sqlx::query(
+                r#"
+                UPDATE inventory
+                SET status = ?, sold_at = CASE WHEN ? = 'available' THEN NULL ELSE sold_at END
+                WHERE id = ? AND status = ? AND deleted_at IS NULL
+                "#,
+            )
+            .bind(&new_value)
+            .bind(&new_value)
+            .bind(inventory_id)
+            .bind(&old_value)
+            .execute(&mut *tx)
+            .await
+        } else {
+            sqlx::query("UPDATE inventory SET location = ? WHERE id = ? AND location IS ?")
+                .bind(&new_value)
+                .bind(inventory_id)
+                .bind(&old_value)
+                .execute(&mut *tx)
+                .await
+        }
+        .map_err(|e| e.to_string())?;
+
+        if updated.rows_affected() == 0 {
+            return Ok(ApiResponse::error(&format!(
+                "Item {} changed after it was counted; run a new stock opname",
+                result.barcode
+            )));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO inventory_adjustments (id, inventory_id, opname_result_id, field, old_value, new_value, approved_by)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(inventory_id)
+        .bind(&result.id)
+        .bind(field)
+        .bind(&old_value)
+        .bind(&new_value)
+        .bind(&session.user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        sqlx::query("UPDATE stock_opname_results SET resolution = 'adjusted' WHERE id = ?")
+            .bind(&result.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    sqlx::query(
+        "UPDATE stock_opname_results SET resolution = 'ignored' WHERE session_id = ? AND resolution IS NULL",
+    )
+    .bind(&session_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let results = get_results(&mut tx, &session_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(results))
+}
+
+/// Only staff of the counted branch, or an owner, may work on a count
+fn same_branch(session: &Session, opname: &StockOpnameSession) -> bool {
+    session.is_owner() || opname.branch_id == session.branch_id
+}
+
+async fn get_session(
+    pool: &sqlx::SqlitePool,
+    session_id: &str,
+) -> Result<StockOpnameSession, String> {
+    sqlx::query_as(
+        r#"
+        SELECT id, branch_id, location, status, notes, opened_by, opened_at,
+               closed_by, closed_at, approved_by, approved_at
+        FROM stock_opname_sessions WHERE id = ?
+        "#,
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Stock opname {} not found", session_id))
+}
+
+async fn get_results(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+) -> Result<Vec<StockOpnameResult>, String> {
+    sqlx::query_as(
+        r#"
+        SELECT id, session_id, kind, barcode, inventory_id, status_before,
+               expected_location, scanned_location, resolution
+        FROM stock_opname_results
+        WHERE session_id = ?
+        ORDER BY kind, barcode
+        "#,
+    )
+    .bind(session_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Inventory row as seen by the reconciliation
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct StockItem {
+    id: String,
+    barcode: String,
+    branch_id: String,
+    status: String,
+    location: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ScanRow {
+    barcode: String,
+    scanned_location: Option<String>,
+    id: Option<String>,
+    branch_id: Option<String>,
+    status: Option<String>,
+    location: Option<String>,
+}
+
+/// A scanned barcode and the inventory row it matched, if any
+#[derive(Debug)]
+struct ScannedItem {
+    barcode: String,
+    location: Option<String>,
+    item: Option<StockItem>,
+}
+
+impl From<ScanRow> for ScannedItem {
+    fn from(row: ScanRow) -> Self {
+        let item = match (row.id, row.branch_id, row.status) {
+            (Some(id), Some(branch_id), Some(status)) => Some(StockItem {
+                id,
+                barcode: row.barcode.clone(),
+                branch_id,
+                status,
+                location: row.location,
+            }),
+            _ => None,
+        };
+        ScannedItem {
+            barcode: row.barcode,
+            location: row.scanned_location,
+            item,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Finding {
+    kind: &'static str,
+    barcode: String,
+    inventory_id: Option<String>,
+    status_before: Option<String>,
+    expected_location: Option<String>,
+    scanned_location: Option<String>,
+}
+
+/// Compare what was scanned with what the inventory says should be there.
+///
+/// `expected` is the on-hand stock of the branch (and location, when the
+/// count is scoped to one). Scans that match it exactly produce no finding.
+fn reconcile(
+    branch_id: &str,
+    location: Option<&str>,
+    expected: &[StockItem],
+    scans: &[ScannedItem],
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for scan in scans {
+        let finding = |kind, item: Option<&StockItem>| Finding {
+            kind,
+            barcode: scan.barcode.clone(),
+            inventory_id: item.map(|i| i.id.clone()),
+            status_before: item.map(|i| i.status.clone()),
+            expected_location: item.and_then(|i| i.location.clone()),
+            scanned_location: scan.location.clone(),
+        };
+
+        let item = match &scan.item {
+            Some(item) if item.branch_id == branch_id => item,
+            other => {
+                findings.push(finding("unexpected", other.as_ref()));
+                continue;
+            }
+        };
+
+        match item.status.as_str() {
+            "sold" => findings.push(finding("sold_but_scanned", Some(item))),
+            "missing" => findings.push(finding("found", Some(item))),
+            status if ON_HAND_STATUSES.contains(&status) => {
+                let misplaced = match (location, &scan.location) {
+                    (Some(expected), _) => item.location.as_deref() != Some(expected),
+                    (None, Some(scanned)) => item.location.as_ref() != Some(scanned),
+                    (None, None) => false,
+                };
+                if misplaced {
+                    findings.push(finding("wrong_location", Some(item)));
+                }
+            }
+            _ => findings.push(finding("unexpected", Some(item))),
+        }
+    }
+
+    for item in expected {
+        let scanned = scans
+            .iter()
+            .any(|scan| scan.item.as_ref().is_some_and(|i| i.id == item.id));
+        if !scanned {
+            findings.push(Finding {
+                kind: "missing",
+                barcode: item.barcode.clone(),
+                inventory_id: Some(item.id.clone()),
+                status_before: Some(item.status.clone()),
+                expected_location: item.location.clone(),
+                scanned_location: None,
+            });
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, branch_id: &str, status: &str, location: &str) -> StockItem {
+        StockItem {
+            id: id.to_string(),
+            barcode: format!("EM-{}", id),
+            branch_id: branch_id.to_string(),
+            status: status.to_string(),
+            location: Some(location.to_string()),
+        }
+    }
+
+    fn scan(item: Option<StockItem>, barcode: &str, location: Option<&str>) -> ScannedItem {
+        ScannedItem {
+            barcode: barcode.to_string(),
+            location: location.map(str::to_string),
+            item,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_location_count() {
+        let on_shelf = item("1", "b1", "available", "Etalase A");
+        let not_scanned = item("2", "b1", "available", "Etalase A");
+        let misplaced = item("3", "b1", "available", "Etalase B");
+        let sold = item("4", "b1", "sold", "Etalase A");
+        let lost = item("5", "b1", "missing", "Etalase A");
+        let other_branch = item("6", "b2", "available", "Etalase A");
+
+        let expected = vec![on_shelf.clone(), not_scanned.clone()];
+        let at = Some("Etalase A");
+        let scans = vec![
+            scan(Some(on_shelf), "EM-1", at),
+            scan(Some(misplaced), "EM-3", at),
+            scan(Some(sold), "EM-4", at),
+            scan(Some(lost), "EM-5", at),
+            scan(Some(other_branch), "EM-6", at),
+            scan(None, "UNKNOWN", at),
+        ];
+
+        let findings = reconcile("b1", at, &expected, &scans);
+        let kinds: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|f| (f.kind, f.barcode.as_str()))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                ("wrong_location", "EM-3"),
+                ("sold_but_scanned", "EM-4"),
+                ("found", "EM-5"),
+                ("unexpected", "EM-6"),
+                ("unexpected", "UNKNOWN"),
+                ("missing", "EM-2"),
+            ]
+        );
+        assert_eq!(findings[0].expected_location.as_deref(), Some("Etalase B"));
+        assert_eq!(findings[0].scanned_location.as_deref(), Some("Etalase A"));
+    }
+
+    #[test]
+    fn test_reconcile_branch_count_without_scan_locations() {
+        let a = item("1", "b1", "available", "Etalase A");
+        let b = item("2", "b1", "reserved", "Etalase B");
+
+        let scans = vec![
+            scan(Some(a.clone()), "EM-1", None),
+            scan(Some(b.clone()), "EM-2", None),
+        ];
+        assert!(reconcile("b1", None, &[a, b], &scans).is_empty());
+    }
+}
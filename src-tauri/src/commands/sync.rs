@@ -1,49 +1,76 @@
 use super::{ApiResponse, DbPool};
-use crate::models::{SaveSyncConfigRequest, SyncConfig, SyncResult, SyncStatus};
+use crate::audit;
+use crate::models::{
+    SaveSyncConfigRequest, SecretStatus, SyncConfig, SyncConfigResponse, SyncConflict,
+    SyncConflictPolicy, SyncDeleteMode, SyncLog, SyncMetadata, SyncResult, SyncStatus,
+};
+use crate::secrets::SecretVault;
+use crate::session::{Role, SessionStore};
+use crate::sync::conflicts::{self, ConflictPolicy};
+use crate::sync::dead_letter;
+use crate::sync::deletions::{self, DeleteMode};
+use crate::sync::pull;
+use crate::sync::engine::{load_config, SYNC_CONFIG_SELECT};
 use crate::sync::SyncEngine;
 use parking_lot::RwLock;
 use sqlx::SqlitePool;
 use std::sync::Arc;
 use tauri::State;
 
-/// Global sync engine state
-pub struct SyncState(pub Arc<RwLock<Option<Arc<SyncEngine>>>>);
+/// Global sync engine state, plus the vault holding the Salesforce secrets
+pub struct SyncState {
+    engine: Arc<RwLock<Option<Arc<SyncEngine>>>>,
+    vault: Arc<SecretVault>,
+}
 
 impl SyncState {
-    pub fn new() -> Self {
-        Self(Arc::new(RwLock::new(None)))
+    pub fn new(vault: Arc<SecretVault>) -> Self {
+        Self {
+            engine: Arc::new(RwLock::new(None)),
+            vault,
+        }
     }
 
     pub fn get_or_init(&self, pool: &SqlitePool) -> Arc<SyncEngine> {
-        let mut engine = self.0.write();
+        let mut engine = self.engine.write();
         if engine.is_none() {
-            *engine = Some(Arc::new(SyncEngine::new(pool.clone())));
+            *engine = Some(Arc::new(SyncEngine::new(pool.clone(), self.vault.clone())));
         }
         engine.as_ref().unwrap().clone()
     }
+
+    pub fn vault(&self) -> &SecretVault {
+        &self.vault
+    }
 }
 
-impl Default for SyncState {
-    fn default() -> Self {
-        Self::new()
+/// The sync engine, set up with the saved Salesforce settings, or the
+/// response to send when it cannot be
+async fn configured_engine<T>(
+    pool: &SqlitePool,
+    sync_state: &SyncState,
+) -> Result<Arc<SyncEngine>, ApiResponse<T>> {
+    let engine = sync_state.get_or_init(pool);
+    match load_config(pool).await {
+        Ok(Some(config)) => engine.configure(&config).map_err(|e| ApiResponse::sync_error(&e))?,
+        Ok(None) => return Err(ApiResponse::error("Salesforce not configured")),
+        Err(e) => return Err(ApiResponse::sync_error(&e)),
     }
+    Ok(engine)
 }
 
 /// Get sync configuration
 #[tauri::command]
-pub async fn get_sync_config(pool: State<'_, DbPool>) -> Result<ApiResponse<Option<SyncConfig>>, String> {
-    let config: Option<SyncConfig> = sqlx::query_as(
-        r#"
-        SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
-               sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at
-        FROM sync_config WHERE id = 'default'
-        "#,
-    )
-    .fetch_optional(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+pub async fn get_sync_config(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Option<SyncConfigResponse>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
 
-    Ok(ApiResponse::success(config))
+    let config = load_config(&pool.0).await?;
+
+    Ok(ApiResponse::success(config.as_ref().map(SyncConfigResponse::from)))
 }
 
 /// Save sync configuration
@@ -51,9 +78,14 @@ pub async fn get_sync_config(pool: State<'_, DbPool>) -> Result<ApiResponse<Opti
 pub async fn save_sync_config(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     request: SaveSyncConfigRequest,
-) -> Result<ApiResponse<SyncConfig>, String> {
+) -> Result<ApiResponse<SyncConfigResponse>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
     let now = chrono::Utc::now().to_rfc3339();
+    let vault = sync_state.vault();
 
     // Determine instance URL based on sandbox flag
     let instance_url = request.sf_instance_url.unwrap_or_else(|| {
@@ -64,61 +96,114 @@ pub async fn save_sync_config(
         }
     });
 
+    let auth_flow = request
+        .sf_auth_flow
+        .unwrap_or_else(|| "password".to_string());
+    if !["password", "jwt_bearer"].contains(&auth_flow.as_str()) {
+        return Ok(ApiResponse::error("Unknown Salesforce auth flow"));
+    }
+    if request.bulk_threshold.is_some_and(|threshold| threshold < 1) {
+        return Ok(ApiResponse::error("Bulk threshold must be at least 1"));
+    }
+    if request.api_usage_threshold.is_some_and(|threshold| !(1..=100).contains(&threshold)) {
+        return Ok(ApiResponse::error("API usage threshold must be between 1 and 100 percent"));
+    }
+
+    // Only secrets that were sent are replaced
+    let encrypt = |secret: Option<String>| match secret.filter(|s| !s.is_empty()) {
+        Some(secret) => vault.encrypt(&secret).map(Some),
+        None => Ok(None),
+    };
+    let (client_secret, password, security_token, jwt_private_key) = match (
+        encrypt(request.sf_client_secret),
+        encrypt(request.sf_password),
+        encrypt(request.sf_security_token),
+        encrypt(request.sf_jwt_private_key),
+    ) {
+        (Ok(client_secret), Ok(password), Ok(security_token), Ok(jwt_private_key)) => {
+            (client_secret, password, security_token, jwt_private_key)
+        }
+        (Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => {
+            return Ok(ApiResponse::error(&e))
+        }
+    };
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let previous = load_config(&mut *tx).await?;
+
     sqlx::query(
         r#"
         INSERT INTO sync_config (id, sf_client_id, sf_client_secret, sf_username, sf_password,
-                                  sf_security_token, sf_instance_url, is_sandbox, sync_enabled,
-                                  sync_interval_minutes, updated_at)
-        VALUES ('default', ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                                  sf_security_token, sf_auth_flow, sf_jwt_private_key,
+                                  sf_jwt_audience, sf_instance_url, is_sandbox, sync_enabled,
+                                  sync_interval_minutes, bulk_threshold, api_usage_threshold, updated_at)
+        VALUES ('default', ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ON CONFLICT(id) DO UPDATE SET
             sf_client_id = excluded.sf_client_id,
-            sf_client_secret = excluded.sf_client_secret,
+            sf_client_secret = COALESCE(excluded.sf_client_secret, sf_client_secret),
             sf_username = excluded.sf_username,
-            sf_password = excluded.sf_password,
-            sf_security_token = excluded.sf_security_token,
+            sf_password = COALESCE(excluded.sf_password, sf_password),
+            sf_security_token = COALESCE(excluded.sf_security_token, sf_security_token),
+            sf_auth_flow = excluded.sf_auth_flow,
+            sf_jwt_private_key = COALESCE(excluded.sf_jwt_private_key, sf_jwt_private_key),
+            sf_jwt_audience = excluded.sf_jwt_audience,
             sf_instance_url = excluded.sf_instance_url,
             is_sandbox = excluded.is_sandbox,
             sync_enabled = excluded.sync_enabled,
             sync_interval_minutes = excluded.sync_interval_minutes,
+            bulk_threshold = excluded.bulk_threshold,
+            api_usage_threshold = excluded.api_usage_threshold,
             updated_at = excluded.updated_at
         "#,
     )
     .bind(&request.sf_client_id)
-    .bind(&request.sf_client_secret)
+    .bind(&client_secret)
     .bind(&request.sf_username)
-    .bind(&request.sf_password)
-    .bind(&request.sf_security_token)
+    .bind(&password)
+    .bind(&security_token)
+    .bind(&auth_flow)
+    .bind(&jwt_private_key)
+    .bind(request.sf_jwt_audience.filter(|a| !a.is_empty()))
     .bind(&instance_url)
     .bind(request.is_sandbox)
     .bind(request.sync_enabled)
     .bind(request.sync_interval_minutes)
+    .bind(request.bulk_threshold)
+    .bind(request.api_usage_threshold)
     .bind(&now)
-    .execute(&pool.0)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
+    let config: SyncConfig = sqlx::query_as(SYNC_CONFIG_SELECT)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = SyncConfigResponse::from(&config);
+
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "sync_config",
+        &config.id,
+        previous
+            .as_ref()
+            .and_then(|p| audit::snapshot(&SyncConfigResponse::from(p))),
+        audit::snapshot(&response),
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     // Reconfigure sync engine with new credentials
     let engine = sync_state.get_or_init(&pool.0);
-    let config = SyncConfig {
-        id: "default".to_string(),
-        sf_client_id: Some(request.sf_client_id),
-        sf_client_secret: Some(request.sf_client_secret),
-        sf_username: Some(request.sf_username),
-        sf_password: Some(request.sf_password),
-        sf_security_token: Some(request.sf_security_token),
-        sf_instance_url: Some(instance_url.clone()),
-        is_sandbox: request.is_sandbox,
-        sync_enabled: request.sync_enabled,
-        sync_interval_minutes: request.sync_interval_minutes,
-        created_at: now.clone(),
-        updated_at: Some(now),
-    };
-
     if let Err(e) = engine.configure(&config) {
         return Ok(ApiResponse::error(&format!("Config saved but failed to apply: {}", e)));
     }
 
-    Ok(ApiResponse::success(config))
+    Ok(ApiResponse::success(response))
 }
 
 /// Test Salesforce connection
@@ -126,32 +211,19 @@ pub async fn save_sync_config(
 pub async fn test_sf_connection(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<String>, String> {
-    let engine = sync_state.get_or_init(&pool.0);
-
-    // Load and apply config if not already configured
-    let config: Option<SyncConfig> = sqlx::query_as(
-        r#"
-        SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
-               sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at
-        FROM sync_config WHERE id = 'default'
-        "#,
-    )
-    .fetch_optional(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+    sessions.authorize(&token, Role::Owner).await?;
 
-    if let Some(cfg) = config {
-        if let Err(e) = engine.configure(&cfg) {
-            return Ok(ApiResponse::error(&e));
-        }
-    } else {
-        return Ok(ApiResponse::error("Salesforce not configured"));
-    }
+    let engine = match configured_engine(&pool.0, &sync_state).await {
+        Ok(engine) => engine,
+        Err(response) => return Ok(response),
+    };
 
     match engine.test_connection().await {
         Ok(message) => Ok(ApiResponse::success(message)),
-        Err(e) => Ok(ApiResponse::error(&e)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
 }
 
@@ -160,12 +232,16 @@ pub async fn test_sf_connection(
 pub async fn get_sync_status(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<SyncStatus>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let engine = sync_state.get_or_init(&pool.0);
 
     match engine.get_status().await {
         Ok(status) => Ok(ApiResponse::success(status)),
-        Err(e) => Ok(ApiResponse::error(&e)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
 }
 
@@ -174,32 +250,19 @@ pub async fn get_sync_status(
 pub async fn manual_sync(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<SyncResult>, String> {
-    let engine = sync_state.get_or_init(&pool.0);
-
-    // Load and apply config if not already configured
-    let config: Option<SyncConfig> = sqlx::query_as(
-        r#"
-        SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
-               sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at
-        FROM sync_config WHERE id = 'default'
-        "#,
-    )
-    .fetch_optional(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+    sessions.authorize(&token, Role::Owner).await?;
 
-    if let Some(cfg) = config {
-        if let Err(e) = engine.configure(&cfg) {
-            return Ok(ApiResponse::error(&e));
-        }
-    } else {
-        return Ok(ApiResponse::error("Salesforce not configured"));
-    }
+    let engine = match configured_engine(&pool.0, &sync_state).await {
+        Ok(engine) => engine,
+        Err(response) => return Ok(response),
+    };
 
     match engine.run_full_sync().await {
         Ok(result) => Ok(ApiResponse::success(result)),
-        Err(e) => Ok(ApiResponse::error(&e)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
 }
 
@@ -208,32 +271,19 @@ pub async fn manual_sync(
 pub async fn pull_gold_prices_from_sf(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<SyncResult>, String> {
-    let engine = sync_state.get_or_init(&pool.0);
+    sessions.authorize(&token, Role::Owner).await?;
 
-    // Load and apply config
-    let config: Option<SyncConfig> = sqlx::query_as(
-        r#"
-        SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
-               sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at
-        FROM sync_config WHERE id = 'default'
-        "#,
-    )
-    .fetch_optional(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    if let Some(cfg) = config {
-        if let Err(e) = engine.configure(&cfg) {
-            return Ok(ApiResponse::error(&e));
-        }
-    } else {
-        return Ok(ApiResponse::error("Salesforce not configured"));
-    }
+    let engine = match configured_engine(&pool.0, &sync_state).await {
+        Ok(engine) => engine,
+        Err(response) => return Ok(response),
+    };
 
     match engine.pull_gold_prices().await {
         Ok(result) => Ok(ApiResponse::success(result)),
-        Err(e) => Ok(ApiResponse::error(&e)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
 }
 
@@ -242,47 +292,514 @@ pub async fn pull_gold_prices_from_sf(
 pub async fn pull_inventory_from_sf(
     pool: State<'_, DbPool>,
     sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     branch_sf_id: Option<String>,
 ) -> Result<ApiResponse<SyncResult>, String> {
-    let engine = sync_state.get_or_init(&pool.0);
+    sessions.authorize(&token, Role::Owner).await?;
 
-    // Load and apply config
-    let config: Option<SyncConfig> = sqlx::query_as(
-        r#"
-        SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
-               sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at
-        FROM sync_config WHERE id = 'default'
-        "#,
-    )
-    .fetch_optional(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
+    let engine = match configured_engine(&pool.0, &sync_state).await {
+        Ok(engine) => engine,
+        Err(response) => return Ok(response),
+    };
 
-    if let Some(cfg) = config {
-        if let Err(e) = engine.configure(&cfg) {
-            return Ok(ApiResponse::error(&e));
-        }
-    } else {
-        return Ok(ApiResponse::error("Salesforce not configured"));
+    match engine.pull_inventory(branch_sf_id.as_deref()).await {
+        Ok(result) => Ok(ApiResponse::success(result)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
+}
 
-    match engine.pull_inventory(branch_sf_id.as_deref()).await {
+/// Pull customers and transactions from Salesforce (optionally of one branch)
+#[tauri::command]
+pub async fn pull_transactions_from_sf(
+    pool: State<'_, DbPool>,
+    sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    branch_sf_id: Option<String>,
+) -> Result<ApiResponse<SyncResult>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let engine = match configured_engine(&pool.0, &sync_state).await {
+        Ok(engine) => engine,
+        Err(response) => return Ok(response),
+    };
+
+    match engine.pull_transactions(branch_sf_id.as_deref()).await {
         Ok(result) => Ok(ApiResponse::success(result)),
-        Err(e) => Ok(ApiResponse::error(&e)),
+        Err(e) => Ok(ApiResponse::sync_error(&e)),
     }
 }
 
+/// Forget where pulls left off, so the next pull of `table_name` (or of
+/// every object) fetches all its records from Salesforce again
+#[tauri::command]
+pub async fn reset_pull_watermarks(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    table_name: Option<String>,
+) -> Result<ApiResponse<Vec<SyncMetadata>>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let reset = pull::reset_watermarks(&mut tx, table_name.as_deref()).await?;
+    for row in &reset {
+        let after = SyncMetadata {
+            pull_watermark: None,
+            ..row.clone()
+        };
+        audit::record(
+            &mut tx,
+            &session,
+            "update",
+            "sync_metadata",
+            &row.table_name,
+            audit::snapshot(row),
+            audit::snapshot(&after),
+        )
+        .await?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(reset))
+}
+
 /// Toggle sync enabled/disabled
 #[tauri::command]
 pub async fn toggle_sync_enabled(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     enabled: bool,
 ) -> Result<ApiResponse<bool>, String> {
-    sqlx::query("UPDATE sync_config SET sync_enabled = ?, updated_at = datetime('now') WHERE id = 'default'")
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let updated = sqlx::query("UPDATE sync_config SET sync_enabled = ?, updated_at = datetime('now') WHERE id = 'default'")
         .bind(enabled)
-        .execute(&pool.0)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
 
+    if updated.rows_affected() > 0 {
+        audit::record(
+            &mut tx,
+            &session,
+            if enabled { "enable" } else { "disable" },
+            "sync_config",
+            "default",
+            None,
+            Some(serde_json::json!({ "sync_enabled": enabled })),
+        )
+        .await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(ApiResponse::success(enabled))
 }
+
+/// How the Salesforce secrets are protected, and whether they are unlocked
+#[tauri::command]
+pub async fn get_secret_status(
+    pool: State<'_, DbPool>,
+    sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<SecretStatus>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let vault = sync_state.vault();
+    Ok(ApiResponse::success(SecretStatus {
+        mode: vault.mode(&pool.0).await?,
+        unlocked: vault.is_unlocked(),
+    }))
+}
+
+/// Unlock passphrase-protected secrets until the app is closed
+#[tauri::command]
+pub async fn unlock_sync_secrets(
+    pool: State<'_, DbPool>,
+    sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    passphrase: String,
+) -> Result<ApiResponse<bool>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    match sync_state.vault().unlock(&pool.0, &passphrase).await {
+        Ok(()) => Ok(ApiResponse::success(true)),
+        Err(e) => Ok(ApiResponse::error(&e)),
+    }
+}
+
+/// Protect the secrets with a passphrase, or go back to the keyfile when
+/// `passphrase` is omitted
+#[tauri::command]
+pub async fn set_sync_passphrase(
+    pool: State<'_, DbPool>,
+    sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    passphrase: Option<String>,
+) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    if let Err(e) = sync_state
+        .vault()
+        .set_passphrase(&pool.0, passphrase.as_deref())
+        .await
+    {
+        return Ok(ApiResponse::error(&e));
+    }
+
+    let mode = if passphrase.is_some() { "passphrase" } else { "keyfile" };
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    audit::record(
+        &mut conn,
+        &session,
+        "set_secret_mode",
+        "sync_config",
+        "default",
+        None,
+        Some(serde_json::json!({ "mode": mode })),
+    )
+    .await?;
+
+    Ok(ApiResponse::success(true))
+}
+
+/// Discard the stored secrets and start over with a new keyfile, for when
+/// the passphrase is forgotten or the keyfile is lost
+#[tauri::command]
+pub async fn reset_sync_secrets(
+    pool: State<'_, DbPool>,
+    sync_state: State<'_, SyncState>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    sync_state.vault().reset(&pool.0).await?;
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    audit::record(
+        &mut conn,
+        &session,
+        "reset_secrets",
+        "sync_config",
+        "default",
+        None,
+        None,
+    )
+    .await?;
+
+    Ok(ApiResponse::success(true))
+}
+
+/// Conflicts found by sync; open ones unless `status` says otherwise
+#[tauri::command]
+pub async fn get_sync_conflicts(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    status: Option<String>,
+) -> Result<ApiResponse<Vec<SyncConflict>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let conflicts: Vec<SyncConflict> = sqlx::query_as(
+        r#"
+        SELECT id, table_name, record_id, salesforce_id, local_json, remote_json, base_json,
+               remote_modstamp, local_changed_at, conflicting_fields, status, resolution,
+               resolved_by, resolved_at, created_at
+        FROM sync_conflicts
+        WHERE status = ?
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(status.as_deref().unwrap_or("open"))
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(conflicts))
+}
+
+/// Settle a conflict by keeping the local record (`"local"`) or taking
+/// Salesforce's (`"server"`)
+#[tauri::command]
+pub async fn resolve_sync_conflict(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    conflict_id: String,
+    resolution: String,
+) -> Result<ApiResponse<SyncConflict>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let before = conflicts::get_conflict(&mut tx, &conflict_id).await?;
+    let resolved = match conflicts::resolve(&mut tx, &conflict_id, &resolution, &session.user_id).await {
+        Ok(resolved) => resolved,
+        Err(e) => return Ok(ApiResponse::sync_error(&e)),
+    };
+
+    audit::record(
+        &mut tx,
+        &session,
+        "resolve",
+        "sync_conflict",
+        &conflict_id,
+        before.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&resolved),
+    )
+    .await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(resolved))
+}
+
+#[tauri::command]
+pub async fn get_conflict_policies(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<SyncConflictPolicy>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let policies: Vec<SyncConflictPolicy> = sqlx::query_as(
+        "SELECT table_name, policy, updated_at FROM sync_conflict_policies ORDER BY table_name",
+    )
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(policies))
+}
+
+/// Set how sync settles conflicts on one table
+#[tauri::command]
+pub async fn set_conflict_policy(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    table_name: String,
+    policy: String,
+) -> Result<ApiResponse<SyncConflictPolicy>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    if ConflictPolicy::parse(&policy).is_none() {
+        return Ok(ApiResponse::error(
+            "Policy must be server_wins, local_wins, merge or manual",
+        ));
+    }
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let previous: Option<SyncConflictPolicy> = sqlx::query_as(
+        "SELECT table_name, policy, updated_at FROM sync_conflict_policies WHERE table_name = ?",
+    )
+    .bind(&table_name)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some(previous) = previous else {
+        return Ok(ApiResponse::error("Table is not synced"));
+    };
+
+    sqlx::query(
+        "UPDATE sync_conflict_policies SET policy = ?, updated_at = datetime('now') WHERE table_name = ?",
+    )
+    .bind(&policy)
+    .bind(&table_name)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let updated: SyncConflictPolicy = sqlx::query_as(
+        "SELECT table_name, policy, updated_at FROM sync_conflict_policies WHERE table_name = ?",
+    )
+    .bind(&table_name)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "sync_conflict_policy",
+        &table_name,
+        audit::snapshot(&previous),
+        audit::snapshot(&updated),
+    )
+    .await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(updated))
+}
+
+#[tauri::command]
+pub async fn get_delete_modes(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<SyncDeleteMode>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let modes: Vec<SyncDeleteMode> = sqlx::query_as(
+        "SELECT table_name, mode, updated_at FROM sync_delete_modes ORDER BY table_name",
+    )
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(modes))
+}
+
+/// Set whether deleting a record on one table deactivates or deletes it in
+/// Salesforce
+#[tauri::command]
+pub async fn set_delete_mode(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    table_name: String,
+    mode: String,
+) -> Result<ApiResponse<SyncDeleteMode>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    match DeleteMode::parse(&mode) {
+        None => return Ok(ApiResponse::error("Mode must be deactivate or delete")),
+        Some(DeleteMode::Deactivate) if !deletions::can_deactivate(&table_name) => {
+            return Ok(ApiResponse::error(
+                "Records of this table cannot be deactivated in Salesforce",
+            ));
+        }
+        Some(_) => {}
+    }
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let previous: Option<SyncDeleteMode> = sqlx::query_as(
+        "SELECT table_name, mode, updated_at FROM sync_delete_modes WHERE table_name = ?",
+    )
+    .bind(&table_name)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some(previous) = previous else {
+        return Ok(ApiResponse::error("Table does not sync deletes"));
+    };
+
+    sqlx::query(
+        "UPDATE sync_delete_modes SET mode = ?, updated_at = datetime('now') WHERE table_name = ?",
+    )
+    .bind(&mode)
+    .bind(&table_name)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let updated: SyncDeleteMode = sqlx::query_as(
+        "SELECT table_name, mode, updated_at FROM sync_delete_modes WHERE table_name = ?",
+    )
+    .bind(&table_name)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "update",
+        "sync_delete_mode",
+        &table_name,
+        audit::snapshot(&previous),
+        audit::snapshot(&updated),
+    )
+    .await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(updated))
+}
+
+/// Changes that failed every retry, with their last error and payload
+#[tauri::command]
+pub async fn get_dead_letters(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<SyncLog>>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let entries = dead_letter::list(&mut conn).await?;
+
+    Ok(ApiResponse::success(entries))
+}
+
+/// Queue a dead-lettered change again, optionally correcting the record's
+/// fields first (`fields` maps column names to new values)
+#[tauri::command]
+pub async fn retry_dead_letter(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    id: String,
+    fields: Option<serde_json::Map<String, serde_json::Value>>,
+) -> Result<ApiResponse<SyncLog>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let before = dead_letter::get(&mut tx, &id).await?;
+    let retried = match dead_letter::retry(&mut tx, &id, fields.as_ref()).await {
+        Ok(retried) => retried,
+        Err(e) => return Ok(ApiResponse::error(&e)),
+    };
+
+    audit::record(
+        &mut tx,
+        &session,
+        "retry",
+        "sync_log",
+        &id,
+        before.as_ref().and_then(audit::snapshot),
+        fields.map(serde_json::Value::Object),
+    )
+    .await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(retried))
+}
+
+/// Give up on a dead-lettered change
+#[tauri::command]
+pub async fn discard_dead_letter(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    id: String,
+) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let discarded = match dead_letter::discard(&mut tx, &id).await {
+        Ok(discarded) => discarded,
+        Err(e) => return Ok(ApiResponse::error(&e)),
+    };
+
+    audit::record(
+        &mut tx,
+        &session,
+        "discard",
+        "sync_log",
+        &id,
+        audit::snapshot(&discarded),
+        None,
+    )
+    .await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(true))
+}
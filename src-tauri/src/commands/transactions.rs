@@ -1,112 +1,373 @@
 use super::{ApiResponse, DbPool};
+use crate::audit;
+use crate::db::sequences;
+use crate::intake;
 use crate::models::{CreateTransactionRequest, Customer, Payment, ProcessPaymentRequest, Transaction};
+use crate::pricing::{self, PriceSide};
+use crate::session::{Role, SessionStore};
+use sqlx::SqliteConnection;
 use tauri::State;
 
 #[tauri::command]
 pub async fn create_transaction(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     request: CreateTransactionRequest,
-    user_id: String,
-    branch_id: String,
 ) -> Result<ApiResponse<Transaction>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Local::now();
-    let today = now.format("%Y%m%d").to_string();
     let invoice_prefix = match request.r#type.as_str() {
         "sale" => "INV",
         "buyback" => "BUY",
         "exchange" => "EXC",
         _ => "TRX",
     };
+    let side = match PriceSide::for_transaction_type(&request.r#type) {
+        Some(side) => side,
+        None => {
+            return Ok(ApiResponse::error(&format!(
+                "Unknown transaction type: {}",
+                request.r#type
+            )))
+        }
+    };
 
-    // Get sequential number for today
-    let pattern = format!("{}-{}-%", invoice_prefix, today);
-    let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM transactions WHERE invoice_no LIKE ?")
-        .bind(&pattern)
-        .fetch_one(&pool.0)
-        .await
-        .map_err(|e| e.to_string())?;
+    // Only exchanges take gold in, and an exchange without it is just a sale
+    let is_exchange = request.r#type == "exchange";
+    if is_exchange && request.trade_ins.is_empty() {
+        return Ok(ApiResponse::error("An exchange needs at least one trade-in item"));
+    }
+    if !is_exchange && !request.trade_ins.is_empty() {
+        return Ok(ApiResponse::error("Trade-in items are only allowed on exchanges"));
+    }
+    for trade_in in &request.trade_ins {
+        if let Err(e) = intake::validate_incoming(trade_in) {
+            return Ok(ApiResponse::error(&e));
+        }
+    }
 
-    let sequence = count.0 + 1;
-    let invoice_no = format!("{}-{}-{:03}", invoice_prefix, today, sequence);
+    // A buyback takes in customer gold by weight rather than existing stock
+    let is_buyback = request.r#type == "buyback";
+    if is_buyback && (request.buyback_items.is_empty() || !request.items.is_empty()) {
+        return Ok(ApiResponse::error(
+            "A buyback needs at least one buyback item and no stock items",
+        ));
+    }
+    if !is_buyback && !request.buyback_items.is_empty() {
+        return Ok(ApiResponse::error("Buyback items are only allowed on buybacks"));
+    }
+    for item in &request.buyback_items {
+        if let Err(e) = intake::validate_incoming(&item.gold)
+            .and_then(|_| intake::status_for_condition(&item.condition).map(|_| ()))
+        {
+            return Ok(ApiResponse::error(&e));
+        }
+    }
+
+    // Everything below runs in a single SQL transaction; any early return
+    // drops `tx` and rolls the whole checkout back.
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    // Allocating the invoice number is the first write, so this checkout
+    // holds SQLite's write lock from here until commit.
+    let invoice_no = sequences::next_invoice_no(
+        &mut tx,
+        &session.branch_id,
+        invoice_prefix,
+        now.date_naive(),
+    )
+    .await?;
+
+    let price_date = now.format("%Y-%m-%d").to_string();
+    let pricing_config = pricing::get_config(&mut tx).await?;
 
-    // Calculate totals
-    let subtotal: i32 = request.items.iter().map(|i| i.unit_price).sum();
+    // Reserve and price every item before writing the header
+    let mut priced_items = Vec::with_capacity(request.items.len());
+    for item in &request.items {
+        // Reserve outgoing stock
+        if (request.r#type == "sale" || is_exchange)
+            && !reserve_item(&mut tx, &item.inventory_id).await?
+        {
+            tx.rollback().await.map_err(|e| e.to_string())?;
+            return Ok(ApiResponse::error(&format!(
+                "Item {} is not available",
+                item.inventory_id
+            )));
+        }
+
+        let quote =
+            match pricing::quote_inventory_item(&mut tx, &item.inventory_id, side, &price_date)
+                .await
+            {
+                Ok(quote) => quote,
+                Err(e) => return Ok(ApiResponse::error(&e)),
+            };
+        let price =
+            match pricing::resolve_unit_price(quote.computed_price, item.unit_price, &pricing_config)
+            {
+                Ok(price) => price,
+                Err(e) => {
+                    return Ok(ApiResponse::error(&format!(
+                        "Item {}: {}",
+                        item.inventory_id, e
+                    )))
+                }
+            };
+
+        priced_items.push((quote, price));
+    }
+
+    // Value trade-ins at today's buy price, less the agreed deduction
+    let mut valued_trade_ins = Vec::with_capacity(request.trade_ins.len());
+    for trade_in in &request.trade_ins {
+        let (buy_price, value) =
+            match pricing::quote_incoming_gold(&mut tx, trade_in, &price_date).await {
+                Ok(quote) => quote,
+                Err(e) => return Ok(ApiResponse::error(&e)),
+            };
+        valued_trade_ins.push((trade_in, buy_price, value));
+    }
+
+    // Price buyback items the same way; pieces we sold before are traced
+    // back to their original inventory row and keep its name and category.
+    let mut bought_items = Vec::with_capacity(request.buyback_items.len());
+    for item in &request.buyback_items {
+        let mut gold = item.gold.clone();
+        let mut origin_inventory_id = None;
+        if let Some(barcode) = &item.barcode {
+            let origin = match intake::find_origin(&mut tx, barcode, &gold).await {
+                Ok(origin) => origin,
+                Err(e) => return Ok(ApiResponse::error(&e)),
+            };
+            gold.name = gold.name.or(Some(origin.name));
+            gold.category_id = gold.category_id.or(origin.category_id);
+            origin_inventory_id = Some(origin.inventory_id);
+        }
+
+        let (buy_price, value) =
+            match pricing::quote_incoming_gold(&mut tx, &gold, &price_date).await {
+                Ok(quote) => quote,
+                Err(e) => return Ok(ApiResponse::error(&e)),
+            };
+        let price = match pricing::resolve_unit_price(value, item.unit_price, &pricing_config) {
+            Ok(price) => price,
+            Err(e) => return Ok(ApiResponse::error(&e)),
+        };
+
+        bought_items.push((item, gold, origin_inventory_id, buy_price, value, price));
+    }
+
+    // Calculate totals. On an exchange the trade-in can be worth more than
+    // the new items, leaving a negative total the shop refunds. On a buyback
+    // the total is what the shop pays the customer.
+    let subtotal: i32 = priced_items
+        .iter()
+        .map(|(_, price)| price.unit_price)
+        .chain(bought_items.iter().map(|(.., price)| price.unit_price))
+        .sum();
+    let trade_in_total: i32 = valued_trade_ins.iter().map(|(_, _, value)| value).sum();
     let discount = request.discount.unwrap_or(0);
-    let total_amount = subtotal - discount;
+    let total_amount = subtotal - trade_in_total - discount;
 
-    // Start transaction
     sqlx::query(
         r#"
-        INSERT INTO transactions (id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount, total_amount, notes, status)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'pending')
+        INSERT INTO transactions (id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
+                                  trade_in_total, total_amount, notes, status)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'pending')
         "#,
     )
     .bind(&id)
-    .bind(&branch_id)
-    .bind(&user_id)
+    .bind(&session.branch_id)
+    .bind(&session.user_id)
     .bind(&request.customer_id)
     .bind(&invoice_no)
     .bind(&request.r#type)
     .bind(subtotal)
     .bind(discount)
+    .bind(trade_in_total)
     .bind(total_amount)
     .bind(&request.notes)
-    .execute(&pool.0)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
     // Add transaction items
-    for item in &request.items {
+    for (quote, price) in &priced_items {
         let item_id = uuid::Uuid::new_v4().to_string();
         sqlx::query(
             r#"
-            INSERT INTO transaction_items (id, transaction_id, inventory_id, quantity, unit_price, subtotal)
-            VALUES (?, ?, ?, 1, ?, ?)
+            INSERT INTO transaction_items (id, transaction_id, inventory_id, quantity, unit_price, subtotal,
+                                           gold_price_ref, computed_price, price_status)
+            VALUES (?, ?, ?, 1, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&item_id)
         .bind(&id)
-        .bind(&item.inventory_id)
-        .bind(item.unit_price)
-        .bind(item.unit_price)
-        .execute(&pool.0)
+        .bind(&quote.inventory_id)
+        .bind(price.unit_price)
+        .bind(price.unit_price)
+        .bind(quote.gold_price_ref)
+        .bind(quote.computed_price)
+        .bind(price.price_status)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    }
 
-        // Update inventory status for sales
-        if request.r#type == "sale" {
-            sqlx::query("UPDATE inventory SET status = 'reserved' WHERE id = ?")
-                .bind(&item.inventory_id)
-                .execute(&pool.0)
-                .await
-                .map_err(|e| e.to_string())?;
-        }
+    // Bought-back gold becomes stock, back on sale or waiting to be reprocessed
+    for (item, gold, origin_inventory_id, buy_price, value, price) in &bought_items {
+        let status = intake::status_for_condition(&item.condition)?;
+        let inventory_id = intake::receive_gold(
+            &mut tx,
+            &session.branch_id,
+            gold,
+            price.unit_price,
+            status,
+            "BB",
+        )
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO transaction_items (id, transaction_id, inventory_id, quantity, unit_price, subtotal,
+                                           gold_price_ref, computed_price, price_status, direction,
+                                           item_condition, origin_inventory_id)
+            VALUES (?, ?, ?, 1, ?, ?, ?, ?, ?, 'in', ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id)
+        .bind(&inventory_id)
+        .bind(price.unit_price)
+        .bind(price.unit_price)
+        .bind(buy_price)
+        .bind(value)
+        .bind(price.price_status)
+        .bind(&item.condition)
+        .bind(origin_inventory_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    // Traded-in gold becomes stock waiting to be reprocessed
+    for (trade_in, buy_price, value) in &valued_trade_ins {
+        let inventory_id = intake::receive_gold(
+            &mut tx,
+            &session.branch_id,
+            trade_in,
+            *value,
+            "reprocess",
+            "TI",
+        )
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO transaction_items (id, transaction_id, inventory_id, quantity, unit_price, subtotal,
+                                           gold_price_ref, computed_price, price_status, direction)
+            VALUES (?, ?, ?, 1, ?, ?, ?, ?, 'computed', 'in')
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id)
+        .bind(&inventory_id)
+        .bind(value)
+        .bind(value)
+        .bind(buy_price)
+        .bind(value)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
     }
 
     let transaction: Transaction = sqlx::query_as::<_, Transaction>(
         r#"
         SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
-               total_amount, notes, status, created_at
+               trade_in_total, total_amount, notes, status, created_at
         FROM transactions WHERE id = ?
         "#,
     )
     .bind(&id)
-    .fetch_one(&pool.0)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(ApiResponse::success(transaction))
 }
 
+/// Reserve an item for checkout. The status guard makes the reservation
+/// fail if another checkout got to the item first, and a deleted item is
+/// never on sale.
+async fn reserve_item(conn: &mut SqliteConnection, inventory_id: &str) -> Result<bool, String> {
+    let reserved = sqlx::query(
+        "UPDATE inventory SET status = 'reserved' WHERE id = ? AND status = 'available' AND deleted_at IS NULL",
+    )
+    .bind(inventory_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(reserved.rows_affected() == 1)
+}
+
+/// A payment has to go the way the money is owed. When an exchange's
+/// trade-in is worth more than the new items the total is negative, and the
+/// shop pays the difference back as one refund, recorded as a negative
+/// payment of the whole total.
+fn check_payment_amount(total_amount: i32, amount: i32) -> Result<(), String> {
+    match total_amount.signum() {
+        1 if amount <= 0 => Err("Payment amount must be positive".to_string()),
+        -1 if amount != total_amount => Err(format!(
+            "The customer is owed a refund of {}; record it as a payment of {}",
+            -total_amount, total_amount
+        )),
+        0 if amount != 0 => Err("Nothing is owed on this transaction".to_string()),
+        _ => Ok(()),
+    }
+}
+
 #[tauri::command]
 pub async fn process_payment(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     request: ProcessPaymentRequest,
 ) -> Result<ApiResponse<Payment>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
 
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
+    let transaction: Transaction = sqlx::query_as::<_, Transaction>(
+        r#"
+        SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
+               trade_in_total, total_amount, notes, status, created_at
+        FROM transactions WHERE id = ?
+        "#,
+    )
+    .bind(&request.transaction_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if transaction.status != "pending" {
+        return Ok(ApiResponse::error(&format!(
+            "Cannot add payment to a {} transaction",
+            transaction.status
+        )));
+    }
+    if let Err(e) = check_payment_amount(transaction.total_amount, request.amount) {
+        return Ok(ApiResponse::error(&e));
+    }
+
     sqlx::query(
         r#"
         INSERT INTO payments (id, transaction_id, method, amount, reference_no, bank_name, status, paid_at)
@@ -120,28 +381,17 @@ pub async fn process_payment(
     .bind(&request.reference_no)
     .bind(&request.bank_name)
     .bind(&now)
-    .execute(&pool.0)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    // Check if transaction is fully paid
-    let transaction: Transaction = sqlx::query_as::<_, Transaction>(
-        r#"
-        SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
-               total_amount, notes, status, created_at
-        FROM transactions WHERE id = ?
-        "#,
-    )
-    .bind(&request.transaction_id)
-    .fetch_one(&pool.0)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
+    // Check if transaction is fully paid. A refund is only taken in full,
+    // so a negative total is settled by its one payment.
     let total_paid: (Option<i64>,) = sqlx::query_as(
         "SELECT SUM(amount) FROM payments WHERE transaction_id = ? AND status = 'success'",
     )
     .bind(&request.transaction_id)
-    .fetch_one(&pool.0)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
@@ -149,23 +399,43 @@ pub async fn process_payment(
         // Mark transaction as completed
         sqlx::query("UPDATE transactions SET status = 'completed' WHERE id = ?")
             .bind(&request.transaction_id)
-            .execute(&pool.0)
+            .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
 
-        // Mark inventory items as sold (for sales)
-        if transaction.r#type == "sale" {
-            sqlx::query(
+        // Mark outgoing items as sold (sales and exchanges). Every item must
+        // still be held by this checkout, otherwise the whole payment is rolled back.
+        if transaction.r#type == "sale" || transaction.r#type == "exchange" {
+            let item_count: (i64,) = sqlx::query_as(
+                "SELECT COUNT(*) FROM transaction_items WHERE transaction_id = ? AND direction = 'out'",
+            )
+            .bind(&request.transaction_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            let sold = sqlx::query(
                 r#"
                 UPDATE inventory SET status = 'sold', sold_at = ?
-                WHERE id IN (SELECT inventory_id FROM transaction_items WHERE transaction_id = ?)
+                WHERE status = 'reserved' AND deleted_at IS NULL
+                  AND id IN (
+                      SELECT inventory_id FROM transaction_items
+                      WHERE transaction_id = ? AND direction = 'out'
+                  )
                 "#,
             )
             .bind(&now)
             .bind(&request.transaction_id)
-            .execute(&pool.0)
+            .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
+
+            if sold.rows_affected() as i64 != item_count.0 {
+                tx.rollback().await.map_err(|e| e.to_string())?;
+                return Ok(ApiResponse::error(
+                    "Some items in this transaction are no longer reserved",
+                ));
+            }
         }
 
         // Update customer transaction count
@@ -174,7 +444,7 @@ pub async fn process_payment(
                 "UPDATE customers SET total_transactions = total_transactions + 1 WHERE id = ?",
             )
             .bind(customer_id)
-            .execute(&pool.0)
+            .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
         }
@@ -187,29 +457,37 @@ pub async fn process_payment(
         "#,
     )
     .bind(&id)
-    .fetch_one(&pool.0)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(ApiResponse::success(payment))
 }
 
 #[tauri::command]
 pub async fn void_transaction(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     transaction_id: String,
     reason: String,
 ) -> Result<ApiResponse<bool>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+
     // Get transaction
     let transaction: Transaction = sqlx::query_as::<_, Transaction>(
         r#"
         SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
-               total_amount, notes, status, created_at
+               trade_in_total, total_amount, notes, status, created_at
         FROM transactions WHERE id = ?
         "#,
     )
     .bind(&transaction_id)
-    .fetch_one(&pool.0)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
@@ -217,65 +495,123 @@ pub async fn void_transaction(
         return Ok(ApiResponse::error("Transaction already voided"));
     }
 
-    // Void transaction
-    let notes = format!("VOID: {}", reason);
-    sqlx::query("UPDATE transactions SET status = 'void', notes = ? WHERE id = ?")
-        .bind(&notes)
+    // Void transaction, keeping any existing notes
+    let void_note = format!("VOID: {}", reason);
+    sqlx::query(
+        r#"
+        UPDATE transactions
+        SET status = 'void',
+            notes = CASE WHEN IFNULL(notes, '') = '' THEN ?1 ELSE notes || char(10) || ?1 END
+        WHERE id = ?2
+        "#,
+    )
+    .bind(&void_note)
+    .bind(&transaction_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    audit::record(
+        &mut tx,
+        &session,
+        "void",
+        "transaction",
+        &transaction_id,
+        audit::snapshot(&transaction),
+        Some(serde_json::json!({ "status": "void", "reason": reason })),
+    )
+    .await?;
+
+    // Restore inventory if it was a sale or exchange
+    if transaction.r#type == "sale" || transaction.r#type == "exchange" {
+        sqlx::query(
+            r#"
+            UPDATE inventory SET status = 'available', sold_at = NULL
+            WHERE deleted_at IS NULL
+              AND id IN (
+                SELECT inventory_id FROM transaction_items
+                WHERE transaction_id = ? AND direction = 'out'
+            )
+            "#,
+        )
         .bind(&transaction_id)
-        .execute(&pool.0)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    }
 
-    // Restore inventory if it was a sale
-    if transaction.r#type == "sale" {
+    // Traded-in or bought-back gold goes back to the customer, unless it has
+    // already been sold on
+    if transaction.r#type == "exchange" || transaction.r#type == "buyback" {
         sqlx::query(
             r#"
-            UPDATE inventory SET status = 'available', sold_at = NULL
-            WHERE id IN (SELECT inventory_id FROM transaction_items WHERE transaction_id = ?)
+            UPDATE inventory SET status = 'returned'
+            WHERE status IN ('available', 'reprocess') AND deleted_at IS NULL
+              AND id IN (
+                  SELECT inventory_id FROM transaction_items
+                  WHERE transaction_id = ? AND direction = 'in'
+              )
             "#,
         )
         .bind(&transaction_id)
-        .execute(&pool.0)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
     }
 
+    // A completed sale had bumped the customer's counter; undo it
+    if transaction.status == "completed" {
+        if let Some(customer_id) = &transaction.customer_id {
+            sqlx::query(
+                "UPDATE customers SET total_transactions = MAX(total_transactions - 1, 0) WHERE id = ?",
+            )
+            .bind(customer_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
     Ok(ApiResponse::success(true))
 }
 
 #[tauri::command]
 pub async fn get_transactions(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     date_from: Option<String>,
     date_to: Option<String>,
     transaction_type: Option<String>,
 ) -> Result<ApiResponse<Vec<Transaction>>, String> {
-    let mut query = String::from(
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    // Staff see their own branch's transactions; an owner sees every branch
+    let transactions: Vec<Transaction> = sqlx::query_as::<_, Transaction>(
         r#"
         SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount,
-               total_amount, notes, status, created_at
-        FROM transactions WHERE 1=1
+               trade_in_total, total_amount, notes, status, created_at
+        FROM transactions
+        WHERE (? OR branch_id = ?)
+          AND (? IS NULL OR DATE(created_at) >= ?)
+          AND (? IS NULL OR DATE(created_at) <= ?)
+          AND (? IS NULL OR type = ?)
+        ORDER BY created_at DESC LIMIT 100
         "#,
-    );
-
-    if let Some(from) = &date_from {
-        query.push_str(&format!(" AND DATE(created_at) >= '{}'", from));
-    }
-
-    if let Some(to) = &date_to {
-        query.push_str(&format!(" AND DATE(created_at) <= '{}'", to));
-    }
-
-    if let Some(t) = &transaction_type {
-        query.push_str(&format!(" AND type = '{}'", t));
-    }
-
-    query.push_str(" ORDER BY created_at DESC LIMIT 100");
-
-    let transactions: Vec<Transaction> = sqlx::query_as::<_, Transaction>(&query)
-        .fetch_all(&pool.0)
-        .await
-        .map_err(|e| e.to_string())?;
+    )
+    .bind(session.is_owner())
+    .bind(&session.branch_id)
+    .bind(&date_from)
+    .bind(&date_from)
+    .bind(&date_to)
+    .bind(&date_to)
+    .bind(&transaction_type)
+    .bind(&transaction_type)
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
 
     Ok(ApiResponse::success(transactions))
 }
@@ -284,9 +620,13 @@ pub async fn get_transactions(
 #[tauri::command]
 pub async fn get_customers(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
 ) -> Result<ApiResponse<Vec<Customer>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let customers: Vec<Customer> = sqlx::query_as::<_, Customer>(
-        "SELECT id, name, phone, nik, address, notes, total_transactions, created_at FROM customers ORDER BY name",
+        "SELECT id, name, phone, nik, address, notes, total_transactions, created_at FROM customers WHERE deleted_at IS NULL ORDER BY name",
     )
     .fetch_all(&pool.0)
     .await
@@ -296,14 +636,19 @@ pub async fn get_customers(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_customer(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     name: String,
     phone: Option<String>,
     nik: Option<String>,
     address: Option<String>,
     notes: Option<String>,
 ) -> Result<ApiResponse<Customer>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let id = uuid::Uuid::new_v4().to_string();
 
     sqlx::query(
@@ -333,15 +678,19 @@ pub async fn create_customer(
 #[tauri::command]
 pub async fn search_customer(
     pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
     query: String,
 ) -> Result<ApiResponse<Vec<Customer>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
     let search_pattern = format!("%{}%", query);
 
     let customers: Vec<Customer> = sqlx::query_as::<_, Customer>(
         r#"
         SELECT id, name, phone, nik, address, notes, total_transactions, created_at
         FROM customers
-        WHERE name LIKE ? OR phone LIKE ?
+        WHERE (name LIKE ? OR phone LIKE ?) AND deleted_at IS NULL
         ORDER BY name
         LIMIT 10
         "#,
@@ -354,3 +703,62 @@ pub async fn search_customer(
 
     Ok(ApiResponse::success(customers))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::IncomingGoldItem;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_deleted_item_cannot_be_reserved() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+
+        let gold = IncomingGoldItem {
+            gold_type: "Lokal".to_string(),
+            gold_purity: 750,
+            weight_gram: 3.0,
+            deduction_percent: 0.0,
+            category_id: None,
+            name: None,
+            notes: None,
+        };
+        let kept = intake::receive_gold(&mut conn, "default", &gold, 1_000, "available", "EM")
+            .await
+            .unwrap();
+        let deleted = intake::receive_gold(&mut conn, "default", &gold, 1_000, "available", "EM")
+            .await
+            .unwrap();
+        sqlx::query("UPDATE inventory SET deleted_at = datetime('now') WHERE id = ?")
+            .bind(&deleted)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        assert!(reserve_item(&mut conn, &kept).await.unwrap());
+        assert!(!reserve_item(&mut conn, &kept).await.unwrap());
+        assert!(!reserve_item(&mut conn, &deleted).await.unwrap());
+    }
+
+    #[test]
+    fn test_payment_direction_follows_total() {
+        assert!(check_payment_amount(500_000, 200_000).is_ok());
+        assert!(check_payment_amount(500_000, 0).is_err());
+        assert!(check_payment_amount(500_000, -500_000).is_err());
+
+        // Trade-in worth more than the new items: only the full refund
+        assert!(check_payment_amount(-150_000, -150_000).is_ok());
+        assert!(check_payment_amount(-150_000, 0).is_err());
+        assert!(check_payment_amount(-150_000, 150_000).is_err());
+        assert!(check_payment_amount(-150_000, -100_000).is_err());
+
+        assert!(check_payment_amount(0, 0).is_ok());
+        assert!(check_payment_amount(0, 10_000).is_err());
+    }
+}
@@ -0,0 +1,620 @@
+use super::{ApiResponse, DbPool};
+use crate::db::sequences;
+use crate::models::{
+    Branch, CreateStockTransferRequest, StockTransfer, TransferManifest, TransferManifestItem,
+};
+use crate::session::{Role, Session, SessionStore};
+use sqlx::SqliteConnection;
+use tauri::State;
+
+#[tauri::command]
+pub async fn get_branches(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+) -> Result<ApiResponse<Vec<Branch>>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
+    let branches: Vec<Branch> = sqlx::query_as::<_, Branch>(
+        r#"
+        SELECT id, name, address, phone, is_active, created_at, updated_at
+        FROM branches
+        WHERE is_active = 1
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(branches))
+}
+
+/// Create a draft transfer of the given barcodes from the caller's branch
+#[tauri::command]
+pub async fn create_stock_transfer(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    request: CreateStockTransferRequest,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let response = create(&mut tx, &session, &request).await?;
+    if response.success {
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(response)
+}
+
+/// Send a draft transfer on its way; its items become unavailable
+#[tauri::command]
+pub async fn dispatch_stock_transfer(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    transfer_id: String,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    let session = sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let response = dispatch(&mut tx, &session, &transfer_id).await?;
+    if response.success {
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(response)
+}
+
+/// Book the items of an in-transit transfer into the receiving branch
+#[tauri::command]
+pub async fn receive_stock_transfer(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    transfer_id: String,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let response = receive(&mut tx, &session, &transfer_id).await?;
+    if response.success {
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(response)
+}
+
+/// Cancel a draft or in-transit transfer; items in transit go back on sale
+#[tauri::command]
+pub async fn cancel_stock_transfer(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    transfer_id: String,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    sessions.authorize(&token, Role::Owner).await?;
+
+    let mut tx = pool.0.begin().await.map_err(|e| e.to_string())?;
+    let response = cancel(&mut tx, &transfer_id).await?;
+    if response.success {
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(response)
+}
+
+/// Transfers sent from or to the caller's branch
+#[tauri::command]
+pub async fn get_stock_transfers(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    status: Option<String>,
+) -> Result<ApiResponse<Vec<StockTransfer>>, String> {
+    let session = sessions.authorize(&token, Role::Kasir).await?;
+
+    let transfers: Vec<StockTransfer> = sqlx::query_as(
+        r#"
+        SELECT id, transfer_no, from_branch_id, to_branch_id, status, notes, created_by, created_at,
+               dispatched_by, dispatched_at, received_by, received_at
+        FROM stock_transfers
+        WHERE (from_branch_id = ? OR to_branch_id = ?) AND (? IS NULL OR status = ?)
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#,
+    )
+    .bind(&session.branch_id)
+    .bind(&session.branch_id)
+    .bind(&status)
+    .bind(&status)
+    .fetch_all(&pool.0)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(transfers))
+}
+
+#[tauri::command]
+pub async fn get_transfer_manifest(
+    pool: State<'_, DbPool>,
+    sessions: State<'_, SessionStore>,
+    token: String,
+    transfer_id: String,
+) -> Result<ApiResponse<TransferManifest>, String> {
+    sessions.authorize(&token, Role::Kasir).await?;
+
+    let mut conn = pool.0.acquire().await.map_err(|e| e.to_string())?;
+    let transfer = get_transfer(&mut conn, &transfer_id).await?;
+
+    let branch_name = |id: String| {
+        sqlx::query_as::<_, (String,)>("SELECT name FROM branches WHERE id = ?").bind(id)
+    };
+    let (from_branch_name,) = branch_name(transfer.from_branch_id.clone())
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let (to_branch_name,) = branch_name(transfer.to_branch_id.clone())
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let items: Vec<TransferManifestItem> = sqlx::query_as(
+        r#"
+        SELECT i.id AS inventory_id, i.barcode, p.name AS product_name, p.gold_type,
+               p.gold_purity, p.weight_gram, i.purchase_price
+        FROM stock_transfer_items t
+        JOIN inventory i ON t.inventory_id = i.id
+        JOIN products p ON i.product_id = p.id
+        WHERE t.transfer_id = ?
+        ORDER BY i.barcode
+        "#,
+    )
+    .bind(&transfer_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let total_weight = items.iter().map(|i| i.weight_gram).sum();
+    let total_value = items.iter().map(|i| i.purchase_price as i64).sum();
+
+    Ok(ApiResponse::success(TransferManifest {
+        transfer,
+        from_branch_name,
+        to_branch_name,
+        items,
+        total_weight,
+        total_value,
+    }))
+}
+
+// Each step runs in the command's transaction, which is only committed
+// when the step succeeds
+
+async fn create(
+    conn: &mut SqliteConnection,
+    session: &Session,
+    request: &CreateStockTransferRequest,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    if request.barcodes.is_empty() {
+        return Ok(ApiResponse::error("A transfer needs at least one item"));
+    }
+    if request.to_branch_id == session.branch_id {
+        return Ok(ApiResponse::error(
+            "Cannot transfer stock to the same branch",
+        ));
+    }
+
+    let destination: Option<(i64,)> =
+        sqlx::query_as("SELECT 1 FROM branches WHERE id = ? AND is_active = 1")
+            .bind(&request.to_branch_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    if destination.is_none() {
+        return Ok(ApiResponse::error("Destination branch not found or inactive"));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let transfer_no = sequences::next_invoice_no(
+        conn,
+        &session.branch_id,
+        "TRF",
+        chrono::Local::now().date_naive(),
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO stock_transfers (id, transfer_no, from_branch_id, to_branch_id, notes, created_by)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&transfer_no)
+    .bind(&session.branch_id)
+    .bind(&request.to_branch_id)
+    .bind(&request.notes)
+    .bind(&session.user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for barcode in &request.barcodes {
+        let item: Option<(String, String, String)> =
+            sqlx::query_as("SELECT id, branch_id, status FROM inventory WHERE barcode = ? AND deleted_at IS NULL")
+                .bind(barcode)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+
+        let inventory_id = match item {
+            Some((id, branch_id, status))
+                if branch_id == session.branch_id && status == "available" =>
+            {
+                id
+            }
+            Some(_) => {
+                return Ok(ApiResponse::error(&format!(
+                    "Item {} is not available in this branch",
+                    barcode
+                )))
+            }
+            None => return Ok(ApiResponse::error(&format!("Item {} not found", barcode))),
+        };
+
+        sqlx::query(
+            "INSERT INTO stock_transfer_items (id, transfer_id, inventory_id) VALUES (?, ?, ?) ON CONFLICT DO NOTHING",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id)
+        .bind(&inventory_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    let transfer = get_transfer(conn, &id).await?;
+
+    Ok(ApiResponse::success(transfer))
+}
+
+async fn dispatch(
+    conn: &mut SqliteConnection,
+    session: &Session,
+    transfer_id: &str,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    let dispatched = sqlx::query(
+        r#"
+        UPDATE stock_transfers SET status = 'in_transit', dispatched_by = ?, dispatched_at = datetime('now')
+        WHERE id = ? AND status = 'draft'
+        "#,
+    )
+    .bind(&session.user_id)
+    .bind(transfer_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if dispatched.rows_affected() == 0 {
+        return Ok(ApiResponse::error(
+            "Only a draft transfer can be dispatched",
+        ));
+    }
+
+    let transfer = get_transfer(conn, transfer_id).await?;
+    let item_ids = get_item_ids(conn, transfer_id).await?;
+
+    // Every item must still be on the shelf of the sending branch
+    let moved = sqlx::query(
+        r#"
+        UPDATE inventory SET status = 'in_transit'
+        WHERE status = 'available' AND branch_id = ? AND deleted_at IS NULL
+          AND id IN (SELECT inventory_id FROM stock_transfer_items WHERE transfer_id = ?)
+        "#,
+    )
+    .bind(&transfer.from_branch_id)
+    .bind(transfer_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if moved.rows_affected() as usize != item_ids.len() {
+        return Ok(ApiResponse::error(
+            "Some items in this transfer are no longer available",
+        ));
+    }
+
+    Ok(ApiResponse::success(transfer))
+}
+
+async fn receive(
+    conn: &mut SqliteConnection,
+    session: &Session,
+    transfer_id: &str,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    // Only the receiving branch (or an owner) can confirm arrival
+    let received = sqlx::query(
+        r#"
+        UPDATE stock_transfers SET status = 'received', received_by = ?, received_at = datetime('now')
+        WHERE id = ? AND status = 'in_transit' AND (to_branch_id = ? OR ?)
+        "#,
+    )
+    .bind(&session.user_id)
+    .bind(transfer_id)
+    .bind(&session.branch_id)
+    .bind(session.is_owner())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if received.rows_affected() == 0 {
+        return Ok(ApiResponse::error(
+            "Only an in-transit transfer to your branch can be received",
+        ));
+    }
+
+    let transfer = get_transfer(conn, transfer_id).await?;
+
+    // The shelf location belonged to the old branch, so it is cleared
+    sqlx::query(
+        r#"
+        UPDATE inventory SET branch_id = ?, status = 'available', location = NULL
+        WHERE status = 'in_transit' AND deleted_at IS NULL
+          AND id IN (SELECT inventory_id FROM stock_transfer_items WHERE transfer_id = ?)
+        "#,
+    )
+    .bind(&transfer.to_branch_id)
+    .bind(transfer_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ApiResponse::success(transfer))
+}
+
+async fn cancel(
+    conn: &mut SqliteConnection,
+    transfer_id: &str,
+) -> Result<ApiResponse<StockTransfer>, String> {
+    let before: Option<(String,)> =
+        sqlx::query_as("SELECT status FROM stock_transfers WHERE id = ?")
+            .bind(transfer_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let was_in_transit = match before {
+        Some((status,)) if status == "draft" => false,
+        Some((status,)) if status == "in_transit" => true,
+        Some(_) => {
+            return Ok(ApiResponse::error(
+                "Only a draft or in-transit transfer can be cancelled",
+            ))
+        }
+        None => return Ok(ApiResponse::error("Transfer not found")),
+    };
+
+    sqlx::query("UPDATE stock_transfers SET status = 'cancelled' WHERE id = ?")
+        .bind(transfer_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if was_in_transit {
+        sqlx::query(
+            r#"
+            UPDATE inventory SET status = 'available'
+            WHERE status = 'in_transit' AND deleted_at IS NULL
+              AND id IN (SELECT inventory_id FROM stock_transfer_items WHERE transfer_id = ?)
+            "#,
+        )
+        .bind(transfer_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    let transfer = get_transfer(conn, transfer_id).await?;
+
+    Ok(ApiResponse::success(transfer))
+}
+
+async fn get_transfer(
+    conn: &mut SqliteConnection,
+    transfer_id: &str,
+) -> Result<StockTransfer, String> {
+    sqlx::query_as(
+        r#"
+        SELECT id, transfer_no, from_branch_id, to_branch_id, status, notes, created_by, created_at,
+               dispatched_by, dispatched_at, received_by, received_at
+        FROM stock_transfers WHERE id = ?
+        "#,
+    )
+    .bind(transfer_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Transfer {} not found", transfer_id))
+}
+
+async fn get_item_ids(
+    conn: &mut SqliteConnection,
+    transfer_id: &str,
+) -> Result<Vec<String>, String> {
+    let rows: Vec<(String,)> =
+        sqlx::query_as("SELECT inventory_id FROM stock_transfer_items WHERE transfer_id = ?")
+            .bind(transfer_id)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::IncomingGoldItem;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Connection;
+
+    fn session(user_id: &str, branch_id: &str, role: Role) -> Session {
+        Session {
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            full_name: user_id.to_string(),
+            branch_id: branch_id.to_string(),
+            role,
+        }
+    }
+
+    async fn stock(conn: &mut SqliteConnection, location: &str) -> (String, String) {
+        let gold = IncomingGoldItem {
+            gold_type: "Lokal".to_string(),
+            gold_purity: 750,
+            weight_gram: 3.0,
+            deduction_percent: 0.0,
+            category_id: None,
+            name: None,
+            notes: None,
+        };
+        let id = crate::intake::receive_gold(conn, "default", &gold, 1_000, "available", "EM")
+            .await
+            .unwrap();
+        sqlx::query_as("UPDATE inventory SET location = ? WHERE id = ? RETURNING id, barcode")
+            .bind(location)
+            .bind(&id)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap()
+    }
+
+    async fn item_state(conn: &mut SqliteConnection, id: &str) -> (String, String, Option<String>) {
+        sqlx::query_as("SELECT branch_id, status, location FROM inventory WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transfer_moves_stock_between_branches() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        for statement in [
+            "INSERT INTO branches (id, name, code) VALUES ('b2', 'Cabang Dua', 'CB2'), ('b3', 'Cabang Tiga', 'CB3')",
+            "UPDATE branches SET is_active = 0 WHERE id = 'b3'",
+            "INSERT INTO users (id, branch_id, username, password_hash, full_name, role) VALUES ('k1', 'default', 'k1', '!', 'Kasir Satu', 'kasir'), ('k2', 'b2', 'k2', '!', 'Kasir Dua', 'kasir')",
+        ] {
+            sqlx::query(statement).execute(&mut *conn).await.unwrap();
+        }
+        let owner = session("admin", "default", Role::Owner);
+        let (first, first_barcode) = stock(&mut conn, "Etalase A").await;
+        let (second, second_barcode) = stock(&mut conn, "Etalase B").await;
+        let request = |to_branch_id: &str, barcodes: &[&String]| CreateStockTransferRequest {
+            to_branch_id: to_branch_id.to_string(),
+            barcodes: barcodes.iter().map(|b| b.to_string()).collect(),
+            notes: None,
+        };
+
+        // The destination has to be a branch that is open
+        for to_branch_id in ["b9", "b3"] {
+            let response = create(&mut conn, &owner, &request(to_branch_id, &[&first_barcode]))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.error.as_deref(),
+                Some("Destination branch not found or inactive")
+            );
+        }
+
+        // An item sold after the transfer was drafted stops the dispatch
+        let transfer = create(
+            &mut conn,
+            &owner,
+            &request("b2", &[&first_barcode, &second_barcode]),
+        )
+        .await
+        .unwrap()
+        .data
+        .unwrap();
+        sqlx::query("UPDATE inventory SET status = 'sold' WHERE id = ?")
+            .bind(&second)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        // Rolled back, as the command does when the step fails
+        let mut tx = conn.begin().await.unwrap();
+        assert!(
+            !dispatch(&mut tx, &owner, &transfer.id)
+                .await
+                .unwrap()
+                .success
+        );
+        drop(tx);
+        assert_eq!(item_state(&mut conn, &first).await.1, "available");
+
+        // Cancelled in transit, the item is back on sale where it was
+        let transfer = create(&mut conn, &owner, &request("b2", &[&first_barcode]))
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+        assert!(
+            dispatch(&mut conn, &owner, &transfer.id)
+                .await
+                .unwrap()
+                .success
+        );
+        assert_eq!(item_state(&mut conn, &first).await.1, "in_transit");
+        assert!(cancel(&mut conn, &transfer.id).await.unwrap().success);
+        assert_eq!(
+            item_state(&mut conn, &first).await,
+            (
+                "default".to_string(),
+                "available".to_string(),
+                Some("Etalase A".to_string())
+            )
+        );
+
+        // Only the receiving branch can book it in
+        let transfer = create(&mut conn, &owner, &request("b2", &[&first_barcode]))
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+        assert!(
+            dispatch(&mut conn, &owner, &transfer.id)
+                .await
+                .unwrap()
+                .success
+        );
+        let sender = session("k1", "default", Role::Kasir);
+        assert!(
+            !receive(&mut conn, &sender, &transfer.id)
+                .await
+                .unwrap()
+                .success
+        );
+        let receiver = session("k2", "b2", Role::Kasir);
+        let received = receive(&mut conn, &receiver, &transfer.id)
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+        assert_eq!(received.status, "received");
+        assert_eq!(
+            item_state(&mut conn, &first).await,
+            ("b2".to_string(), "available".to_string(), None)
+        );
+        assert!(!cancel(&mut conn, &transfer.id).await.unwrap().success);
+    }
+}
@@ -0,0 +1,482 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, Row, SqliteConnection, SqlitePool};
+
+/// A numbered schema change, applied at most once per database.
+///
+/// The runner records each applied migration in `schema_migrations` with a
+/// checksum of its steps. Editing a migration that has already shipped is
+/// therefore an error on the next start; add a new migration instead.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+pub enum Step {
+    /// A single SQL statement
+    Sql(&'static str),
+    /// Add a column unless it is already there. Only for bringing databases
+    /// created before versioned migrations up to the baseline schema.
+    /// SQLite cannot add a UNIQUE column, so a trailing `UNIQUE` becomes a
+    /// unique index instead.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+    /// Recreate `table` from `create_sql`, which must create `{table}_rebuild`.
+    ///
+    /// SQLite cannot alter a CHECK constraint (or most other column
+    /// properties) in place, so the rows are copied into a fresh table that
+    /// then takes over the old name, and the table's indexes and triggers are
+    /// recreated on it. Columns missing from the new table are dropped.
+    RebuildTable {
+        table: &'static str,
+        create_sql: &'static str,
+    },
+    /// Create insert, update and delete triggers on `table` that queue the
+    /// change in `sync_log`, with the row's `columns` as a JSON payload.
+    ///
+    /// Updates that only touch `salesforce_id` (the push writing back the id
+    /// Salesforce assigned) are not captured, and nothing is captured while a
+    /// row exists in `sync_capture_suspended`, which pulls use to mirror
+    /// Salesforce without echoing it back. A column added to `table` later
+    /// needs the triggers recreated by a new migration to be captured.
+    CaptureChanges {
+        table: &'static str,
+        columns: &'static [&'static str],
+    },
+    /// Create a trigger on `table` that queues a delete in `sync_log` when
+    /// its `deleted_at` is first set, with the row's `columns` as payload,
+    /// so a soft-deleted record is removed from Salesforce like a deleted
+    /// one. `deleted_at` must not be among the `CaptureChanges` columns.
+    CaptureSoftDeletes {
+        table: &'static str,
+        columns: &'static [&'static str],
+    },
+}
+
+impl Step {
+    /// Text the checksum is computed over; whitespace is normalised so
+    /// reformatting a migration does not count as changing it.
+    fn canonical(&self) -> String {
+        let text = match self {
+            Step::Sql(sql) => sql.to_string(),
+            Step::AddColumn {
+                table,
+                column,
+                definition,
+            } => format!("ADD COLUMN {}.{} {}", table, column, definition),
+            Step::RebuildTable { table, create_sql } => {
+                format!("REBUILD {} AS {}", table, create_sql)
+            }
+            Step::CaptureChanges { table, columns } => {
+                format!("CAPTURE {} ({})", table, columns.join(", "))
+            }
+            Step::CaptureSoftDeletes { table, columns } => {
+                format!("CAPTURE SOFT DELETES {} ({})", table, columns.join(", "))
+            }
+        };
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    async fn apply(&self, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+        match self {
+            Step::Sql(sql) => {
+                sqlx::query(sql).execute(&mut *conn).await?;
+            }
+            Step::AddColumn {
+                table,
+                column,
+                definition,
+            } => {
+                if !table_columns(conn, table)
+                    .await?
+                    .iter()
+                    .any(|c| c == column)
+                {
+                    let (definition, unique) = match definition.strip_suffix(" UNIQUE") {
+                        Some(definition) => (definition, true),
+                        None => (*definition, false),
+                    };
+                    sqlx::query(&format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table, column, definition
+                    ))
+                    .execute(&mut *conn)
+                    .await?;
+                    if unique {
+                        sqlx::query(&format!(
+                            "CREATE UNIQUE INDEX idx_{}_{}_unique ON {}({})",
+                            table, column, table, column
+                        ))
+                        .execute(&mut *conn)
+                        .await?;
+                    }
+                }
+            }
+            Step::RebuildTable { table, create_sql } => {
+                rebuild_table(conn, table, create_sql).await?;
+            }
+            Step::CaptureChanges { table, columns } => {
+                for sql in capture_triggers(table, columns) {
+                    sqlx::query(&sql).execute(&mut *conn).await?;
+                }
+            }
+            Step::CaptureSoftDeletes { table, columns } => {
+                sqlx::query(&soft_delete_trigger(table, columns))
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{} {}\n", self.version, self.name));
+        for step in self.steps {
+            hasher.update(step.canonical());
+            hasher.update("\n");
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Apply every migration in `migrations` (ordered by version) that the
+/// database has not seen yet, each in its own transaction.
+pub async fn run(pool: &SqlitePool, migrations: &[Migration]) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let applied: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT version, name, checksum FROM schema_migrations ORDER BY version")
+            .fetch_all(&mut *conn)
+            .await?;
+
+    for (version, name, checksum) in &applied {
+        match migrations.iter().find(|m| m.version == *version) {
+            Some(migration) if migration.checksum() == *checksum => {}
+            Some(_) => {
+                return Err(migration_error(format!(
+                    "Migration {} ({}) was changed after it was applied",
+                    version, name
+                )))
+            }
+            None => {
+                return Err(migration_error(format!(
+                    "Database has migration {} ({}) that this version of the app does not know",
+                    version, name
+                )))
+            }
+        }
+    }
+
+    for migration in migrations {
+        if applied
+            .iter()
+            .any(|(version, _, _)| *version == migration.version)
+        {
+            continue;
+        }
+        log::info!(
+            "Applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        apply(&mut conn, migration).await.map_err(|e| {
+            migration_error(format!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.name, e
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+async fn apply(conn: &mut SqliteConnection, migration: &Migration) -> Result<(), sqlx::Error> {
+    // Table rebuilds need foreign keys off, and SQLite ignores that pragma
+    // inside a transaction, so it is switched for the whole migration
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+
+    let result = async {
+        let mut tx = conn.begin().await?;
+
+        for step in migration.steps {
+            step.apply(&mut tx).await?;
+        }
+
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+    .await;
+
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+
+    result
+}
+
+async fn table_columns(
+    conn: &mut SqliteConnection,
+    table: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(columns.into_iter().map(|(name,)| name).collect())
+}
+
+async fn rebuild_table(
+    conn: &mut SqliteConnection,
+    table: &str,
+    create_sql: &str,
+) -> Result<(), sqlx::Error> {
+    // Indexes and triggers are dropped along with the table; keep their
+    // definitions to recreate them on the new one
+    let dependents: Vec<(String,)> = sqlx::query_as(
+        "SELECT sql FROM sqlite_master WHERE tbl_name = ? AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+    )
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let rebuilt = format!("{}_rebuild", table);
+    sqlx::query(create_sql).execute(&mut *conn).await?;
+
+    let new_columns = table_columns(conn, &rebuilt).await?;
+    let columns = table_columns(conn, table)
+        .await?
+        .into_iter()
+        .filter(|c| new_columns.contains(c))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    sqlx::query(&format!(
+        "INSERT INTO {} ({}) SELECT {} FROM {}",
+        rebuilt, columns, columns, table
+    ))
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(&format!("DROP TABLE {}", table))
+        .execute(&mut *conn)
+        .await?;
+    // Legacy rename skips re-checking other tables' triggers and views, which
+    // may mention `table` and would fail while it does not exist
+    sqlx::query("PRAGMA legacy_alter_table = ON")
+        .execute(&mut *conn)
+        .await?;
+    let renamed = sqlx::query(&format!("ALTER TABLE {} RENAME TO {}", rebuilt, table))
+        .execute(&mut *conn)
+        .await;
+    sqlx::query("PRAGMA legacy_alter_table = OFF")
+        .execute(&mut *conn)
+        .await?;
+    renamed?;
+
+    for (sql,) in dependents {
+        sqlx::query(&sql).execute(&mut *conn).await?;
+    }
+
+    // With foreign keys off nothing stopped the copy from breaking them
+    if let Some(row) = sqlx::query(&format!("PRAGMA foreign_key_check({})", table))
+        .fetch_optional(&mut *conn)
+        .await?
+    {
+        let parent: String = row.try_get("parent")?;
+        return Err(migration_error(format!(
+            "Rebuilding {} broke its reference to {}",
+            table, parent
+        )));
+    }
+
+    Ok(())
+}
+
+/// `columns` of the trigger row `row` as a JSON object
+fn capture_payload(columns: &[&str], row: &str) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|c| format!("'{}', {}.{}", c, row, c))
+        .collect();
+    format!("json_object({})", fields.join(", "))
+}
+
+fn capture_triggers(table: &str, columns: &[&str]) -> Vec<String> {
+    let watched: Vec<&str> = columns
+        .iter()
+        .copied()
+        .filter(|c| *c != "salesforce_id")
+        .collect();
+
+    [
+        ("insert", "INSERT".to_string(), "NEW"),
+        ("update", format!("UPDATE OF {}", watched.join(", ")), "NEW"),
+        ("delete", "DELETE".to_string(), "OLD"),
+    ]
+    .into_iter()
+    .map(|(action, event, row)| {
+        format!(
+            r#"
+            CREATE TRIGGER sync_capture_{table}_{action} AFTER {event} ON {table}
+            WHEN NOT EXISTS (SELECT 1 FROM sync_capture_suspended)
+            BEGIN
+                INSERT INTO sync_log (id, table_name, record_id, action, payload)
+                VALUES (lower(hex(randomblob(16))), '{table}', {row}.id, '{action}', {payload});
+            END
+            "#,
+            payload = capture_payload(columns, row),
+        )
+    })
+    .collect()
+}
+
+fn soft_delete_trigger(table: &str, columns: &[&str]) -> String {
+    format!(
+        r#"
+        CREATE TRIGGER sync_capture_{table}_soft_delete AFTER UPDATE OF deleted_at ON {table}
+        WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL
+         AND NOT EXISTS (SELECT 1 FROM sync_capture_suspended)
+        BEGIN
+            INSERT INTO sync_log (id, table_name, record_id, action, payload)
+            VALUES (lower(hex(randomblob(16))), '{table}', NEW.id, 'delete', {payload});
+        END
+        "#,
+        payload = capture_payload(columns, "NEW"),
+    )
+}
+
+fn migration_error(message: String) -> sqlx::Error {
+    sqlx::Error::Configuration(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    const V1: Migration = Migration {
+        version: 1,
+        name: "items",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE items (id TEXT PRIMARY KEY, status TEXT CHECK (status IN ('a')))",
+            ),
+            Step::Sql("CREATE INDEX idx_items_status ON items(status)"),
+        ],
+    };
+    const V2: Migration = Migration {
+        version: 2,
+        name: "widen_items_status",
+        steps: &[Step::RebuildTable {
+            table: "items",
+            create_sql: "CREATE TABLE items_rebuild (id TEXT PRIMARY KEY, status TEXT CHECK (status IN ('a', 'b')))",
+        }],
+    };
+
+    #[tokio::test]
+    async fn test_migrations_apply_once_and_rebuild_tables() {
+        let pool = memory_pool().await;
+
+        run(&pool, &[V1]).await.unwrap();
+        sqlx::query("INSERT INTO items (id, status) VALUES ('1', 'a')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(
+            sqlx::query("INSERT INTO items (id, status) VALUES ('2', 'b')")
+                .execute(&pool)
+                .await
+                .is_err()
+        );
+
+        // V1 is already recorded, so only V2 runs
+        run(&pool, &[V1, V2]).await.unwrap();
+        run(&pool, &[V1, V2]).await.unwrap();
+        sqlx::query("INSERT INTO items (id, status) VALUES ('2', 'b')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM items")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 2);
+        let index: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM sqlite_master WHERE name = 'idx_items_status'")
+                .fetch_optional(&pool)
+                .await
+                .unwrap();
+        assert!(index.is_some());
+        let versions: Vec<(i64,)> = sqlx::query_as("SELECT version FROM schema_migrations")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(versions, vec![(1,), (2,)]);
+    }
+
+    #[tokio::test]
+    async fn test_edited_or_failing_migrations_are_reported() {
+        let pool = memory_pool().await;
+        run(&pool, &[V1]).await.unwrap();
+
+        let edited = Migration {
+            version: 1,
+            name: "items",
+            steps: &[Step::Sql("CREATE TABLE items (id TEXT PRIMARY KEY)")],
+        };
+        assert!(run(&pool, &[edited]).await.is_err());
+        assert!(run(&pool, &[]).await.is_err());
+
+        // A failing migration leaves nothing behind, not even its first step
+        let broken = Migration {
+            version: 2,
+            name: "broken",
+            steps: &[
+                Step::Sql("CREATE TABLE other (id TEXT PRIMARY KEY)"),
+                Step::Sql("CREATE TABLE items (id TEXT PRIMARY KEY)"),
+            ],
+        };
+        assert!(run(&pool, &[V1, broken]).await.is_err());
+        let other: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM sqlite_master WHERE name = 'other'")
+                .fetch_optional(&pool)
+                .await
+                .unwrap();
+        assert!(other.is_none());
+    }
+}
@@ -2,7 +2,9 @@ use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
 use std::path::PathBuf;
 use tauri::{AppHandle, Manager};
 
+pub mod migrations;
 pub mod schema;
+pub mod sequences;
 
 pub async fn init_database(app_handle: &AppHandle) -> Result<SqlitePool, sqlx::Error> {
     let app_dir = app_handle
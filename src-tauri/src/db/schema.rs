@@ -1,3 +1,4 @@
+use super::migrations::{self, Migration, Step};
 use sqlx::SqlitePool;
 
 pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
@@ -6,293 +7,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await?;
 
-    // Create branches table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS branches (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            code TEXT UNIQUE,
-            address TEXT,
-            phone TEXT,
-            is_active INTEGER DEFAULT 1,
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now')),
-            updated_at TEXT
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create users table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS users (
-            id TEXT PRIMARY KEY,
-            branch_id TEXT REFERENCES branches(id),
-            username TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            full_name TEXT NOT NULL,
-            role TEXT NOT NULL CHECK (role IN ('owner', 'kasir')),
-            is_active INTEGER DEFAULT 1,
-            last_login TEXT,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create customers table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS customers (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            phone TEXT,
-            nik TEXT,
-            address TEXT,
-            notes TEXT,
-            total_transactions INTEGER DEFAULT 0,
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create categories table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS categories (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            description TEXT,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create products table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS products (
-            id TEXT PRIMARY KEY,
-            category_id TEXT REFERENCES categories(id),
-            sku TEXT UNIQUE,
-            name TEXT NOT NULL,
-            description TEXT,
-            gold_type TEXT NOT NULL CHECK (gold_type IN ('LM', 'UBS', 'Lokal')),
-            gold_purity INTEGER NOT NULL CHECK (gold_purity BETWEEN 375 AND 999),
-            weight_gram REAL NOT NULL,
-            labor_cost INTEGER DEFAULT 0,
-            images TEXT,
-            is_active INTEGER DEFAULT 1,
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create inventory table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS inventory (
-            id TEXT PRIMARY KEY,
-            product_id TEXT NOT NULL REFERENCES products(id),
-            branch_id TEXT NOT NULL REFERENCES branches(id),
-            barcode TEXT UNIQUE NOT NULL,
-            status TEXT NOT NULL DEFAULT 'available' CHECK (status IN ('available', 'sold', 'reserved')),
-            location TEXT,
-            purchase_price INTEGER NOT NULL,
-            purchase_date TEXT,
-            supplier TEXT,
-            notes TEXT,
-            sold_at TEXT,
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create gold_prices table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS gold_prices (
-            id TEXT PRIMARY KEY,
-            date TEXT NOT NULL,
-            gold_type TEXT NOT NULL,
-            purity INTEGER NOT NULL,
-            buy_price INTEGER NOT NULL,
-            sell_price INTEGER NOT NULL,
-            source TEXT,
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now')),
-            UNIQUE(date, gold_type, purity)
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create transactions table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS transactions (
-            id TEXT PRIMARY KEY,
-            branch_id TEXT NOT NULL REFERENCES branches(id),
-            user_id TEXT NOT NULL REFERENCES users(id),
-            customer_id TEXT REFERENCES customers(id),
-            invoice_no TEXT UNIQUE NOT NULL,
-            type TEXT NOT NULL CHECK (type IN ('sale', 'buyback', 'exchange')),
-            subtotal INTEGER NOT NULL,
-            discount INTEGER DEFAULT 0,
-            total_amount INTEGER NOT NULL,
-            notes TEXT,
-            status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'completed', 'void')),
-            salesforce_id TEXT UNIQUE,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create transaction_items table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS transaction_items (
-            id TEXT PRIMARY KEY,
-            transaction_id TEXT NOT NULL REFERENCES transactions(id),
-            inventory_id TEXT NOT NULL REFERENCES inventory(id),
-            quantity INTEGER DEFAULT 1,
-            unit_price INTEGER NOT NULL,
-            subtotal INTEGER NOT NULL,
-            gold_price_ref INTEGER,
-            salesforce_id TEXT UNIQUE
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create payments table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS payments (
-            id TEXT PRIMARY KEY,
-            transaction_id TEXT NOT NULL REFERENCES transactions(id),
-            method TEXT NOT NULL CHECK (method IN ('cash', 'qris', 'bank_transfer')),
-            amount INTEGER NOT NULL,
-            reference_no TEXT,
-            bank_name TEXT,
-            status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'success', 'failed')),
-            paid_at TEXT,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create sync_log table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS sync_log (
-            id TEXT PRIMARY KEY,
-            table_name TEXT NOT NULL,
-            record_id TEXT NOT NULL,
-            action TEXT NOT NULL CHECK (action IN ('insert', 'update', 'delete')),
-            payload TEXT,
-            synced INTEGER DEFAULT 0,
-            synced_at TEXT,
-            error_message TEXT,
-            retry_count INTEGER DEFAULT 0,
-            created_at TEXT DEFAULT (datetime('now'))
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create sync_config table for Salesforce credentials
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS sync_config (
-            id TEXT PRIMARY KEY DEFAULT 'default',
-            sf_client_id TEXT,
-            sf_client_secret TEXT,
-            sf_username TEXT,
-            sf_password TEXT,
-            sf_security_token TEXT,
-            sf_instance_url TEXT DEFAULT 'https://login.salesforce.com',
-            is_sandbox INTEGER DEFAULT 1,
-            sync_enabled INTEGER DEFAULT 0,
-            sync_interval_minutes INTEGER DEFAULT 15,
-            created_at TEXT DEFAULT (datetime('now')),
-            updated_at TEXT
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Create sync_metadata table for tracking sync timestamps per table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS sync_metadata (
-            table_name TEXT PRIMARY KEY,
-            last_pull_at TEXT,
-            last_push_at TEXT,
-            last_full_sync_at TEXT,
-            records_pulled INTEGER DEFAULT 0,
-            records_pushed INTEGER DEFAULT 0
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    // Run migrations for existing databases (add new columns)
-    // This MUST run before any indexes on new columns are created
-    run_column_migrations(pool).await?;
-
-    // Create indexes
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_inventory_barcode ON inventory(barcode)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_inventory_status ON inventory(status)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_transactions_date ON transactions(created_at)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_transactions_invoice ON transactions(invoice_no)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_gold_prices_date ON gold_prices(date)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_sync_log_synced ON sync_log(synced)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_sync_log_table_record ON sync_log(table_name, record_id)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_inventory_salesforce ON inventory(salesforce_id)")
-        .execute(pool)
-        .await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_transactions_salesforce ON transactions(salesforce_id)")
-        .execute(pool)
-        .await?;
+    migrations::run(pool, MIGRATIONS).await?;
 
     // Insert default data if tables are empty
     insert_default_data(pool).await?;
@@ -300,92 +15,989 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     Ok(())
 }
 
-/// Add new columns to existing tables (for database upgrades)
-async fn run_column_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    // Helper to check if column exists
-    async fn column_exists(pool: &SqlitePool, table: &str, column: &str) -> bool {
-        let query = format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?", table);
-        sqlx::query(&query)
-            .bind(column)
-            .fetch_optional(pool)
-            .await
-            .map(|r| r.is_some())
-            .unwrap_or(false)
-    }
-
-    // Add salesforce_id to branches
-    if !column_exists(pool, "branches", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE branches ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-    if !column_exists(pool, "branches", "code").await {
-        let _ = sqlx::query("ALTER TABLE branches ADD COLUMN code TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to customers
-    if !column_exists(pool, "customers", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE customers ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to products
-    if !column_exists(pool, "products", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE products ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to inventory
-    if !column_exists(pool, "inventory", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE inventory ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to gold_prices
-    if !column_exists(pool, "gold_prices", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE gold_prices ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to transactions
-    if !column_exists(pool, "transactions", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE transactions ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add salesforce_id to transaction_items
-    if !column_exists(pool, "transaction_items", "salesforce_id").await {
-        let _ = sqlx::query("ALTER TABLE transaction_items ADD COLUMN salesforce_id TEXT UNIQUE")
-            .execute(pool)
-            .await;
-    }
-
-    // Add new columns to sync_log
-    if !column_exists(pool, "sync_log", "payload").await {
-        let _ = sqlx::query("ALTER TABLE sync_log ADD COLUMN payload TEXT")
-            .execute(pool)
-            .await;
-    }
-    if !column_exists(pool, "sync_log", "error_message").await {
-        let _ = sqlx::query("ALTER TABLE sync_log ADD COLUMN error_message TEXT")
-            .execute(pool)
-            .await;
-    }
-    if !column_exists(pool, "sync_log", "retry_count").await {
-        let _ = sqlx::query("ALTER TABLE sync_log ADD COLUMN retry_count INTEGER DEFAULT 0")
-            .execute(pool)
-            .await;
-    }
-
-    Ok(())
-}
+/// Schema history, oldest first. Never edit a migration once it has shipped;
+/// append a new one.
+const MIGRATIONS: &[Migration] = &[
+    // The schema as it stood when versioned migrations were introduced.
+    // Databases created before then already have some or all of it, hence
+    // `IF NOT EXISTS` and the column backfills.
+    Migration {
+        version: 1,
+        name: "baseline",
+        steps: &[
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS branches (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    code TEXT UNIQUE,
+                    address TEXT,
+                    phone TEXT,
+                    is_active INTEGER DEFAULT 1,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now')),
+                    updated_at TEXT
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS users (
+                    id TEXT PRIMARY KEY,
+                    branch_id TEXT REFERENCES branches(id),
+                    username TEXT UNIQUE NOT NULL,
+                    password_hash TEXT NOT NULL,
+                    full_name TEXT NOT NULL,
+                    role TEXT NOT NULL CHECK (role IN ('owner', 'kasir')),
+                    is_active INTEGER DEFAULT 1,
+                    last_login TEXT,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS sessions (
+                    token_hash TEXT PRIMARY KEY,
+                    user_id TEXT NOT NULL REFERENCES users(id),
+                    expires_at TEXT NOT NULL,
+                    revoked_at TEXT,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS customers (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    phone TEXT,
+                    nik TEXT,
+                    address TEXT,
+                    notes TEXT,
+                    total_transactions INTEGER DEFAULT 0,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS categories (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS products (
+                    id TEXT PRIMARY KEY,
+                    category_id TEXT REFERENCES categories(id),
+                    sku TEXT UNIQUE,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    gold_type TEXT NOT NULL CHECK (gold_type IN ('LM', 'UBS', 'Lokal')),
+                    gold_purity INTEGER NOT NULL CHECK (gold_purity BETWEEN 375 AND 999),
+                    weight_gram REAL NOT NULL,
+                    labor_cost INTEGER DEFAULT 0,
+                    images TEXT,
+                    is_active INTEGER DEFAULT 1,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS inventory (
+                    id TEXT PRIMARY KEY,
+                    product_id TEXT NOT NULL REFERENCES products(id),
+                    branch_id TEXT NOT NULL REFERENCES branches(id),
+                    barcode TEXT UNIQUE NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'available' CHECK (status IN ('available', 'sold', 'reserved', 'reprocess', 'returned', 'missing', 'in_transit')),
+                    location TEXT,
+                    purchase_price INTEGER NOT NULL,
+                    purchase_date TEXT,
+                    supplier TEXT,
+                    notes TEXT,
+                    sold_at TEXT,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS gold_prices (
+                    id TEXT PRIMARY KEY,
+                    date TEXT NOT NULL,
+                    gold_type TEXT NOT NULL,
+                    purity INTEGER NOT NULL,
+                    buy_price INTEGER NOT NULL,
+                    sell_price INTEGER NOT NULL,
+                    source TEXT,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now')),
+                    UNIQUE(date, gold_type, purity)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS transactions (
+                    id TEXT PRIMARY KEY,
+                    branch_id TEXT NOT NULL REFERENCES branches(id),
+                    user_id TEXT NOT NULL REFERENCES users(id),
+                    customer_id TEXT REFERENCES customers(id),
+                    invoice_no TEXT UNIQUE NOT NULL,
+                    type TEXT NOT NULL CHECK (type IN ('sale', 'buyback', 'exchange')),
+                    subtotal INTEGER NOT NULL,
+                    discount INTEGER DEFAULT 0,
+                    trade_in_total INTEGER NOT NULL DEFAULT 0,
+                    total_amount INTEGER NOT NULL,
+                    notes TEXT,
+                    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'completed', 'void')),
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS transaction_items (
+                    id TEXT PRIMARY KEY,
+                    transaction_id TEXT NOT NULL REFERENCES transactions(id),
+                    inventory_id TEXT NOT NULL REFERENCES inventory(id),
+                    quantity INTEGER DEFAULT 1,
+                    unit_price INTEGER NOT NULL,
+                    subtotal INTEGER NOT NULL,
+                    gold_price_ref INTEGER,
+                    computed_price INTEGER,
+                    price_status TEXT NOT NULL DEFAULT 'computed' CHECK (price_status IN ('computed', 'override', 'flagged')),
+                    direction TEXT NOT NULL DEFAULT 'out' CHECK (direction IN ('out', 'in')),
+                    item_condition TEXT CHECK (item_condition IN ('good', 'damaged', 'scrap')),
+                    origin_inventory_id TEXT REFERENCES inventory(id),
+                    salesforce_id TEXT UNIQUE
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS payments (
+                    id TEXT PRIMARY KEY,
+                    transaction_id TEXT NOT NULL REFERENCES transactions(id),
+                    method TEXT NOT NULL CHECK (method IN ('cash', 'qris', 'bank_transfer')),
+                    amount INTEGER NOT NULL,
+                    reference_no TEXT,
+                    bank_name TEXT,
+                    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'success', 'failed')),
+                    paid_at TEXT,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS sync_log (
+                    id TEXT PRIMARY KEY,
+                    table_name TEXT NOT NULL,
+                    record_id TEXT NOT NULL,
+                    action TEXT NOT NULL CHECK (action IN ('insert', 'update', 'delete')),
+                    payload TEXT,
+                    synced INTEGER DEFAULT 0,
+                    synced_at TEXT,
+                    error_message TEXT,
+                    retry_count INTEGER DEFAULT 0,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS sync_config (
+                    id TEXT PRIMARY KEY DEFAULT 'default',
+                    sf_client_id TEXT,
+                    sf_client_secret TEXT,
+                    sf_username TEXT,
+                    sf_password TEXT,
+                    sf_security_token TEXT,
+                    sf_instance_url TEXT DEFAULT 'https://login.salesforce.com',
+                    is_sandbox INTEGER DEFAULT 1,
+                    sync_enabled INTEGER DEFAULT 0,
+                    sync_interval_minutes INTEGER DEFAULT 15,
+                    created_at TEXT DEFAULT (datetime('now')),
+                    updated_at TEXT
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS invoice_sequences (
+                    branch_code TEXT NOT NULL,
+                    prefix TEXT NOT NULL,
+                    date TEXT NOT NULL,
+                    last_value INTEGER NOT NULL,
+                    updated_at TEXT DEFAULT (datetime('now')),
+                    PRIMARY KEY (branch_code, prefix, date)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS pricing_config (
+                    id TEXT PRIMARY KEY DEFAULT 'default',
+                    override_tolerance_percent REAL NOT NULL DEFAULT 2.0,
+                    override_policy TEXT NOT NULL DEFAULT 'reject' CHECK (override_policy IN ('reject', 'flag')),
+                    updated_at TEXT
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stock_opname_sessions (
+                    id TEXT PRIMARY KEY,
+                    branch_id TEXT NOT NULL REFERENCES branches(id),
+                    location TEXT,
+                    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'closed', 'approved')),
+                    notes TEXT,
+                    opened_by TEXT NOT NULL REFERENCES users(id),
+                    opened_at TEXT DEFAULT (datetime('now')),
+                    closed_by TEXT REFERENCES users(id),
+                    closed_at TEXT,
+                    approved_by TEXT REFERENCES users(id),
+                    approved_at TEXT
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stock_opname_scans (
+                    id TEXT PRIMARY KEY,
+                    session_id TEXT NOT NULL REFERENCES stock_opname_sessions(id),
+                    barcode TEXT NOT NULL,
+                    inventory_id TEXT REFERENCES inventory(id),
+                    location TEXT,
+                    scanned_by TEXT NOT NULL REFERENCES users(id),
+                    scanned_at TEXT DEFAULT (datetime('now')),
+                    UNIQUE(session_id, barcode)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stock_opname_results (
+                    id TEXT PRIMARY KEY,
+                    session_id TEXT NOT NULL REFERENCES stock_opname_sessions(id),
+                    kind TEXT NOT NULL CHECK (kind IN ('missing', 'unexpected', 'wrong_location', 'sold_but_scanned', 'found')),
+                    barcode TEXT NOT NULL,
+                    inventory_id TEXT REFERENCES inventory(id),
+                    status_before TEXT,
+                    expected_location TEXT,
+                    scanned_location TEXT,
+                    resolution TEXT CHECK (resolution IN ('adjusted', 'ignored'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stock_transfers (
+                    id TEXT PRIMARY KEY,
+                    transfer_no TEXT UNIQUE NOT NULL,
+                    from_branch_id TEXT NOT NULL REFERENCES branches(id),
+                    to_branch_id TEXT NOT NULL REFERENCES branches(id),
+                    status TEXT NOT NULL DEFAULT 'draft' CHECK (status IN ('draft', 'in_transit', 'received', 'cancelled')),
+                    notes TEXT,
+                    created_by TEXT NOT NULL REFERENCES users(id),
+                    created_at TEXT DEFAULT (datetime('now')),
+                    dispatched_by TEXT REFERENCES users(id),
+                    dispatched_at TEXT,
+                    received_by TEXT REFERENCES users(id),
+                    received_at TEXT,
+                    CHECK (from_branch_id != to_branch_id)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stock_transfer_items (
+                    id TEXT PRIMARY KEY,
+                    transfer_id TEXT NOT NULL REFERENCES stock_transfers(id),
+                    inventory_id TEXT NOT NULL REFERENCES inventory(id),
+                    UNIQUE(transfer_id, inventory_id)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS inventory_adjustments (
+                    id TEXT PRIMARY KEY,
+                    inventory_id TEXT NOT NULL REFERENCES inventory(id),
+                    opname_result_id TEXT REFERENCES stock_opname_results(id),
+                    field TEXT NOT NULL CHECK (field IN ('status', 'location')),
+                    old_value TEXT,
+                    new_value TEXT,
+                    approved_by TEXT NOT NULL REFERENCES users(id),
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                    id TEXT PRIMARY KEY,
+                    actor_id TEXT NOT NULL,
+                    actor_username TEXT NOT NULL,
+                    branch_id TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    entity_type TEXT NOT NULL,
+                    entity_id TEXT NOT NULL,
+                    before_json TEXT,
+                    after_json TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS sync_metadata (
+                    table_name TEXT PRIMARY KEY,
+                    last_pull_at TEXT,
+                    last_push_at TEXT,
+                    last_full_sync_at TEXT,
+                    records_pulled INTEGER DEFAULT 0,
+                    records_pushed INTEGER DEFAULT 0
+                )
+                "#,
+            ),
+            // Columns added to tables after their first release
+            Step::AddColumn {
+                table: "branches",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "branches",
+                column: "code",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "customers",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "products",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "inventory",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "gold_prices",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "transactions",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "salesforce_id",
+                definition: "TEXT UNIQUE",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "computed_price",
+                definition: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "price_status",
+                definition: "TEXT NOT NULL DEFAULT 'computed' CHECK (price_status IN ('computed', 'override', 'flagged'))",
+            },
+            Step::AddColumn {
+                table: "transactions",
+                column: "trade_in_total",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "direction",
+                definition: "TEXT NOT NULL DEFAULT 'out' CHECK (direction IN ('out', 'in'))",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "item_condition",
+                definition: "TEXT CHECK (item_condition IN ('good', 'damaged', 'scrap'))",
+            },
+            Step::AddColumn {
+                table: "transaction_items",
+                column: "origin_inventory_id",
+                definition: "TEXT REFERENCES inventory(id)",
+            },
+            Step::AddColumn {
+                table: "sync_log",
+                column: "payload",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "sync_log",
+                column: "error_message",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "sync_log",
+                column: "retry_count",
+                definition: "INTEGER DEFAULT 0",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_inventory_barcode ON inventory(barcode)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_inventory_status ON inventory(status)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_transactions_date ON transactions(created_at)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_transactions_invoice ON transactions(invoice_no)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_gold_prices_date ON gold_prices(date)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_sync_log_synced ON sync_log(synced)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_sync_log_table_record ON sync_log(table_name, record_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id)"),
+            Step::Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_stock_opname_open ON stock_opname_sessions(branch_id, IFNULL(location, '')) WHERE status = 'open'"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_stock_transfer_items_inventory ON stock_transfer_items(inventory_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_stock_opname_results_session ON stock_opname_results(session_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_inventory_adjustments_inventory ON inventory_adjustments(inventory_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log(entity_type, entity_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_inventory_salesforce ON inventory(salesforce_id)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_transactions_salesforce ON transactions(salesforce_id)"),
+            // The audit log is append-only, even for code with direct SQL access
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END",
+            ),
+        ],
+    },
+    // Inventory statuses: `reprocess` holds gold taken in from customers until
+    // it is melted or refurbished; `returned` is a traded-in piece handed back
+    // because its transaction was voided; `missing` is stock a stock opname
+    // could not find; `in_transit` is stock being transferred to another
+    // branch. Older databases have a narrower CHECK.
+    Migration {
+        version: 2,
+        name: "widen_inventory_status",
+        steps: &[Step::RebuildTable {
+            table: "inventory",
+            create_sql: r#"
+                CREATE TABLE inventory_rebuild (
+                    id TEXT PRIMARY KEY,
+                    product_id TEXT NOT NULL REFERENCES products(id),
+                    branch_id TEXT NOT NULL REFERENCES branches(id),
+                    barcode TEXT UNIQUE NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'available' CHECK (status IN ('available', 'sold', 'reserved', 'reprocess', 'returned', 'missing', 'in_transit')),
+                    location TEXT,
+                    purchase_price INTEGER NOT NULL,
+                    purchase_date TEXT,
+                    supplier TEXT,
+                    notes TEXT,
+                    sold_at TEXT,
+                    salesforce_id TEXT UNIQUE,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+            "#,
+        }],
+    },
+    // Key settings for the encrypted secrets in `sync_config`. The verifier is
+    // a known value encrypted with the key, to recognise the right key.
+    Migration {
+        version: 3,
+        name: "secret_keys",
+        steps: &[Step::Sql(
+            r#"
+            CREATE TABLE secret_keys (
+                id TEXT PRIMARY KEY DEFAULT 'default',
+                mode TEXT NOT NULL CHECK (mode IN ('keyfile', 'passphrase')),
+                kdf_salt TEXT,
+                kdf_iterations INTEGER,
+                verifier TEXT NOT NULL,
+                updated_at TEXT
+            )
+            "#,
+        )],
+    },
+    Migration {
+        version: 4,
+        name: "sync_jwt_bearer",
+        steps: &[
+            Step::Sql(
+                r#"
+                ALTER TABLE sync_config ADD COLUMN sf_auth_flow TEXT NOT NULL DEFAULT 'password'
+                    CHECK (sf_auth_flow IN ('password', 'jwt_bearer'))
+                "#,
+            ),
+            Step::Sql("ALTER TABLE sync_config ADD COLUMN sf_jwt_private_key TEXT"),
+            Step::Sql("ALTER TABLE sync_config ADD COLUMN sf_jwt_audience TEXT"),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "payments_salesforce_id",
+        steps: &[
+            Step::Sql("ALTER TABLE payments ADD COLUMN salesforce_id TEXT"),
+            Step::Sql(
+                "CREATE UNIQUE INDEX idx_payments_salesforce_id_unique ON payments(salesforce_id)",
+            ),
+        ],
+    },
+    // Queue every local write to the synced tables for push, so no command
+    // has to remember to
+    Migration {
+        version: 6,
+        name: "sync_change_capture",
+        steps: &[
+            Step::Sql("CREATE TABLE sync_capture_suspended (id INTEGER PRIMARY KEY)"),
+            Step::CaptureChanges {
+                table: "products",
+                columns: &[
+                    "id", "category_id", "sku", "name", "description", "gold_type", "gold_purity",
+                    "weight_gram", "labor_cost", "images", "is_active", "salesforce_id",
+                    "created_at",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "inventory",
+                columns: &[
+                    "id", "product_id", "branch_id", "barcode", "status", "location",
+                    "purchase_price", "purchase_date", "supplier", "notes", "sold_at",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "customers",
+                columns: &[
+                    "id", "name", "phone", "nik", "address", "notes", "total_transactions",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "gold_prices",
+                columns: &[
+                    "id", "date", "gold_type", "purity", "buy_price", "sell_price", "source",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "transactions",
+                columns: &[
+                    "id", "branch_id", "user_id", "customer_id", "invoice_no", "type", "subtotal",
+                    "discount", "trade_in_total", "total_amount", "notes", "status",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "transaction_items",
+                columns: &[
+                    "id", "transaction_id", "inventory_id", "quantity", "unit_price", "subtotal",
+                    "gold_price_ref", "computed_price", "price_status", "direction",
+                    "item_condition", "origin_inventory_id", "salesforce_id",
+                ],
+            },
+            Step::CaptureChanges {
+                table: "payments",
+                columns: &[
+                    "id", "transaction_id", "method", "amount", "reference_no", "bank_name",
+                    "status", "paid_at", "salesforce_id", "created_at",
+                ],
+            },
+        ],
+    },
+    // One sync_log row per record. A new change to a record that is already
+    // queued is merged into its row by the sync_log_coalesce trigger.
+    Migration {
+        version: 7,
+        name: "sync_log_unique_record",
+        steps: &[
+            // Merge the rows queued so far the way the trigger below would
+            Step::Sql(
+                r#"
+                DELETE FROM sync_log
+                WHERE rowid IN (
+                    SELECT s.rowid FROM sync_log s
+                    WHERE EXISTS (SELECT 1 FROM sync_log i
+                                  WHERE i.table_name = s.table_name AND i.record_id = s.record_id
+                                    AND i.synced = 0 AND i.action = 'insert')
+                      AND EXISTS (SELECT 1 FROM sync_log d
+                                  WHERE d.table_name = s.table_name AND d.record_id = s.record_id
+                                    AND d.synced = 0 AND d.action = 'delete'
+                                    AND d.rowid = (SELECT MAX(rowid) FROM sync_log l
+                                                   WHERE l.table_name = d.table_name
+                                                     AND l.record_id = d.record_id))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                UPDATE sync_log SET action = 'insert'
+                WHERE synced = 0
+                  AND rowid = (SELECT MAX(rowid) FROM sync_log l
+                               WHERE l.table_name = sync_log.table_name
+                                 AND l.record_id = sync_log.record_id)
+                  AND EXISTS (SELECT 1 FROM sync_log i
+                              WHERE i.table_name = sync_log.table_name
+                                AND i.record_id = sync_log.record_id
+                                AND i.synced = 0 AND i.action = 'insert')
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                DELETE FROM sync_log
+                WHERE rowid NOT IN (SELECT MAX(rowid) FROM sync_log GROUP BY table_name, record_id)
+                "#,
+            ),
+            Step::Sql("DROP INDEX IF EXISTS idx_sync_log_table_record"),
+            Step::Sql(
+                "CREATE UNIQUE INDEX idx_sync_log_table_record ON sync_log(table_name, record_id)",
+            ),
+            // Folds a new change into the row already queued for the record:
+            // insert then update stays an insert, insert then delete cancels
+            // out (the record never reached Salesforce), and anything else
+            // takes the new action. A record that was already pushed starts a
+            // fresh pending change.
+            Step::Sql(
+                r#"
+                CREATE TRIGGER sync_log_coalesce BEFORE INSERT ON sync_log
+                WHEN EXISTS (SELECT 1 FROM sync_log
+                             WHERE table_name = NEW.table_name AND record_id = NEW.record_id)
+                BEGIN
+                    DELETE FROM sync_log
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id
+                      AND synced = 0 AND action = 'insert' AND NEW.action = 'delete';
+                    UPDATE sync_log SET
+                        action = CASE
+                            WHEN synced = 0 AND action = 'insert' THEN 'insert'
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'insert' THEN 'update'
+                            ELSE NEW.action
+                        END,
+                        payload = NEW.payload,
+                        created_at = CASE WHEN synced = 0 THEN created_at ELSE datetime('now') END,
+                        synced = 0,
+                        synced_at = NULL,
+                        error_message = NULL,
+                        retry_count = 0
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id;
+                    SELECT RAISE(IGNORE);
+                END
+                "#,
+            ),
+        ],
+    },
+    // Per-record sync versions, so edits made on both sides between syncs
+    // are caught and settled by the table's conflict policy
+    Migration {
+        version: 8,
+        name: "sync_conflicts",
+        steps: &[
+            // The record as last agreed with Salesforce, in Salesforce field
+            // names, and Salesforce's SystemModstamp for that version
+            Step::Sql(
+                r#"
+                CREATE TABLE sync_versions (
+                    table_name TEXT NOT NULL,
+                    record_id TEXT NOT NULL,
+                    sf_modstamp TEXT,
+                    base_json TEXT,
+                    synced_at TEXT DEFAULT (datetime('now')),
+                    PRIMARY KEY (table_name, record_id)
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE sync_conflict_policies (
+                    table_name TEXT PRIMARY KEY,
+                    policy TEXT NOT NULL
+                        CHECK (policy IN ('server_wins', 'local_wins', 'merge', 'manual')),
+                    updated_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            // Prices and the catalogue are managed in Salesforce; sales are
+            // recorded at the counter
+            Step::Sql(
+                r#"
+                INSERT INTO sync_conflict_policies (table_name, policy) VALUES
+                    ('gold_prices', 'server_wins'),
+                    ('products', 'server_wins'),
+                    ('inventory', 'merge'),
+                    ('customers', 'merge'),
+                    ('transactions', 'manual'),
+                    ('transaction_items', 'local_wins'),
+                    ('payments', 'local_wins')
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TABLE sync_conflicts (
+                    id TEXT PRIMARY KEY,
+                    table_name TEXT NOT NULL,
+                    record_id TEXT NOT NULL,
+                    salesforce_id TEXT,
+                    local_json TEXT NOT NULL,
+                    remote_json TEXT NOT NULL,
+                    base_json TEXT,
+                    remote_modstamp TEXT,
+                    local_changed_at TEXT,
+                    conflicting_fields TEXT NOT NULL DEFAULT '[]',
+                    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'resolved')),
+                    resolution TEXT CHECK (resolution IN ('local', 'server')),
+                    resolved_by TEXT REFERENCES users(id),
+                    resolved_at TEXT,
+                    created_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE UNIQUE INDEX idx_sync_conflicts_open
+                ON sync_conflicts(table_name, record_id) WHERE status = 'open'
+                "#,
+            ),
+        ],
+    },
+    // Failed pushes back off between retries and, once out of retries, wait
+    // in the dead-letter queue for the owner
+    Migration {
+        version: 9,
+        name: "sync_dead_letter",
+        steps: &[
+            Step::Sql("ALTER TABLE sync_log ADD COLUMN next_retry_at TEXT"),
+            Step::Sql("ALTER TABLE sync_log ADD COLUMN dead_lettered_at TEXT"),
+            Step::Sql(
+                r#"
+                UPDATE sync_log SET dead_lettered_at = datetime('now')
+                WHERE synced = 0 AND retry_count >= 5
+                "#,
+            ),
+            // As in version 7, plus a new change to a dead-lettered record
+            // puts it back in the queue
+            Step::Sql("DROP TRIGGER sync_log_coalesce"),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER sync_log_coalesce BEFORE INSERT ON sync_log
+                WHEN EXISTS (SELECT 1 FROM sync_log
+                             WHERE table_name = NEW.table_name AND record_id = NEW.record_id)
+                BEGIN
+                    DELETE FROM sync_log
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id
+                      AND synced = 0 AND action = 'insert' AND NEW.action = 'delete';
+                    UPDATE sync_log SET
+                        action = CASE
+                            WHEN synced = 0 AND action = 'insert' THEN 'insert'
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'insert' THEN 'update'
+                            ELSE NEW.action
+                        END,
+                        payload = NEW.payload,
+                        created_at = CASE WHEN synced = 0 THEN created_at ELSE datetime('now') END,
+                        synced = 0,
+                        synced_at = NULL,
+                        error_message = NULL,
+                        retry_count = 0,
+                        next_retry_at = NULL,
+                        dead_lettered_at = NULL
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id;
+                    SELECT RAISE(IGNORE);
+                END
+                "#,
+            ),
+        ],
+    },
+    // Transactions pulled from other branches were not made by a local
+    // user. This one owns them; it is inactive, so nobody can log in as it.
+    Migration {
+        version: 10,
+        name: "salesforce_sync_user",
+        steps: &[Step::Sql(
+            r#"
+            INSERT INTO users (id, branch_id, username, password_hash, full_name, role, is_active)
+            VALUES ('salesforce-sync', NULL, 'salesforce-sync', '!', 'Salesforce', 'kasir', 0)
+            "#,
+        )],
+    },
+    // Pulls fetch what changed since the newest SystemModstamp already
+    // pulled, kept per object (and per branch for branch pulls). Without
+    // one the object is pulled in full, which is also how existing installs
+    // start. The single "full" row is no longer written.
+    Migration {
+        version: 11,
+        name: "sync_pull_watermarks",
+        steps: &[
+            Step::Sql("ALTER TABLE sync_metadata ADD COLUMN pull_watermark TEXT"),
+            Step::Sql("DELETE FROM sync_metadata WHERE table_name = 'full'"),
+        ],
+    },
+    // Deleting master data only marks it deleted: sold items and past
+    // transactions still refer to it. Setting deleted_at is pushed as a
+    // delete, and records deleted in Salesforce are marked the same way.
+    Migration {
+        version: 12,
+        name: "sync_deletions",
+        steps: &[
+            Step::Sql("ALTER TABLE products ADD COLUMN deleted_at TEXT"),
+            Step::Sql("ALTER TABLE inventory ADD COLUMN deleted_at TEXT"),
+            Step::Sql("ALTER TABLE customers ADD COLUMN deleted_at TEXT"),
+            Step::CaptureSoftDeletes {
+                table: "products",
+                columns: &[
+                    "id", "category_id", "sku", "name", "description", "gold_type", "gold_purity",
+                    "weight_gram", "labor_cost", "images", "is_active", "salesforce_id",
+                    "created_at",
+                ],
+            },
+            Step::CaptureSoftDeletes {
+                table: "inventory",
+                columns: &[
+                    "id", "product_id", "branch_id", "barcode", "status", "location",
+                    "purchase_price", "purchase_date", "supplier", "notes", "sold_at",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            Step::CaptureSoftDeletes {
+                table: "customers",
+                columns: &[
+                    "id", "name", "phone", "nik", "address", "notes", "total_transactions",
+                    "salesforce_id", "created_at",
+                ],
+            },
+            // How a local delete reaches Salesforce: 'deactivate' clears
+            // Is_Active__c (for objects that have it), 'delete' removes the
+            // record. Tables without a row are deleted.
+            Step::Sql(
+                r#"
+                CREATE TABLE sync_delete_modes (
+                    table_name TEXT PRIMARY KEY,
+                    mode TEXT NOT NULL CHECK (mode IN ('deactivate', 'delete')),
+                    updated_at TEXT DEFAULT (datetime('now'))
+                )
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                INSERT INTO sync_delete_modes (table_name, mode) VALUES
+                    ('products', 'deactivate'),
+                    ('inventory', 'delete'),
+                    ('customers', 'delete')
+                "#,
+            ),
+        ],
+    },
+    // Record count above which a pull or push goes through the Bulk API;
+    // NULL keeps the built-in default
+    Migration {
+        version: 13,
+        name: "sync_bulk_threshold",
+        steps: &[Step::Sql("ALTER TABLE sync_config ADD COLUMN bulk_threshold INTEGER")],
+    },
+    // API requests each table's last pull and push took, and the share of
+    // the org's daily API limit (in percent) past which syncs only pull
+    // what selling needs; NULL keeps the built-in default
+    Migration {
+        version: 14,
+        name: "sync_api_usage",
+        steps: &[
+            Step::Sql("ALTER TABLE sync_metadata ADD COLUMN pull_requests INTEGER DEFAULT 0"),
+            Step::Sql("ALTER TABLE sync_metadata ADD COLUMN push_requests INTEGER DEFAULT 0"),
+            Step::Sql("ALTER TABLE sync_config ADD COLUMN api_usage_threshold INTEGER"),
+        ],
+    },
+    // Each change folded into a queued row bumps its revision, so a push
+    // that read an older revision does not mark the newer change synced
+    Migration {
+        version: 15,
+        name: "sync_log_revision",
+        steps: &[
+            Step::Sql("ALTER TABLE sync_log ADD COLUMN revision INTEGER NOT NULL DEFAULT 0"),
+            // As in version 9, plus the revision
+            Step::Sql("DROP TRIGGER sync_log_coalesce"),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER sync_log_coalesce BEFORE INSERT ON sync_log
+                WHEN EXISTS (SELECT 1 FROM sync_log
+                             WHERE table_name = NEW.table_name AND record_id = NEW.record_id)
+                BEGIN
+                    DELETE FROM sync_log
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id
+                      AND synced = 0 AND action = 'insert' AND NEW.action = 'delete';
+                    UPDATE sync_log SET
+                        action = CASE
+                            WHEN synced = 0 AND action = 'insert' THEN 'insert'
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'insert' THEN 'update'
+                            ELSE NEW.action
+                        END,
+                        payload = NEW.payload,
+                        created_at = CASE WHEN synced = 0 THEN created_at ELSE datetime('now') END,
+                        synced = 0,
+                        synced_at = NULL,
+                        error_message = NULL,
+                        retry_count = 0,
+                        next_retry_at = NULL,
+                        dead_lettered_at = NULL,
+                        revision = revision + 1
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id;
+                    SELECT RAISE(IGNORE);
+                END
+                "#,
+            ),
+        ],
+    },
+    // A record deleted but not yet pushed stays deleted when it is written
+    // again, so the write cannot bring it back in Salesforce
+    Migration {
+        version: 16,
+        name: "sync_log_keep_delete",
+        steps: &[
+            // As in version 15, plus an update after a delete stays a delete
+            Step::Sql("DROP TRIGGER sync_log_coalesce"),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER sync_log_coalesce BEFORE INSERT ON sync_log
+                WHEN EXISTS (SELECT 1 FROM sync_log
+                             WHERE table_name = NEW.table_name AND record_id = NEW.record_id)
+                BEGIN
+                    DELETE FROM sync_log
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id
+                      AND synced = 0 AND action = 'insert' AND NEW.action = 'delete';
+                    UPDATE sync_log SET
+                        action = CASE
+                            WHEN synced = 0 AND action = 'insert' THEN 'insert'
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'insert' THEN 'update'
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'update' THEN 'delete'
+                            ELSE NEW.action
+                        END,
+                        payload = CASE
+                            WHEN synced = 0 AND action = 'delete' AND NEW.action = 'update' THEN payload
+                            ELSE NEW.payload
+                        END,
+                        created_at = CASE WHEN synced = 0 THEN created_at ELSE datetime('now') END,
+                        synced = 0,
+                        synced_at = NULL,
+                        error_message = NULL,
+                        retry_count = 0,
+                        next_retry_at = NULL,
+                        dead_lettered_at = NULL,
+                        revision = revision + 1
+                    WHERE table_name = NEW.table_name AND record_id = NEW.record_id;
+                    SELECT RAISE(IGNORE);
+                END
+                "#,
+            ),
+        ],
+    },
+];
 
 async fn insert_default_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     // Check if branches exist
@@ -451,6 +1063,11 @@ async fn insert_default_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
             ("Lokal", 375, 440000, 490000),
         ];
 
+        // Sample prices are not real data, so keep them out of the sync outbox
+        let mut tx = pool.begin().await?;
+        sqlx::query("INSERT INTO sync_capture_suspended (id) VALUES (1)")
+            .execute(&mut *tx)
+            .await?;
         for (gold_type, purity, buy_price, sell_price) in prices {
             let id = uuid::Uuid::new_v4().to_string();
             sqlx::query(
@@ -465,9 +1082,13 @@ async fn insert_default_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
             .bind(purity)
             .bind(buy_price)
             .bind(sell_price)
-            .execute(pool)
+            .execute(&mut *tx)
             .await?;
         }
+        sqlx::query("DELETE FROM sync_capture_suspended")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
     }
 
     Ok(())
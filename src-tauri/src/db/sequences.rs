@@ -0,0 +1,87 @@
+use sqlx::SqliteConnection;
+
+/// Allocate the next document number for a branch, prefix and day
+/// (INV/BUY/EXC for invoices, TRF for stock transfers).
+///
+/// The counter lives in `invoice_sequences` and is bumped with a single upsert,
+/// so two checkouts can never read the same value. Call this as the first
+/// write of the checkout transaction: SQLite then holds the write lock for the
+/// rest of the checkout, and a rollback hands the number back.
+pub async fn next_invoice_no(
+    conn: &mut SqliteConnection,
+    branch_id: &str,
+    prefix: &str,
+    date: chrono::NaiveDate,
+) -> Result<String, String> {
+    let day = date.format("%Y%m%d").to_string();
+
+    // Resolve the branch code inside the upsert itself so the statement is a
+    // write from the start (no read-then-upgrade window).
+    let allocated: Option<(String, i64)> = sqlx::query_as(
+        r#"
+        INSERT INTO invoice_sequences (branch_code, prefix, date, last_value)
+        SELECT COALESCE(NULLIF(code, ''), id), ?, ?, 1 FROM branches WHERE id = ?
+        ON CONFLICT(branch_code, prefix, date) DO UPDATE SET
+            last_value = last_value + 1,
+            updated_at = datetime('now')
+        RETURNING branch_code, last_value
+        "#,
+    )
+    .bind(prefix)
+    .bind(&day)
+    .bind(branch_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to allocate invoice number: {}", e))?;
+
+    let (branch_code, sequence) =
+        allocated.ok_or_else(|| format!("Branch {} not found", branch_id))?;
+
+    Ok(format!("{}-{}-{}-{:04}", prefix, branch_code, day, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_sequences_are_per_branch_prefix_and_day() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        sqlx::query("INSERT INTO branches (id, name, code) VALUES ('b2', 'Cabang 2', 'TES-002')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let next_day = chrono::NaiveDate::from_ymd_opt(2024, 3, 2).unwrap();
+
+        assert_eq!(
+            next_invoice_no(&mut conn, "default", "INV", day).await.unwrap(),
+            "INV-TES-001-20240301-0001"
+        );
+        assert_eq!(
+            next_invoice_no(&mut conn, "default", "INV", day).await.unwrap(),
+            "INV-TES-001-20240301-0002"
+        );
+        assert_eq!(
+            next_invoice_no(&mut conn, "default", "BUY", day).await.unwrap(),
+            "BUY-TES-001-20240301-0001"
+        );
+        assert_eq!(
+            next_invoice_no(&mut conn, "b2", "INV", day).await.unwrap(),
+            "INV-TES-002-20240301-0001"
+        );
+        assert_eq!(
+            next_invoice_no(&mut conn, "default", "INV", next_day).await.unwrap(),
+            "INV-TES-001-20240302-0001"
+        );
+        assert!(next_invoice_no(&mut conn, "missing", "INV", day).await.is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::models::IncomingGoldItem;
+use sqlx::SqliteConnection;
+
+/// Check a customer gold item before it is valued
+pub fn validate_incoming(item: &IncomingGoldItem) -> Result<(), String> {
+    if !["LM", "UBS", "Lokal"].contains(&item.gold_type.as_str()) {
+        return Err(format!("Unknown gold type: {}", item.gold_type));
+    }
+    if !(375..=999).contains(&item.gold_purity) {
+        return Err(format!("Invalid gold purity: {}", item.gold_purity));
+    }
+    if item.weight_gram <= 0.0 {
+        return Err("Weight must be greater than zero".to_string());
+    }
+    if !(0.0..=100.0).contains(&item.deduction_percent) {
+        return Err(format!(
+            "Deduction must be between 0 and 100%, got {}",
+            item.deduction_percent
+        ));
+    }
+    Ok(())
+}
+
+/// Inventory status for a bought-back piece in the given condition
+pub fn status_for_condition(condition: &str) -> Result<&'static str, String> {
+    match condition {
+        "good" => Ok("available"),
+        "damaged" | "scrap" => Ok("reprocess"),
+        _ => Err(format!("Unknown item condition: {}", condition)),
+    }
+}
+
+/// A piece this shop sold before, found by its barcode
+#[derive(Debug)]
+pub struct OriginItem {
+    pub inventory_id: String,
+    pub category_id: Option<String>,
+    pub name: String,
+}
+
+/// Look up a returning piece by the barcode it was sold under.
+///
+/// The piece must have been sold, not bought back already, and must match
+/// the gold type and purity the customer is declaring.
+pub async fn find_origin(
+    conn: &mut SqliteConnection,
+    barcode: &str,
+    item: &IncomingGoldItem,
+) -> Result<OriginItem, String> {
+    let row: Option<(String, String, Option<String>, String, String, i32)> = sqlx::query_as(
+        r#"
+        SELECT i.id, i.status, p.category_id, p.name, p.gold_type, p.gold_purity
+        FROM inventory i
+        JOIN products p ON i.product_id = p.id
+        WHERE i.barcode = ?
+        "#,
+    )
+    .bind(barcode)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let (inventory_id, status, category_id, name, gold_type, gold_purity) =
+        row.ok_or_else(|| format!("Barcode {} was not issued by this shop", barcode))?;
+
+    if status != "sold" {
+        return Err(format!(
+            "Item {} has not been sold (status: {})",
+            barcode, status
+        ));
+    }
+    if gold_type != item.gold_type || gold_purity != item.gold_purity {
+        return Err(format!(
+            "Item {} was sold as {} {}, not {} {}",
+            barcode, gold_type, gold_purity, item.gold_type, item.gold_purity
+        ));
+    }
+
+    let bought_back: Option<(i64,)> = sqlx::query_as(
+        r#"
+        SELECT 1 FROM transaction_items ti
+        JOIN transactions t ON ti.transaction_id = t.id
+        WHERE ti.origin_inventory_id = ? AND t.status != 'void'
+        "#,
+    )
+    .bind(&inventory_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    if bought_back.is_some() {
+        return Err(format!("Item {} has already been bought back", barcode));
+    }
+
+    Ok(OriginItem {
+        inventory_id,
+        category_id,
+        name,
+    })
+}
+
+/// Register customer gold as stock.
+///
+/// Every incoming piece gets its own product (its weight is what it is) and an
+/// inventory row in `status`, bought at `purchase_price`. Returns the new
+/// inventory id.
+pub async fn receive_gold(
+    conn: &mut SqliteConnection,
+    branch_id: &str,
+    item: &IncomingGoldItem,
+    purchase_price: i32,
+    status: &str,
+    barcode_prefix: &str,
+) -> Result<String, String> {
+    let product_id = uuid::Uuid::new_v4().to_string();
+    let name = item.name.clone().unwrap_or_else(|| {
+        format!(
+            "{} {} {:.2}g",
+            item.gold_type, item.gold_purity, item.weight_gram
+        )
+    });
+
+    sqlx::query(
+        r#"
+        INSERT INTO products (id, category_id, name, gold_type, gold_purity, weight_gram, labor_cost, is_active)
+        VALUES (?, ?, ?, ?, ?, ?, 0, 1)
+        "#,
+    )
+    .bind(&product_id)
+    .bind(&item.category_id)
+    .bind(&name)
+    .bind(&item.gold_type)
+    .bind(item.gold_purity)
+    .bind(item.weight_gram)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let inventory_id = uuid::Uuid::new_v4().to_string();
+    let barcode = format!(
+        "{}-{}",
+        barcode_prefix,
+        uuid::Uuid::new_v4().simple().to_string()[..10].to_uppercase()
+    );
+
+    sqlx::query(
+        r#"
+        INSERT INTO inventory (id, product_id, branch_id, barcode, status, purchase_price, purchase_date, notes)
+        VALUES (?, ?, ?, ?, ?, ?, date('now', 'localtime'), ?)
+        "#,
+    )
+    .bind(&inventory_id)
+    .bind(&product_id)
+    .bind(branch_id)
+    .bind(&barcode)
+    .bind(status)
+    .bind(purchase_price)
+    .bind(&item.notes)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(inventory_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn gold(gold_type: &str, gold_purity: i32) -> IncomingGoldItem {
+        IncomingGoldItem {
+            gold_type: gold_type.to_string(),
+            gold_purity,
+            weight_gram: 3.0,
+            deduction_percent: 0.0,
+            category_id: None,
+            name: None,
+            notes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_origin_requires_sold_matching_item() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+
+        let id = receive_gold(
+            &mut conn,
+            "default",
+            &gold("Lokal", 750),
+            1_000,
+            "available",
+            "EM",
+        )
+        .await
+        .unwrap();
+        let barcode: (String,) = sqlx::query_as("SELECT barcode FROM inventory WHERE id = ?")
+            .bind(&id)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+
+        // Still in stock, so it cannot be coming back from a customer
+        assert!(find_origin(&mut conn, &barcode.0, &gold("Lokal", 750))
+            .await
+            .is_err());
+
+        sqlx::query("UPDATE inventory SET status = 'sold' WHERE id = ?")
+            .bind(&id)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        assert!(find_origin(&mut conn, &barcode.0, &gold("Lokal", 375))
+            .await
+            .is_err());
+        let origin = find_origin(&mut conn, &barcode.0, &gold("Lokal", 750))
+            .await
+            .unwrap();
+        assert_eq!(origin.inventory_id, id);
+        assert_eq!(origin.name, "Lokal 750 3.00g");
+
+        assert!(find_origin(&mut conn, "NOT-OURS", &gold("Lokal", 750))
+            .await
+            .is_err());
+    }
+}
@@ -1,10 +1,18 @@
+mod audit;
 mod commands;
 mod db;
+mod intake;
 mod models;
+mod pricing;
+mod secrets;
 mod salesforce;
+mod session;
 mod sync;
 
 use commands::{DbPool, SyncState};
+use secrets::SecretVault;
+use session::SessionStore;
+use std::sync::Arc;
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -18,14 +26,29 @@ pub fn run() {
                 let pool = db::init_database(&app_handle)
                     .await
                     .expect("Failed to initialize database");
+
+                // Without the key sync stays unconfigured; the owner can
+                // unlock or reset the secrets from the settings page
+                let keyfile = app_handle
+                    .path()
+                    .app_data_dir()
+                    .expect("Failed to get app data directory")
+                    .join("secrets.key");
+                let vault = Arc::new(SecretVault::new(keyfile));
+                if let Err(e) = vault.init(&pool).await {
+                    log::error!("Failed to load the secrets key: {}", e);
+                }
+
+                app_handle.manage(SessionStore::new(pool.clone()));
                 app_handle.manage(DbPool(pool));
-                app_handle.manage(SyncState::new());
+                app_handle.manage(SyncState::new(vault));
             });
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Auth commands
             commands::login,
+            commands::logout,
             commands::get_users,
             commands::create_user,
             commands::change_password,
@@ -56,6 +79,9 @@ pub fn run() {
             commands::get_price_history,
             commands::get_all_prices_for_date,
             commands::get_price_for_calculation,
+            commands::quote_item_price,
+            commands::get_pricing_config,
+            commands::save_pricing_config,
             // Report commands
             commands::get_dashboard_summary,
             commands::get_sales_report,
@@ -69,7 +95,40 @@ pub fn run() {
             commands::manual_sync,
             commands::pull_gold_prices_from_sf,
             commands::pull_inventory_from_sf,
+            commands::pull_transactions_from_sf,
+            commands::reset_pull_watermarks,
             commands::toggle_sync_enabled,
+            commands::get_secret_status,
+            commands::unlock_sync_secrets,
+            commands::set_sync_passphrase,
+            commands::reset_sync_secrets,
+            commands::get_sync_conflicts,
+            commands::resolve_sync_conflict,
+            commands::get_conflict_policies,
+            commands::set_conflict_policy,
+            commands::get_delete_modes,
+            commands::set_delete_mode,
+            commands::get_dead_letters,
+            commands::retry_dead_letter,
+            commands::discard_dead_letter,
+            // Stock opname commands
+            commands::open_stock_opname,
+            commands::record_opname_scan,
+            commands::close_stock_opname,
+            commands::get_stock_opname_sessions,
+            commands::get_stock_opname_results,
+            commands::approve_stock_opname,
+            // Stock transfer commands
+            commands::get_branches,
+            commands::create_stock_transfer,
+            commands::dispatch_stock_transfer,
+            commands::receive_stock_transfer,
+            commands::cancel_stock_transfer,
+            commands::get_stock_transfers,
+            commands::get_transfer_manifest,
+            // Audit log commands
+            commands::get_audit_log,
+            commands::export_audit_log,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
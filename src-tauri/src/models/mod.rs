@@ -132,6 +132,9 @@ pub struct Transaction {
     pub r#type: String, // "sale" | "buyback" | "exchange"
     pub subtotal: i32,
     pub discount: i32,
+    /// Value of customer gold taken in on an exchange
+    pub trade_in_total: i32,
+    /// subtotal - trade_in_total - discount; negative when the shop pays out
     pub total_amount: i32,
     pub notes: Option<String>,
     pub status: String, // "pending" | "completed" | "void"
@@ -157,6 +160,12 @@ pub struct TransactionItem {
     pub unit_price: i32,
     pub subtotal: i32,
     pub gold_price_ref: Option<i32>,
+    pub computed_price: Option<i32>,
+    pub price_status: String, // "computed" | "override" | "flagged"
+    pub direction: String,    // "out" (sold to customer) | "in" (traded in)
+    pub item_condition: Option<String>, // "good" | "damaged" | "scrap", incoming items only
+    /// Inventory row this piece was originally sold as, for buybacks of our own goods
+    pub origin_inventory_id: Option<String>,
     // Joined - skip for sqlx
     #[sqlx(skip)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -187,10 +196,15 @@ pub struct SyncLog {
     pub synced_at: Option<String>,
     pub error_message: Option<String>,
     pub retry_count: i32,
+    pub next_retry_at: Option<String>,
+    pub dead_lettered_at: Option<String>,
     pub created_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+/// Sync settings as stored. The secrets are ciphertext; use
+/// `SecretVault::decrypt_config` to get them in plaintext. Never sent to the
+/// frontend, see `SyncConfigResponse`.
+#[derive(Debug, Clone, FromRow)]
 pub struct SyncConfig {
     pub id: String,
     pub sf_client_id: Option<String>,
@@ -198,14 +212,197 @@ pub struct SyncConfig {
     pub sf_username: Option<String>,
     pub sf_password: Option<String>,
     pub sf_security_token: Option<String>,
+    pub sf_auth_flow: String, // "password" | "jwt_bearer"
+    pub sf_jwt_private_key: Option<String>,
+    pub sf_jwt_audience: Option<String>,
     pub sf_instance_url: Option<String>,
     pub is_sandbox: bool,
     pub sync_enabled: bool,
     pub sync_interval_minutes: i32,
+    /// Records above which sync uses the Bulk API; `None` for the default
+    pub bulk_threshold: Option<i32>,
+    /// Percent of the daily API limit past which optional pulls are
+    /// skipped; `None` for the default
+    pub api_usage_threshold: Option<i32>,
     pub created_at: String,
     pub updated_at: Option<String>,
 }
 
+/// Sync settings for the frontend: secrets are reduced to whether they are set
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConfigResponse {
+    pub id: String,
+    pub sf_client_id: Option<String>,
+    pub sf_username: Option<String>,
+    pub sf_auth_flow: String,
+    pub sf_jwt_audience: Option<String>,
+    pub sf_instance_url: Option<String>,
+    pub is_sandbox: bool,
+    pub sync_enabled: bool,
+    pub sync_interval_minutes: i32,
+    pub bulk_threshold: Option<i32>,
+    pub api_usage_threshold: Option<i32>,
+    pub has_client_secret: bool,
+    pub has_password: bool,
+    pub has_security_token: bool,
+    pub has_jwt_private_key: bool,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+impl From<&SyncConfig> for SyncConfigResponse {
+    fn from(config: &SyncConfig) -> Self {
+        let is_set = |secret: &Option<String>| secret.as_deref().is_some_and(|s| !s.is_empty());
+
+        Self {
+            id: config.id.clone(),
+            sf_client_id: config.sf_client_id.clone(),
+            sf_username: config.sf_username.clone(),
+            sf_auth_flow: config.sf_auth_flow.clone(),
+            sf_jwt_audience: config.sf_jwt_audience.clone(),
+            sf_instance_url: config.sf_instance_url.clone(),
+            is_sandbox: config.is_sandbox,
+            sync_enabled: config.sync_enabled,
+            sync_interval_minutes: config.sync_interval_minutes,
+            bulk_threshold: config.bulk_threshold,
+            api_usage_threshold: config.api_usage_threshold,
+            has_client_secret: is_set(&config.sf_client_secret),
+            has_password: is_set(&config.sf_password),
+            has_security_token: is_set(&config.sf_security_token),
+            has_jwt_private_key: is_set(&config.sf_jwt_private_key),
+            created_at: config.created_at.clone(),
+            updated_at: config.updated_at.clone(),
+        }
+    }
+}
+
+/// Whether stored secrets can be read right now
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretStatus {
+    pub mode: String, // "keyfile" | "passphrase"
+    pub unlocked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PricingConfig {
+    pub id: String,
+    pub override_tolerance_percent: f64,
+    pub override_policy: String, // "reject" | "flag"
+    pub updated_at: Option<String>,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            id: "default".to_string(),
+            override_tolerance_percent: 2.0,
+            override_policy: "reject".to_string(),
+            updated_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StockOpnameSession {
+    pub id: String,
+    pub branch_id: String,
+    /// Location being counted; `None` counts the whole branch
+    pub location: Option<String>,
+    pub status: String, // "open" | "closed" | "approved"
+    pub notes: Option<String>,
+    pub opened_by: String,
+    pub opened_at: String,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<String>,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StockOpnameScan {
+    pub id: String,
+    pub session_id: String,
+    pub barcode: String,
+    pub inventory_id: Option<String>,
+    pub location: Option<String>,
+    pub scanned_by: String,
+    pub scanned_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StockOpnameResult {
+    pub id: String,
+    pub session_id: String,
+    pub kind: String, // "missing" | "unexpected" | "wrong_location" | "sold_but_scanned" | "found"
+    pub barcode: String,
+    pub inventory_id: Option<String>,
+    pub status_before: Option<String>,
+    pub expected_location: Option<String>,
+    pub scanned_location: Option<String>,
+    pub resolution: Option<String>, // "adjusted" | "ignored"
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpnameScanResponse {
+    pub scan: StockOpnameScan,
+    /// The barcode was already scanned in this session
+    pub duplicate: bool,
+    pub inventory: Option<Inventory>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StockTransfer {
+    pub id: String,
+    pub transfer_no: String,
+    pub from_branch_id: String,
+    pub to_branch_id: String,
+    pub status: String, // "draft" | "in_transit" | "received" | "cancelled"
+    pub notes: Option<String>,
+    pub created_by: String,
+    pub created_at: String,
+    pub dispatched_by: Option<String>,
+    pub dispatched_at: Option<String>,
+    pub received_by: Option<String>,
+    pub received_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TransferManifestItem {
+    pub inventory_id: String,
+    pub barcode: String,
+    pub product_name: String,
+    pub gold_type: String,
+    pub gold_purity: i32,
+    pub weight_gram: f64,
+    pub purchase_price: i32,
+}
+
+/// Printable list of what a transfer carries
+#[derive(Debug, Serialize)]
+pub struct TransferManifest {
+    pub transfer: StockTransfer,
+    pub from_branch_name: String,
+    pub to_branch_name: String,
+    pub items: Vec<TransferManifestItem>,
+    pub total_weight: f64,
+    pub total_value: i64,
+}
+
+/// One recorded change to a sensitive record; snapshots are JSON strings
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: String,
+    pub actor_id: String,
+    pub actor_username: String,
+    pub branch_id: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub created_at: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct SyncMetadata {
     pub table_name: String,
@@ -214,6 +411,10 @@ pub struct SyncMetadata {
     pub last_full_sync_at: Option<String>,
     pub records_pulled: i32,
     pub records_pushed: i32,
+    pub pull_watermark: Option<String>,
+    /// API requests the last pull and push of the table took
+    pub pull_requests: i32,
+    pub push_requests: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -222,7 +423,33 @@ pub struct SyncStatus {
     pub sync_enabled: bool,
     pub last_sync_at: Option<String>,
     pub pending_changes: i32,
+    /// Changes that ran out of retries and wait for the owner
+    pub dead_letters: i32,
     pub error_message: Option<String>,
+    /// Kind of the last error, from `SyncError::code`
+    pub error_code: Option<String>,
+    /// As of the last response from Salesforce; `None` before any request
+    pub api_usage: Option<ApiUsage>,
+    /// Usage is past the configured threshold, so optional pulls are skipped
+    pub api_limit_reached: bool,
+}
+
+/// API requests made in the org today, against its daily limit, as
+/// Salesforce reports them with every response
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ApiUsage {
+    pub used: u32,
+    pub limit: u32,
+}
+
+impl ApiUsage {
+    /// Share of the limit used, in percent
+    pub fn percent(&self) -> u32 {
+        if self.limit == 0 {
+            return 100;
+        }
+        (u64::from(self.used) * 100 / u64::from(self.limit)) as u32
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -234,6 +461,42 @@ pub struct SyncResult {
     pub completed_at: String,
 }
 
+/// A record edited both locally and in Salesforce since the last sync, held
+/// back from sync until the owner picks a side. The JSON columns use
+/// Salesforce field names.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SyncConflict {
+    pub id: String,
+    pub table_name: String,
+    pub record_id: String,
+    pub salesforce_id: Option<String>,
+    pub local_json: String,
+    pub remote_json: String,
+    pub base_json: Option<String>,
+    pub remote_modstamp: Option<String>,
+    pub local_changed_at: Option<String>,
+    pub conflicting_fields: String, // JSON array of field names
+    pub status: String,             // "open" | "resolved"
+    pub resolution: Option<String>, // "local" | "server"
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SyncConflictPolicy {
+    pub table_name: String,
+    pub policy: String, // "server_wins" | "local_wins" | "merge" | "manual"
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SyncDeleteMode {
+    pub table_name: String,
+    pub mode: String, // "deactivate" | "delete"
+    pub updated_at: Option<String>,
+}
+
 // Request/Response types
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
@@ -264,6 +527,12 @@ pub struct CreateTransactionRequest {
     pub customer_id: Option<String>,
     pub r#type: String,
     pub items: Vec<CreateTransactionItem>,
+    /// Customer gold handed over on an exchange
+    #[serde(default)]
+    pub trade_ins: Vec<IncomingGoldItem>,
+    /// Gold bought on a buyback
+    #[serde(default)]
+    pub buyback_items: Vec<BuybackItem>,
     pub discount: Option<i32>,
     pub notes: Option<String>,
 }
@@ -271,7 +540,35 @@ pub struct CreateTransactionRequest {
 #[derive(Debug, Deserialize)]
 pub struct CreateTransactionItem {
     pub inventory_id: String,
-    pub unit_price: i32,
+    /// Manual price override; computed from today's gold price when omitted
+    pub unit_price: Option<i32>,
+}
+
+/// Gold received from a customer, valued at today's buy price
+#[derive(Debug, Clone, Deserialize)]
+pub struct IncomingGoldItem {
+    pub gold_type: String,
+    pub gold_purity: i32,
+    pub weight_gram: f64,
+    /// Percentage knocked off the gold value for wear or impurities
+    #[serde(default)]
+    pub deduction_percent: f64,
+    pub category_id: Option<String>,
+    pub name: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// A line of a buyback: gold bought from a walk-in customer
+#[derive(Debug, Deserialize)]
+pub struct BuybackItem {
+    #[serde(flatten)]
+    pub gold: IncomingGoldItem,
+    /// "good" goes back on sale as is; "damaged" and "scrap" are reprocessed
+    pub condition: String,
+    /// Barcode of the piece if this shop originally sold it
+    pub barcode: Option<String>,
+    /// Manual price override for the line; computed from the buy price when omitted
+    pub unit_price: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -283,6 +580,24 @@ pub struct ProcessPaymentRequest {
     pub bank_name: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateStockTransferRequest {
+    pub to_branch_id: String,
+    pub barcodes: Vec<String>,
+    pub notes: Option<String>,
+}
+
+/// Audit log query; every field narrows the result
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditLogFilter {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SetGoldPriceRequest {
     pub gold_type: String,
@@ -291,17 +606,35 @@ pub struct SetGoldPriceRequest {
     pub sell_price: i32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SavePricingConfigRequest {
+    pub override_tolerance_percent: f64,
+    pub override_policy: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SaveSyncConfigRequest {
     pub sf_client_id: String,
-    pub sf_client_secret: String,
     pub sf_username: String,
-    pub sf_password: String,
-    pub sf_security_token: String,
+    // Secrets are write-only: leave one out to keep the stored value
+    pub sf_client_secret: Option<String>,
+    pub sf_password: Option<String>,
+    pub sf_security_token: Option<String>,
+    /// "password" (default) or "jwt_bearer"
+    pub sf_auth_flow: Option<String>,
+    /// PEM private key of the connected app certificate, write-only too
+    pub sf_jwt_private_key: Option<String>,
+    pub sf_jwt_audience: Option<String>,
     pub sf_instance_url: Option<String>,
     pub is_sandbox: bool,
     pub sync_enabled: bool,
     pub sync_interval_minutes: i32,
+    /// Leave out to use the default
+    #[serde(default)]
+    pub bulk_threshold: Option<i32>,
+    /// Leave out to use the default
+    #[serde(default)]
+    pub api_usage_threshold: Option<i32>,
 }
 
 // Dashboard summary types
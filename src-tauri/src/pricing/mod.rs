@@ -0,0 +1,243 @@
+use crate::models::{IncomingGoldItem, PricingConfig};
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+/// Which side of the daily gold price a line item is valued at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSide {
+    /// Shop sells to customer: `sell_price` per gram plus labor cost
+    Sell,
+    /// Shop buys from customer: `buy_price` per gram, labor is not refunded
+    Buy,
+}
+
+impl PriceSide {
+    pub fn for_transaction_type(transaction_type: &str) -> Option<Self> {
+        match transaction_type {
+            "sale" | "exchange" => Some(PriceSide::Sell),
+            "buyback" => Some(PriceSide::Buy),
+            _ => None,
+        }
+    }
+}
+
+/// Server-computed price for a single inventory item
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceQuote {
+    pub inventory_id: String,
+    pub gold_type: String,
+    pub gold_purity: i32,
+    pub weight_gram: f64,
+    pub labor_cost: i32,
+    /// Per-gram price taken from `gold_prices` (sell or buy side)
+    pub gold_price_ref: i32,
+    pub computed_price: i32,
+}
+
+/// Final price of a line item after applying any manual override
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPrice {
+    pub unit_price: i32,
+    /// "computed" | "override" | "flagged"
+    pub price_status: &'static str,
+}
+
+/// Price of `weight_gram` grams at `price_per_gram`, rounded to the nearest rupiah
+pub fn compute_price(weight_gram: f64, price_per_gram: i32, labor_cost: i32, side: PriceSide) -> i32 {
+    let gold_value = (weight_gram * price_per_gram as f64).round() as i32;
+    match side {
+        PriceSide::Sell => gold_value + labor_cost,
+        PriceSide::Buy => gold_value,
+    }
+}
+
+/// Value of customer gold at the buy price, less a deduction for wear,
+/// soldering or impurities, rounded to the nearest rupiah
+pub fn compute_trade_in_value(weight_gram: f64, buy_price_per_gram: i32, deduction_percent: f64) -> i32 {
+    let gold_value = weight_gram * buy_price_per_gram as f64;
+    (gold_value * (1.0 - deduction_percent / 100.0)).round() as i32
+}
+
+/// Check whether `requested` deviates from `computed` by at most `tolerance_percent`
+pub fn within_tolerance(computed: i32, requested: i32, tolerance_percent: f64) -> bool {
+    let deviation = (requested as f64 - computed as f64).abs();
+    deviation <= computed.abs() as f64 * tolerance_percent / 100.0
+}
+
+/// Apply a caller-supplied unit price on top of the computed quote.
+///
+/// Prices inside the configured tolerance are accepted as overrides. Prices
+/// outside it are rejected, or accepted but flagged when the policy is `flag`.
+pub fn resolve_unit_price(
+    computed_price: i32,
+    requested: Option<i32>,
+    config: &PricingConfig,
+) -> Result<ResolvedPrice, String> {
+    let requested = match requested {
+        Some(price) if price != computed_price => price,
+        _ => {
+            return Ok(ResolvedPrice {
+                unit_price: computed_price,
+                price_status: "computed",
+            })
+        }
+    };
+
+    if within_tolerance(computed_price, requested, config.override_tolerance_percent) {
+        return Ok(ResolvedPrice {
+            unit_price: requested,
+            price_status: "override",
+        });
+    }
+
+    if config.override_policy == "flag" {
+        Ok(ResolvedPrice {
+            unit_price: requested,
+            price_status: "flagged",
+        })
+    } else {
+        Err(format!(
+            "Price {} deviates from computed price {} by more than {}%",
+            requested, computed_price, config.override_tolerance_percent
+        ))
+    }
+}
+
+/// Quote an inventory item against the gold price of `date` (YYYY-MM-DD)
+pub async fn quote_inventory_item(
+    conn: &mut SqliteConnection,
+    inventory_id: &str,
+    side: PriceSide,
+    date: &str,
+) -> Result<PriceQuote, String> {
+    let product: Option<(String, i32, f64, i32)> = sqlx::query_as(
+        r#"
+        SELECT p.gold_type, p.gold_purity, p.weight_gram, p.labor_cost
+        FROM inventory i
+        JOIN products p ON i.product_id = p.id
+        WHERE i.id = ?
+        "#,
+    )
+    .bind(inventory_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let (gold_type, gold_purity, weight_gram, labor_cost) =
+        product.ok_or_else(|| format!("Inventory {} not found", inventory_id))?;
+
+    let gold_price_ref = get_price_per_gram(conn, &gold_type, gold_purity, side, date).await?;
+
+    Ok(PriceQuote {
+        inventory_id: inventory_id.to_string(),
+        computed_price: compute_price(weight_gram, gold_price_ref, labor_cost, side),
+        gold_type,
+        gold_purity,
+        weight_gram,
+        labor_cost,
+        gold_price_ref,
+    })
+}
+
+/// Value customer gold at the buy price of `date` (YYYY-MM-DD).
+/// Returns the per-gram price used and the value after deduction.
+pub async fn quote_incoming_gold(
+    conn: &mut SqliteConnection,
+    item: &IncomingGoldItem,
+    date: &str,
+) -> Result<(i32, i32), String> {
+    let buy_price =
+        get_price_per_gram(conn, &item.gold_type, item.gold_purity, PriceSide::Buy, date).await?;
+    let value = compute_trade_in_value(item.weight_gram, buy_price, item.deduction_percent);
+    Ok((buy_price, value))
+}
+
+/// Look up the per-gram buy or sell price for a gold type and purity on `date`
+pub async fn get_price_per_gram(
+    conn: &mut SqliteConnection,
+    gold_type: &str,
+    purity: i32,
+    side: PriceSide,
+    date: &str,
+) -> Result<i32, String> {
+    let price: Option<(i32, i32)> = sqlx::query_as(
+        "SELECT buy_price, sell_price FROM gold_prices WHERE date = ? AND gold_type = ? AND purity = ?",
+    )
+    .bind(date)
+    .bind(gold_type)
+    .bind(purity)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let (buy_price, sell_price) = price.ok_or_else(|| {
+        format!("No gold price set for {} {} on {}", gold_type, purity, date)
+    })?;
+
+    Ok(match side {
+        PriceSide::Sell => sell_price,
+        PriceSide::Buy => buy_price,
+    })
+}
+
+/// Load pricing settings, falling back to defaults when none are saved
+pub async fn get_config(conn: &mut SqliteConnection) -> Result<PricingConfig, String> {
+    let config: Option<PricingConfig> = sqlx::query_as(
+        "SELECT id, override_tolerance_percent, override_policy, updated_at FROM pricing_config WHERE id = 'default'",
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(config.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(policy: &str) -> PricingConfig {
+        PricingConfig {
+            override_policy: policy.to_string(),
+            ..PricingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_compute_price_sell_includes_labor() {
+        assert_eq!(compute_price(2.5, 1_250_000, 150_000, PriceSide::Sell), 3_275_000);
+    }
+
+    #[test]
+    fn test_compute_price_buy_excludes_labor() {
+        assert_eq!(compute_price(2.5, 1_150_000, 150_000, PriceSide::Buy), 2_875_000);
+        assert_eq!(compute_price(1.333, 1_000_001, 0, PriceSide::Buy), 1_333_001);
+    }
+
+    #[test]
+    fn test_compute_trade_in_value_applies_deduction() {
+        assert_eq!(compute_trade_in_value(5.0, 950_000, 0.0), 4_750_000);
+        assert_eq!(compute_trade_in_value(5.0, 950_000, 10.0), 4_275_000);
+        assert_eq!(compute_trade_in_value(5.0, 950_000, 100.0), 0);
+    }
+
+    #[test]
+    fn test_resolve_unit_price() {
+        let reject = config("reject");
+        let flag = config("flag");
+
+        let computed = resolve_unit_price(1_000_000, None, &reject).unwrap();
+        assert_eq!(computed.price_status, "computed");
+        assert_eq!(computed.unit_price, 1_000_000);
+
+        let within = resolve_unit_price(1_000_000, Some(990_000), &reject).unwrap();
+        assert_eq!(within.price_status, "override");
+        assert_eq!(within.unit_price, 990_000);
+
+        assert!(resolve_unit_price(1_000_000, Some(900_000), &reject).is_err());
+
+        let flagged = resolve_unit_price(1_000_000, Some(900_000), &flag).unwrap();
+        assert_eq!(flagged.price_status, "flagged");
+        assert_eq!(flagged.unit_price, 900_000);
+    }
+}
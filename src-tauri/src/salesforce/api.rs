@@ -1,5 +1,13 @@
-use super::client::{SalesforceClient, SaveResult};
+use super::bulk::{BulkClient, IngestResult, DEFAULT_BULK_THRESHOLD};
+use super::client::{QueryResult, SalesforceClient, SaveResult};
+use super::soql::Soql;
+use crate::models::ApiUsage;
+use crate::sync::SyncError;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// Salesforce Branch__c record
@@ -17,6 +25,8 @@ pub struct SfBranch {
     pub phone: Option<String>,
     #[serde(rename = "Is_Active__c")]
     pub is_active: bool,
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Product__c record
@@ -40,6 +50,9 @@ pub struct SfProduct {
     pub labor_cost: i32,
     #[serde(rename = "Is_Active__c")]
     pub is_active: bool,
+    /// Salesforce's last change to the record; read only
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Inventory__c record
@@ -69,6 +82,9 @@ pub struct SfInventory {
     pub notes: Option<String>,
     #[serde(rename = "Sold_At__c", skip_serializing_if = "Option::is_none")]
     pub sold_at: Option<String>,
+    /// Salesforce's last change to the record; read only
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Gold_Price__c record
@@ -90,6 +106,9 @@ pub struct SfGoldPrice {
     pub sell_price: i32,
     #[serde(rename = "Source__c", skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    /// Salesforce's last change to the record; read only
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Customer__c record
@@ -109,6 +128,9 @@ pub struct SfCustomer {
     pub notes: Option<String>,
     #[serde(rename = "Total_Transactions__c")]
     pub total_transactions: i32,
+    /// Salesforce's last change to the record; read only
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Transaction__c record
@@ -130,6 +152,9 @@ pub struct SfTransaction {
     pub subtotal: i32,
     #[serde(rename = "Discount__c")]
     pub discount: i32,
+    /// Value of the gold the customer traded in, on an exchange
+    #[serde(rename = "Trade_In_Total__c", default)]
+    pub trade_in_total: i32,
     #[serde(rename = "Total_Amount__c")]
     pub total_amount: i32,
     #[serde(rename = "Notes__c", skip_serializing_if = "Option::is_none")]
@@ -138,6 +163,9 @@ pub struct SfTransaction {
     pub status: String,
     #[serde(rename = "Created_At__c", skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
+    /// Salesforce's last change to the record; read only
+    #[serde(rename = "SystemModstamp", default, skip_serializing)]
+    pub system_modstamp: Option<String>,
 }
 
 /// Salesforce Transaction_Item__c record
@@ -147,6 +175,10 @@ pub struct SfTransactionItem {
     pub id: Option<String>,
     #[serde(rename = "Name", skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Local id, so a retried push updates the record instead of duplicating it.
+    /// Not queried on pull: items created in Salesforce have none
+    #[serde(rename = "External_Id__c", default)]
+    pub external_id: String,
     #[serde(rename = "Transaction__c")]
     pub transaction_id: String,
     #[serde(rename = "Inventory__c")]
@@ -157,45 +189,140 @@ pub struct SfTransactionItem {
     pub unit_price: i32,
     #[serde(rename = "Subtotal__c")]
     pub subtotal: i32,
+    /// "out" for gold sold to the customer, "in" for gold taken in
+    #[serde(rename = "Direction__c", default = "direction_out")]
+    pub direction: String,
+    #[serde(rename = "Item_Condition__c", skip_serializing_if = "Option::is_none")]
+    pub item_condition: Option<String>,
+    /// Inventory record a bought-back piece was first sold as
+    #[serde(rename = "Origin_Inventory__c", skip_serializing_if = "Option::is_none")]
+    pub origin_inventory_id: Option<String>,
+}
+
+/// Items recorded before directions were synced were all sales
+fn direction_out() -> String {
+    "out".to_string()
+}
+
+/// Salesforce Payment__c record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SfPayment {
+    #[serde(rename = "Id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Local id, so a retried push updates the record instead of duplicating it
+    #[serde(rename = "External_Id__c")]
+    pub external_id: String,
+    #[serde(rename = "Transaction__c")]
+    pub transaction_id: String,
+    #[serde(rename = "Method__c")]
+    pub method: String,
+    #[serde(rename = "Amount__c")]
+    pub amount: i32,
+    #[serde(rename = "Reference_No__c", skip_serializing_if = "Option::is_none")]
+    pub reference_no: Option<String>,
+    #[serde(rename = "Bank_Name__c", skip_serializing_if = "Option::is_none")]
+    pub bank_name: Option<String>,
+    #[serde(rename = "Status__c")]
+    pub status: String,
+    #[serde(rename = "Paid_At__c", skip_serializing_if = "Option::is_none")]
+    pub paid_at: Option<String>,
+}
+
+/// A record in the Salesforce recycle bin
+#[derive(Debug, Clone, Deserialize)]
+pub struct SfDeleted {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "SystemModstamp", default)]
+    pub system_modstamp: Option<String>,
 }
 
 /// High-level Salesforce API operations
 pub struct SalesforceApi {
     client: Arc<SalesforceClient>,
+    bulk: BulkClient,
+    bulk_threshold: AtomicUsize,
 }
 
 impl SalesforceApi {
     pub fn new(client: Arc<SalesforceClient>) -> Self {
-        Self { client }
+        Self {
+            bulk: BulkClient::new(client.clone()),
+            client,
+            bulk_threshold: AtomicUsize::new(DEFAULT_BULK_THRESHOLD),
+        }
     }
 
-    // ==================== Branch Operations ====================
+    /// Records above which queries and pushes go through the Bulk API
+    pub fn bulk_threshold(&self) -> usize {
+        self.bulk_threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_bulk_threshold(&self, threshold: usize) {
+        self.bulk_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// The org's daily API usage as Salesforce last reported it
+    pub fn api_usage(&self) -> Option<ApiUsage> {
+        self.client.api_usage()
+    }
 
-    pub async fn get_branches(&self, last_modified_since: Option<&str>) -> Result<Vec<SfBranch>, String> {
-        let mut soql = "SELECT Id, Name, Code__c, Address__c, Phone__c, Is_Active__c FROM Branch__c".to_string();
-        if let Some(since) = last_modified_since {
-            soql.push_str(&format!(" WHERE LastModifiedDate > {}", since));
+    /// API requests sent so far; see [`SalesforceClient::request_count`]
+    pub fn request_count(&self) -> u64 {
+        self.client.request_count()
+    }
+
+    /// Run a query built by `changed_since`, oldest change first. One that
+    /// matches more records than the bulk threshold is run as a Bulk API
+    /// job instead, unordered.
+    async fn select<T: DeserializeOwned + Clone>(&self, query: Soql) -> Result<Vec<T>, SyncError> {
+        let first: QueryResult<T> = self
+            .client
+            .query(&query.clone().order_by("SystemModstamp").to_string())
+            .await?;
+        if !first.done && first.total_size as usize > self.bulk_threshold() {
+            return self.bulk.query(&query.to_string()).await;
         }
-        self.client.query_all(&soql).await
+        self.client.remaining_pages(first).await
+    }
+
+    /// Upsert many records in one Bulk API job
+    pub async fn bulk_upsert(
+        &self,
+        sobject: &str,
+        external_id_field: &str,
+        records: &[serde_json::Value],
+    ) -> Result<IngestResult, SyncError> {
+        self.bulk.upsert(sobject, external_id_field, records).await
+    }
+
+    // ==================== Branch Operations ====================
+
+    pub async fn get_branches(&self, modified_since: Option<&str>) -> Result<Vec<SfBranch>, SyncError> {
+        let query = Soql::new(
+            "Branch__c",
+            &["Id", "Name", "Code__c", "Address__c", "Phone__c", "Is_Active__c", "SystemModstamp"],
+        );
+        self.select(changed_since(query, modified_since)?).await
     }
 
-    pub async fn upsert_branch(&self, branch: &SfBranch) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(branch).map_err(|e| e.to_string())?;
+    pub async fn upsert_branch(&self, branch: &SfBranch) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(branch)?;
         self.client.upsert("Branch__c", "Code__c", &branch.code, &data).await
     }
 
     // ==================== Product Operations ====================
 
-    pub async fn get_products(&self, last_modified_since: Option<&str>) -> Result<Vec<SfProduct>, String> {
-        let mut soql = "SELECT Id, Name, SKU__c, Description__c, Gold_Type__c, Gold_Purity__c, Weight_Gram__c, Labor_Cost__c, Is_Active__c FROM Product__c".to_string();
-        if let Some(since) = last_modified_since {
-            soql.push_str(&format!(" WHERE LastModifiedDate > {}", since));
-        }
-        self.client.query_all(&soql).await
+    pub async fn get_products(&self, modified_since: Option<&str>) -> Result<Vec<SfProduct>, SyncError> {
+        let query = Soql::new(
+            "Product__c",
+            &["Id", "Name", "SKU__c", "Description__c", "Gold_Type__c", "Gold_Purity__c", "Weight_Gram__c", "Labor_Cost__c", "Is_Active__c", "SystemModstamp"],
+        );
+        self.select(changed_since(query, modified_since)?).await
     }
 
-    pub async fn upsert_product(&self, product: &SfProduct) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(product).map_err(|e| e.to_string())?;
+    pub async fn upsert_product(&self, product: &SfProduct) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(product)?;
         if let Some(sku) = &product.sku {
             self.client.upsert("Product__c", "SKU__c", sku, &data).await
         } else {
@@ -205,31 +332,23 @@ impl SalesforceApi {
 
     // ==================== Inventory Operations ====================
 
-    pub async fn get_inventory(&self, last_modified_since: Option<&str>, branch_sf_id: Option<&str>) -> Result<Vec<SfInventory>, String> {
-        let mut soql = "SELECT Id, Name, Barcode__c, Product__c, Branch__c, Status__c, Location__c, Purchase_Price__c, Purchase_Date__c, Supplier__c, Notes__c, Sold_At__c FROM Inventory__c".to_string();
-
-        let mut conditions = Vec::new();
-        if let Some(since) = last_modified_since {
-            conditions.push(format!("LastModifiedDate > {}", since));
-        }
+    pub async fn get_inventory(&self, modified_since: Option<&str>, branch_sf_id: Option<&str>) -> Result<Vec<SfInventory>, SyncError> {
+        let mut query = Soql::new(
+            "Inventory__c",
+            &["Id", "Name", "Barcode__c", "Product__c", "Branch__c", "Status__c", "Location__c", "Purchase_Price__c", "Purchase_Date__c", "Supplier__c", "Notes__c", "Sold_At__c", "SystemModstamp"],
+        );
         if let Some(branch_id) = branch_sf_id {
-            conditions.push(format!("Branch__c = '{}'", branch_id));
+            query = query.where_eq("Branch__c", branch_id);
         }
-
-        if !conditions.is_empty() {
-            soql.push_str(" WHERE ");
-            soql.push_str(&conditions.join(" AND "));
-        }
-
-        self.client.query_all(&soql).await
+        self.select(changed_since(query, modified_since)?).await
     }
 
-    pub async fn upsert_inventory(&self, inventory: &SfInventory) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(inventory).map_err(|e| e.to_string())?;
+    pub async fn upsert_inventory(&self, inventory: &SfInventory) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(inventory)?;
         self.client.upsert("Inventory__c", "Barcode__c", &inventory.barcode, &data).await
     }
 
-    pub async fn batch_upsert_inventory(&self, items: Vec<SfInventory>) -> Result<Vec<Result<SaveResult, String>>, String> {
+    pub async fn batch_upsert_inventory(&self, items: Vec<SfInventory>) -> Result<Vec<Result<SaveResult, SyncError>>, SyncError> {
         let records: Vec<(String, serde_json::Value)> = items
             .into_iter()
             .filter_map(|item| {
@@ -244,20 +363,16 @@ impl SalesforceApi {
 
     // ==================== Gold Price Operations ====================
 
-    pub async fn get_gold_prices(&self, date: Option<&str>) -> Result<Vec<SfGoldPrice>, String> {
-        let soql = if let Some(d) = date {
-            format!(
-                "SELECT Id, Name, Date__c, Gold_Type__c, Purity__c, Buy_Price__c, Sell_Price__c, Source__c FROM Gold_Price__c WHERE Date__c = {}",
-                d
-            )
-        } else {
-            "SELECT Id, Name, Date__c, Gold_Type__c, Purity__c, Buy_Price__c, Sell_Price__c, Source__c FROM Gold_Price__c ORDER BY Date__c DESC LIMIT 50".to_string()
-        };
-        self.client.query_all(&soql).await
+    pub async fn get_gold_prices(&self, modified_since: Option<&str>) -> Result<Vec<SfGoldPrice>, SyncError> {
+        let query = Soql::new(
+            "Gold_Price__c",
+            &["Id", "Name", "Date__c", "Gold_Type__c", "Purity__c", "Buy_Price__c", "Sell_Price__c", "Source__c", "SystemModstamp"],
+        );
+        self.select(changed_since(query, modified_since)?).await
     }
 
-    pub async fn upsert_gold_price(&self, price: &SfGoldPrice) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(price).map_err(|e| e.to_string())?;
+    pub async fn upsert_gold_price(&self, price: &SfGoldPrice) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(price)?;
         // Create unique identifier for upsert: date_goldtype_purity
         let external_id = format!("{}_{}_{}",
             price.date.replace("-", ""),
@@ -270,16 +385,16 @@ impl SalesforceApi {
 
     // ==================== Customer Operations ====================
 
-    pub async fn get_customers(&self, last_modified_since: Option<&str>) -> Result<Vec<SfCustomer>, String> {
-        let mut soql = "SELECT Id, Name, Phone__c, NIK__c, Address__c, Notes__c, Total_Transactions__c FROM Customer__c".to_string();
-        if let Some(since) = last_modified_since {
-            soql.push_str(&format!(" WHERE LastModifiedDate > {}", since));
-        }
-        self.client.query_all(&soql).await
+    pub async fn get_customers(&self, modified_since: Option<&str>) -> Result<Vec<SfCustomer>, SyncError> {
+        let query = Soql::new(
+            "Customer__c",
+            &["Id", "Name", "Phone__c", "NIK__c", "Address__c", "Notes__c", "Total_Transactions__c", "SystemModstamp"],
+        );
+        self.select(changed_since(query, modified_since)?).await
     }
 
-    pub async fn upsert_customer(&self, customer: &SfCustomer) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(customer).map_err(|e| e.to_string())?;
+    pub async fn upsert_customer(&self, customer: &SfCustomer) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(customer)?;
         if let Some(phone) = &customer.phone {
             self.client.upsert("Customer__c", "Phone__c", phone, &data).await
         } else {
@@ -289,41 +404,162 @@ impl SalesforceApi {
 
     // ==================== Transaction Operations ====================
 
-    pub async fn get_transactions(&self, last_modified_since: Option<&str>, branch_sf_id: Option<&str>) -> Result<Vec<SfTransaction>, String> {
-        let mut soql = "SELECT Id, Name, Invoice_Number__c, Branch__c, Customer__c, Type__c, Subtotal__c, Discount__c, Total_Amount__c, Notes__c, Status__c, Created_At__c FROM Transaction__c".to_string();
+    pub async fn get_transactions(&self, modified_since: Option<&str>, branch_sf_id: Option<&str>) -> Result<Vec<SfTransaction>, SyncError> {
+        let mut query = Soql::new(
+            "Transaction__c",
+            &["Id", "Name", "Invoice_Number__c", "Branch__c", "Customer__c", "Type__c", "Subtotal__c", "Discount__c", "Trade_In_Total__c", "Total_Amount__c", "Notes__c", "Status__c", "Created_At__c", "SystemModstamp"],
+        );
+        if let Some(branch_id) = branch_sf_id {
+            query = query.where_eq("Branch__c", branch_id);
+        }
+        self.select(changed_since(query, modified_since)?).await
+    }
+
+    pub async fn upsert_transaction(&self, transaction: &SfTransaction) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(transaction)?;
+        self.client.upsert("Transaction__c", "Invoice_Number__c", &transaction.invoice_no, &data).await
+    }
+
+    pub async fn get_transaction_items(&self, transaction_sf_id: &str) -> Result<Vec<SfTransactionItem>, SyncError> {
+        let query = Soql::new("Transaction_Item__c", TRANSACTION_ITEM_FIELDS).where_eq("Transaction__c", transaction_sf_id);
+        self.client.query_all(&query.to_string()).await
+    }
 
-        let mut conditions = Vec::new();
-        if let Some(since) = last_modified_since {
-            conditions.push(format!("LastModifiedDate > {}", since));
+    /// Line items of all the given transactions
+    pub async fn get_items_for_transactions(&self, transaction_sf_ids: &[String]) -> Result<Vec<SfTransactionItem>, SyncError> {
+        let mut items = Vec::new();
+        // Keep the IN list well under the SOQL length limit
+        for chunk in transaction_sf_ids.chunks(200) {
+            let query = Soql::new("Transaction_Item__c", TRANSACTION_ITEM_FIELDS).where_in("Transaction__c", chunk);
+            items.extend(self.client.query_all::<SfTransactionItem>(&query.to_string()).await?);
         }
+        Ok(items)
+    }
+
+    pub async fn create_transaction_item(&self, item: &SfTransactionItem) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(item)?;
+        self.client.create("Transaction_Item__c", &data).await
+    }
+
+    pub async fn upsert_transaction_item(&self, item: &SfTransactionItem) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(item)?;
+        self.client.upsert("Transaction_Item__c", "External_Id__c", &item.external_id, &data).await
+    }
+
+    // ==================== Payment Operations ====================
+
+    pub async fn upsert_payment(&self, payment: &SfPayment) -> Result<SaveResult, SyncError> {
+        let data = serde_json::to_value(payment)?;
+        self.client.upsert("Payment__c", "External_Id__c", &payment.external_id, &data).await
+    }
+
+    // ==================== Deletions ====================
+
+    /// Records of `sobject` deleted at or after `modified_since`, as long
+    /// as Salesforce still has them in its recycle bin
+    pub async fn get_deleted(
+        &self,
+        sobject: &str,
+        modified_since: Option<&str>,
+        branch_sf_id: Option<&str>,
+    ) -> Result<Vec<SfDeleted>, SyncError> {
+        let mut query = Soql::new(sobject, &["Id", "SystemModstamp"]).where_eq("IsDeleted", true);
         if let Some(branch_id) = branch_sf_id {
-            conditions.push(format!("Branch__c = '{}'", branch_id));
+            query = query.where_eq("Branch__c", branch_id);
         }
+        let query = changed_since(query, modified_since)?;
+        self.client.query_all_including_deleted(&query.to_string()).await
+    }
+
+    pub async fn delete_record(&self, sobject: &str, id: &str) -> Result<(), SyncError> {
+        self.client.delete_record(sobject, id).await
+    }
 
-        if !conditions.is_empty() {
-            soql.push_str(" WHERE ");
-            soql.push_str(&conditions.join(" AND "));
+    /// Clear `Is_Active__c`, for objects that are retired rather than deleted
+    pub async fn deactivate_record(&self, sobject: &str, id: &str) -> Result<(), SyncError> {
+        self.client.update(sobject, id, &serde_json::json!({ "Is_Active__c": false })).await
+    }
+
+    // ==================== Change Tracking ====================
+
+    /// SystemModstamp of each of the given records, keyed by Salesforce ID.
+    /// Records deleted in Salesforce are missing from the result.
+    pub async fn get_modstamps(&self, sobject: &str, ids: &[String]) -> Result<HashMap<String, String>, SyncError> {
+        #[derive(Debug, Clone, Deserialize)]
+        struct Modstamp {
+            #[serde(rename = "Id")]
+            id: String,
+            #[serde(rename = "SystemModstamp")]
+            system_modstamp: String,
         }
-        soql.push_str(" ORDER BY Created_At__c DESC");
 
-        self.client.query_all(&soql).await
+        let mut modstamps = HashMap::new();
+        // Keep the IN list well under the SOQL length limit
+        for chunk in ids.chunks(200) {
+            let query = Soql::new(sobject, &["Id", "SystemModstamp"]).where_in("Id", chunk);
+            let records: Vec<Modstamp> = self.client.query_all(&query.to_string()).await?;
+            modstamps.extend(records.into_iter().map(|r| (r.id, r.system_modstamp)));
+        }
+        Ok(modstamps)
     }
 
-    pub async fn upsert_transaction(&self, transaction: &SfTransaction) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(transaction).map_err(|e| e.to_string())?;
-        self.client.upsert("Transaction__c", "Invoice_Number__c", &transaction.invoice_no, &data).await
+    /// Fetch a whole record, with Salesforce field names
+    pub async fn get_record(&self, sobject: &str, id: &str) -> Result<serde_json::Value, SyncError> {
+        self.client.get(&format!("/sobjects/{}/{}", sobject, id)).await
     }
+}
 
-    pub async fn get_transaction_items(&self, transaction_sf_id: &str) -> Result<Vec<SfTransactionItem>, String> {
-        let soql = format!(
-            "SELECT Id, Name, Transaction__c, Inventory__c, Quantity__c, Unit_Price__c, Subtotal__c FROM Transaction_Item__c WHERE Transaction__c = '{}'",
-            transaction_sf_id
-        );
-        self.client.query_all(&soql).await
+/// Fields read of each transaction item
+const TRANSACTION_ITEM_FIELDS: &[&str] = &[
+    "Id",
+    "Name",
+    "Transaction__c",
+    "Inventory__c",
+    "Quantity__c",
+    "Unit_Price__c",
+    "Subtotal__c",
+    "Direction__c",
+    "Item_Condition__c",
+    "Origin_Inventory__c",
+];
+
+/// Filter a query to records changed at or after `modified_since` (a
+/// SystemModstamp) if given. The filter is inclusive as the SOQL literal
+/// drops the milliseconds.
+fn changed_since(query: Soql, modified_since: Option<&str>) -> Result<Soql, SyncError> {
+    match modified_since {
+        Some(since) => Ok(query.where_gte("SystemModstamp", parse_modstamp(since)?)),
+        None => Ok(query),
     }
+}
 
-    pub async fn create_transaction_item(&self, item: &SfTransactionItem) -> Result<SaveResult, String> {
-        let data = serde_json::to_value(item).map_err(|e| e.to_string())?;
-        self.client.create("Transaction_Item__c", &data).await
+/// A SystemModstamp as Salesforce returns it, `2024-01-31T08:15:00.000+0000`
+fn parse_modstamp(modstamp: &str) -> Result<DateTime<Utc>, SyncError> {
+    DateTime::parse_from_rfc3339(modstamp)
+        .or_else(|_| DateTime::parse_from_str(modstamp, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .map(|parsed| parsed.with_timezone(&Utc))
+        .map_err(|_| SyncError::Mapping(format!("Invalid SystemModstamp: {}", modstamp)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_query_filters_on_modstamp() {
+        assert_eq!(
+            changed_since(Soql::new("Product__c", &["Id"]), None).unwrap().to_string(),
+            "SELECT Id FROM Product__c"
+        );
+        assert_eq!(
+            changed_since(
+                Soql::new("Inventory__c", &["Id"]).where_eq("Branch__c", "a01"),
+                Some("2024-01-31T15:15:00.000+0700"),
+            )
+            .unwrap()
+            .to_string(),
+            "SELECT Id FROM Inventory__c WHERE Branch__c = 'a01' AND SystemModstamp >= 2024-01-31T08:15:00Z"
+        );
+        assert!(changed_since(Soql::new("Product__c", &["Id"]), Some("yesterday")).is_err());
     }
 }
@@ -1,5 +1,7 @@
+use crate::sync::SyncError;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
 use parking_lot::RwLock;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 
 /// OAuth 2.0 token response from Salesforce
@@ -9,6 +11,8 @@ pub struct TokenResponse {
     pub instance_url: String,
     pub id: String,
     pub token_type: String,
+    /// Not included in JWT bearer responses
+    #[serde(default)]
     pub issued_at: String,
 }
 
@@ -26,15 +30,50 @@ struct CachedToken {
     obtained_at: Instant,
 }
 
+/// How the app proves its identity to Salesforce
+#[derive(Debug, Clone)]
+pub enum AuthFlow {
+    /// Username-password flow; the security token is appended to the password
+    Password {
+        client_secret: String,
+        password: String,
+        security_token: String,
+    },
+    /// JWT bearer flow: an assertion signed with the private key of the
+    /// certificate uploaded to the connected app
+    JwtBearer {
+        private_key: String,
+        audience: String,
+    },
+}
+
 /// Salesforce OAuth credentials
 #[derive(Debug, Clone)]
 pub struct SalesforceCredentials {
     pub client_id: String,
-    pub client_secret: String,
     pub username: String,
-    pub password: String,
-    pub security_token: String,
     pub login_url: String,
+    pub flow: AuthFlow,
+}
+
+/// Claims of a JWT bearer assertion
+#[derive(Debug, Serialize, Deserialize)]
+struct JwtClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: i64,
+}
+
+/// Salesforce accepts assertions for at most three minutes
+const JWT_LIFETIME_SECS: i64 = 180;
+
+fn login_url(is_sandbox: bool) -> String {
+    if is_sandbox {
+        "https://test.salesforce.com".to_string()
+    } else {
+        "https://login.salesforce.com".to_string()
+    }
 }
 
 impl SalesforceCredentials {
@@ -46,21 +85,45 @@ impl SalesforceCredentials {
         security_token: String,
         is_sandbox: bool,
     ) -> Self {
-        let login_url = if is_sandbox {
-            "https://test.salesforce.com".to_string()
-        } else {
-            "https://login.salesforce.com".to_string()
-        };
-
         Self {
             client_id,
-            client_secret,
             username,
-            password,
-            security_token,
-            login_url,
+            login_url: login_url(is_sandbox),
+            flow: AuthFlow::Password {
+                client_secret,
+                password,
+                security_token,
+            },
         }
     }
+
+    /// Credentials for the JWT bearer flow. `username` is the assertion's
+    /// subject; the audience defaults to the login URL.
+    pub fn new_jwt_bearer(
+        client_id: String,
+        username: String,
+        private_key: String,
+        audience: Option<String>,
+        is_sandbox: bool,
+    ) -> Result<Self, String> {
+        EncodingKey::from_rsa_pem(private_key.as_bytes())
+            .map_err(|e| format!("Invalid JWT private key: {}", e))?;
+
+        let login_url = login_url(is_sandbox);
+        let audience = audience
+            .filter(|a| !a.is_empty())
+            .unwrap_or_else(|| login_url.clone());
+
+        Ok(Self {
+            client_id,
+            username,
+            login_url,
+            flow: AuthFlow::JwtBearer {
+                private_key,
+                audience,
+            },
+        })
+    }
 }
 
 /// Token manager for Salesforce OAuth 2.0
@@ -106,7 +169,7 @@ impl TokenManager {
     }
 
     /// Get valid access token, refreshing if necessary
-    pub async fn get_token(&self) -> Result<TokenResponse, String> {
+    pub async fn get_token(&self) -> Result<TokenResponse, SyncError> {
         // Check if we have a valid cached token
         {
             let cached = self.cached_token.read();
@@ -122,10 +185,12 @@ impl TokenManager {
     }
 
     /// Force refresh the token
-    pub async fn refresh_token(&self) -> Result<TokenResponse, String> {
+    pub async fn refresh_token(&self) -> Result<TokenResponse, SyncError> {
         let credentials = {
             let creds = self.credentials.read();
-            creds.clone().ok_or_else(|| "Salesforce credentials not configured".to_string())?
+            creds
+                .clone()
+                .ok_or_else(|| SyncError::Auth("Salesforce credentials not configured".to_string()))?
         };
 
         let token = self.authenticate(&credentials).await?;
@@ -142,20 +207,31 @@ impl TokenManager {
         Ok(token)
     }
 
-    /// Perform OAuth 2.0 username-password flow authentication
-    async fn authenticate(&self, credentials: &SalesforceCredentials) -> Result<TokenResponse, String> {
+    /// Obtain a new access token with the configured flow
+    async fn authenticate(&self, credentials: &SalesforceCredentials) -> Result<TokenResponse, SyncError> {
         let token_url = format!("{}/services/oauth2/token", credentials.login_url);
 
-        // Password + security token combined
-        let password_with_token = format!("{}{}", credentials.password, credentials.security_token);
-
-        let params = [
-            ("grant_type", "password"),
-            ("client_id", &credentials.client_id),
-            ("client_secret", &credentials.client_secret),
-            ("username", &credentials.username),
-            ("password", &password_with_token),
-        ];
+        let params = match &credentials.flow {
+            AuthFlow::Password {
+                client_secret,
+                password,
+                security_token,
+            } => vec![
+                ("grant_type", "password".to_string()),
+                ("client_id", credentials.client_id.clone()),
+                ("client_secret", client_secret.clone()),
+                ("username", credentials.username.clone()),
+                // Password + security token combined
+                ("password", format!("{}{}", password, security_token)),
+            ],
+            AuthFlow::JwtBearer {
+                private_key,
+                audience,
+            } => vec![
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string()),
+                ("assertion", jwt_assertion(credentials, private_key, audience).map_err(SyncError::Auth)?),
+            ],
+        };
 
         let response = self
             .http_client
@@ -163,34 +239,55 @@ impl TokenManager {
             .form(&params)
             .send()
             .await
-            .map_err(|e| format!("Failed to connect to Salesforce: {}", e))?;
+            .map_err(|e| SyncError::Network(format!("Failed to connect to Salesforce: {}", e)))?;
 
         let status = response.status();
         let body = response
             .text()
             .await
-            .map_err(|e| format!("Failed to read response: {}", e))?;
+            .map_err(|e| SyncError::Network(format!("Failed to read response: {}", e)))?;
 
         if status.is_success() {
             serde_json::from_str::<TokenResponse>(&body)
-                .map_err(|e| format!("Failed to parse token response: {}", e))
+                .map_err(|e| SyncError::Mapping(format!("Failed to parse token response: {}", e)))
+        } else if status.is_server_error() {
+            Err(SyncError::Network(format!("Salesforce login failed: {} - {}", status, body)))
         } else {
             // Try to parse error response
             if let Ok(error) = serde_json::from_str::<OAuthError>(&body) {
-                Err(format!("Salesforce authentication failed: {} - {}", error.error, error.error_description))
+                Err(SyncError::Auth(format!("{} - {}", error.error, error.error_description)))
             } else {
-                Err(format!("Salesforce authentication failed: {} - {}", status, body))
+                Err(SyncError::Auth(format!("{} - {}", status, body)))
             }
         }
     }
 
     /// Test the connection with current credentials
-    pub async fn test_connection(&self) -> Result<String, String> {
+    pub async fn test_connection(&self) -> Result<String, SyncError> {
         let token = self.get_token().await?;
         Ok(format!("Connected to: {}", token.instance_url))
     }
 }
 
+/// Sign a short-lived assertion for the JWT bearer flow
+fn jwt_assertion(
+    credentials: &SalesforceCredentials,
+    private_key: &str,
+    audience: &str,
+) -> Result<String, String> {
+    let key = EncodingKey::from_rsa_pem(private_key.as_bytes())
+        .map_err(|e| format!("Invalid JWT private key: {}", e))?;
+    let claims = JwtClaims {
+        iss: credentials.client_id.clone(),
+        sub: credentials.username.clone(),
+        aud: audience.to_string(),
+        exp: chrono::Utc::now().timestamp() + JWT_LIFETIME_SECS,
+    };
+
+    jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)
+        .map_err(|e| format!("Failed to sign JWT assertion: {}", e))
+}
+
 impl Default for TokenManager {
     fn default() -> Self {
         Self::new()
@@ -200,6 +297,10 @@ impl Default for TokenManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use jsonwebtoken::{DecodingKey, Validation};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
     #[test]
     fn test_credentials_sandbox_url() {
@@ -226,4 +327,102 @@ mod tests {
         );
         assert_eq!(creds.login_url, "https://login.salesforce.com");
     }
+
+    const TEST_KEY: &str = include_str!("testdata/jwt_test_key.pem");
+    const TEST_PUBLIC_KEY: &str = include_str!("testdata/jwt_test_key.pub.pem");
+
+    /// Token endpoint on a local port that answers every request with the
+    /// same token and passes each request's form body to the test
+    async fn mock_token_endpoint() -> (String, UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (requests, received) = unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                let l = l.to_lowercase();
+                                l.strip_prefix("content-length:")?.trim().parse::<usize>().ok()
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length || n == 0 {
+                            break body.to_string();
+                        }
+                    }
+                };
+                requests.send(body).unwrap();
+
+                let token = r#"{"access_token":"mock-token","instance_url":"https://mock.my.salesforce.com","id":"https://test.salesforce.com/id/00D/005","token_type":"Bearer","scope":"api"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    token.len(),
+                    token
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_jwt_bearer_flow_against_mock_endpoint() {
+        assert!(SalesforceCredentials::new_jwt_bearer(
+            "client_id".to_string(),
+            "user@test.com".to_string(),
+            "not a key".to_string(),
+            None,
+            true,
+        )
+        .is_err());
+
+        let (url, mut received) = mock_token_endpoint().await;
+        let mut creds = SalesforceCredentials::new_jwt_bearer(
+            "client_id".to_string(),
+            "user@test.com".to_string(),
+            TEST_KEY.to_string(),
+            None,
+            true,
+        )
+        .unwrap();
+        creds.login_url = url;
+
+        let manager = TokenManager::new();
+        manager.set_credentials(creds);
+
+        // The second call is served from the cache
+        assert_eq!(manager.get_token().await.unwrap().access_token, "mock-token");
+        assert_eq!(manager.get_token().await.unwrap().access_token, "mock-token");
+        let body = received.recv().await.unwrap();
+        assert!(received.try_recv().is_err());
+
+        let form: std::collections::HashMap<String, String> =
+            url::form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+        assert_eq!(form["grant_type"], "urn:ietf:params:oauth:grant-type:jwt-bearer");
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&["https://test.salesforce.com"]);
+        let claims = jsonwebtoken::decode::<JwtClaims>(
+            &form["assertion"],
+            &DecodingKey::from_rsa_pem(TEST_PUBLIC_KEY.as_bytes()).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(claims.iss, "client_id");
+        assert_eq!(claims.sub, "user@test.com");
+
+        manager.refresh_token().await.unwrap();
+        assert!(received.recv().await.is_some());
+    }
 }
@@ -0,0 +1,287 @@
+use super::client::SalesforceClient;
+use crate::sync::SyncError;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// Records above which sync goes through the Bulk API, unless configured
+/// otherwise
+pub const DEFAULT_BULK_THRESHOLD: usize = 2000;
+
+/// How often a running job is checked, and how long it may take
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const JOB_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Bulk API 2.0 job as reported by Salesforce
+#[derive(Debug, Clone, Deserialize)]
+struct JobInfo {
+    id: String,
+    state: String,
+    #[serde(rename = "errorMessage")]
+    error_message: Option<String>,
+}
+
+/// Outcome of an ingest job, per record
+#[derive(Debug, Default)]
+pub struct IngestResult {
+    pub successful: Vec<IngestSuccess>,
+    pub failed: Vec<IngestFailure>,
+}
+
+#[derive(Debug)]
+pub struct IngestSuccess {
+    pub sf_id: String,
+    pub created: bool,
+    /// The record's fields as uploaded
+    pub record: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct IngestFailure {
+    pub error: SyncError,
+    pub record: HashMap<String, String>,
+}
+
+/// Salesforce Bulk API 2.0: loads and queries run as background jobs that
+/// exchange CSV, taking a handful of API calls however many records there are
+pub struct BulkClient {
+    client: Arc<SalesforceClient>,
+}
+
+impl BulkClient {
+    pub fn new(client: Arc<SalesforceClient>) -> Self {
+        Self { client }
+    }
+
+    /// Upsert `records` (with Salesforce field names) on `external_id_field`
+    /// in one ingest job. Rows that Salesforce rejects are returned in
+    /// `failed`; an error means the job as a whole failed.
+    pub async fn upsert(
+        &self,
+        sobject: &str,
+        external_id_field: &str,
+        records: &[Value],
+    ) -> Result<IngestResult, SyncError> {
+        let job: JobInfo = self
+            .client
+            .post(
+                "/jobs/ingest",
+                &json!({
+                    "object": sobject,
+                    "externalIdFieldName": external_id_field,
+                    "operation": "upsert",
+                    "contentType": "CSV",
+                    "lineEnding": "LF",
+                }),
+            )
+            .await?;
+
+        let uploaded = async {
+            self.client
+                .put_csv(
+                    &format!("/jobs/ingest/{}/batches", job.id),
+                    to_csv(records)?,
+                )
+                .await?;
+            self.client
+                .patch(
+                    &format!("/jobs/ingest/{}", job.id),
+                    &json!({ "state": "UploadComplete" }),
+                )
+                .await
+        }
+        .await;
+        if let Err(e) = uploaded {
+            self.abort("ingest", &job.id).await;
+            return Err(e);
+        }
+        self.wait_for("ingest", &job.id).await?;
+
+        let (successful, _) = self
+            .client
+            .get_csv(&format!("/jobs/ingest/{}/successfulResults", job.id))
+            .await?;
+        let (failed, _) = self
+            .client
+            .get_csv(&format!("/jobs/ingest/{}/failedResults", job.id))
+            .await?;
+
+        Ok(IngestResult {
+            successful: from_csv::<HashMap<String, String>>(&successful)?
+                .into_iter()
+                .map(|mut record| IngestSuccess {
+                    sf_id: record.remove("sf__Id").unwrap_or_default(),
+                    created: record.remove("sf__Created").as_deref() == Some("true"),
+                    record,
+                })
+                .collect(),
+            failed: from_csv::<HashMap<String, String>>(&failed)?
+                .into_iter()
+                .map(|mut record| {
+                    record.remove("sf__Id");
+                    IngestFailure {
+                        error: SyncError::from_bulk(&record.remove("sf__Error").unwrap_or_default()),
+                        record,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Run `soql` as a query job and read all its result pages. Bulk queries
+    /// cannot be ordered.
+    pub async fn query<T: DeserializeOwned>(&self, soql: &str) -> Result<Vec<T>, SyncError> {
+        let job: JobInfo = self
+            .client
+            .post(
+                "/jobs/query",
+                &json!({
+                    "operation": "query",
+                    "query": soql,
+                    "contentType": "CSV",
+                    "lineEnding": "LF",
+                }),
+            )
+            .await?;
+        self.wait_for("query", &job.id).await?;
+
+        let mut records = Vec::new();
+        let mut locator: Option<String> = None;
+        loop {
+            let endpoint = match &locator {
+                Some(locator) => format!("/jobs/query/{}/results?locator={}", job.id, locator),
+                None => format!("/jobs/query/{}/results", job.id),
+            };
+            let (csv, next) = self.client.get_csv(&endpoint).await?;
+            records.extend(from_csv::<T>(&csv)?);
+            match next {
+                Some(next) => locator = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Poll a job until Salesforce has finished processing it
+    async fn wait_for(&self, kind: &str, job_id: &str) -> Result<(), SyncError> {
+        let started = Instant::now();
+        loop {
+            let job: JobInfo = self
+                .client
+                .get(&format!("/jobs/{}/{}", kind, job_id))
+                .await?;
+            match job.state.as_str() {
+                "JobComplete" => return Ok(()),
+                "Failed" | "Aborted" => {
+                    return Err(SyncError::Rejected {
+                        code: job.state,
+                        message: format!("Bulk {} job: {}", kind, job.error_message.unwrap_or_default()),
+                    })
+                }
+                _ if started.elapsed() > JOB_TIMEOUT => {
+                    self.abort(kind, job_id).await;
+                    return Err(SyncError::Network(format!("Bulk {} job timed out", kind)));
+                }
+                _ => sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Stop a job that will not be completed; Salesforce cleans it up
+    async fn abort(&self, kind: &str, job_id: &str) {
+        let aborted = self
+            .client
+            .patch(
+                &format!("/jobs/{}/{}", kind, job_id),
+                &json!({ "state": "Aborted" }),
+            )
+            .await;
+        if let Err(e) = aborted {
+            log::warn!("Failed to abort bulk {} job {}: {}", kind, job_id, e);
+        }
+    }
+}
+
+/// Records as CSV, one column per field found in any of them. Salesforce
+/// clears a field given as `#N/A`, and leaves one that is empty alone.
+fn to_csv(records: &[Value]) -> Result<String, SyncError> {
+    let mut columns: Vec<&str> = Vec::new();
+    for record in records {
+        for field in record.as_object().into_iter().flat_map(|r| r.keys()) {
+            if !columns.contains(&field.as_str()) {
+                columns.push(field);
+            }
+        }
+    }
+
+    let mut writer = csv::WriterBuilder::new().from_writer(Vec::new());
+    writer.write_record(&columns).map_err(mapping)?;
+    for record in records {
+        let row = columns.iter().map(|column| match record.get(column) {
+            None => String::new(),
+            Some(Value::Null) => "#N/A".to_string(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        });
+        writer.write_record(row).map_err(mapping)?;
+    }
+
+    let csv = writer.into_inner().map_err(mapping)?;
+    String::from_utf8(csv).map_err(mapping)
+}
+
+fn mapping(e: impl ToString) -> SyncError {
+    SyncError::Mapping(e.to_string())
+}
+
+/// Rows of a CSV result, by column name. Empty fields read as `None`.
+fn from_csv<T: DeserializeOwned>(csv: &str) -> Result<Vec<T>, SyncError> {
+    csv::Reader::from_reader(csv.as_bytes())
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|e| SyncError::Mapping(format!("Failed to parse bulk results: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salesforce::api::SfInventory;
+
+    #[test]
+    fn test_records_round_trip_through_csv() {
+        let records = vec![
+            json!({ "Barcode__c": "EM-01", "Status__c": "available", "Notes__c": null }),
+            json!({ "Barcode__c": "EM-02", "Purchase_Price__c": 3500000, "Notes__c": "ada, \"gores\"" }),
+        ];
+        assert_eq!(
+            to_csv(&records).unwrap(),
+            "Barcode__c,Notes__c,Status__c,Purchase_Price__c\n\
+             EM-01,#N/A,available,\n\
+             EM-02,\"ada, \"\"gores\"\"\",,3500000\n"
+        );
+
+        let results = "\"sf__Id\",\"sf__Created\",Barcode__c\n\"a05\",\"true\",EM-01\n";
+        let rows: Vec<HashMap<String, String>> = from_csv(results).unwrap();
+        assert_eq!(rows[0]["sf__Id"], "a05");
+        assert_eq!(rows[0]["Barcode__c"], "EM-01");
+
+        // Query results are typed by the record they are read into
+        let results = "\"Id\",\"Name\",\"Barcode__c\",\"Product__c\",\"Branch__c\",\"Status__c\",\
+                       \"Location__c\",\"Purchase_Price__c\",\"Purchase_Date__c\",\"Supplier__c\",\
+                       \"Notes__c\",\"Sold_At__c\",\"SystemModstamp\"\n\
+                       \"a05\",\"EM-01\",\"EM-01\",\"aP1\",\"aB1\",\"available\",\"\",\"3500000\",\
+                       \"\",\"\",\"\",\"\",\"2024-01-31T08:15:00.000Z\"\n";
+        let inventory: Vec<SfInventory> = from_csv(results).unwrap();
+        assert_eq!(inventory[0].purchase_price, 3500000);
+        assert_eq!(inventory[0].location, None);
+        assert_eq!(
+            inventory[0].system_modstamp.as_deref(),
+            Some("2024-01-31T08:15:00.000Z")
+        );
+    }
+}
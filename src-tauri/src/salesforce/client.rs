@@ -1,6 +1,11 @@
 use super::auth::TokenManager;
+use crate::models::ApiUsage;
+use crate::sync::SyncError;
+use parking_lot::RwLock;
+use reqwest::header::HeaderMap;
 use reqwest::{Client, Method, StatusCode};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
@@ -70,11 +75,23 @@ pub struct SaveResult {
     pub errors: Vec<SalesforceError>,
 }
 
+/// Body of a request
+enum Payload {
+    Json(serde_json::Value),
+    Csv(String),
+}
+
 /// Salesforce REST API client
 pub struct SalesforceClient {
     token_manager: Arc<TokenManager>,
     http_client: Client,
     api_version: String,
+    /// Overrides the instance URL that comes with the token
+    base_url: RwLock<Option<String>>,
+    /// The org's daily API usage as of the last response
+    api_usage: RwLock<Option<ApiUsage>>,
+    /// API requests sent since the app started
+    request_count: AtomicU64,
     max_retries: u32,
     retry_delay: Duration,
 }
@@ -88,75 +105,147 @@ impl SalesforceClient {
                 .build()
                 .expect("Failed to create HTTP client"),
             api_version: "v59.0".to_string(),
+            base_url: RwLock::new(None),
+            api_usage: RwLock::new(None),
+            request_count: AtomicU64::new(0),
             max_retries: 3,
             retry_delay: Duration::from_secs(1),
         }
     }
 
+    /// Send API requests to `base_url` rather than the instance URL
+    /// Salesforce hands out with the token, such as a proxy or a local mock
+    /// server. `None` goes back to the instance URL.
+    pub fn set_base_url(&self, base_url: Option<String>) {
+        *self.base_url.write() = base_url;
+    }
+
+    /// The org's daily API usage as Salesforce last reported it
+    pub fn api_usage(&self) -> Option<ApiUsage> {
+        *self.api_usage.read()
+    }
+
+    /// API requests sent so far, retries included. Compare two readings to
+    /// count the requests made in between.
+    pub fn request_count(&self) -> u64 {
+        self.request_count.load(Ordering::Relaxed)
+    }
+
     /// Build the API URL for a given endpoint
     fn build_url(&self, instance_url: &str, endpoint: &str) -> String {
-        format!("{}/services/data/{}{}", instance_url, self.api_version, endpoint)
+        let base_url = self.base_url.read();
+        let base_url = base_url.as_deref().unwrap_or(instance_url);
+        format!("{}/services/data/{}{}", base_url.trim_end_matches('/'), self.api_version, endpoint)
     }
 
     /// Execute a GET request
-    pub async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, String> {
+    pub async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, SyncError> {
         self.request(Method::GET, endpoint, None::<&()>).await
     }
 
     /// Execute a POST request
-    pub async fn post<T: DeserializeOwned, B: Serialize>(&self, endpoint: &str, body: &B) -> Result<T, String> {
+    pub async fn post<T: DeserializeOwned, B: Serialize>(&self, endpoint: &str, body: &B) -> Result<T, SyncError> {
         self.request(Method::POST, endpoint, Some(body)).await
     }
 
     /// Execute a PATCH request
-    pub async fn patch<B: Serialize>(&self, endpoint: &str, body: &B) -> Result<(), String> {
+    pub async fn patch<B: Serialize>(&self, endpoint: &str, body: &B) -> Result<(), SyncError> {
         let _: serde_json::Value = self.request(Method::PATCH, endpoint, Some(body)).await?;
         Ok(())
     }
 
     /// Execute a DELETE request
-    pub async fn delete(&self, endpoint: &str) -> Result<(), String> {
+    pub async fn delete(&self, endpoint: &str) -> Result<(), SyncError> {
         let _: serde_json::Value = self.request(Method::DELETE, endpoint, None::<&()>).await?;
         Ok(())
     }
 
-    /// Execute a request with automatic retry and token refresh
+    /// Upload CSV data, such as a Bulk API job's records
+    pub async fn put_csv(&self, endpoint: &str, csv: String) -> Result<(), SyncError> {
+        self.send(Method::PUT, endpoint, Some(&Payload::Csv(csv))).await?;
+        Ok(())
+    }
+
+    /// Fetch CSV data, such as a Bulk API job's results, with the locator of
+    /// the next page if there is one
+    pub async fn get_csv(&self, endpoint: &str) -> Result<(String, Option<String>), SyncError> {
+        let (headers, body) = self.send(Method::GET, endpoint, None).await?;
+        let locator = headers
+            .get("Sforce-Locator")
+            .and_then(|v| v.to_str().ok())
+            .filter(|locator| *locator != "null")
+            .map(String::from);
+        Ok((body, locator))
+    }
+
+    /// Execute a JSON request
     async fn request<T: DeserializeOwned, B: Serialize>(
         &self,
         method: Method,
         endpoint: &str,
         body: Option<&B>,
-    ) -> Result<T, String> {
-        let mut last_error = String::new();
+    ) -> Result<T, SyncError> {
+        let payload = body
+            .map(|b| serde_json::to_value(b).map(Payload::Json))
+            .transpose()
+            .map_err(|e| SyncError::Mapping(format!("Failed to encode request: {}", e)))?;
+        let (_, body_text) = self.send(method, endpoint, payload.as_ref()).await?;
+
+        // Handle no content responses
+        if body_text.is_empty() {
+            // Return empty JSON value for void responses
+            return serde_json::from_str("null")
+                .map_err(|_| SyncError::Mapping("Failed to parse empty response".to_string()));
+        }
 
+        serde_json::from_str(&body_text)
+            .map_err(|e| SyncError::Mapping(format!("Failed to parse response: {} - Body: {}", e, body_text)))
+    }
+
+    /// Execute a request with automatic retry and token refresh, returning
+    /// the response headers and body
+    async fn send(
+        &self,
+        method: Method,
+        endpoint: &str,
+        payload: Option<&Payload>,
+    ) -> Result<(HeaderMap, String), SyncError> {
         for attempt in 0..=self.max_retries {
             // Get fresh token
             let token = self.token_manager.get_token().await?;
             let url = self.build_url(&token.instance_url, endpoint);
 
-            let mut request = self
+            let request = self
                 .http_client
                 .request(method.clone(), &url)
-                .header("Authorization", format!("Bearer {}", token.access_token))
-                .header("Content-Type", "application/json");
-
-            if let Some(b) = body {
-                request = request.json(b);
-            }
+                .header("Authorization", format!("Bearer {}", token.access_token));
+            let request = match payload {
+                Some(Payload::Csv(csv)) => request.header("Content-Type", "text/csv").body(csv.clone()),
+                Some(Payload::Json(json)) => request.json(json),
+                None => request.header("Content-Type", "application/json"),
+            };
 
             let response = match request.send().await {
                 Ok(r) => r,
                 Err(e) => {
-                    last_error = format!("Request failed: {}", e);
                     if attempt < self.max_retries {
                         sleep(self.retry_delay * (attempt + 1)).await;
                         continue;
                     }
-                    return Err(last_error);
+                    return Err(SyncError::Network(format!("Request failed: {}", e)));
                 }
             };
 
             let status = response.status();
+            self.request_count.fetch_add(1, Ordering::Relaxed);
+            let usage = response
+                .headers()
+                .get("Sforce-Limit-Info")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_limit_info);
+            if usage.is_some() {
+                *self.api_usage.write() = usage;
+            }
 
             // Handle rate limiting
             if status == StatusCode::TOO_MANY_REQUESTS {
@@ -170,7 +259,7 @@ impl SalesforceClient {
                     sleep(Duration::from_secs(retry_after)).await;
                     continue;
                 }
-                return Err("Rate limited by Salesforce".to_string());
+                return Err(SyncError::RateLimited("still limited after retrying".to_string()));
             }
 
             // Handle session expired - force token refresh
@@ -179,56 +268,64 @@ impl SalesforceClient {
                     self.token_manager.refresh_token().await?;
                     continue;
                 }
-                return Err("Session expired and refresh failed".to_string());
+                return Err(SyncError::Auth("Session expired and refresh failed".to_string()));
             }
 
+            let headers = response.headers().clone();
             let body_text = response
                 .text()
                 .await
-                .map_err(|e| format!("Failed to read response: {}", e))?;
+                .map_err(|e| SyncError::Network(format!("Failed to read response: {}", e)))?;
 
-            // Handle no content responses
-            if status == StatusCode::NO_CONTENT || body_text.is_empty() {
-                // Return empty JSON value for void responses
-                return serde_json::from_str("null")
-                    .map_err(|_| "Failed to parse empty response".to_string());
+            if status.is_success() || body_text.is_empty() {
+                return Ok((headers, body_text));
             }
 
-            if status.is_success() {
-                return serde_json::from_str(&body_text)
-                    .map_err(|e| format!("Failed to parse response: {} - Body: {}", e, body_text));
+            if status.is_server_error() {
+                if attempt < self.max_retries {
+                    sleep(self.retry_delay * (attempt + 1)).await;
+                    continue;
+                }
+                return Err(SyncError::Network(format!("Salesforce error ({}): {}", status, body_text)));
             }
 
             // Parse error response
-            if let Ok(errors) = serde_json::from_str::<Vec<SalesforceError>>(&body_text) {
-                let error_messages: Vec<String> = errors.iter().map(|e| e.message.clone()).collect();
-                last_error = format!("Salesforce error: {}", error_messages.join(", "));
-            } else {
-                last_error = format!("Salesforce error ({}): {}", status, body_text);
-            }
-
-            if attempt < self.max_retries && status.is_server_error() {
-                sleep(self.retry_delay * (attempt + 1)).await;
-                continue;
-            }
-
-            return Err(last_error);
+            let errors = serde_json::from_str::<Vec<SalesforceError>>(&body_text).unwrap_or_default();
+            return Err(SyncError::from_response(status, &errors, &body_text));
         }
 
-        Err(last_error)
+        unreachable!("the last attempt always returns")
     }
 
     /// Execute a SOQL query
-    pub async fn query<T: DeserializeOwned>(&self, soql: &str) -> Result<QueryResult<T>, String> {
+    pub async fn query<T: DeserializeOwned>(&self, soql: &str) -> Result<QueryResult<T>, SyncError> {
         let encoded = url::form_urlencoded::byte_serialize(soql.as_bytes()).collect::<String>();
         let endpoint = format!("/query?q={}", encoded);
         self.get(&endpoint).await
     }
 
+    /// Execute a SOQL query that also sees deleted records (`queryAll`)
+    pub async fn query_including_deleted<T: DeserializeOwned>(&self, soql: &str) -> Result<QueryResult<T>, SyncError> {
+        let encoded = url::form_urlencoded::byte_serialize(soql.as_bytes()).collect::<String>();
+        let endpoint = format!("/queryAll?q={}", encoded);
+        self.get(&endpoint).await
+    }
+
     /// Query with automatic pagination
-    pub async fn query_all<T: DeserializeOwned + Clone>(&self, soql: &str) -> Result<Vec<T>, String> {
+    pub async fn query_all<T: DeserializeOwned + Clone>(&self, soql: &str) -> Result<Vec<T>, SyncError> {
+        let result = self.query(soql).await?;
+        self.remaining_pages(result).await
+    }
+
+    /// Query deleted records too, with automatic pagination
+    pub async fn query_all_including_deleted<T: DeserializeOwned + Clone>(&self, soql: &str) -> Result<Vec<T>, SyncError> {
+        let result = self.query_including_deleted(soql).await?;
+        self.remaining_pages(result).await
+    }
+
+    /// Records of a query result and of the pages after it
+    pub async fn remaining_pages<T: DeserializeOwned + Clone>(&self, mut result: QueryResult<T>) -> Result<Vec<T>, SyncError> {
         let mut all_records = Vec::new();
-        let mut result: QueryResult<T> = self.query(soql).await?;
         all_records.extend(result.records);
 
         while let Some(next_url) = result.next_records_url {
@@ -237,7 +334,7 @@ impl SalesforceClient {
                 .split("/services/data/")
                 .nth(1)
                 .map(|s| format!("/{}", s.split('/').skip(1).collect::<Vec<_>>().join("/")))
-                .ok_or_else(|| "Invalid next records URL".to_string())?;
+                .ok_or_else(|| SyncError::Mapping("Invalid next records URL".to_string()))?;
 
             result = self.get(&endpoint).await?;
             all_records.extend(result.records);
@@ -247,13 +344,13 @@ impl SalesforceClient {
     }
 
     /// Create a record
-    pub async fn create(&self, sobject: &str, data: &serde_json::Value) -> Result<SaveResult, String> {
+    pub async fn create(&self, sobject: &str, data: &serde_json::Value) -> Result<SaveResult, SyncError> {
         let endpoint = format!("/sobjects/{}", sobject);
         self.post(&endpoint, data).await
     }
 
     /// Update a record
-    pub async fn update(&self, sobject: &str, id: &str, data: &serde_json::Value) -> Result<(), String> {
+    pub async fn update(&self, sobject: &str, id: &str, data: &serde_json::Value) -> Result<(), SyncError> {
         let endpoint = format!("/sobjects/{}/{}", sobject, id);
         self.patch(&endpoint, data).await
     }
@@ -265,25 +362,31 @@ impl SalesforceClient {
         external_id_field: &str,
         external_id_value: &str,
         data: &serde_json::Value,
-    ) -> Result<SaveResult, String> {
+    ) -> Result<SaveResult, SyncError> {
         let endpoint = format!(
             "/sobjects/{}/{}/{}",
             sobject, external_id_field, external_id_value
         );
 
-        // PATCH returns 200 for update or 201 for create
-        // The response body contains the result
-        self.post(&endpoint, data).await
+        // The external ID comes from the URL; Salesforce rejects it in the body
+        let mut data = data.clone();
+        if let Some(fields) = data.as_object_mut() {
+            fields.remove(external_id_field);
+        }
+
+        // PATCH returns 200 for update or 201 for create, so retrying an
+        // upsert never creates a duplicate. The response body contains the result
+        self.request(Method::PATCH, &endpoint, Some(&data)).await
     }
 
     /// Delete a record
-    pub async fn delete_record(&self, sobject: &str, id: &str) -> Result<(), String> {
+    pub async fn delete_record(&self, sobject: &str, id: &str) -> Result<(), SyncError> {
         let endpoint = format!("/sobjects/{}/{}", sobject, id);
         self.delete(&endpoint).await
     }
 
     /// Execute composite request for batch operations
-    pub async fn composite(&self, request: CompositeRequest) -> Result<CompositeResponse, String> {
+    pub async fn composite(&self, request: CompositeRequest) -> Result<CompositeResponse, SyncError> {
         self.post("/composite", &request).await
     }
 
@@ -293,7 +396,7 @@ impl SalesforceClient {
         sobject: &str,
         external_id_field: &str,
         records: Vec<(String, serde_json::Value)>, // (external_id_value, data)
-    ) -> Result<Vec<Result<SaveResult, String>>, String> {
+    ) -> Result<Vec<Result<SaveResult, SyncError>>, SyncError> {
         let mut results = Vec::new();
 
         // Process in batches of 25 (Salesforce composite API limit)
@@ -333,8 +436,9 @@ impl SalesforceClient {
                         });
                     results.push(Ok(save_result));
                 } else {
-                    let error_msg = subresponse.body.to_string();
-                    results.push(Err(error_msg));
+                    let status = StatusCode::from_u16(subresponse.http_status_code as u16).unwrap_or(StatusCode::BAD_REQUEST);
+                    let errors = serde_json::from_value::<Vec<SalesforceError>>(subresponse.body.clone()).unwrap_or_default();
+                    results.push(Err(SyncError::from_response(status, &errors, &subresponse.body.to_string())));
                 }
             }
         }
@@ -342,3 +446,133 @@ impl SalesforceClient {
         Ok(results)
     }
 }
+
+/// The org's usage from a `Sforce-Limit-Info` header, such as
+/// `api-usage=25/15000; per-app-api-usage=17/250(appName=emaspos)`
+fn parse_limit_info(header: &str) -> Option<ApiUsage> {
+    let usage = header.split([';', ',']).find_map(|part| part.trim().strip_prefix("api-usage="))?;
+    let (used, limit) = usage.split_once('/')?;
+    Some(ApiUsage {
+        used: used.trim().parse().ok()?,
+        limit: limit.trim().parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salesforce::mock::MockSalesforce;
+    use serde_json::{json, Value};
+
+    /// A client logged in to a fresh mock org
+    async fn mock_client() -> (MockSalesforce, SalesforceClient) {
+        let mock = MockSalesforce::start().await;
+        let token_manager = Arc::new(TokenManager::new());
+        token_manager.set_credentials(mock.credentials());
+        let client = SalesforceClient::new(token_manager);
+        client.set_base_url(Some(mock.url().to_string()));
+        (mock, client)
+    }
+
+    #[tokio::test]
+    async fn test_query_all_follows_pages() {
+        let (mock, client) = mock_client().await;
+        mock.set_page_size(2);
+        for name in ["Cincin", "Kalung", "Gelang", "Anting", "Liontin"] {
+            mock.insert("Product__c", json!({ "Name": name, "Is_Active__c": true }));
+        }
+        let retired = mock.insert(
+            "Product__c",
+            json!({ "Name": "Koin", "Is_Active__c": false }),
+        );
+        mock.delete("Product__c", &retired);
+
+        let products: Vec<Value> = client
+            .query_all("SELECT Id, Name FROM Product__c WHERE Is_Active__c = true ORDER BY Name")
+            .await
+            .unwrap();
+        let names: Vec<&str> = products
+            .iter()
+            .map(|p| p["Name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Anting", "Cincin", "Gelang", "Kalung", "Liontin"]);
+        assert_eq!(mock.requests().len(), 3);
+
+        // Only queryAll sees the recycle bin
+        let soql = "SELECT Id FROM Product__c WHERE IsDeleted = true";
+        assert!(client.query_all::<Value>(soql).await.unwrap().is_empty());
+        let deleted: Vec<Value> = client.query_all_including_deleted(soql).await.unwrap();
+        assert_eq!(deleted[0]["Id"], retired.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_requests_retry_through_injected_failures() {
+        let (mock, client) = mock_client().await;
+        let id = client
+            .create("Customer__c", &json!({ "Name": "Budi" }))
+            .await
+            .unwrap()
+            .id;
+
+        // An expired session is renewed, a server error and rate limiting
+        // are waited out
+        mock.expire_session();
+        mock.fail_next(StatusCode::INTERNAL_SERVER_ERROR);
+        mock.fail_next(StatusCode::TOO_MANY_REQUESTS);
+        client
+            .update("Customer__c", &id, &json!({ "Phone__c": "0811" }))
+            .await
+            .unwrap();
+        assert_eq!(mock.records("Customer__c")[0]["Phone__c"], "0811");
+        assert_eq!(mock.token_requests(), 2);
+
+        // Upserting on the external ID updates the same record
+        let saved = client
+            .upsert(
+                "Customer__c",
+                "Phone__c",
+                "0811",
+                &json!({ "Name": "Budi Santoso", "Phone__c": "0811" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(saved.id, id);
+        let results = client
+            .batch_upsert(
+                "Customer__c",
+                "Phone__c",
+                vec![
+                    ("0811".to_string(), json!({ "Name": "Budi S." })),
+                    ("0822".to_string(), json!({ "Name": "Sari" })),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(results.iter().all(Result::is_ok));
+        let customers = mock.records("Customer__c");
+        assert_eq!(customers.len(), 2);
+        assert_eq!(customers[0]["Name"], "Budi S.");
+
+        // Retries run out
+        for _ in 0..=client.max_retries {
+            mock.fail_next(StatusCode::TOO_MANY_REQUESTS);
+        }
+        let endpoint = format!("/sobjects/Customer__c/{}", id);
+        assert_eq!(
+            client.get::<Value>(&endpoint).await.unwrap_err().code(),
+            "rate_limited"
+        );
+        assert_eq!(
+            client.get::<Value>(&endpoint).await.unwrap()["Name"],
+            "Budi S."
+        );
+
+        // Salesforce's error codes tell what went wrong
+        let error = client
+            .get::<Value>("/sobjects/Customer__c/a00000000000000")
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        assert!(!error.is_retryable());
+    }
+}
@@ -1,5 +1,5 @@
-use crate::models::{Branch, Customer, GoldPrice, Inventory, Product, Transaction, TransactionItem};
-use super::api::{SfBranch, SfCustomer, SfGoldPrice, SfInventory, SfProduct, SfTransaction, SfTransactionItem};
+use crate::models::{Branch, Customer, GoldPrice, Inventory, Payment, Product, Transaction, TransactionItem};
+use super::api::{SfBranch, SfCustomer, SfGoldPrice, SfInventory, SfPayment, SfProduct, SfTransaction, SfTransactionItem};
 
 /// Trait for converting local models to Salesforce format
 pub trait ToSalesforce {
@@ -47,6 +47,20 @@ impl SfLookups {
     pub fn get_transaction_sf_id(&self, local_id: &str) -> Option<&String> {
         self.transactions.get(local_id)
     }
+
+    /// Remember the Salesforce ID of a record pushed during this sync, so
+    /// records pushed after it can link to it
+    pub fn insert(&mut self, table_name: &str, local_id: &str, sf_id: &str) {
+        let map = match table_name {
+            "branches" => &mut self.branches,
+            "products" => &mut self.products,
+            "inventory" => &mut self.inventory,
+            "customers" => &mut self.customers,
+            "transactions" => &mut self.transactions,
+            _ => return,
+        };
+        map.insert(local_id.to_string(), sf_id.to_string());
+    }
 }
 
 // ==================== Branch Mapping ====================
@@ -62,6 +76,7 @@ impl ToSalesforce for Branch {
             address: self.address.clone(),
             phone: self.phone.clone(),
             is_active: self.is_active,
+            system_modstamp: None,
         }
     }
 }
@@ -98,6 +113,7 @@ impl ToSalesforce for Product {
             weight_gram: self.weight_gram,
             labor_cost: self.labor_cost,
             is_active: self.is_active,
+            system_modstamp: None,
         }
     }
 }
@@ -142,6 +158,7 @@ impl ToSalesforce for Inventory {
             supplier: self.supplier.clone(),
             notes: self.notes.clone(),
             sold_at: self.sold_at.clone(),
+            system_modstamp: None,
         }
     }
 }
@@ -183,6 +200,7 @@ impl ToSalesforce for GoldPrice {
             buy_price: self.buy_price,
             sell_price: self.sell_price,
             source: self.source.clone(),
+            system_modstamp: None,
         }
     }
 }
@@ -218,6 +236,7 @@ impl ToSalesforce for Customer {
             address: self.address.clone(),
             notes: self.notes.clone(),
             total_transactions: self.total_transactions,
+            system_modstamp: None,
         }
     }
 }
@@ -254,10 +273,12 @@ impl ToSalesforce for Transaction {
             transaction_type: self.r#type.clone(),
             subtotal: self.subtotal,
             discount: self.discount,
+            trade_in_total: self.trade_in_total,
             total_amount: self.total_amount,
             notes: self.notes.clone(),
             status: self.status.clone(),
             created_at: Some(self.created_at.clone()),
+            system_modstamp: None,
         }
     }
 }
@@ -275,6 +296,7 @@ impl FromSalesforce for SfTransaction {
             r#type: self.transaction_type.clone(),
             subtotal: self.subtotal,
             discount: self.discount,
+            trade_in_total: self.trade_in_total,
             total_amount: self.total_amount,
             notes: self.notes.clone(),
             status: self.status.clone(),
@@ -295,11 +317,15 @@ impl ToSalesforce for TransactionItem {
         SfTransactionItem {
             id: None,
             name: None,
+            external_id: self.id.clone(),
             transaction_id: lookups.get_transaction_sf_id(&self.transaction_id).cloned().unwrap_or_default(),
             inventory_id: lookups.get_inventory_sf_id(&self.inventory_id).cloned().unwrap_or_default(),
             quantity: self.quantity,
             unit_price: self.unit_price,
             subtotal: self.subtotal,
+            direction: self.direction.clone(),
+            item_condition: self.item_condition.clone(),
+            origin_inventory_id: self.origin_inventory_id.as_ref().and_then(|id| lookups.get_inventory_sf_id(id).cloned()),
         }
     }
 }
@@ -316,7 +342,81 @@ impl FromSalesforce for SfTransactionItem {
             unit_price: self.unit_price,
             subtotal: self.subtotal,
             gold_price_ref: None,
+            computed_price: None,
+            price_status: "computed".to_string(),
+            direction: self.direction.clone(),
+            item_condition: self.item_condition.clone(),
+            origin_inventory_id: None, // Will be resolved by caller
             inventory: None,
         }
     }
 }
+
+// ==================== Payment Mapping ====================
+
+impl ToSalesforce for Payment {
+    type SfType = SfPayment;
+
+    fn to_salesforce(&self, lookups: &SfLookups) -> SfPayment {
+        SfPayment {
+            id: None,
+            external_id: self.id.clone(),
+            transaction_id: lookups.get_transaction_sf_id(&self.transaction_id).cloned().unwrap_or_default(),
+            method: self.method.clone(),
+            amount: self.amount,
+            reference_no: self.reference_no.clone(),
+            bank_name: self.bank_name.clone(),
+            status: self.status.clone(),
+            paid_at: self.paid_at.clone(),
+        }
+    }
+}
+
+impl FromSalesforce for SfPayment {
+    type LocalType = Payment;
+
+    fn from_salesforce(&self) -> Payment {
+        Payment {
+            id: uuid::Uuid::new_v4().to_string(),
+            transaction_id: String::new(), // Will be resolved by caller
+            method: self.method.clone(),
+            amount: self.amount,
+            reference_no: self.reference_no.clone(),
+            bank_name: self.bank_name.clone(),
+            status: self.status.clone(),
+            paid_at: self.paid_at.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payment_links_to_pushed_transaction() {
+        let mut lookups = SfLookups::new();
+        lookups.insert("transactions", "trx-1", "a01000000000001");
+
+        let payment = Payment {
+            id: "pay-1".to_string(),
+            transaction_id: "trx-1".to_string(),
+            method: "qris".to_string(),
+            amount: 1_500_000,
+            reference_no: Some("QR123".to_string()),
+            bank_name: None,
+            status: "success".to_string(),
+            paid_at: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+        };
+        let sf_payment = payment.to_salesforce(&lookups);
+
+        // The local id doubles as the upsert key
+        assert_eq!(sf_payment.external_id, "pay-1");
+        assert_eq!(sf_payment.transaction_id, "a01000000000001");
+        let json = serde_json::to_value(&sf_payment).unwrap();
+        assert_eq!(json["External_Id__c"], "pay-1");
+        assert!(json.get("Bank_Name__c").is_none());
+    }
+}
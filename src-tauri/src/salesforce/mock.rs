@@ -0,0 +1,558 @@
+use super::auth::SalesforceCredentials;
+use axum::body::Bytes;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use chrono::{DateTime, FixedOffset};
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// What the token endpoint gives as the instance URL. Nothing answers
+/// there, so clients must send their requests to the mock's own URL.
+pub const MOCK_INSTANCE_URL: &str = "https://mock.my.salesforce.com";
+
+/// A fake Salesforce org served on a local port, for tests that run the
+/// client and the sync against real HTTP without network access.
+///
+/// It covers the OAuth token endpoint, SOQL queries (`query` and
+/// `queryAll`) with pagination, sObject create, read, update, delete and
+/// upsert by external ID, and the composite API. Queries understand what
+/// the app sends: a field list, `WHERE` conditions joined by `AND` using
+/// `=`, `>=` or `IN`, and `ORDER BY` one field.
+pub struct MockSalesforce {
+    url: String,
+    org: Arc<Mutex<Org>>,
+}
+
+#[derive(Default)]
+struct Org {
+    /// Records by sObject, deleted ones included
+    records: HashMap<String, Vec<Map<String, Value>>>,
+    next_id: u64,
+    /// Records left to serve of paged queries, by cursor
+    cursors: HashMap<String, Vec<Value>>,
+    next_cursor: u64,
+    page_size: usize,
+    access_token: Option<String>,
+    token_requests: usize,
+    /// Statuses to answer the next API requests with
+    failures: VecDeque<StatusCode>,
+    /// Method and endpoint of every API request served
+    requests: Vec<String>,
+    /// Reported in the Sforce-Limit-Info header
+    api_used: u32,
+    api_limit: u32,
+}
+
+impl MockSalesforce {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let org = Arc::new(Mutex::new(Org {
+            page_size: 2000,
+            api_limit: 15000,
+            ..Org::default()
+        }));
+
+        let app = Router::new().fallback(handle).with_state(org.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self { url, org }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Password flow credentials that log in to the mock
+    pub fn credentials(&self) -> SalesforceCredentials {
+        let mut credentials = SalesforceCredentials::new(
+            "mock-client".to_string(),
+            "mock-secret".to_string(),
+            "sync@mock.test".to_string(),
+            "password".to_string(),
+            "token".to_string(),
+            false,
+        );
+        credentials.login_url = self.url.clone();
+        credentials
+    }
+
+    /// Records per query result page; Salesforce uses 2000
+    pub fn set_page_size(&self, page_size: usize) {
+        self.org.lock().page_size = page_size;
+    }
+
+    /// Answer the next API request with `status` instead of serving it.
+    /// Calls queue up, one request each.
+    pub fn fail_next(&self, status: StatusCode) {
+        self.org.lock().failures.push_back(status);
+    }
+
+    /// Set the org's API requests made today and its daily limit; each
+    /// request served adds one
+    pub fn set_api_usage(&self, used: u32, limit: u32) {
+        let mut org = self.org.lock();
+        org.api_used = used;
+        org.api_limit = limit;
+    }
+
+    /// Make the current access token invalid, as when a session times out
+    pub fn expire_session(&self) {
+        self.org.lock().access_token = None;
+    }
+
+    pub fn token_requests(&self) -> usize {
+        self.org.lock().token_requests
+    }
+
+    /// API requests served so far, as "METHOD /endpoint"
+    pub fn requests(&self) -> Vec<String> {
+        self.org.lock().requests.clone()
+    }
+
+    /// Create a record as a Salesforce user would, returning its ID
+    pub fn insert(&self, sobject: &str, fields: Value) -> String {
+        self.org.lock().create(sobject, fields)
+    }
+
+    /// Change fields of a record as a Salesforce user would
+    pub fn update(&self, sobject: &str, id: &str, fields: Value) {
+        let mut org = self.org.lock();
+        let record = org.find(sobject, id).expect("no such record");
+        merge(record, fields);
+    }
+
+    /// Move a record to the recycle bin as a Salesforce user would
+    pub fn delete(&self, sobject: &str, id: &str) {
+        let mut org = self.org.lock();
+        let record = org.find(sobject, id).expect("no such record");
+        merge(record, json!({ "IsDeleted": true }));
+    }
+
+    /// Records of `sobject` that are not deleted, in creation order
+    pub fn records(&self, sobject: &str) -> Vec<Value> {
+        let org = self.org.lock();
+        org.records
+            .get(sobject)
+            .into_iter()
+            .flatten()
+            .filter(|r| !is_deleted(r))
+            .map(|r| Value::Object(r.clone()))
+            .collect()
+    }
+}
+
+async fn handle(
+    axum::extract::State(org): axum::extract::State<Arc<Mutex<Org>>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut org = org.lock();
+    let path = uri.path();
+
+    if path == "/services/oauth2/token" && method == Method::POST {
+        org.token_requests += 1;
+        let access_token = format!("mock-token-{}", org.token_requests);
+        org.access_token = Some(access_token.clone());
+        return (
+            StatusCode::OK,
+            axum::Json(json!({
+                "access_token": access_token,
+                "instance_url": MOCK_INSTANCE_URL,
+                "id": format!("{}/id/00D000000000001/005000000000001", MOCK_INSTANCE_URL),
+                "token_type": "Bearer",
+                "issued_at": "1706688900000",
+            })),
+        )
+            .into_response();
+    }
+
+    // Everything else is under /services/data/<version>
+    let Some(endpoint) = path
+        .strip_prefix("/services/data/")
+        .and_then(|rest| rest.split_once('/'))
+        .map(|(_, endpoint)| format!("/{}", endpoint))
+    else {
+        return error(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "The requested resource does not exist",
+        );
+    };
+
+    let bearer = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if bearer.is_none() || bearer != org.access_token.as_deref() {
+        return error(
+            StatusCode::UNAUTHORIZED,
+            "INVALID_SESSION_ID",
+            "Session expired or invalid",
+        );
+    }
+
+    org.api_used += 1;
+    let usage = format!("api-usage={}/{}", org.api_used, org.api_limit);
+    let query = uri.query().unwrap_or_default();
+    let mut response = api_response(&mut org, &method, &endpoint, query, &body);
+    response
+        .headers_mut()
+        .insert("Sforce-Limit-Info", usage.parse().unwrap());
+    response
+}
+
+fn api_response(
+    org: &mut Org,
+    method: &Method,
+    endpoint: &str,
+    query: &str,
+    body: &[u8],
+) -> Response {
+    org.requests.push(format!("{} {}", method, endpoint));
+    if let Some(status) = org.failures.pop_front() {
+        return match status {
+            StatusCode::TOO_MANY_REQUESTS => (
+                status,
+                [("Retry-After", "0")],
+                axum::Json(error_body("REQUEST_LIMIT_EXCEEDED", "Too many requests")),
+            )
+                .into_response(),
+            StatusCode::UNAUTHORIZED => {
+                error(status, "INVALID_SESSION_ID", "Session expired or invalid")
+            }
+            _ => error(status, "UNKNOWN_EXCEPTION", "An unexpected error occurred"),
+        };
+    }
+
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_slice(body) {
+            Ok(body) => body,
+            Err(e) => return error(StatusCode::BAD_REQUEST, "JSON_PARSER_ERROR", &e.to_string()),
+        }
+    };
+    let (status, body) = org.serve(method, endpoint, query, body);
+    if body.is_null() {
+        status.into_response()
+    } else {
+        (status, axum::Json(body)).into_response()
+    }
+}
+
+fn error(status: StatusCode, code: &str, message: &str) -> Response {
+    (status, axum::Json(error_body(code, message))).into_response()
+}
+
+fn error_body(code: &str, message: &str) -> Value {
+    json!([{ "message": message, "errorCode": code }])
+}
+
+impl Org {
+    /// Serve one API request; the body is `Null` for no content
+    fn serve(
+        &mut self,
+        method: &Method,
+        endpoint: &str,
+        query: &str,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        let segments: Vec<&str> = endpoint.trim_start_matches('/').split('/').collect();
+        match (method.as_str(), segments.as_slice()) {
+            ("GET", ["query" | "queryAll"]) => {
+                let soql = url::form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == "q")
+                    .map(|(_, soql)| soql.into_owned())
+                    .unwrap_or_default();
+                match self.query(&soql, segments[0] == "queryAll") {
+                    Ok(records) => (StatusCode::OK, self.page(records)),
+                    Err(e) => (StatusCode::BAD_REQUEST, error_body("MALFORMED_QUERY", &e)),
+                }
+            }
+            ("GET", ["query" | "queryAll", cursor]) => match self.cursors.remove(*cursor) {
+                Some(records) => (StatusCode::OK, self.page(records)),
+                None => (
+                    StatusCode::NOT_FOUND,
+                    error_body("INVALID_QUERY_LOCATOR", "Invalid query locator"),
+                ),
+            },
+            ("POST", ["sobjects", sobject]) => {
+                let id = self.create(sobject, body);
+                (
+                    StatusCode::CREATED,
+                    json!({ "id": id, "success": true, "errors": [] }),
+                )
+            }
+            ("GET", ["sobjects", sobject, id]) => match self.find(sobject, id) {
+                Some(record) => (StatusCode::OK, Value::Object(record.clone())),
+                None => not_found(),
+            },
+            ("PATCH", ["sobjects", sobject, id]) => match self.find(sobject, id) {
+                Some(record) => {
+                    merge(record, body);
+                    (StatusCode::NO_CONTENT, Value::Null)
+                }
+                None => not_found(),
+            },
+            ("DELETE", ["sobjects", sobject, id]) => match self.find(sobject, id) {
+                Some(record) => {
+                    merge(record, json!({ "IsDeleted": true }));
+                    (StatusCode::NO_CONTENT, Value::Null)
+                }
+                None => not_found(),
+            },
+            ("PATCH", ["sobjects", sobject, field, value]) => {
+                if body.get(*field).is_some() {
+                    let message = format!("{} is not allowed in the body of an upsert", field);
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        error_body("INVALID_FIELD", &message),
+                    );
+                }
+                let existing = self
+                    .records
+                    .get_mut(*sobject)
+                    .into_iter()
+                    .flatten()
+                    .find(|r| {
+                        !is_deleted(r) && r.get(*field).and_then(Value::as_str) == Some(value)
+                    });
+                match existing {
+                    Some(record) => {
+                        merge(record, body);
+                        let id = record["Id"].clone();
+                        (
+                            StatusCode::OK,
+                            json!({ "id": id, "success": true, "errors": [], "created": false }),
+                        )
+                    }
+                    None => {
+                        let mut fields = body;
+                        fields[*field] = json!(value);
+                        let id = self.create(sobject, fields);
+                        (
+                            StatusCode::CREATED,
+                            json!({ "id": id, "success": true, "errors": [], "created": true }),
+                        )
+                    }
+                }
+            }
+            ("POST", ["composite"]) => {
+                let subrequests = body["compositeRequest"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                let responses: Vec<Value> = subrequests
+                    .into_iter()
+                    .map(|sub| {
+                        let method = sub["method"]
+                            .as_str()
+                            .unwrap_or("GET")
+                            .parse()
+                            .unwrap_or(Method::GET);
+                        let url = sub["url"].as_str().unwrap_or_default();
+                        let endpoint = url
+                            .strip_prefix("/services/data/")
+                            .and_then(|rest| rest.split_once('/'))
+                            .map(|(_, endpoint)| format!("/{}", endpoint))
+                            .unwrap_or_default();
+                        let (path, query) = endpoint.split_once('?').unwrap_or((&endpoint, ""));
+                        let (status, body) = self.serve(&method, path, query, sub["body"].clone());
+                        json!({
+                            "body": body,
+                            "httpHeaders": {},
+                            "httpStatusCode": status.as_u16(),
+                            "referenceId": sub["referenceId"],
+                        })
+                    })
+                    .collect();
+                (StatusCode::OK, json!({ "compositeResponse": responses }))
+            }
+            _ => not_found(),
+        }
+    }
+
+    fn create(&mut self, sobject: &str, fields: Value) -> String {
+        self.next_id += 1;
+        let id = format!("a0M{:012}", self.next_id);
+        let mut record = Map::new();
+        record.insert("Id".to_string(), json!(id));
+        record.insert("IsDeleted".to_string(), json!(false));
+        merge(&mut record, fields);
+        self.records
+            .entry(sobject.to_string())
+            .or_default()
+            .push(record);
+        id
+    }
+
+    fn find(&mut self, sobject: &str, id: &str) -> Option<&mut Map<String, Value>> {
+        self.records
+            .get_mut(sobject)?
+            .iter_mut()
+            .find(|r| !is_deleted(r) && r["Id"] == id)
+    }
+
+    /// Records matching `soql`, with the fields it selects
+    fn query(&self, soql: &str, include_deleted: bool) -> Result<Vec<Value>, String> {
+        let rest = soql.strip_prefix("SELECT ").ok_or("Expected SELECT")?;
+        let (fields, rest) = rest.split_once(" FROM ").ok_or("Expected FROM")?;
+        let (rest, order_by) = match rest.split_once(" ORDER BY ") {
+            Some((rest, order_by)) => (rest, Some(order_by.trim())),
+            None => (rest, None),
+        };
+        let (sobject, conditions) = match rest.split_once(" WHERE ") {
+            Some((sobject, conditions)) => (
+                sobject,
+                conditions.split(" AND ").map(Condition::parse).collect(),
+            ),
+            None => (rest, Ok(Vec::new())),
+        };
+        let conditions: Vec<Condition> = conditions?;
+
+        let mut matching: Vec<&Map<String, Value>> = self
+            .records
+            .get(sobject.trim())
+            .into_iter()
+            .flatten()
+            .filter(|r| include_deleted || !is_deleted(r))
+            .filter(|r| conditions.iter().all(|c| c.matches(r)))
+            .collect();
+        if let Some(field) = order_by {
+            matching.sort_by_key(|r| r.get(field).map(Value::to_string).unwrap_or_default());
+        }
+
+        Ok(matching
+            .into_iter()
+            .map(|record| {
+                let mut selected = Map::new();
+                selected.insert("attributes".to_string(), json!({ "type": sobject.trim() }));
+                for field in fields.split(',').map(str::trim) {
+                    selected.insert(
+                        field.to_string(),
+                        record.get(field).cloned().unwrap_or(Value::Null),
+                    );
+                }
+                Value::Object(selected)
+            })
+            .collect())
+    }
+
+    /// The first page of `records`, keeping the rest behind a cursor
+    fn page(&mut self, mut records: Vec<Value>) -> Value {
+        let total_size = records.len();
+        let rest = records.split_off(records.len().min(self.page_size));
+        let next_records_url = if rest.is_empty() {
+            None
+        } else {
+            self.next_cursor += 1;
+            let cursor = format!("01gMOCK{:012}", self.next_cursor);
+            self.cursors.insert(cursor.clone(), rest);
+            Some(format!("/services/data/v59.0/query/{}", cursor))
+        };
+        json!({
+            "totalSize": total_size,
+            "done": next_records_url.is_none(),
+            "nextRecordsUrl": next_records_url,
+            "records": records,
+        })
+    }
+}
+
+fn not_found() -> (StatusCode, Value) {
+    (
+        StatusCode::NOT_FOUND,
+        error_body("NOT_FOUND", "The requested resource does not exist"),
+    )
+}
+
+fn is_deleted(record: &Map<String, Value>) -> bool {
+    record.get("IsDeleted") == Some(&json!(true))
+}
+
+/// Set `fields` on a record, stamping it as changed
+fn merge(record: &mut Map<String, Value>, fields: Value) {
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+    let now = chrono::Utc::now()
+        .format("%Y-%m-%dT%H:%M:%S%.3f+0000")
+        .to_string();
+    record.insert("SystemModstamp".to_string(), json!(now));
+}
+
+/// One `WHERE` condition
+struct Condition {
+    field: String,
+    op: &'static str,
+    values: Vec<Value>,
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Result<Self, String> {
+        for op in [" IN ", " >= ", " = "] {
+            if let Some((field, value)) = condition.split_once(op) {
+                let values = if op == " IN " {
+                    let list = value
+                        .trim()
+                        .strip_prefix('(')
+                        .and_then(|v| v.strip_suffix(')'));
+                    list.ok_or("Expected a list after IN")?
+                        .split(',')
+                        .map(literal)
+                        .collect()
+                } else {
+                    vec![literal(value)]
+                };
+                return Ok(Self {
+                    field: field.trim().to_string(),
+                    op: op.trim(),
+                    values,
+                });
+            }
+        }
+        Err(format!("Unsupported condition: {}", condition))
+    }
+
+    fn matches(&self, record: &Map<String, Value>) -> bool {
+        let value = record.get(&self.field).unwrap_or(&Value::Null);
+        match self.op {
+            ">=" => match (datetime(value), datetime(&self.values[0])) {
+                (Some(value), Some(since)) => value >= since,
+                _ => false,
+            },
+            _ => self.values.contains(value),
+        }
+    }
+}
+
+/// A SOQL literal as the JSON value a record would hold
+fn literal(value: &str) -> Value {
+    let value = value.trim();
+    match value {
+        "true" => json!(true),
+        "false" => json!(false),
+        "null" => Value::Null,
+        _ => match value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            Some(text) => json!(text),
+            None => value
+                .parse::<i64>()
+                .map(|n| json!(n))
+                .unwrap_or_else(|_| json!(value)),
+        },
+    }
+}
+
+fn datetime(value: &Value) -> Option<DateTime<FixedOffset>> {
+    let value = value.as_str()?;
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .ok()
+}
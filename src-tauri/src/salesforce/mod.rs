@@ -1,4 +1,8 @@
 pub mod auth;
 pub mod client;
 pub mod api;
+pub mod bulk;
 pub mod mapper;
+pub mod soql;
+#[cfg(test)]
+pub mod mock;
@@ -0,0 +1,244 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt::{self, Write};
+
+/// A value compared against in a SOQL condition. Text is quoted and
+/// escaped when written out, so it can never end the literal early.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Null => f.write_str("null"),
+            Literal::Bool(value) => write!(f, "{}", value),
+            Literal::Int(value) => write!(f, "{}", value),
+            Literal::Float(value) => write!(f, "{}", value),
+            Literal::Text(text) => {
+                f.write_char('\'')?;
+                for c in text.chars() {
+                    match c {
+                        '\\' => f.write_str("\\\\")?,
+                        '\'' => f.write_str("\\'")?,
+                        '"' => f.write_str("\\\"")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        '\u{8}' => f.write_str("\\b")?,
+                        '\u{c}' => f.write_str("\\f")?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('\'')
+            }
+            Literal::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            // Salesforce takes no fractional seconds in a datetime literal
+            Literal::DateTime(datetime) => write!(f, "{}", datetime.format("%Y-%m-%dT%H:%M:%SZ")),
+        }
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::Text(value.to_string())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::Text(value)
+    }
+}
+
+impl From<&String> for Literal {
+    fn from(value: &String) -> Self {
+        Literal::Text(value.clone())
+    }
+}
+
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Literal::Bool(value)
+    }
+}
+
+impl From<i32> for Literal {
+    fn from(value: i32) -> Self {
+        Literal::Int(value.into())
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(value: i64) -> Self {
+        Literal::Int(value)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Literal::Float(value)
+    }
+}
+
+impl From<NaiveDate> for Literal {
+    fn from(value: NaiveDate) -> Self {
+        Literal::Date(value)
+    }
+}
+
+impl From<DateTime<Utc>> for Literal {
+    fn from(value: DateTime<Utc>) -> Self {
+        Literal::DateTime(value)
+    }
+}
+
+/// A SOQL query, written out with `to_string()`. Object and field names
+/// are taken as given and must come from code; every value goes through
+/// [`Literal`].
+#[derive(Debug, Clone)]
+pub struct Soql {
+    sobject: String,
+    fields: Vec<String>,
+    /// Conditions joined by AND
+    conditions: Vec<String>,
+    order_by: Option<String>,
+    limit: Option<usize>,
+}
+
+impl Soql {
+    pub fn new(sobject: &str, fields: &[&str]) -> Self {
+        Self {
+            sobject: sobject.to_string(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            conditions: Vec::new(),
+            order_by: None,
+            limit: None,
+        }
+    }
+
+    /// `field = value`
+    pub fn where_eq(self, field: &str, value: impl Into<Literal>) -> Self {
+        self.condition(field, "=", value.into().to_string())
+    }
+
+    /// `field >= value`
+    pub fn where_gte(self, field: &str, value: impl Into<Literal>) -> Self {
+        self.condition(field, ">=", value.into().to_string())
+    }
+
+    /// `field IN (values)`. Salesforce rejects an empty list, so callers
+    /// skip the query when there is nothing to look for.
+    pub fn where_in<V: Into<Literal>>(
+        self,
+        field: &str,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values: Vec<String> = values.into_iter().map(|v| v.into().to_string()).collect();
+        debug_assert!(!values.is_empty(), "IN list of {} is empty", field);
+        self.condition(field, "IN", format!("({})", values.join(", ")))
+    }
+
+    pub fn order_by(mut self, field: &str) -> Self {
+        self.order_by = Some(field.to_string());
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn condition(mut self, field: &str, op: &str, value: String) -> Self {
+        self.conditions.push(format!("{} {} {}", field, op, value));
+        self
+    }
+}
+
+impl fmt::Display for Soql {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT {} FROM {}", self.fields.join(", "), self.sobject)?;
+        if !self.conditions.is_empty() {
+            write!(f, " WHERE {}", self.conditions.join(" AND "))?;
+        }
+        if let Some(field) = &self.order_by {
+            write!(f, " ORDER BY {}", field)?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_literals_cannot_break_out_of_the_query() {
+        let query = |value: &str| {
+            Soql::new("Customer__c", &["Id"])
+                .where_eq("Name", value)
+                .to_string()
+        };
+
+        assert_eq!(
+            query("O'Brien"),
+            r"SELECT Id FROM Customer__c WHERE Name = 'O\'Brien'"
+        );
+        assert_eq!(
+            query("x' OR Name != 'y"),
+            r"SELECT Id FROM Customer__c WHERE Name = 'x\' OR Name != \'y'"
+        );
+        // A trailing backslash must not escape the closing quote
+        assert_eq!(
+            query(r"a\' OR Id != null OR Name = '"),
+            r"SELECT Id FROM Customer__c WHERE Name = 'a\\\' OR Id != null OR Name = \''"
+        );
+        assert_eq!(
+            query("baris\nbaru\t\"kutip\""),
+            r#"SELECT Id FROM Customer__c WHERE Name = 'baris\nbaru\t\"kutip\"'"#
+        );
+        assert_eq!(
+            query("Toko Emas Ñandú"),
+            "SELECT Id FROM Customer__c WHERE Name = 'Toko Emas Ñandú'"
+        );
+
+        let ids = ["a01", "a02' OR Id != '"];
+        assert_eq!(
+            Soql::new("Transaction_Item__c", &["Id", "Transaction__c"])
+                .where_in("Transaction__c", ids)
+                .to_string(),
+            r"SELECT Id, Transaction__c FROM Transaction_Item__c WHERE Transaction__c IN ('a01', 'a02\' OR Id != \'')"
+        );
+    }
+
+    #[test]
+    fn test_query_with_typed_literals() {
+        let since = Utc.with_ymd_and_hms(2024, 1, 31, 8, 15, 0).unwrap()
+            + chrono::Duration::milliseconds(250);
+        let query = Soql::new("Gold_Price__c", &["Id", "Buy_Price__c"])
+            .where_eq("Date__c", NaiveDate::from_ymd_opt(2024, 1, 31).unwrap())
+            .where_eq("Purity__c", 750)
+            .where_eq("Is_Active__c", true)
+            .where_gte("SystemModstamp", since)
+            .order_by("SystemModstamp")
+            .limit(10);
+
+        assert_eq!(
+            query.to_string(),
+            "SELECT Id, Buy_Price__c FROM Gold_Price__c WHERE Date__c = 2024-01-31 AND Purity__c = 750 \
+             AND Is_Active__c = true AND SystemModstamp >= 2024-01-31T08:15:00Z ORDER BY SystemModstamp LIMIT 10"
+        );
+        assert_eq!(Literal::Null.to_string(), "null");
+        assert_eq!(Literal::from(3.5).to_string(), "3.5");
+    }
+}
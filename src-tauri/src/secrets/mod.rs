@@ -0,0 +1,457 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use parking_lot::RwLock;
+use sha2::Sha256;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::path::PathBuf;
+
+use crate::models::SyncConfig;
+
+/// Marks a stored value as ciphertext, and which format it is in
+const PREFIX: &str = "enc:v1:";
+
+/// `sync_config` columns that hold secrets
+const SECRET_COLUMNS: &[&str] = &[
+    "sf_client_secret",
+    "sf_password",
+    "sf_security_token",
+    "sf_jwt_private_key",
+];
+
+/// Known plaintext stored encrypted in `secret_keys`, to tell a wrong
+/// passphrase or keyfile apart from the right one
+const VERIFIER: &str = "emaspos-secrets";
+
+const KDF_ITERATIONS: u32 = 600_000;
+const NONCE_LEN: usize = 12;
+
+/// Holds the key that Salesforce secrets in `sync_config` are encrypted with.
+///
+/// By default the key is random and kept in a keyfile next to the database,
+/// which protects a copied database but not a copied data directory. An owner
+/// can switch to a passphrase instead: the key is then derived from it with
+/// PBKDF2 and only held in memory, so after every start the vault stays
+/// locked (and sync stays off) until the owner unlocks it.
+pub struct SecretVault {
+    keyfile: PathBuf,
+    kdf_iterations: u32,
+    key: RwLock<Option<Key>>,
+}
+
+impl SecretVault {
+    pub fn new(keyfile: PathBuf) -> Self {
+        Self {
+            keyfile,
+            kdf_iterations: KDF_ITERATIONS,
+            key: RwLock::new(None),
+        }
+    }
+
+    /// Load the key at startup. In keyfile mode this creates the keyfile on
+    /// first run and encrypts any secrets still stored in plaintext; in
+    /// passphrase mode the vault stays locked.
+    pub async fn init(&self, pool: &SqlitePool) -> Result<(), String> {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+        match key_settings(&mut conn).await? {
+            None => {
+                let key = match self.read_keyfile()? {
+                    Some(key) => key,
+                    None => {
+                        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                        self.write_keyfile(&key)?;
+                        key
+                    }
+                };
+                save_key_settings(&mut conn, "keyfile", None, &key).await?;
+                *self.key.write() = Some(key);
+            }
+            Some(settings) if settings.mode == "keyfile" => {
+                let key = self.read_keyfile()?.ok_or_else(|| {
+                    "The secrets keyfile is missing; reset the Salesforce secrets".to_string()
+                })?;
+                verify(&key, &settings.verifier)?;
+                *self.key.write() = Some(key);
+            }
+            Some(_) => return Ok(()),
+        }
+
+        self.encrypt_plaintext_secrets(&mut conn).await
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.read().is_some()
+    }
+
+    /// `"keyfile"` or `"passphrase"`
+    pub async fn mode(&self, pool: &SqlitePool) -> Result<String, String> {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        Ok(key_settings(&mut conn)
+            .await?
+            .map(|s| s.mode)
+            .unwrap_or_else(|| "keyfile".to_string()))
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        Ok(seal(&self.current_key()?, plaintext))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> Result<String, String> {
+        open(&self.current_key()?, stored)
+    }
+
+    /// A copy of `config` with its secrets decrypted, for the sync engine
+    pub fn decrypt_config(&self, config: &SyncConfig) -> Result<SyncConfig, String> {
+        let decrypt =
+            |secret: &Option<String>| secret.as_deref().map(|s| self.decrypt(s)).transpose();
+
+        Ok(SyncConfig {
+            sf_client_secret: decrypt(&config.sf_client_secret)?,
+            sf_password: decrypt(&config.sf_password)?,
+            sf_security_token: decrypt(&config.sf_security_token)?,
+            sf_jwt_private_key: decrypt(&config.sf_jwt_private_key)?,
+            ..config.clone()
+        })
+    }
+
+    /// Unlock a passphrase-protected vault for this run of the app
+    pub async fn unlock(&self, pool: &SqlitePool, passphrase: &str) -> Result<(), String> {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+        let settings = key_settings(&mut conn)
+            .await?
+            .filter(|s| s.mode == "passphrase")
+            .ok_or_else(|| "Secrets are not protected by a passphrase".to_string())?;
+        let salt = BASE64
+            .decode(settings.kdf_salt.unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+        let iterations = settings.kdf_iterations.unwrap_or(KDF_ITERATIONS as i64) as u32;
+
+        let key = derive_key(passphrase, &salt, iterations);
+        verify(&key, &settings.verifier).map_err(|_| "Wrong passphrase".to_string())?;
+
+        *self.key.write() = Some(key);
+        Ok(())
+    }
+
+    /// Protect secrets with `passphrase`, or with a keyfile again when it is
+    /// `None`. Stored secrets are re-encrypted under the new key.
+    pub async fn set_passphrase(
+        &self,
+        pool: &SqlitePool,
+        passphrase: Option<&str>,
+    ) -> Result<(), String> {
+        let old_key = self.current_key()?;
+        if passphrase.is_none() && self.mode(pool).await? == "keyfile" {
+            return Err("Secrets are already protected by the keyfile".to_string());
+        }
+
+        let (mode, salt, new_key) = match passphrase {
+            Some(passphrase) => {
+                if passphrase.chars().count() < 8 {
+                    return Err("Passphrase must be at least 8 characters".to_string());
+                }
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                let key = derive_key(passphrase, &salt, self.kdf_iterations);
+                ("passphrase", Some(salt), key)
+            }
+            None => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                // Written before the switch is committed so the key is never lost
+                self.write_keyfile(&key)?;
+                ("keyfile", None, key)
+            }
+        };
+
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        reencrypt_secrets(&mut tx, Some(&old_key), &new_key).await?;
+        save_key_settings(
+            &mut tx,
+            mode,
+            salt.as_ref().map(|s| (s.as_slice(), self.kdf_iterations)),
+            &new_key,
+        )
+        .await?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+
+        *self.key.write() = Some(new_key);
+
+        if passphrase.is_some() {
+            if let Err(e) = std::fs::remove_file(&self.keyfile) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove the old secrets keyfile: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget the stored secrets and start over with a new keyfile. For when
+    /// the passphrase is forgotten or the keyfile lost.
+    pub async fn reset(&self, pool: &SqlitePool) -> Result<(), String> {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        self.write_keyfile(&key)?;
+
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        sqlx::query(
+            "UPDATE sync_config SET sf_client_secret = NULL, sf_password = NULL, sf_security_token = NULL, sf_jwt_private_key = NULL, sync_enabled = 0",
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        save_key_settings(&mut tx, "keyfile", None, &key).await?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+
+        *self.key.write() = Some(key);
+        Ok(())
+    }
+
+    fn current_key(&self) -> Result<Key, String> {
+        self.key.read().ok_or_else(|| {
+            "Salesforce secrets are locked; unlock them with the owner passphrase".to_string()
+        })
+    }
+
+    async fn encrypt_plaintext_secrets(&self, conn: &mut SqliteConnection) -> Result<(), String> {
+        let key = self.current_key()?;
+        reencrypt_secrets(conn, None, &key).await
+    }
+
+    fn read_keyfile(&self) -> Result<Option<Key>, String> {
+        match std::fs::read(&self.keyfile) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Some(*Key::from_slice(&bytes))),
+            Ok(_) => Err(format!("{} is not a valid keyfile", self.keyfile.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", self.keyfile.display(), e)),
+        }
+    }
+
+    fn write_keyfile(&self, key: &Key) -> Result<(), String> {
+        use std::io::Write;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options
+            .open(&self.keyfile)
+            .and_then(|mut file| file.write_all(key.as_slice()))
+            .map_err(|e| format!("Failed to write {}: {}", self.keyfile.display(), e))
+    }
+}
+
+struct KeySettings {
+    mode: String,
+    kdf_salt: Option<String>,
+    kdf_iterations: Option<i64>,
+    verifier: String,
+}
+
+async fn key_settings(conn: &mut SqliteConnection) -> Result<Option<KeySettings>, String> {
+    let row: Option<(String, Option<String>, Option<i64>, String)> = sqlx::query_as(
+        "SELECT mode, kdf_salt, kdf_iterations, verifier FROM secret_keys WHERE id = 'default'",
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(
+        row.map(|(mode, kdf_salt, kdf_iterations, verifier)| KeySettings {
+            mode,
+            kdf_salt,
+            kdf_iterations,
+            verifier,
+        }),
+    )
+}
+
+async fn save_key_settings(
+    conn: &mut SqliteConnection,
+    mode: &str,
+    kdf: Option<(&[u8], u32)>,
+    key: &Key,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        INSERT INTO secret_keys (id, mode, kdf_salt, kdf_iterations, verifier, updated_at)
+        VALUES ('default', ?, ?, ?, ?, datetime('now'))
+        ON CONFLICT(id) DO UPDATE SET
+            mode = excluded.mode,
+            kdf_salt = excluded.kdf_salt,
+            kdf_iterations = excluded.kdf_iterations,
+            verifier = excluded.verifier,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(mode)
+    .bind(kdf.map(|(salt, _)| BASE64.encode(salt)))
+    .bind(kdf.map(|(_, iterations)| iterations as i64))
+    .bind(seal(key, VERIFIER))
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Re-encrypt every stored secret under `to`. With `from` as `None` the
+/// stored values are plaintext left over from before encryption, and values
+/// that are already encrypted are kept.
+async fn reencrypt_secrets(
+    conn: &mut SqliteConnection,
+    from: Option<&Key>,
+    to: &Key,
+) -> Result<(), String> {
+    for column in SECRET_COLUMNS {
+        let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+            "SELECT id, {} FROM sync_config WHERE {} IS NOT NULL",
+            column, column
+        ))
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for (id, stored) in rows {
+            let plaintext = match from {
+                Some(from) => open(from, &stored)?,
+                None if stored.starts_with(PREFIX) => continue,
+                None => stored,
+            };
+
+            sqlx::query(&format!(
+                "UPDATE sync_config SET {} = ? WHERE id = ?",
+                column
+            ))
+            .bind(seal(to, &plaintext))
+            .bind(&id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+fn verify(key: &Key, verifier: &str) -> Result<(), String> {
+    match open(key, verifier) {
+        Ok(plaintext) if plaintext == VERIFIER => Ok(()),
+        _ => Err("The secrets key does not match the one they were stored with".to_string()),
+    }
+}
+
+/// Encrypt to `enc:v1:` + base64(nonce || ciphertext)
+fn seal(key: &Key, plaintext: &str) -> String {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext.as_bytes())
+        .expect("encryption into a Vec cannot fail");
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    format!("{}{}", PREFIX, BASE64.encode(sealed))
+}
+
+fn open(key: &Key, stored: &str) -> Result<String, String> {
+    let sealed = stored
+        .strip_prefix(PREFIX)
+        .and_then(|encoded| BASE64.decode(encoded).ok())
+        .filter(|sealed| sealed.len() > NONCE_LEN)
+        .ok_or_else(|| "Stored secret is not encrypted".to_string())?;
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plaintext = ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Stored secret could not be decrypted with the current key".to_string())?;
+
+    String::from_utf8(plaintext).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let other = ChaCha20Poly1305::generate_key(&mut OsRng);
+
+        let sealed = seal(&key, "s3cret");
+        assert!(sealed.starts_with(PREFIX));
+        assert!(!sealed.contains("s3cret"));
+        // A fresh nonce every time
+        assert_ne!(sealed, seal(&key, "s3cret"));
+
+        assert_eq!(open(&key, &sealed).unwrap(), "s3cret");
+        assert!(open(&other, &sealed).is_err());
+        assert!(open(&key, "s3cret").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vault_encrypts_and_switches_to_passphrase() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        sqlx::query("INSERT INTO sync_config (id, sf_client_id, sf_password) VALUES ('default', 'client', 'hunter2')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let keyfile = std::env::temp_dir().join(format!("emaspos-{}.key", uuid::Uuid::new_v4()));
+        let vault = SecretVault {
+            keyfile: keyfile.clone(),
+            kdf_iterations: 1_000,
+            key: RwLock::new(None),
+        };
+
+        // Plaintext left from before encryption is encrypted on start
+        vault.init(&pool).await.unwrap();
+        let (stored,): (String,) = sqlx::query_as("SELECT sf_password FROM sync_config")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(stored.starts_with(PREFIX));
+        assert_eq!(vault.decrypt(&stored).unwrap(), "hunter2");
+
+        vault
+            .set_passphrase(&pool, Some("correct horse"))
+            .await
+            .unwrap();
+        assert!(!keyfile.exists());
+
+        // After a restart the vault is locked until the passphrase is given
+        let restarted = SecretVault {
+            keyfile: keyfile.clone(),
+            kdf_iterations: 1_000,
+            key: RwLock::new(None),
+        };
+        restarted.init(&pool).await.unwrap();
+        assert!(!restarted.is_unlocked());
+        assert!(restarted.unlock(&pool, "wrong horse").await.is_err());
+        restarted.unlock(&pool, "correct horse").await.unwrap();
+
+        let (stored,): (String,) = sqlx::query_as("SELECT sf_password FROM sync_config")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(restarted.decrypt(&stored).unwrap(), "hunter2");
+    }
+}
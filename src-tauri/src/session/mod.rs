@@ -0,0 +1,197 @@
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+/// User roles, ordered by privilege: an owner may do everything a kasir can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Kasir,
+    Owner,
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "owner" => Some(Role::Owner),
+            "kasir" => Some(Role::Kasir),
+            _ => None,
+        }
+    }
+}
+
+/// The authenticated user behind a session token
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: String,
+    pub username: String,
+    pub full_name: String,
+    pub branch_id: String,
+    pub role: Role,
+}
+
+impl Session {
+    pub fn is_owner(&self) -> bool {
+        self.role == Role::Owner
+    }
+}
+
+/// Session store backed by the `sessions` table.
+///
+/// Tokens are random and opaque; only their SHA-256 hash is stored, so a copy
+/// of the database cannot be used to hijack a session. Every lookup re-reads
+/// the user, which means deactivating a user or changing their role takes
+/// effect on their next command.
+pub struct SessionStore {
+    pool: SqlitePool,
+    ttl: chrono::Duration,
+}
+
+impl SessionStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            ttl: chrono::Duration::hours(12),
+        }
+    }
+
+    /// Create a session for a user. Returns the token and its RFC 3339 expiry.
+    pub async fn create(&self, user_id: &str) -> Result<(String, String), String> {
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let expires_at = chrono::Utc::now() + self.ttl;
+
+        sqlx::query("INSERT INTO sessions (token_hash, user_id, expires_at) VALUES (?, ?, ?)")
+            .bind(hash_token(&token))
+            .bind(user_id)
+            .bind(expires_at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to create session: {}", e))?;
+
+        Ok((token, expires_at.to_rfc3339()))
+    }
+
+    /// Resolve a token to the current user
+    pub async fn resolve(&self, token: &str) -> Result<Session, String> {
+        let row: Option<(String, String, String, String, String)> = sqlx::query_as(
+            r#"
+            SELECT u.id, u.username, u.full_name, u.branch_id, u.role
+            FROM sessions s
+            JOIN users u ON s.user_id = u.id
+            WHERE s.token_hash = ?
+              AND s.revoked_at IS NULL
+              AND s.expires_at > datetime('now')
+              AND u.is_active = 1
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let (user_id, username, full_name, branch_id, role) =
+            row.ok_or_else(|| "Session expired, please log in again".to_string())?;
+
+        Ok(Session {
+            user_id,
+            username,
+            full_name,
+            branch_id,
+            role: Role::parse(&role).ok_or_else(|| format!("Unknown role: {}", role))?,
+        })
+    }
+
+    /// Resolve a token and require at least the given role
+    pub async fn authorize(&self, token: &str, required: Role) -> Result<Session, String> {
+        let session = self.resolve(token).await?;
+        if session.role < required {
+            return Err("You do not have permission to perform this action".to_string());
+        }
+        Ok(session)
+    }
+
+    /// Revoke a single session (logout)
+    pub async fn revoke(&self, token: &str) -> Result<(), String> {
+        sqlx::query(
+            "UPDATE sessions SET revoked_at = datetime('now') WHERE token_hash = ? AND revoked_at IS NULL",
+        )
+        .bind(hash_token(token))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Revoke every session of a user, optionally keeping the caller's own
+    pub async fn revoke_user(&self, user_id: &str, keep_token: Option<&str>) -> Result<(), String> {
+        sqlx::query(
+            r#"
+            UPDATE sessions SET revoked_at = datetime('now')
+            WHERE user_id = ? AND revoked_at IS NULL AND token_hash IS NOT ?
+            "#,
+        )
+        .bind(user_id)
+        .bind(keep_token.map(hash_token))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Delete sessions that expired or were revoked more than a day ago
+    pub async fn cleanup(&self) -> Result<(), String> {
+        sqlx::query(
+            r#"
+            DELETE FROM sessions
+            WHERE expires_at < datetime('now', '-1 day')
+               OR revoked_at < datetime('now', '-1 day')
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let store = SessionStore::new(pool.clone());
+
+        let (token, _) = store.create("admin").await.unwrap();
+        let session = store.authorize(&token, Role::Owner).await.unwrap();
+        assert_eq!(session.user_id, "admin");
+        assert!(store.resolve("not-a-token").await.is_err());
+
+        // Demoted users lose owner access on their next command
+        sqlx::query("UPDATE users SET role = 'kasir' WHERE id = 'admin'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(store.authorize(&token, Role::Owner).await.is_err());
+        assert!(store.authorize(&token, Role::Kasir).await.is_ok());
+
+        store.revoke(&token).await.unwrap();
+        assert!(store.resolve(&token).await.is_err());
+    }
+}
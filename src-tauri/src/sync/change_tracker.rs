@@ -1,7 +1,36 @@
-use sqlx::SqlitePool;
+use super::SyncError;
+use sqlx::{SqliteConnection, SqlitePool};
 use uuid::Uuid;
 
-/// Change tracker for logging local changes to sync_log table
+/// Stop the `sync_log` triggers from capturing writes made on `conn`, which
+/// must be inside a transaction. For writes that mirror Salesforce (pulls) and
+/// must not be pushed back. Call `resume_capture` before committing; if the
+/// transaction is rolled back instead, capture resumes on its own.
+pub async fn suspend_capture(conn: &mut SqliteConnection) -> Result<(), SyncError> {
+    sqlx::query("INSERT INTO sync_capture_suspended (id) VALUES (1)")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| SyncError::Db(format!("Failed to suspend change capture: {}", e)))?;
+    Ok(())
+}
+
+pub async fn resume_capture(conn: &mut SqliteConnection) -> Result<(), SyncError> {
+    sqlx::query("DELETE FROM sync_capture_suspended")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| SyncError::Db(format!("Failed to resume change capture: {}", e)))?;
+    Ok(())
+}
+
+/// Failed attempts before a change is dead-lettered
+pub const MAX_RETRIES: i32 = 5;
+
+/// Wait before the first retry of a failed change
+const RETRY_BACKOFF_SECONDS: i64 = 30;
+
+/// Change tracker for the sync_log table. Writes to the synced tables are
+/// logged by triggers (see `Step::CaptureChanges`); `log_change` is only for
+/// queueing a record by hand.
 pub struct ChangeTracker {
     pool: SqlitePool,
 }
@@ -11,25 +40,21 @@ impl ChangeTracker {
         Self { pool }
     }
 
-    /// Log a change for later sync
+    /// Log a change for later sync. If the record already has a change
+    /// queued, the `sync_log_coalesce` trigger merges this one into it.
     pub async fn log_change(
         &self,
         table_name: &str,
         record_id: &str,
         action: &str,
         payload: Option<&str>,
-    ) -> Result<(), String> {
+    ) -> Result<(), SyncError> {
         let id = Uuid::new_v4().to_string();
 
         sqlx::query(
             r#"
             INSERT INTO sync_log (id, table_name, record_id, action, payload, synced)
             VALUES (?, ?, ?, ?, ?, 0)
-            ON CONFLICT(table_name, record_id) DO UPDATE SET
-                action = excluded.action,
-                payload = excluded.payload,
-                synced = 0,
-                created_at = datetime('now')
             "#,
         )
         .bind(&id)
@@ -39,99 +64,125 @@ impl ChangeTracker {
         .bind(payload)
         .execute(&self.pool)
         .await
-        .map_err(|e| format!("Failed to log change: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to log change: {}", e)))?;
 
         Ok(())
     }
 
     /// Get pending changes for a specific table
-    pub async fn get_pending_changes(&self, table_name: &str) -> Result<Vec<PendingChange>, String> {
+    pub async fn get_pending_changes(&self, table_name: &str) -> Result<Vec<PendingChange>, SyncError> {
         let changes: Vec<PendingChange> = sqlx::query_as::<_, PendingChange>(
             r#"
-            SELECT id, table_name, record_id, action, payload, retry_count
+            SELECT id, table_name, record_id, action, payload, retry_count, revision, created_at
             FROM sync_log
-            WHERE table_name = ? AND synced = 0 AND retry_count < 5
+            WHERE table_name = ? AND synced = 0 AND dead_lettered_at IS NULL
+              AND (next_retry_at IS NULL OR next_retry_at <= datetime('now'))
             ORDER BY created_at ASC
             "#,
         )
         .bind(table_name)
         .fetch_all(&self.pool)
         .await
-        .map_err(|e| format!("Failed to get pending changes: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to get pending changes: {}", e)))?;
 
         Ok(changes)
     }
 
     /// Get all pending changes across all tables
-    pub async fn get_all_pending_changes(&self) -> Result<Vec<PendingChange>, String> {
+    pub async fn get_all_pending_changes(&self) -> Result<Vec<PendingChange>, SyncError> {
         let changes: Vec<PendingChange> = sqlx::query_as::<_, PendingChange>(
             r#"
-            SELECT id, table_name, record_id, action, payload, retry_count
+            SELECT id, table_name, record_id, action, payload, retry_count, revision, created_at
             FROM sync_log
-            WHERE synced = 0 AND retry_count < 5
+            WHERE synced = 0 AND dead_lettered_at IS NULL
+              AND (next_retry_at IS NULL OR next_retry_at <= datetime('now'))
             ORDER BY created_at ASC
             "#,
         )
         .fetch_all(&self.pool)
         .await
-        .map_err(|e| format!("Failed to get pending changes: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to get pending changes: {}", e)))?;
 
         Ok(changes)
     }
 
-    /// Count pending changes
-    pub async fn count_pending_changes(&self) -> Result<i32, String> {
+    /// Count pending changes, including those waiting to be retried
+    pub async fn count_pending_changes(&self) -> Result<i32, SyncError> {
+        let count: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM sync_log WHERE synced = 0 AND dead_lettered_at IS NULL",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| SyncError::Db(format!("Failed to count pending changes: {}", e)))?;
+
+        Ok(count.0 as i32)
+    }
+
+    /// Count changes that ran out of retries
+    pub async fn count_dead_letters(&self) -> Result<i32, SyncError> {
         let count: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM sync_log WHERE synced = 0 AND retry_count < 5",
+            "SELECT COUNT(*) FROM sync_log WHERE synced = 0 AND dead_lettered_at IS NOT NULL",
         )
         .fetch_one(&self.pool)
         .await
-        .map_err(|e| format!("Failed to count pending changes: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to count dead letters: {}", e)))?;
 
         Ok(count.0 as i32)
     }
 
-    /// Mark a change as synced
-    pub async fn mark_synced(&self, id: &str) -> Result<(), String> {
+    /// Mark a change as synced, unless the record changed again since it
+    /// was read, in which case the newer change stays queued
+    pub async fn mark_synced(&self, change: &PendingChange) -> Result<(), SyncError> {
         sqlx::query(
-            "UPDATE sync_log SET synced = 1, synced_at = datetime('now') WHERE id = ?",
+            "UPDATE sync_log SET synced = 1, synced_at = datetime('now') WHERE id = ? AND revision = ?",
         )
-        .bind(id)
+        .bind(&change.id)
+        .bind(change.revision)
         .execute(&self.pool)
         .await
-        .map_err(|e| format!("Failed to mark as synced: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to mark as synced: {}", e)))?;
 
         Ok(())
     }
 
     /// Mark multiple changes as synced
-    pub async fn mark_synced_batch(&self, ids: &[String]) -> Result<(), String> {
-        for id in ids {
-            self.mark_synced(id).await?;
+    pub async fn mark_synced_batch(&self, changes: &[PendingChange]) -> Result<(), SyncError> {
+        for change in changes {
+            self.mark_synced(change).await?;
         }
         Ok(())
     }
 
-    /// Mark a change as failed with error message
-    pub async fn mark_failed(&self, id: &str, error_message: &str) -> Result<(), String> {
+    /// Mark a change as failed with the error. It is retried after a
+    /// backoff that doubles with each failure; after `MAX_RETRIES`, or at
+    /// once if retrying cannot help, it is dead-lettered instead. A newer
+    /// change to the record is left to be pushed on its own.
+    pub async fn mark_failed(&self, change: &PendingChange, error: &SyncError) -> Result<(), SyncError> {
         sqlx::query(
             r#"
             UPDATE sync_log
-            SET error_message = ?, retry_count = retry_count + 1
-            WHERE id = ?
+            SET error_message = ?,
+                retry_count = retry_count + 1,
+                next_retry_at = datetime('now', '+' || (? << retry_count) || ' seconds'),
+                dead_lettered_at = CASE WHEN ? OR retry_count + 1 >= ? THEN datetime('now') END
+            WHERE id = ? AND revision = ?
             "#,
         )
-        .bind(error_message)
-        .bind(id)
+        .bind(error.to_string())
+        .bind(RETRY_BACKOFF_SECONDS)
+        .bind(!error.is_retryable())
+        .bind(MAX_RETRIES)
+        .bind(&change.id)
+        .bind(change.revision)
         .execute(&self.pool)
         .await
-        .map_err(|e| format!("Failed to mark as failed: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to mark as failed: {}", e)))?;
 
         Ok(())
     }
 
     /// Clear old synced records (keep last 7 days)
-    pub async fn cleanup_old_records(&self) -> Result<i32, String> {
+    pub async fn cleanup_old_records(&self) -> Result<i32, SyncError> {
         let result = sqlx::query(
             r#"
             DELETE FROM sync_log
@@ -140,7 +191,7 @@ impl ChangeTracker {
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| format!("Failed to cleanup old records: {}", e))?;
+        .map_err(|e| SyncError::Db(format!("Failed to cleanup old records: {}", e)))?;
 
         Ok(result.rows_affected() as i32)
     }
@@ -155,4 +206,186 @@ pub struct PendingChange {
     pub action: String,
     pub payload: Option<String>,
     pub retry_count: i32,
+    /// Bumped each time a later change is folded into this one
+    pub revision: i64,
+    pub created_at: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    async fn log_entries(pool: &SqlitePool) -> Vec<(String, String, Option<String>, bool)> {
+        sqlx::query_as("SELECT record_id, action, payload, synced FROM sync_log ORDER BY rowid")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn actions(pool: &SqlitePool) -> Vec<(String, String)> {
+        log_entries(pool)
+            .await
+            .into_iter()
+            .map(|(record_id, action, _, _)| (record_id, action))
+            .collect()
+    }
+
+    async fn mark_all_synced(pool: &SqlitePool) {
+        sqlx::query("UPDATE sync_log SET synced = 1, synced_at = datetime('now')")
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_triggers_capture_local_writes_only() {
+        let pool = memory_pool().await;
+        let tracker = ChangeTracker::new(pool.clone());
+        // Sample data from the first start is not queued
+        assert!(log_entries(&pool).await.is_empty());
+
+        sqlx::query("INSERT INTO customers (id, name, phone) VALUES ('c1', 'Budi', '0811')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            actions(&pool).await,
+            vec![("c1".to_string(), "insert".to_string())]
+        );
+        mark_all_synced(&pool).await;
+
+        // Writing back the Salesforce id is not a change to push
+        sqlx::query("UPDATE customers SET salesforce_id = 'a02' WHERE id = 'c1'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+
+        // Nor is a pulled write
+        let mut tx = pool.begin().await.unwrap();
+        suspend_capture(&mut tx).await.unwrap();
+        sqlx::query("UPDATE customers SET notes = 'from Salesforce' WHERE id = 'c1'")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        resume_capture(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+
+        sqlx::query("UPDATE customers SET name = 'Budi S' WHERE id = 'c1'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM customers WHERE id = 'c1'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let entries = log_entries(&pool).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].1, "delete");
+        assert!(!entries[0].3);
+        let payload: serde_json::Value =
+            serde_json::from_str(entries[0].2.as_deref().unwrap()).unwrap();
+        assert_eq!(payload["name"], "Budi S");
+        assert_eq!(payload["salesforce_id"], "a02");
+    }
+
+    #[tokio::test]
+    async fn test_changes_to_one_record_are_coalesced() {
+        let pool = memory_pool().await;
+        let tracker = ChangeTracker::new(pool.clone());
+        let log = |record_id: &'static str, action: &'static str| {
+            let tracker = &tracker;
+            async move {
+                tracker
+                    .log_change("products", record_id, action, Some(action))
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // Insert then update stays an insert, with the latest payload
+        log("p1", "insert").await;
+        log("p1", "update").await;
+        // Insert then delete cancels out
+        log("p2", "insert").await;
+        log("p2", "delete").await;
+        // Update then delete becomes a delete
+        log("p3", "update").await;
+        log("p3", "delete").await;
+        // Delete then update stays the delete, so the record is not restored
+        log("p4", "delete").await;
+        log("p4", "update").await;
+
+        let entries = log_entries(&pool).await;
+        assert_eq!(
+            entries
+                .iter()
+                .map(|(id, action, payload, _)| (id.as_str(), action.as_str(), payload.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("p1", "insert", Some("update")),
+                ("p3", "delete", Some("delete")),
+                ("p4", "delete", Some("delete"))
+            ]
+        );
+
+        // Once pushed, a record's next change is queued as itself
+        mark_all_synced(&pool).await;
+        log("p1", "update").await;
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 1);
+        assert_eq!(
+            actions(&pool).await,
+            vec![
+                ("p1".to_string(), "update".to_string()),
+                ("p3".to_string(), "delete".to_string()),
+                ("p4".to_string(), "delete".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_made_during_push_stays_queued() {
+        let pool = memory_pool().await;
+        let tracker = ChangeTracker::new(pool.clone());
+        tracker
+            .log_change("customers", "c1", "update", Some("first"))
+            .await
+            .unwrap();
+        let pushing = tracker.get_pending_changes("customers").await.unwrap().remove(0);
+
+        // Edited while the first version is on its way to Salesforce
+        tracker
+            .log_change("customers", "c1", "update", Some("second"))
+            .await
+            .unwrap();
+        tracker.mark_synced(&pushing).await.unwrap();
+
+        let pending = tracker.get_pending_changes("customers").await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, pushing.id);
+        assert_eq!(pending[0].payload.as_deref(), Some("second"));
+
+        // Nor does a failure of the older version count against the newer
+        tracker
+            .mark_failed(&pushing, &SyncError::Duplicate("duplicate".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(tracker.count_dead_letters().await.unwrap(), 0);
+
+        tracker.mark_synced(&pending[0]).await.unwrap();
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+    }
 }
@@ -0,0 +1,587 @@
+use super::change_tracker::{resume_capture, suspend_capture};
+use super::push;
+use super::SyncError;
+use crate::models::SyncConflict;
+use crate::salesforce::mapper::SfLookups;
+use serde_json::{Map, Value};
+use sqlx::SqliteConnection;
+
+/// Tables whose Salesforce changes are pulled, so a conflict found while
+/// pushing can be left for the pull to settle
+pub const PULLED_TABLES: &[&str] = &[
+    "gold_prices",
+    "products",
+    "inventory",
+    "customers",
+    "transactions",
+];
+
+/// Fields that say nothing about the record's content
+const IGNORED_FIELDS: &[&str] = &["Id", "attributes", "SystemModstamp", "LastModifiedDate"];
+
+/// What to do when a record changed both locally and in Salesforce since it
+/// was last in step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    ServerWins,
+    LocalWins,
+    /// Take each side's changed fields; fall back to manual when both sides
+    /// changed the same field
+    Merge,
+    /// Queue the conflict for the owner
+    Manual,
+}
+
+impl ConflictPolicy {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy {
+            "server_wins" => Some(Self::ServerWins),
+            "local_wins" => Some(Self::LocalWins),
+            "merge" => Some(Self::Merge),
+            "manual" => Some(Self::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// The record as it was when both sides last agreed
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SyncVersion {
+    pub sf_modstamp: Option<String>,
+    pub base_json: Option<String>,
+}
+
+impl SyncVersion {
+    /// Whether Salesforce has changed the record since. Records synced before
+    /// versions were kept count as unchanged.
+    pub fn remote_changed(&self, remote_modstamp: Option<&str>) -> bool {
+        match (self.sf_modstamp.as_deref(), remote_modstamp) {
+            (Some(known), Some(remote)) => known != remote,
+            _ => false,
+        }
+    }
+
+    fn base(&self) -> Option<Value> {
+        self.base_json
+            .as_deref()
+            .and_then(|b| serde_json::from_str(b).ok())
+    }
+}
+
+/// Outcome of comparing a pulled record with the local one
+#[derive(Debug)]
+pub enum PullDecision {
+    /// Write this record (the pulled one, or a merge of both sides) locally
+    Apply(Value),
+    /// Leave the local record alone
+    Skip,
+}
+
+pub async fn policy(conn: &mut SqliteConnection, table: &str) -> Result<ConflictPolicy, SyncError> {
+    let policy: Option<(String,)> =
+        sqlx::query_as("SELECT policy FROM sync_conflict_policies WHERE table_name = ?")
+            .bind(table)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    Ok(policy
+        .and_then(|(p,)| ConflictPolicy::parse(&p))
+        .unwrap_or(ConflictPolicy::Manual))
+}
+
+pub async fn version(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+) -> Result<Option<SyncVersion>, SyncError> {
+    sqlx::query_as::<_, SyncVersion>(
+        "SELECT sf_modstamp, base_json FROM sync_versions WHERE table_name = ? AND record_id = ?",
+    )
+    .bind(table)
+    .bind(record_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+/// Record that the local record and Salesforce agree on `base`
+pub async fn save_version(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+    sf_modstamp: Option<&str>,
+    base: &Value,
+) -> Result<(), SyncError> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_versions (table_name, record_id, sf_modstamp, base_json, synced_at)
+        VALUES (?, ?, ?, ?, datetime('now'))
+        ON CONFLICT(table_name, record_id) DO UPDATE SET
+            sf_modstamp = excluded.sf_modstamp,
+            base_json = excluded.base_json,
+            synced_at = excluded.synced_at
+        "#,
+    )
+    .bind(table)
+    .bind(record_id)
+    .bind(sf_modstamp)
+    .bind(base.to_string())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// When the record's queued local change was made, if it has one
+pub async fn pending_change(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+) -> Result<Option<String>, SyncError> {
+    let pending: Option<(Option<String>,)> = sqlx::query_as(
+        "SELECT created_at FROM sync_log WHERE table_name = ? AND record_id = ? AND synced = 0",
+    )
+    .bind(table)
+    .bind(record_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(pending.map(|(at,)| at.unwrap_or_default()))
+}
+
+pub async fn has_open_conflict(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+) -> Result<bool, SyncError> {
+    let open: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM sync_conflicts WHERE table_name = ? AND record_id = ? AND status = 'open'",
+    )
+    .bind(table)
+    .bind(record_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(open.0 > 0)
+}
+
+/// Three-way merge of a record's fields. Each field takes the side that
+/// changed it since `base`; fields both sides changed differently are
+/// returned as the error.
+pub fn merge(base: Option<&Value>, local: &Value, remote: &Value) -> Result<Value, Vec<String>> {
+    let empty = Map::new();
+    let fields = |v: Option<&Value>| v.and_then(Value::as_object).unwrap_or(&empty).clone();
+    let (base, local, mut merged) = (fields(base), fields(Some(local)), fields(Some(remote)));
+    let remote = merged.clone();
+
+    let mut conflicting = Vec::new();
+    for (field, local_value) in &local {
+        if IGNORED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+        let remote_value = remote.get(field).unwrap_or(&Value::Null);
+        let base_value = base.get(field).unwrap_or(&Value::Null);
+
+        if local_value == remote_value || local_value == base_value {
+            continue;
+        }
+        if remote_value == base_value {
+            merged.insert(field.clone(), local_value.clone());
+        } else {
+            conflicting.push(field.clone());
+        }
+    }
+
+    if conflicting.is_empty() {
+        Ok(Value::Object(merged))
+    } else {
+        Err(conflicting)
+    }
+}
+
+/// Decide what a pull does with `remote`, the Salesforce version of local
+/// record `record_id`, given both sides' changes since they last agreed
+pub async fn check_pull(
+    conn: &mut SqliteConnection,
+    lookups: &SfLookups,
+    table: &str,
+    record_id: &str,
+    remote_modstamp: Option<&str>,
+    remote: &Value,
+) -> Result<PullDecision, SyncError> {
+    if has_open_conflict(conn, table, record_id).await? {
+        refresh_conflict(conn, table, record_id, remote_modstamp, remote).await?;
+        return Ok(PullDecision::Skip);
+    }
+
+    let Some(local_changed_at) = pending_change(conn, table, record_id).await? else {
+        return Ok(PullDecision::Apply(remote.clone()));
+    };
+    let version = version(conn, table, record_id).await?;
+    let remote_changed = version
+        .as_ref()
+        .is_some_and(|v| v.remote_changed(remote_modstamp));
+    if !remote_changed {
+        // Only the local side changed (or the record predates versions and
+        // is treated as such); the push sends it
+        return Ok(PullDecision::Skip);
+    }
+
+    let Some(local) = push::local_record(conn, table, record_id, lookups).await? else {
+        return Ok(PullDecision::Apply(remote.clone()));
+    };
+    let base = version.as_ref().and_then(SyncVersion::base);
+
+    match policy(conn, table).await? {
+        ConflictPolicy::ServerWins => {
+            discard_pending_change(conn, table, record_id).await?;
+            Ok(PullDecision::Apply(remote.clone()))
+        }
+        ConflictPolicy::LocalWins => {
+            // The push may now overwrite this Salesforce version
+            save_version(conn, table, record_id, remote_modstamp, remote).await?;
+            Ok(PullDecision::Skip)
+        }
+        policy => match merge(base.as_ref(), &local, remote) {
+            // The local change stays queued, so the push sends the merge
+            Ok(merged) if policy == ConflictPolicy::Merge => Ok(PullDecision::Apply(merged)),
+            merged => {
+                let fields = merged.err().unwrap_or_default();
+                record_conflict(
+                    conn,
+                    NewConflict {
+                        table,
+                        record_id,
+                        local: &local,
+                        remote,
+                        base: base.as_ref(),
+                        remote_modstamp,
+                        local_changed_at: Some(&local_changed_at),
+                        fields: &fields,
+                    },
+                )
+                .await?;
+                Ok(PullDecision::Skip)
+            }
+        },
+    }
+}
+
+pub struct NewConflict<'a> {
+    pub table: &'a str,
+    pub record_id: &'a str,
+    pub local: &'a Value,
+    pub remote: &'a Value,
+    pub base: Option<&'a Value>,
+    pub remote_modstamp: Option<&'a str>,
+    pub local_changed_at: Option<&'a str>,
+    /// Fields both sides changed since they last agreed
+    pub fields: &'a [String],
+}
+
+/// Queue a conflict for the owner. Until it is resolved, neither push nor
+/// pull touches the record.
+pub async fn record_conflict(
+    conn: &mut SqliteConnection,
+    conflict: NewConflict<'_>,
+) -> Result<(), SyncError> {
+    let salesforce_id = conflict
+        .remote
+        .get("Id")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    sqlx::query(
+        r#"
+        INSERT INTO sync_conflicts (id, table_name, record_id, salesforce_id, local_json,
+                                    remote_json, base_json, remote_modstamp, local_changed_at,
+                                    conflicting_fields)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(conflict.table)
+    .bind(conflict.record_id)
+    .bind(salesforce_id)
+    .bind(conflict.local.to_string())
+    .bind(conflict.remote.to_string())
+    .bind(conflict.base.map(Value::to_string))
+    .bind(conflict.remote_modstamp)
+    .bind(conflict.local_changed_at)
+    .bind(serde_json::to_string(conflict.fields)?)
+    .execute(&mut *conn)
+    .await?;
+
+    log::warn!(
+        "Sync conflict on {} {} queued for review",
+        conflict.table,
+        conflict.record_id
+    );
+    Ok(())
+}
+
+/// Keep an open conflict showing the latest Salesforce version
+async fn refresh_conflict(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+    remote_modstamp: Option<&str>,
+    remote: &Value,
+) -> Result<(), SyncError> {
+    sqlx::query(
+        r#"
+        UPDATE sync_conflicts SET remote_json = ?, remote_modstamp = ?
+        WHERE table_name = ? AND record_id = ? AND status = 'open'
+        "#,
+    )
+    .bind(remote.to_string())
+    .bind(remote_modstamp)
+    .bind(table)
+    .bind(record_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn discard_pending_change(
+    conn: &mut SqliteConnection,
+    table: &str,
+    record_id: &str,
+) -> Result<(), SyncError> {
+    sqlx::query("DELETE FROM sync_log WHERE table_name = ? AND record_id = ? AND synced = 0")
+        .bind(table)
+        .bind(record_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Settle an open conflict with the local (`"local"`) or Salesforce
+/// (`"server"`) version of the record. Returns the resolved conflict. Run it
+/// in a transaction, so a failure part way leaves the conflict open.
+pub async fn resolve(
+    conn: &mut SqliteConnection,
+    conflict_id: &str,
+    resolution: &str,
+    resolved_by: &str,
+) -> Result<SyncConflict, SyncError> {
+    let conflict = get_conflict(conn, conflict_id)
+        .await?
+        .filter(|c| c.status == "open")
+        .ok_or_else(|| SyncError::NotFound("Open conflict not found".to_string()))?;
+    let remote: Value = serde_json::from_str(&conflict.remote_json)?;
+
+    match resolution {
+        "local" => {
+            // With Salesforce's version recorded as seen, the next push sends
+            // the local change over it. Queue the record if nothing is pending.
+            let pending = pending_change(conn, &conflict.table_name, &conflict.record_id).await?;
+            if pending.is_none() {
+                sqlx::query(
+                    r#"
+                    INSERT INTO sync_log (id, table_name, record_id, action, payload)
+                    VALUES (?, ?, ?, 'update', NULL)
+                    "#,
+                )
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(&conflict.table_name)
+                .bind(&conflict.record_id)
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+        "server" => {
+            // Taking Salesforce's version is not a local change to push
+            suspend_capture(conn).await?;
+            super::pull::apply_remote(conn, &conflict.table_name, &remote).await?;
+            resume_capture(conn).await?;
+            discard_pending_change(conn, &conflict.table_name, &conflict.record_id).await?;
+        }
+        _ => {
+            return Err(SyncError::Validation {
+                field: Some("resolution".to_string()),
+                message: "must be 'local' or 'server'".to_string(),
+            })
+        }
+    }
+    save_version(
+        conn,
+        &conflict.table_name,
+        &conflict.record_id,
+        conflict.remote_modstamp.as_deref(),
+        &remote,
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE sync_conflicts
+        SET status = 'resolved', resolution = ?, resolved_by = ?, resolved_at = datetime('now')
+        WHERE id = ?
+        "#,
+    )
+    .bind(resolution)
+    .bind(resolved_by)
+    .bind(conflict_id)
+    .execute(&mut *conn)
+    .await?;
+
+    get_conflict(conn, conflict_id)
+        .await?
+        .ok_or_else(|| SyncError::NotFound("Conflict not found".to_string()))
+}
+
+pub async fn get_conflict(
+    conn: &mut SqliteConnection,
+    conflict_id: &str,
+) -> Result<Option<SyncConflict>, SyncError> {
+    sqlx::query_as::<_, SyncConflict>(
+        r#"
+        SELECT id, table_name, record_id, salesforce_id, local_json, remote_json, base_json,
+               remote_modstamp, local_changed_at, conflicting_fields, status, resolution,
+               resolved_by, resolved_at, created_at
+        FROM sync_conflicts WHERE id = ?
+        "#,
+    )
+    .bind(conflict_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    fn sf_product(name: &str, labor_cost: i32) -> Value {
+        json!({
+            "Id": "a01", "Name": name, "SKU__c": "CC-01", "Gold_Type__c": "LM",
+            "Gold_Purity__c": 750, "Weight_Gram__c": 5.0, "Labor_Cost__c": labor_cost,
+            "Is_Active__c": true
+        })
+    }
+
+    #[test]
+    fn test_merge_takes_each_sides_changes() {
+        let base =
+            json!({ "Id": "a01", "Name": "Cincin", "Labor_Cost__c": 50000, "Is_Active__c": true });
+        let local = json!({ "Name": "Cincin", "Labor_Cost__c": 60000, "Is_Active__c": true });
+        let remote = json!({ "Id": "a01", "Name": "Cincin Emas", "Labor_Cost__c": 50000, "Is_Active__c": true });
+
+        let merged = merge(Some(&base), &local, &remote).unwrap();
+        assert_eq!(merged["Name"], "Cincin Emas");
+        assert_eq!(merged["Labor_Cost__c"], 60000);
+        assert_eq!(merged["Id"], "a01");
+
+        // Both sides changed the labour cost
+        let remote =
+            json!({ "Id": "a01", "Name": "Cincin", "Labor_Cost__c": 55000, "Is_Active__c": true });
+        assert_eq!(
+            merge(Some(&base), &local, &remote).unwrap_err(),
+            vec!["Labor_Cost__c".to_string()]
+        );
+        // Without a base every difference is a conflict
+        assert!(merge(None, &local, &remote).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_manual_conflict_is_held_until_resolved() {
+        let pool = memory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO products (id, category_id, sku, name, gold_type, gold_purity, weight_gram, labor_cost, salesforce_id)
+            VALUES ('p1', 'cat-1', 'CC-01', 'Cincin', 'LM', 750, 5.0, 50000, 'a01')
+            "#,
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        sqlx::query("DELETE FROM sync_log")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query(
+            "UPDATE sync_conflict_policies SET policy = 'manual' WHERE table_name = 'products'",
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        save_version(
+            &mut conn,
+            "products",
+            "p1",
+            Some("t1"),
+            &sf_product("Cincin", 50000),
+        )
+        .await
+        .unwrap();
+
+        // Unchanged in Salesforce: the pulled record is applied as is
+        let lookups = SfLookups::new();
+        let remote = sf_product("Cincin Emas", 50000);
+        assert!(matches!(
+            check_pull(&mut conn, &lookups, "products", "p1", Some("t1"), &remote)
+                .await
+                .unwrap(),
+            PullDecision::Apply(_)
+        ));
+
+        // Edited on both sides
+        sqlx::query("UPDATE products SET labor_cost = 60000 WHERE id = 'p1'")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        assert!(matches!(
+            check_pull(&mut conn, &lookups, "products", "p1", Some("t2"), &remote)
+                .await
+                .unwrap(),
+            PullDecision::Skip
+        ));
+        assert!(has_open_conflict(&mut conn, "products", "p1")
+            .await
+            .unwrap());
+
+        let (conflict_id,): (String,) = sqlx::query_as("SELECT id FROM sync_conflicts")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert!(resolve(&mut conn, &conflict_id, "mine", "admin")
+            .await
+            .is_err());
+        let resolved = resolve(&mut conn, &conflict_id, "server", "admin")
+            .await
+            .unwrap();
+        assert_eq!(resolved.status, "resolved");
+        assert_eq!(resolved.resolution.as_deref(), Some("server"));
+
+        // Salesforce's version is written locally, without queueing it for push
+        let product: (String, i32) =
+            sqlx::query_as("SELECT name, labor_cost FROM products WHERE id = 'p1'")
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(product, ("Cincin Emas".to_string(), 50000));
+        assert_eq!(
+            pending_change(&mut conn, "products", "p1").await.unwrap(),
+            None
+        );
+        let version = version(&mut conn, "products", "p1").await.unwrap().unwrap();
+        assert_eq!(version.sf_modstamp.as_deref(), Some("t2"));
+    }
+}
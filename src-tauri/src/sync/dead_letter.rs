@@ -0,0 +1,247 @@
+use crate::models::SyncLog;
+use serde_json::{Map, Value};
+use sqlx::SqliteConnection;
+
+/// Columns a retry may not change: they tie the record to its sync history
+const PROTECTED_COLUMNS: &[&str] = &["id", "salesforce_id", "created_at"];
+
+const SYNC_LOG_COLUMNS: &str = "id, table_name, record_id, action, payload, synced, synced_at, \
+     error_message, retry_count, next_retry_at, dead_lettered_at, created_at";
+
+/// Changes that ran out of retries, oldest first
+pub async fn list(conn: &mut SqliteConnection) -> Result<Vec<SyncLog>, String> {
+    sqlx::query_as::<_, SyncLog>(&format!(
+        "SELECT {} FROM sync_log WHERE synced = 0 AND dead_lettered_at IS NOT NULL ORDER BY dead_lettered_at",
+        SYNC_LOG_COLUMNS
+    ))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn get(conn: &mut SqliteConnection, id: &str) -> Result<Option<SyncLog>, String> {
+    sqlx::query_as::<_, SyncLog>(&format!(
+        "SELECT {} FROM sync_log WHERE id = ? AND synced = 0 AND dead_lettered_at IS NOT NULL",
+        SYNC_LOG_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Put a dead-lettered change back in the queue, with a fresh set of
+/// retries. `edited` holds corrected column values: for inserts and updates
+/// they are written to the local record, since that is what gets pushed;
+/// for deletes they replace the payload.
+pub async fn retry(
+    conn: &mut SqliteConnection,
+    id: &str,
+    edited: Option<&Map<String, Value>>,
+) -> Result<SyncLog, String> {
+    let entry = get(conn, id)
+        .await?
+        .ok_or_else(|| "Dead-lettered change not found".to_string())?;
+
+    if let Some(edited) = edited {
+        if entry.action == "delete" {
+            sqlx::query("UPDATE sync_log SET payload = ? WHERE id = ?")
+                .bind(Value::Object(edited.clone()).to_string())
+                .bind(id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+        } else {
+            update_record(conn, &entry.table_name, &entry.record_id, edited).await?;
+        }
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE sync_log
+        SET retry_count = 0, error_message = NULL, next_retry_at = NULL, dead_lettered_at = NULL
+        WHERE id = ?
+        "#,
+    )
+    .bind(id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    sqlx::query_as::<_, SyncLog>(&format!(
+        "SELECT {} FROM sync_log WHERE id = ?",
+        SYNC_LOG_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Drop a dead-lettered change; the record stays as it is locally
+pub async fn discard(conn: &mut SqliteConnection, id: &str) -> Result<SyncLog, String> {
+    let entry = get(conn, id)
+        .await?
+        .ok_or_else(|| "Dead-lettered change not found".to_string())?;
+
+    sqlx::query("DELETE FROM sync_log WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(entry)
+}
+
+/// Write `edited` to the local record. Only existing, unprotected columns
+/// of the table are accepted, which also keeps the column names safe to
+/// put into the statement.
+async fn update_record(
+    conn: &mut SqliteConnection,
+    table_name: &str,
+    record_id: &str,
+    edited: &Map<String, Value>,
+) -> Result<(), String> {
+    let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?)")
+        .bind(table_name)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let columns: Vec<String> = columns.into_iter().map(|(name,)| name).collect();
+
+    for field in edited.keys() {
+        if !columns.contains(field) {
+            return Err(format!("{} has no column {}", table_name, field));
+        }
+        if PROTECTED_COLUMNS.contains(&field.as_str()) {
+            return Err(format!("{} cannot be edited", field));
+        }
+    }
+    if edited.is_empty() {
+        return Ok(());
+    }
+
+    let assignments: Vec<String> = edited
+        .keys()
+        .map(|field| format!("{} = ?", field))
+        .collect();
+    let statement = format!(
+        "UPDATE {} SET {} WHERE id = ?",
+        table_name,
+        assignments.join(", ")
+    );
+    let mut query = sqlx::query(&statement);
+    for value in edited.values() {
+        query = match value {
+            Value::Null => query.bind(None::<String>),
+            Value::Bool(b) => query.bind(*b),
+            Value::Number(n) if n.is_i64() => query.bind(n.as_i64()),
+            Value::Number(n) => query.bind(n.as_f64()),
+            Value::String(s) => query.bind(s.clone()),
+            other => query.bind(other.to_string()),
+        };
+    }
+    let updated = query
+        .bind(record_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if updated.rows_affected() == 0 {
+        return Err("The record no longer exists".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::change_tracker::{ChangeTracker, MAX_RETRIES};
+    use crate::sync::SyncError;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_failed_change_backs_off_then_dead_letters() {
+        let pool = memory_pool().await;
+        let tracker = ChangeTracker::new(pool.clone());
+        sqlx::query("INSERT INTO customers (id, name, phone) VALUES ('c1', 'Budi', '0811')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let change = tracker
+            .get_pending_changes("customers")
+            .await
+            .unwrap()
+            .remove(0);
+
+        // Held back until the backoff has passed
+        let timeout = SyncError::Network("timeout".to_string());
+        tracker.mark_failed(&change, &timeout).await.unwrap();
+        assert!(tracker
+            .get_pending_changes("customers")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 1);
+
+        for _ in 1..MAX_RETRIES {
+            tracker.mark_failed(&change, &timeout).await.unwrap();
+        }
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+        assert_eq!(tracker.count_dead_letters().await.unwrap(), 1);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let dead = list(&mut conn).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].error_message.as_deref(), Some("timeout"));
+
+        // Unknown and protected columns are refused
+        let bad = json!({ "salesforce_id": "a02" });
+        assert!(retry(&mut conn, &change.id, bad.as_object()).await.is_err());
+        let bad = json!({ "phone_no": "0812" });
+        assert!(retry(&mut conn, &change.id, bad.as_object()).await.is_err());
+
+        let fix = json!({ "phone": "0812" });
+        let retried = retry(&mut conn, &change.id, fix.as_object()).await.unwrap();
+        assert_eq!(retried.action, "insert");
+        assert_eq!(retried.retry_count, 0);
+        assert!(retried.dead_lettered_at.is_none());
+        let phone: (String,) = sqlx::query_as("SELECT phone FROM customers WHERE id = 'c1'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(phone.0, "0812");
+        drop(conn);
+        let mut pending = tracker.get_pending_changes("customers").await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let change = pending.remove(0);
+
+        // A value Salesforce refuses is not retried
+        let invalid = SyncError::Validation {
+            field: Some("Phone__c".to_string()),
+            message: "invalid".to_string(),
+        };
+        tracker.mark_failed(&change, &invalid).await.unwrap();
+        assert_eq!(tracker.count_dead_letters().await.unwrap(), 1);
+
+        // Discarding drops the change but not the record
+        let mut conn = pool.acquire().await.unwrap();
+        discard(&mut conn, &change.id).await.unwrap();
+        assert!(get(&mut conn, &change.id).await.unwrap().is_none());
+        drop(conn);
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+        assert_eq!(tracker.count_dead_letters().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,180 @@
+use super::conflicts::{self, ConflictPolicy};
+use super::SyncError;
+use sqlx::SqliteConnection;
+
+/// Tables whose records are deleted by setting `deleted_at`. Records
+/// deleted in Salesforce are marked the same way when pulled.
+pub const SOFT_DELETE_TABLES: &[&str] = &["products", "inventory", "customers"];
+
+/// Tables whose Salesforce object has an `Is_Active__c` field
+const DEACTIVATABLE_TABLES: &[&str] = &["products"];
+
+/// How a local delete is carried out in Salesforce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Clear `Is_Active__c`, keeping the record
+    Deactivate,
+    Delete,
+}
+
+impl DeleteMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "deactivate" => Some(Self::Deactivate),
+            "delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+}
+
+pub fn can_deactivate(table: &str) -> bool {
+    DEACTIVATABLE_TABLES.contains(&table)
+}
+
+pub async fn mode(conn: &mut SqliteConnection, table: &str) -> Result<DeleteMode, SyncError> {
+    let mode: Option<(String,)> =
+        sqlx::query_as("SELECT mode FROM sync_delete_modes WHERE table_name = ?")
+            .bind(table)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    Ok(mode
+        .and_then(|(m,)| DeleteMode::parse(&m))
+        .filter(|m| *m == DeleteMode::Delete || can_deactivate(table))
+        .unwrap_or(DeleteMode::Delete))
+}
+
+/// Mark the local copy of a record deleted in Salesforce as deleted; run it
+/// with change capture suspended. A record with a local change waiting to be
+/// pushed is kept, unless the table's conflict policy is server wins.
+/// Returns the local ID of the record marked.
+pub async fn mirror_remote_delete(
+    conn: &mut SqliteConnection,
+    table: &str,
+    salesforce_id: &str,
+) -> Result<Option<String>, SyncError> {
+    if !SOFT_DELETE_TABLES.contains(&table) {
+        return Err(SyncError::Mapping(format!("{} records cannot be deleted", table)));
+    }
+
+    let local: Option<(String,)> = sqlx::query_as(&format!(
+        "SELECT id FROM {} WHERE salesforce_id = ? AND deleted_at IS NULL",
+        table
+    ))
+    .bind(salesforce_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some((id,)) = local else {
+        return Ok(None);
+    };
+
+    if conflicts::has_open_conflict(conn, table, &id).await? {
+        return Ok(None);
+    }
+    if conflicts::pending_change(conn, table, &id).await?.is_some() {
+        if conflicts::policy(conn, table).await? != ConflictPolicy::ServerWins {
+            return Ok(None);
+        }
+        conflicts::discard_pending_change(conn, table, &id).await?;
+    }
+
+    sqlx::query(&format!(
+        "UPDATE {} SET deleted_at = datetime('now') WHERE id = ?",
+        table
+    ))
+    .bind(&id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::change_tracker::{resume_capture, suspend_capture, ChangeTracker};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    async fn execute(pool: &SqlitePool, sql: &str) {
+        sqlx::query(sql).execute(pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_soft_deletes_are_pushed_and_mirrored() {
+        let pool = memory_pool().await;
+        let tracker = ChangeTracker::new(pool.clone());
+        execute(&pool, "INSERT INTO customers (id, name, salesforce_id) VALUES ('c1', 'Budi', 'aC1'), ('c2', 'Sari', 'aC2')").await;
+        execute(&pool, "UPDATE sync_log SET synced = 1").await;
+
+        // A local soft delete is queued as a delete that still knows the
+        // Salesforce record
+        execute(
+            &pool,
+            "UPDATE customers SET deleted_at = datetime('now') WHERE id = 'c1'",
+        )
+        .await;
+        let changes = tracker.get_pending_changes("customers").await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].action, "delete");
+        let payload: serde_json::Value =
+            serde_json::from_str(changes[0].payload.as_deref().unwrap()).unwrap();
+        assert_eq!(payload["salesforce_id"], "aC1");
+        execute(&pool, "UPDATE sync_log SET synced = 1").await;
+
+        // Deleted in Salesforce while changed locally: kept, so the change
+        // can still be pushed
+        execute(
+            &pool,
+            "UPDATE customers SET name = 'Sari W' WHERE id = 'c2'",
+        )
+        .await;
+        let mut tx = pool.begin().await.unwrap();
+        suspend_capture(&mut tx).await.unwrap();
+        assert_eq!(
+            mirror_remote_delete(&mut tx, "customers", "aC2")
+                .await
+                .unwrap(),
+            None
+        );
+        resume_capture(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
+        execute(&pool, "UPDATE sync_log SET synced = 1").await;
+
+        let mut tx = pool.begin().await.unwrap();
+        suspend_capture(&mut tx).await.unwrap();
+        assert_eq!(
+            mirror_remote_delete(&mut tx, "customers", "aC2")
+                .await
+                .unwrap(),
+            Some("c2".to_string())
+        );
+        assert_eq!(
+            mirror_remote_delete(&mut tx, "customers", "aC9")
+                .await
+                .unwrap(),
+            None
+        );
+        resume_capture(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let deleted: Vec<(String,)> =
+            sqlx::query_as("SELECT id FROM customers WHERE deleted_at IS NOT NULL ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(deleted, vec![("c1".to_string(),), ("c2".to_string(),)]);
+        // Mirroring Salesforce is not pushed back
+        assert_eq!(tracker.count_pending_changes().await.unwrap(), 0);
+    }
+}
@@ -1,16 +1,38 @@
 use super::change_tracker::ChangeTracker;
 use super::pull::PullSync;
 use super::push::PushSync;
-use crate::models::{SyncConfig, SyncResult, SyncStatus};
+use super::SyncError;
+use crate::models::{ApiUsage, SyncConfig, SyncResult, SyncStatus};
 use crate::salesforce::api::SalesforceApi;
 use crate::salesforce::auth::{SalesforceCredentials, TokenManager};
+use crate::salesforce::bulk::DEFAULT_BULK_THRESHOLD;
 use crate::salesforce::client::SalesforceClient;
+use crate::secrets::SecretVault;
 use parking_lot::RwLock;
-use sqlx::SqlitePool;
+use sqlx::{SqliteExecutor, SqlitePool};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::interval;
 
+/// Percent of the org's daily API limit past which only critical data is
+/// pulled, unless configured otherwise
+pub const DEFAULT_API_USAGE_THRESHOLD: u32 = 90;
+
+/// Reads the Salesforce settings, in `SyncConfig` field order
+pub const SYNC_CONFIG_SELECT: &str = r#"
+    SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token,
+           sf_auth_flow, sf_jwt_private_key, sf_jwt_audience, sf_instance_url, is_sandbox,
+           sync_enabled, sync_interval_minutes, bulk_threshold, api_usage_threshold,
+           created_at, updated_at
+    FROM sync_config WHERE id = 'default'
+    "#;
+
+/// The saved Salesforce settings, if any
+pub async fn load_config<'e>(executor: impl SqliteExecutor<'e>) -> Result<Option<SyncConfig>, SyncError> {
+    Ok(sqlx::query_as(SYNC_CONFIG_SELECT).fetch_optional(executor).await?)
+}
+
 /// Sync engine orchestrating push and pull operations
 pub struct SyncEngine {
     pool: SqlitePool,
@@ -21,11 +43,13 @@ pub struct SyncEngine {
     pull_sync: Arc<PullSync>,
     tracker: Arc<ChangeTracker>,
     is_syncing: Arc<RwLock<bool>>,
-    last_error: Arc<RwLock<Option<String>>>,
+    last_error: Arc<RwLock<Option<SyncError>>>,
+    api_usage_threshold: AtomicU32,
+    vault: Arc<SecretVault>,
 }
 
 impl SyncEngine {
-    pub fn new(pool: SqlitePool) -> Self {
+    pub fn new(pool: SqlitePool, vault: Arc<SecretVault>) -> Self {
         let token_manager = Arc::new(TokenManager::new());
         let client = Arc::new(SalesforceClient::new(token_manager.clone()));
         let api = Arc::new(SalesforceApi::new(client.clone()));
@@ -43,71 +67,107 @@ impl SyncEngine {
             tracker,
             is_syncing: Arc::new(RwLock::new(false)),
             last_error: Arc::new(RwLock::new(None)),
+            api_usage_threshold: AtomicU32::new(DEFAULT_API_USAGE_THRESHOLD),
+            vault,
         }
     }
 
-    /// Configure Salesforce credentials
-    pub fn configure(&self, config: &SyncConfig) -> Result<(), String> {
-        if config.sf_client_id.is_none() || config.sf_client_secret.is_none() {
-            return Err("Missing Salesforce credentials".to_string());
+    /// Configure Salesforce credentials from the stored (encrypted) config
+    pub fn configure(&self, config: &SyncConfig) -> Result<(), SyncError> {
+        let jwt_bearer = config.sf_auth_flow == "jwt_bearer";
+        let missing = if jwt_bearer {
+            config.sf_jwt_private_key.is_none() || config.sf_username.is_none()
+        } else {
+            config.sf_client_secret.is_none()
+        };
+        if config.sf_client_id.is_none() || missing {
+            return Err(SyncError::Auth("Missing Salesforce credentials".to_string()));
         }
+        let config = self.vault.decrypt_config(config).map_err(SyncError::Auth)?;
 
-        let credentials = SalesforceCredentials::new(
-            config.sf_client_id.clone().unwrap(),
-            config.sf_client_secret.clone().unwrap(),
-            config.sf_username.clone().unwrap_or_default(),
-            config.sf_password.clone().unwrap_or_default(),
-            config.sf_security_token.clone().unwrap_or_default(),
-            config.is_sandbox,
-        );
+        let credentials = if jwt_bearer {
+            SalesforceCredentials::new_jwt_bearer(
+                config.sf_client_id.clone().unwrap(),
+                config.sf_username.clone().unwrap(),
+                config.sf_jwt_private_key.clone().unwrap(),
+                config.sf_jwt_audience.clone(),
+                config.is_sandbox,
+            )
+            .map_err(SyncError::Auth)?
+        } else {
+            SalesforceCredentials::new(
+                config.sf_client_id.clone().unwrap(),
+                config.sf_client_secret.clone().unwrap(),
+                config.sf_username.clone().unwrap_or_default(),
+                config.sf_password.clone().unwrap_or_default(),
+                config.sf_security_token.clone().unwrap_or_default(),
+                config.is_sandbox,
+            )
+        };
 
         self.token_manager.set_credentials(credentials);
+        self.api.set_bulk_threshold(
+            config.bulk_threshold.map_or(DEFAULT_BULK_THRESHOLD, |threshold| threshold as usize),
+        );
+        self.api_usage_threshold.store(
+            config.api_usage_threshold.map_or(DEFAULT_API_USAGE_THRESHOLD, |threshold| threshold as u32),
+            Ordering::Relaxed,
+        );
         Ok(())
     }
 
+    /// The org's API usage, if it is past the configured threshold
+    fn api_limit_reached(&self) -> Option<ApiUsage> {
+        let threshold = self.api_usage_threshold.load(Ordering::Relaxed);
+        self.api.api_usage().filter(|usage| usage.percent() >= threshold)
+    }
+
     /// Test Salesforce connection
-    pub async fn test_connection(&self) -> Result<String, String> {
+    pub async fn test_connection(&self) -> Result<String, SyncError> {
         self.token_manager.test_connection().await
     }
 
     /// Get current sync status
-    pub async fn get_status(&self) -> Result<SyncStatus, String> {
+    pub async fn get_status(&self) -> Result<SyncStatus, SyncError> {
         let is_syncing = *self.is_syncing.read();
         let pending_changes = self.tracker.count_pending_changes().await?;
+        let dead_letters = self.tracker.count_dead_letters().await?;
         let last_error = self.last_error.read().clone();
 
         // Get sync config
-        let config: Option<SyncConfig> = sqlx::query_as(
-            "SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token, sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at FROM sync_config WHERE id = 'default'",
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        let config = load_config(&self.pool).await?;
 
         // Get last sync time from metadata
         let last_sync: Option<(String,)> = sqlx::query_as(
             "SELECT MAX(last_pull_at) FROM sync_metadata",
         )
         .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
         Ok(SyncStatus {
             is_connected: self.token_manager.has_credentials(),
             sync_enabled: config.map(|c| c.sync_enabled).unwrap_or(false),
             last_sync_at: last_sync.and_then(|l| Some(l.0)),
             pending_changes,
-            error_message: if is_syncing { Some("Syncing...".to_string()) } else { last_error },
+            dead_letters,
+            error_message: if is_syncing {
+                Some("Syncing...".to_string())
+            } else {
+                last_error.as_ref().map(|e| e.to_string())
+            },
+            error_code: last_error.filter(|_| !is_syncing).map(|e| e.code().to_string()),
+            api_usage: self.api.api_usage(),
+            api_limit_reached: self.api_limit_reached().is_some(),
         })
     }
 
     /// Run a full sync (push then pull)
-    pub async fn run_full_sync(&self) -> Result<SyncResult, String> {
+    pub async fn run_full_sync(&self) -> Result<SyncResult, SyncError> {
         // Check if already syncing
         {
             let mut is_syncing = self.is_syncing.write();
             if *is_syncing {
-                return Err("Sync already in progress".to_string());
+                return Err(SyncError::InProgress);
             }
             *is_syncing = true;
         }
@@ -136,7 +196,7 @@ impl SyncEngine {
     }
 
     /// Internal sync implementation
-    async fn do_sync(&self) -> Result<SyncResult, String> {
+    async fn do_sync(&self) -> Result<SyncResult, SyncError> {
         let mut total_pushed = 0;
         let mut total_pulled = 0;
         let mut all_errors = Vec::new();
@@ -152,8 +212,17 @@ impl SyncEngine {
             }
         }
 
-        // Then pull from Salesforce
-        match self.pull_sync.pull_all().await {
+        // Then pull from Salesforce. Close to the org's daily API limit,
+        // only gold prices are pulled, as sales cannot go on without them;
+        // pushes go ahead so no sale is held back.
+        let pulled = match self.api_limit_reached() {
+            Some(usage) => {
+                log::warn!("{}; pulling gold prices only", limit_reached(usage));
+                self.pull_sync.pull_gold_prices().await
+            }
+            None => self.pull_sync.pull_all().await,
+        };
+        match pulled {
             Ok(pull_result) => {
                 total_pulled = pull_result.records_pulled;
                 all_errors.extend(pull_result.errors);
@@ -176,7 +245,7 @@ impl SyncEngine {
     }
 
     /// Pull only gold prices (quick sync)
-    pub async fn pull_gold_prices(&self) -> Result<SyncResult, String> {
+    pub async fn pull_gold_prices(&self) -> Result<SyncResult, SyncError> {
         match self.pull_sync.pull_gold_prices().await {
             Ok(result) => Ok(SyncResult {
                 success: result.errors.is_empty(),
@@ -190,7 +259,10 @@ impl SyncEngine {
     }
 
     /// Pull inventory from other branches
-    pub async fn pull_inventory(&self, branch_sf_id: Option<&str>) -> Result<SyncResult, String> {
+    pub async fn pull_inventory(&self, branch_sf_id: Option<&str>) -> Result<SyncResult, SyncError> {
+        if let Some(usage) = self.api_limit_reached() {
+            return Err(limit_reached(usage));
+        }
         match self.pull_sync.pull_inventory(branch_sf_id).await {
             Ok(result) => Ok(SyncResult {
                 success: result.errors.is_empty(),
@@ -203,6 +275,25 @@ impl SyncEngine {
         }
     }
 
+    /// Pull customers, then transactions with their items (optionally of
+    /// one branch), so purchases made at other branches show up here
+    pub async fn pull_transactions(&self, branch_sf_id: Option<&str>) -> Result<SyncResult, SyncError> {
+        if let Some(usage) = self.api_limit_reached() {
+            return Err(limit_reached(usage));
+        }
+        let mut result = self.pull_sync.pull_branches().await?;
+        result.merge(self.pull_sync.pull_customers().await?);
+        result.merge(self.pull_sync.pull_transactions(branch_sf_id).await?);
+
+        Ok(SyncResult {
+            success: result.errors.is_empty(),
+            records_pushed: 0,
+            records_pulled: result.records_pulled,
+            errors: result.errors,
+            completed_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
     /// Start background sync worker
     pub async fn start_background_sync(self: Arc<Self>, interval_minutes: u64) {
         let mut interval = interval(Duration::from_secs(interval_minutes * 60));
@@ -211,12 +302,7 @@ impl SyncEngine {
             interval.tick().await;
 
             // Check if sync is enabled
-            let config: Option<SyncConfig> = match sqlx::query_as(
-                "SELECT id, sf_client_id, sf_client_secret, sf_username, sf_password, sf_security_token, sf_instance_url, is_sandbox, sync_enabled, sync_interval_minutes, created_at, updated_at FROM sync_config WHERE id = 'default'",
-            )
-            .fetch_optional(&self.pool)
-            .await
-            {
+            let config = match load_config(&self.pool).await {
                 Ok(c) => c,
                 Err(_) => continue,
             };
@@ -251,3 +337,157 @@ impl SyncEngine {
         }
     }
 }
+
+fn limit_reached(usage: ApiUsage) -> SyncError {
+    SyncError::RateLimited(format!(
+        "{} of {} daily API requests used",
+        usage.used, usage.limit
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salesforce::mock::MockSalesforce;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn mock_engine() -> (MockSalesforce, SyncEngine, SqlitePool) {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let engine = SyncEngine::new(
+            pool.clone(),
+            Arc::new(SecretVault::new("unused.key".into())),
+        );
+        let mock = MockSalesforce::start().await;
+        engine.token_manager.set_credentials(mock.credentials());
+        engine.client.set_base_url(Some(mock.url().to_string()));
+        (mock, engine, pool)
+    }
+
+    #[tokio::test]
+    async fn test_full_sync_against_mock_org() {
+        let (mock, engine, pool) = mock_engine().await;
+
+        let product_id = mock.insert(
+            "Product__c",
+            json!({
+                "Name": "Cincin Polos", "SKU__c": "CP-01", "Gold_Type__c": "LM", "Gold_Purity__c": 750,
+                "Weight_Gram__c": 3.5, "Labor_Cost__c": 50000, "Is_Active__c": true,
+            }),
+        );
+        let remote_customer = mock.insert(
+            "Customer__c",
+            json!({ "Name": "Sari", "Phone__c": "0822", "Total_Transactions__c": 0 }),
+        );
+        sqlx::query("INSERT INTO customers (id, name, phone) VALUES ('c1', 'Budi', '0811')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Local changes go up, Salesforce's records come down
+        let result = engine.run_full_sync().await.unwrap();
+        assert!(result.success, "{:?}", result.errors);
+        assert_eq!(result.records_pushed, 1);
+        let pushed = mock.records("Customer__c");
+        assert_eq!(pushed[1]["Name"], "Budi");
+        assert_eq!(pushed[1]["Phone__c"], "0811");
+        let (name,): (String,) =
+            sqlx::query_as("SELECT name FROM products WHERE salesforce_id = ?")
+                .bind(&product_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(name, "Cincin Polos");
+
+        // The next sync brings edits and deletions made in Salesforce
+        mock.update(
+            "Product__c",
+            &product_id,
+            json!({ "Name": "Cincin Polos 3,5g" }),
+        );
+        mock.delete("Customer__c", &remote_customer);
+        let result = engine.run_full_sync().await.unwrap();
+        assert!(result.success, "{:?}", result.errors);
+        assert_eq!(result.records_pushed, 0);
+        let (name,): (String,) =
+            sqlx::query_as("SELECT name FROM products WHERE salesforce_id = ?")
+                .bind(&product_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(name, "Cincin Polos 3,5g");
+        let (deleted,): (bool,) =
+            sqlx::query_as("SELECT deleted_at IS NOT NULL FROM customers WHERE phone = '0822'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(deleted);
+        assert_eq!(engine.get_status().await.unwrap().pending_changes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_near_api_limit_pulls_gold_prices_only() {
+        let (mock, engine, pool) = mock_engine().await;
+        mock.set_api_usage(1000, 15000);
+        let result = engine.run_full_sync().await.unwrap();
+        assert!(result.success, "{:?}", result.errors);
+        let status = engine.get_status().await.unwrap();
+        assert!(status.api_usage.unwrap().used > 1000);
+        assert!(!status.api_limit_reached);
+
+        // The push brings the org's usage past 90%
+        mock.set_api_usage(13600, 15000);
+        sqlx::query("INSERT INTO customers (id, name, phone) VALUES ('c1', 'Budi', '0811')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        mock.insert(
+            "Gold_Price__c",
+            json!({
+                "Date__c": "2024-01-31", "Gold_Type__c": "LM", "Purity__c": 999,
+                "Buy_Price__c": 1100000, "Sell_Price__c": 1150000,
+            }),
+        );
+        mock.insert(
+            "Product__c",
+            json!({
+                "Name": "Cincin Polos", "SKU__c": "CP-01", "Gold_Type__c": "LM", "Gold_Purity__c": 750,
+                "Weight_Gram__c": 3.5, "Labor_Cost__c": 50000, "Is_Active__c": true,
+            }),
+        );
+
+        let result = engine.run_full_sync().await.unwrap();
+        assert!(result.success, "{:?}", result.errors);
+        assert_eq!(result.records_pushed, 1);
+        assert_eq!(result.records_pulled, 1);
+        let (products,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM products")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(products, 0);
+        let status = engine.get_status().await.unwrap();
+        assert!(status.api_limit_reached);
+        assert!(engine.pull_inventory(None).await.is_err());
+
+        // Each table's last pull and push are counted
+        let (pull_requests,): (i32,) = sqlx::query_as(
+            "SELECT pull_requests FROM sync_metadata WHERE table_name = 'gold_prices'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(pull_requests, 1);
+        let (push_requests,): (i32,) = sqlx::query_as(
+            "SELECT push_requests FROM sync_metadata WHERE table_name = 'customers'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(push_requests >= 1);
+    }
+}
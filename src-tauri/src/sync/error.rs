@@ -0,0 +1,243 @@
+use crate::salesforce::client::SalesforceError;
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Why a sync step failed, in enough detail to decide whether a change is
+/// worth retrying and to tell the owner what to fix
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SyncError {
+    /// Credentials missing or rejected, or a session that could not be renewed
+    #[error("Salesforce authentication failed: {0}")]
+    Auth(String),
+    /// Salesforce could not be reached, or failed on its side
+    #[error("{0}")]
+    Network(String),
+    /// Salesforce kept asking to slow down, or the org's API limit is used up
+    #[error("Rate limited by Salesforce: {0}")]
+    RateLimited(String),
+    /// Salesforce rejected a value; `field` is `None` when it does not say
+    /// which field
+    #[error("Invalid {}: {message}", .field.as_deref().unwrap_or("record"))]
+    Validation {
+        field: Option<String>,
+        message: String,
+    },
+    /// Another record already holds a unique or external ID value
+    #[error("Duplicate record: {0}")]
+    Duplicate(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Db(String),
+    /// A record could not be converted between its local and Salesforce
+    /// form, or links to one that is not in Salesforce yet
+    #[error("{0}")]
+    Mapping(String),
+    /// Salesforce refused the request for a reason not covered above
+    #[error("Salesforce error: {message}")]
+    Rejected { code: String, message: String },
+    #[error("Sync already in progress")]
+    InProgress,
+}
+
+impl SyncError {
+    /// Stable name of the kind of error, for the UI to act on
+    pub fn code(&self) -> &'static str {
+        match self {
+            SyncError::Auth(_) => "auth",
+            SyncError::Network(_) => "network",
+            SyncError::RateLimited(_) => "rate_limited",
+            SyncError::Validation { .. } => "validation",
+            SyncError::Duplicate(_) => "duplicate",
+            SyncError::NotFound(_) => "not_found",
+            SyncError::Db(_) => "db",
+            SyncError::Mapping(_) => "mapping",
+            SyncError::Rejected { .. } => "rejected",
+            SyncError::InProgress => "in_progress",
+        }
+    }
+
+    /// Whether a change that failed this way may go through on a later
+    /// sync. Records Salesforce refuses as they are go straight to the dead
+    /// letters, where the owner can correct them.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            SyncError::Validation { .. }
+                | SyncError::Duplicate(_)
+                | SyncError::NotFound(_)
+                | SyncError::Rejected { .. }
+        )
+    }
+
+    /// Error for a failed response, from the first of the errors Salesforce
+    /// sent with it, or from the status when there are none
+    pub fn from_response(status: StatusCode, errors: &[SalesforceError], body: &str) -> Self {
+        match errors.first() {
+            Some(error) => Self::from_salesforce(error),
+            None => match status {
+                StatusCode::NOT_FOUND => {
+                    SyncError::NotFound(format!("Not found in Salesforce: {}", body))
+                }
+                StatusCode::BAD_REQUEST => SyncError::Validation {
+                    field: None,
+                    message: body.to_string(),
+                },
+                _ => SyncError::Rejected {
+                    code: status.as_u16().to_string(),
+                    message: body.to_string(),
+                },
+            },
+        }
+    }
+
+    /// Map one of Salesforce's error codes to the kind of error it is
+    pub fn from_salesforce(error: &SalesforceError) -> Self {
+        let message = error.message.clone();
+        match error.error_code.as_str() {
+            "INVALID_SESSION_ID" | "INVALID_AUTH_HEADER" => SyncError::Auth(message),
+            "REQUEST_LIMIT_EXCEEDED" => SyncError::RateLimited(message),
+            "UNABLE_TO_LOCK_ROW" | "SERVER_UNAVAILABLE" => SyncError::Network(message),
+            "DUPLICATE_VALUE" | "DUPLICATES_DETECTED" | "DUPLICATE_EXTERNAL_ID" => {
+                SyncError::Duplicate(message)
+            }
+            "NOT_FOUND" | "ENTITY_IS_DELETED" | "INVALID_CROSS_REFERENCE_KEY" => {
+                SyncError::NotFound(message)
+            }
+            "REQUIRED_FIELD_MISSING"
+            | "FIELD_CUSTOM_VALIDATION_EXCEPTION"
+            | "FIELD_INTEGRITY_EXCEPTION"
+            | "INVALID_FIELD"
+            | "INVALID_FIELD_FOR_INSERT_UPDATE"
+            | "INVALID_TYPE_ON_FIELD_IN_RECORD"
+            | "INVALID_OR_NULL_FOR_RESTRICTED_PICKLIST"
+            | "INVALID_EMAIL_ADDRESS"
+            | "NUMBER_OUTSIDE_VALID_RANGE"
+            | "STRING_TOO_LONG"
+            | "MALFORMED_ID"
+            | "JSON_PARSER_ERROR" => SyncError::Validation {
+                field: error.fields.as_ref().and_then(|f| f.first()).cloned(),
+                message,
+            },
+            code => SyncError::Rejected {
+                code: code.to_string(),
+                message,
+            },
+        }
+    }
+
+    /// Map a Bulk API result row's `sf__Error`, written as
+    /// `CODE:message:Field1,Field2 --`
+    pub fn from_bulk(error: &str) -> Self {
+        let (code, rest) = error.split_once(':').unwrap_or(("", error));
+        let (message, fields) = match rest
+            .strip_suffix("--")
+            .and_then(|r| r.trim_end().rsplit_once(':'))
+        {
+            Some((message, fields)) => (message, Some(fields).filter(|f| !f.is_empty())),
+            None => (rest, None),
+        };
+        Self::from_salesforce(&SalesforceError {
+            message: message.to_string(),
+            error_code: code.to_string(),
+            fields: fields.map(|f| f.split(',').map(|field| field.trim().to_string()).collect()),
+        })
+    }
+}
+
+impl From<sqlx::Error> for SyncError {
+    fn from(e: sqlx::Error) -> Self {
+        SyncError::Db(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SyncError {
+    fn from(e: serde_json::Error) -> Self {
+        SyncError::Mapping(e.to_string())
+    }
+}
+
+/// For callers that still report errors as text
+impl From<SyncError> for String {
+    fn from(e: SyncError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn salesforce_error(code: &str, message: &str, fields: &[&str]) -> SalesforceError {
+        SalesforceError {
+            message: message.to_string(),
+            error_code: code.to_string(),
+            fields: Some(fields.iter().map(|f| f.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_salesforce_errors_decide_retry_or_dead_letter() {
+        let error = SyncError::from_salesforce(&salesforce_error(
+            "STRING_TOO_LONG",
+            "Phone: data value too large",
+            &["Phone__c"],
+        ));
+        assert_eq!(
+            error,
+            SyncError::Validation {
+                field: Some("Phone__c".to_string()),
+                message: "Phone: data value too large".to_string(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Invalid Phone__c: Phone: data value too large"
+        );
+        assert!(!error.is_retryable());
+
+        let error = SyncError::from_salesforce(&salesforce_error(
+            "DUPLICATE_VALUE",
+            "duplicate value found",
+            &[],
+        ));
+        assert_eq!(error.code(), "duplicate");
+        assert!(!error.is_retryable());
+
+        let error = SyncError::from_salesforce(&salesforce_error(
+            "UNABLE_TO_LOCK_ROW",
+            "unable to obtain exclusive access",
+            &[],
+        ));
+        assert_eq!(error.code(), "network");
+        assert!(error.is_retryable());
+
+        let error = SyncError::from_salesforce(&salesforce_error(
+            "INSUFFICIENT_ACCESS_OR_READONLY",
+            "insufficient access",
+            &[],
+        ));
+        assert_eq!(error.code(), "rejected");
+        assert_eq!(error.to_string(), "Salesforce error: insufficient access");
+
+        let error = SyncError::from_response(StatusCode::NOT_FOUND, &[], "");
+        assert_eq!(error.code(), "not_found");
+    }
+
+    #[test]
+    fn test_bulk_errors_are_mapped() {
+        assert_eq!(
+            SyncError::from_bulk(
+                "REQUIRED_FIELD_MISSING:Required fields are missing: [Name]:Name --"
+            ),
+            SyncError::Validation {
+                field: Some("Name".to_string()),
+                message: "Required fields are missing: [Name]".to_string(),
+            }
+        );
+        assert_eq!(
+            SyncError::from_bulk("DUPLICATE_VALUE:duplicate value found: Barcode__c duplicates value on record with id: a05:--"),
+            SyncError::Duplicate("duplicate value found: Barcode__c duplicates value on record with id: a05".to_string())
+        );
+    }
+}
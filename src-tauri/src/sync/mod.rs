@@ -1,6 +1,11 @@
 pub mod change_tracker;
+pub mod conflicts;
+pub mod dead_letter;
+pub mod deletions;
+pub mod error;
 pub mod push;
 pub mod pull;
 pub mod engine;
 
 pub use engine::SyncEngine;
+pub use error::SyncError;
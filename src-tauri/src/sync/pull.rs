@@ -1,8 +1,22 @@
-use crate::salesforce::api::SalesforceApi;
+use super::change_tracker::{resume_capture, suspend_capture};
+use super::conflicts::{self, PullDecision};
+use super::deletions;
+use super::push::build_lookups;
+use super::SyncError;
+use crate::models::{Customer, Inventory, SyncMetadata, Transaction, TransactionItem};
+use crate::salesforce::api::{
+    SalesforceApi, SfBranch, SfCustomer, SfDeleted, SfGoldPrice, SfInventory, SfProduct, SfTransaction, SfTransactionItem,
+};
 use crate::salesforce::mapper::FromSalesforce;
-use sqlx::SqlitePool;
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Owner of transactions pulled from other branches (see the
+/// `salesforce_sync_user` migration)
+const SYNC_USER_ID: &str = "salesforce-sync";
+
 /// Pull sync handler - retrieves data from Salesforce
 pub struct PullSync {
     pool: SqlitePool,
@@ -15,350 +29,1020 @@ impl PullSync {
     }
 
     /// Pull all data from Salesforce
-    pub async fn pull_all(&self) -> Result<PullResult, String> {
+    pub async fn pull_all(&self) -> Result<PullResult, SyncError> {
         let mut result = PullResult::default();
 
         // Pull in order (master data first)
+        result.merge(self.pull_branches().await?);
         result.merge(self.pull_gold_prices().await?);
         result.merge(self.pull_products().await?);
         result.merge(self.pull_inventory(None).await?);
-
-        // Update sync metadata
-        self.update_sync_metadata("full", result.records_pulled).await?;
+        result.merge(self.pull_customers().await?);
+        result.merge(self.pull_transactions(None).await?);
 
         Ok(result)
     }
 
     /// Pull gold prices from Salesforce
-    pub async fn pull_gold_prices(&self) -> Result<PullResult, String> {
+    pub async fn pull_gold_prices(&self) -> Result<PullResult, SyncError> {
         let mut result = PullResult::default();
+        let requests = self.api.request_count();
+
+        let since = self.get_watermark("gold_prices").await?;
+        let sf_prices = self.api.get_gold_prices(since.as_deref()).await?;
+        let mut watermark = Watermark::default();
 
-        // Get today's date
-        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-        let sf_prices = self.api.get_gold_prices(Some(&today)).await?;
+        let mut tx = self.pool.begin().await?;
+        suspend_capture(&mut tx).await?;
+        let lookups = build_lookups(&mut tx).await?;
 
         for sf_price in sf_prices {
-            let local_price = sf_price.from_salesforce();
-
-            // Upsert based on date, gold_type, purity
-            let existing: Option<(String,)> = sqlx::query_as(
-                "SELECT id FROM gold_prices WHERE date = ? AND gold_type = ? AND purity = ?",
-            )
-            .bind(&local_price.date)
-            .bind(&local_price.gold_type)
-            .bind(local_price.purity)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| e.to_string())?;
-
-            if let Some((id,)) = existing {
-                // Update existing
-                sqlx::query(
-                    r#"
-                    UPDATE gold_prices
-                    SET buy_price = ?, sell_price = ?, source = ?, salesforce_id = ?
-                    WHERE id = ?
-                    "#,
-                )
-                .bind(local_price.buy_price)
-                .bind(local_price.sell_price)
-                .bind(&local_price.source)
-                .bind(sf_price.id.as_ref())
-                .bind(&id)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
-            } else {
-                // Insert new
-                sqlx::query(
-                    r#"
-                    INSERT INTO gold_prices (id, date, gold_type, purity, buy_price, sell_price, source, salesforce_id)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-                    "#,
-                )
-                .bind(&local_price.id)
-                .bind(&local_price.date)
-                .bind(&local_price.gold_type)
-                .bind(local_price.purity)
-                .bind(local_price.buy_price)
-                .bind(local_price.sell_price)
-                .bind(&local_price.source)
-                .bind(sf_price.id.as_ref())
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
-            }
+            let existing = find_gold_price(&mut tx, &sf_price).await?;
+            let remote = to_json(&sf_price)?;
+            let modstamp = sf_price.system_modstamp.as_deref();
+
+            let record = match &existing {
+                Some(id) => match conflicts::check_pull(&mut tx, &lookups, "gold_prices", id, modstamp, &remote).await? {
+                    PullDecision::Apply(record) => from_json(record)?,
+                    PullDecision::Skip => {
+                        watermark.pulled(modstamp);
+                        continue;
+                    }
+                },
+                None => sf_price.clone(),
+            };
 
+            let id = write_gold_price(&mut tx, &record, existing.as_deref()).await?;
+            conflicts::save_version(&mut tx, "gold_prices", &id, modstamp, &remote).await?;
+            watermark.pulled(modstamp);
             result.records_pulled += 1;
         }
 
-        self.update_sync_metadata("gold_prices", result.records_pulled).await?;
+        resume_capture(&mut tx).await?;
+        tx.commit().await?;
+
+        self.update_sync_metadata("gold_prices", result.records_pulled, requests, since, watermark).await?;
 
         Ok(result)
     }
 
     /// Pull products from Salesforce
-    pub async fn pull_products(&self) -> Result<PullResult, String> {
+    pub async fn pull_products(&self) -> Result<PullResult, SyncError> {
         let mut result = PullResult::default();
+        let requests = self.api.request_count();
+
+        let since = self.get_watermark("products").await?;
+        let sf_products = self.api.get_products(since.as_deref()).await?;
+        let deleted = self.api.get_deleted("Product__c", since.as_deref(), None).await?;
+        let mut watermark = Watermark::default();
 
-        // Get last sync time
-        let last_sync = self.get_last_sync_time("products").await?;
-        let sf_products = self.api.get_products(last_sync.as_deref()).await?;
+        let mut tx = self.pool.begin().await?;
+        suspend_capture(&mut tx).await?;
+        let lookups = build_lookups(&mut tx).await?;
 
         for sf_product in sf_products {
-            let local_product = sf_product.from_salesforce();
-            let sf_id = sf_product.id.as_ref();
-
-            // Check if product exists by SKU or SF ID
-            let existing: Option<(String,)> = if let Some(sku) = &sf_product.sku {
-                sqlx::query_as("SELECT id FROM products WHERE sku = ? OR salesforce_id = ?")
-                    .bind(sku)
-                    .bind(sf_id)
-                    .fetch_optional(&self.pool)
-                    .await
-                    .map_err(|e| e.to_string())?
-            } else {
-                sqlx::query_as("SELECT id FROM products WHERE salesforce_id = ?")
-                    .bind(sf_id)
-                    .fetch_optional(&self.pool)
-                    .await
-                    .map_err(|e| e.to_string())?
-            };
+            let existing = find_product(&mut tx, &sf_product).await?;
+            let remote = to_json(&sf_product)?;
+            let modstamp = sf_product.system_modstamp.as_deref();
 
-            if let Some((id,)) = existing {
-                // Update existing
-                sqlx::query(
-                    r#"
-                    UPDATE products
-                    SET name = ?, description = ?, gold_type = ?, gold_purity = ?,
-                        weight_gram = ?, labor_cost = ?, is_active = ?, salesforce_id = ?
-                    WHERE id = ?
-                    "#,
-                )
-                .bind(&local_product.name)
-                .bind(&local_product.description)
-                .bind(&local_product.gold_type)
-                .bind(local_product.gold_purity)
-                .bind(local_product.weight_gram)
-                .bind(local_product.labor_cost)
-                .bind(local_product.is_active)
-                .bind(sf_id)
-                .bind(&id)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
-            } else {
-                // Insert new
-                let id = uuid::Uuid::new_v4().to_string();
-                sqlx::query(
-                    r#"
-                    INSERT INTO products (id, category_id, sku, name, description, gold_type, gold_purity, weight_gram, labor_cost, is_active, salesforce_id)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#,
-                )
-                .bind(&id)
-                .bind(&local_product.category_id)
-                .bind(&local_product.sku)
-                .bind(&local_product.name)
-                .bind(&local_product.description)
-                .bind(&local_product.gold_type)
-                .bind(local_product.gold_purity)
-                .bind(local_product.weight_gram)
-                .bind(local_product.labor_cost)
-                .bind(local_product.is_active)
-                .bind(sf_id)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
-            }
+            let record = match &existing {
+                Some(id) => match conflicts::check_pull(&mut tx, &lookups, "products", id, modstamp, &remote).await? {
+                    PullDecision::Apply(record) => from_json(record)?,
+                    PullDecision::Skip => {
+                        watermark.pulled(modstamp);
+                        continue;
+                    }
+                },
+                None => sf_product.clone(),
+            };
 
+            let id = write_product(&mut tx, &record, existing.as_deref()).await?;
+            conflicts::save_version(&mut tx, "products", &id, modstamp, &remote).await?;
+            watermark.pulled(modstamp);
             result.records_pulled += 1;
         }
+        mirror_deletions(&mut tx, "products", deleted, &mut result, &mut watermark).await?;
+
+        resume_capture(&mut tx).await?;
+        tx.commit().await?;
 
-        self.update_sync_metadata("products", result.records_pulled).await?;
+        self.update_sync_metadata("products", result.records_pulled, requests, since, watermark).await?;
 
         Ok(result)
     }
 
     /// Pull inventory from Salesforce (optionally for other branches)
-    pub async fn pull_inventory(&self, branch_sf_id: Option<&str>) -> Result<PullResult, String> {
+    pub async fn pull_inventory(&self, branch_sf_id: Option<&str>) -> Result<PullResult, SyncError> {
         let mut result = PullResult::default();
+        let requests = self.api.request_count();
 
-        // Get last sync time
-        let last_sync = self.get_last_sync_time("inventory").await?;
-        let sf_inventory = self.api.get_inventory(last_sync.as_deref(), branch_sf_id).await?;
+        let key = watermark_key("inventory", branch_sf_id);
+        let since = self.get_watermark(&key).await?;
+        let sf_inventory = self.api.get_inventory(since.as_deref(), branch_sf_id).await?;
+        let deleted = self.api.get_deleted("Inventory__c", since.as_deref(), branch_sf_id).await?;
+        let mut watermark = Watermark::default();
 
-        // Build reverse lookup: SF ID -> local ID
-        let product_lookup = self.build_product_lookup().await?;
-        let branch_lookup = self.build_branch_lookup().await?;
+        let mut tx = self.pool.begin().await?;
+        suspend_capture(&mut tx).await?;
+        let lookups = build_lookups(&mut tx).await?;
+        let local_ids = LocalIds::load(&mut tx).await?;
 
         for sf_item in sf_inventory {
-            let mut local_item = sf_item.from_salesforce();
-            let sf_id = sf_item.id.as_ref();
-
-            // Resolve product ID
-            if let Some(product_sf_id) = &sf_item.product_id {
-                if let Some(local_product_id) = product_lookup.get(product_sf_id) {
-                    local_item.product_id = local_product_id.clone();
-                } else {
-                    // Skip if product not found locally
-                    result.errors.push(format!("Product {} not found for inventory {}", product_sf_id, sf_item.barcode));
+            let existing = find_inventory(&mut tx, &sf_item).await?;
+            let remote = to_json(&sf_item)?;
+            let modstamp = sf_item.system_modstamp.as_deref();
+
+            let record = match &existing {
+                Some(id) => match conflicts::check_pull(&mut tx, &lookups, "inventory", id, modstamp, &remote).await? {
+                    PullDecision::Apply(record) => from_json(record)?,
+                    PullDecision::Skip => {
+                        watermark.pulled(modstamp);
+                        continue;
+                    }
+                },
+                None => sf_item.clone(),
+            };
+
+            // Skip if product not found locally
+            let local_item = match local_ids.inventory(&record) {
+                Ok(item) => item,
+                Err(e) => {
+                    watermark.failed(modstamp);
+                    result.errors.push(e.to_string());
                     continue;
                 }
-            }
+            };
+
+            let id = write_inventory(&mut tx, &local_item, record.id.as_deref(), existing.as_deref()).await?;
+            conflicts::save_version(&mut tx, "inventory", &id, modstamp, &remote).await?;
+            watermark.pulled(modstamp);
+            result.records_pulled += 1;
+        }
+        mirror_deletions(&mut tx, "inventory", deleted, &mut result, &mut watermark).await?;
+
+        resume_capture(&mut tx).await?;
+        tx.commit().await?;
+
+        self.update_sync_metadata(&key, result.records_pulled, requests, since, watermark).await?;
+
+        Ok(result)
+    }
+
+    /// Pull branches, so records of other branches can be linked to them
+    pub async fn pull_branches(&self) -> Result<PullResult, SyncError> {
+        let mut result = PullResult::default();
+        let requests = self.api.request_count();
 
-            // Resolve branch ID
-            if let Some(branch_sf_id) = &sf_item.branch_id {
-                if let Some(local_branch_id) = branch_lookup.get(branch_sf_id) {
-                    local_item.branch_id = local_branch_id.clone();
-                } else {
-                    // Use default branch
-                    local_item.branch_id = "default".to_string();
+        let since = self.get_watermark("branches").await?;
+        let sf_branches = self.api.get_branches(since.as_deref()).await?;
+        let mut watermark = Watermark::default();
+
+        let mut tx = self.pool.begin().await?;
+        for sf_branch in sf_branches {
+            write_branch(&mut tx, &sf_branch).await?;
+            watermark.pulled(sf_branch.system_modstamp.as_deref());
+            result.records_pulled += 1;
+        }
+        tx.commit().await?;
+
+        self.update_sync_metadata("branches", result.records_pulled, requests, since, watermark).await?;
+
+        Ok(result)
+    }
+
+    /// Pull customers from Salesforce, including those of other branches
+    pub async fn pull_customers(&self) -> Result<PullResult, SyncError> {
+        let mut result = PullResult::default();
+        let requests = self.api.request_count();
+
+        let since = self.get_watermark("customers").await?;
+        let sf_customers = self.api.get_customers(since.as_deref()).await?;
+        let deleted = self.api.get_deleted("Customer__c", since.as_deref(), None).await?;
+        let mut watermark = Watermark::default();
+
+        let mut tx = self.pool.begin().await?;
+        suspend_capture(&mut tx).await?;
+        let lookups = build_lookups(&mut tx).await?;
+
+        for sf_customer in sf_customers {
+            let existing = find_customer(&mut tx, &sf_customer).await?;
+            let remote = to_json(&sf_customer)?;
+            let modstamp = sf_customer.system_modstamp.as_deref();
+
+            let record = match &existing {
+                Some(id) => match conflicts::check_pull(&mut tx, &lookups, "customers", id, modstamp, &remote).await? {
+                    PullDecision::Apply(record) => from_json(record)?,
+                    PullDecision::Skip => {
+                        watermark.pulled(modstamp);
+                        continue;
+                    }
+                },
+                None => sf_customer.clone(),
+            };
+
+            let id = write_customer(&mut tx, &record, existing.as_deref()).await?;
+            conflicts::save_version(&mut tx, "customers", &id, modstamp, &remote).await?;
+            watermark.pulled(modstamp);
+            result.records_pulled += 1;
+        }
+        mirror_deletions(&mut tx, "customers", deleted, &mut result, &mut watermark).await?;
+
+        resume_capture(&mut tx).await?;
+        tx.commit().await?;
+
+        self.update_sync_metadata("customers", result.records_pulled, requests, since, watermark).await?;
+
+        Ok(result)
+    }
+
+    /// Pull transactions with their line items (optionally for one branch)
+    pub async fn pull_transactions(&self, branch_sf_id: Option<&str>) -> Result<PullResult, SyncError> {
+        let mut result = PullResult::default();
+        let requests = self.api.request_count();
+
+        let key = watermark_key("transactions", branch_sf_id);
+        let since = self.get_watermark(&key).await?;
+        let sf_transactions = self.api.get_transactions(since.as_deref(), branch_sf_id).await?;
+        let mut watermark = Watermark::default();
+        let sf_ids: Vec<String> = sf_transactions.iter().filter_map(|t| t.id.clone()).collect();
+        let mut sf_items: HashMap<String, Vec<SfTransactionItem>> = HashMap::new();
+        for item in self.api.get_items_for_transactions(&sf_ids).await? {
+            sf_items.entry(item.transaction_id.clone()).or_default().push(item);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        suspend_capture(&mut tx).await?;
+        let lookups = build_lookups(&mut tx).await?;
+        let local_ids = LocalIds::load(&mut tx).await?;
+
+        for sf_transaction in sf_transactions {
+            let existing = find_transaction(&mut tx, &sf_transaction).await?;
+            let remote = to_json(&sf_transaction)?;
+            let modstamp = sf_transaction.system_modstamp.as_deref();
+
+            let record = match &existing {
+                Some(id) => match conflicts::check_pull(&mut tx, &lookups, "transactions", id, modstamp, &remote).await? {
+                    PullDecision::Apply(record) => from_json(record)?,
+                    PullDecision::Skip => {
+                        watermark.pulled(modstamp);
+                        continue;
+                    }
+                },
+                None => sf_transaction.clone(),
+            };
+
+            let local_transaction = match local_ids.transaction(&record) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    watermark.failed(modstamp);
+                    result.errors.push(e.to_string());
+                    continue;
                 }
-            } else {
-                local_item.branch_id = "default".to_string();
-            }
+            };
 
-            // Check if inventory exists by barcode or SF ID
-            let existing: Option<(String,)> = sqlx::query_as(
-                "SELECT id FROM inventory WHERE barcode = ? OR salesforce_id = ?",
-            )
-            .bind(&sf_item.barcode)
-            .bind(sf_id)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| e.to_string())?;
-
-            if let Some((id,)) = existing {
-                // Update existing (SF wins for status and location)
-                sqlx::query(
-                    r#"
-                    UPDATE inventory
-                    SET status = ?, location = ?, purchase_price = ?, supplier = ?,
-                        notes = ?, sold_at = ?, salesforce_id = ?
-                    WHERE id = ?
-                    "#,
-                )
-                .bind(&local_item.status)
-                .bind(&local_item.location)
-                .bind(local_item.purchase_price)
-                .bind(&local_item.supplier)
-                .bind(&local_item.notes)
-                .bind(&local_item.sold_at)
-                .bind(sf_id)
-                .bind(&id)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
+            let id = write_transaction(&mut tx, &local_transaction, record.id.as_deref(), existing.as_deref()).await?;
+            conflicts::save_version(&mut tx, "transactions", &id, modstamp, &remote).await?;
+            result.records_pulled += 1;
+
+            let items = record.id.as_ref().and_then(|sf_id| sf_items.remove(sf_id)).unwrap_or_default();
+            let mut items_failed = false;
+            for sf_item in items {
+                match local_ids.transaction_item(&sf_item, &id) {
+                    Ok(item) => write_transaction_item(&mut tx, &item, sf_item.id.as_deref()).await?,
+                    Err(e) => {
+                        items_failed = true;
+                        result.errors.push(e.to_string());
+                    }
+                }
+            }
+            // Fetch the transaction again next time, to retry its items
+            if items_failed {
+                watermark.failed(modstamp);
             } else {
-                // Insert new
-                let id = uuid::Uuid::new_v4().to_string();
-                sqlx::query(
-                    r#"
-                    INSERT INTO inventory (id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, salesforce_id)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#,
-                )
-                .bind(&id)
-                .bind(&local_item.product_id)
-                .bind(&local_item.branch_id)
-                .bind(&local_item.barcode)
-                .bind(&local_item.status)
-                .bind(&local_item.location)
-                .bind(local_item.purchase_price)
-                .bind(&local_item.purchase_date)
-                .bind(&local_item.supplier)
-                .bind(&local_item.notes)
-                .bind(&local_item.sold_at)
-                .bind(sf_id)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e.to_string())?;
+                watermark.pulled(modstamp);
             }
-
-            result.records_pulled += 1;
         }
 
-        self.update_sync_metadata("inventory", result.records_pulled).await?;
+        resume_capture(&mut tx).await?;
+        tx.commit().await?;
+
+        self.update_sync_metadata(&key, result.records_pulled, requests, since, watermark).await?;
 
         Ok(result)
     }
 
-    /// Get last sync time for a table
-    async fn get_last_sync_time(&self, table_name: &str) -> Result<Option<String>, String> {
+    /// SystemModstamp the next pull into `key` starts from; `None` means a
+    /// full pull
+    async fn get_watermark(&self, key: &str) -> Result<Option<String>, SyncError> {
         let result: Option<(Option<String>,)> = sqlx::query_as(
-            "SELECT last_pull_at FROM sync_metadata WHERE table_name = ?",
+            "SELECT pull_watermark FROM sync_metadata WHERE table_name = ?",
         )
-        .bind(table_name)
+        .bind(key)
         .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
         Ok(result.and_then(|r| r.0))
     }
 
-    /// Update sync metadata for a table
-    async fn update_sync_metadata(&self, table_name: &str, records_count: i32) -> Result<(), String> {
+    /// Update sync metadata after pulling into `key`, which started from
+    /// the watermark `since` when `requests` API requests had been sent
+    async fn update_sync_metadata(
+        &self,
+        key: &str,
+        records_count: i32,
+        requests: u64,
+        since: Option<String>,
+        watermark: Watermark,
+    ) -> Result<(), SyncError> {
         let now = chrono::Utc::now().to_rfc3339();
+        let full_sync_at = since.is_none().then(|| now.clone());
+        let requests = (self.api.request_count() - requests) as i64;
 
         sqlx::query(
             r#"
-            INSERT INTO sync_metadata (table_name, last_pull_at, records_pulled)
-            VALUES (?, ?, ?)
+            INSERT INTO sync_metadata (table_name, last_pull_at, last_full_sync_at, records_pulled, pull_watermark, pull_requests)
+            VALUES (?, ?, ?, ?, ?, ?)
             ON CONFLICT(table_name) DO UPDATE SET
                 last_pull_at = excluded.last_pull_at,
-                records_pulled = records_pulled + excluded.records_pulled
+                last_full_sync_at = COALESCE(excluded.last_full_sync_at, last_full_sync_at),
+                records_pulled = records_pulled + excluded.records_pulled,
+                pull_watermark = excluded.pull_watermark,
+                pull_requests = excluded.pull_requests
             "#,
         )
-        .bind(table_name)
+        .bind(key)
         .bind(&now)
+        .bind(full_sync_at)
         .bind(records_count)
+        .bind(watermark.next(since))
+        .bind(requests)
         .execute(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
         Ok(())
     }
+}
 
-    /// Build reverse lookup: SF ID -> local ID for products
-    async fn build_product_lookup(&self) -> Result<std::collections::HashMap<String, String>, String> {
-        let products: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM products WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+/// Mark the local copies of records deleted in Salesforce as deleted
+async fn mirror_deletions(
+    conn: &mut SqliteConnection,
+    table_name: &str,
+    deleted: Vec<SfDeleted>,
+    result: &mut PullResult,
+    watermark: &mut Watermark,
+) -> Result<(), SyncError> {
+    for record in deleted {
+        if deletions::mirror_remote_delete(conn, table_name, &record.id).await?.is_some() {
+            result.records_pulled += 1;
+        }
+        watermark.pulled(record.system_modstamp.as_deref());
+    }
+    Ok(())
+}
+
+/// sync_metadata row holding the watermark of a pull. Pulls limited to one
+/// branch see only part of the object, so they keep their own.
+fn watermark_key(table_name: &str, branch_sf_id: Option<&str>) -> String {
+    match branch_sf_id {
+        Some(branch_sf_id) => format!("{}@{}", table_name, branch_sf_id),
+        None => table_name.to_string(),
+    }
+}
 
-        let mut lookup = std::collections::HashMap::new();
-        for (local_id, sf_id) in products {
-            if let Some(id) = sf_id {
-                lookup.insert(id, local_id);
+/// Tracks the SystemModstamps of the records a pull fetched, to work out
+/// where the next pull starts
+#[derive(Debug, Default)]
+struct Watermark {
+    latest: Option<String>,
+    earliest_failed: Option<String>,
+}
+
+impl Watermark {
+    /// The record is written, or deliberately left as it is
+    fn pulled(&mut self, modstamp: Option<&str>) {
+        if let Some(modstamp) = modstamp {
+            if self.latest.as_deref().is_none_or(|latest| modstamp > latest) {
+                self.latest = Some(modstamp.to_string());
             }
         }
+    }
+
+    /// The record could not be written and has to be fetched again
+    fn failed(&mut self, modstamp: Option<&str>) {
+        if let Some(modstamp) = modstamp {
+            if self.earliest_failed.as_deref().is_none_or(|earliest| modstamp < earliest) {
+                self.earliest_failed = Some(modstamp.to_string());
+            }
+        }
+    }
+
+    /// Watermark for the next pull. Queries are inclusive, so starting at
+    /// a failed record fetches it again; with nothing fetched, the old
+    /// watermark stands.
+    fn next(self, previous: Option<String>) -> Option<String> {
+        self.earliest_failed.or(self.latest).or(previous)
+    }
+}
+
+/// Forget pull watermarks, so the next pull of `table_name` (or of every
+/// object) fetches all records. Returns the rows as they were.
+pub async fn reset_watermarks(
+    conn: &mut SqliteConnection,
+    table_name: Option<&str>,
+) -> Result<Vec<SyncMetadata>, SyncError> {
+    let rows: Vec<SyncMetadata> = sqlx::query_as(
+        r#"
+        SELECT table_name, last_pull_at, last_push_at, last_full_sync_at,
+               records_pulled, records_pushed, pull_watermark, pull_requests, push_requests
+        FROM sync_metadata
+        WHERE pull_watermark IS NOT NULL
+          AND (?1 IS NULL OR table_name = ?1 OR substr(table_name, 1, length(?1) + 1) = ?1 || '@')
+        "#,
+    )
+    .bind(table_name)
+    .fetch_all(&mut *conn)
+    .await?;
 
-        Ok(lookup)
+    for row in &rows {
+        sqlx::query("UPDATE sync_metadata SET pull_watermark = NULL WHERE table_name = ?")
+            .bind(&row.table_name)
+            .execute(&mut *conn)
+            .await?;
     }
 
-    /// Build reverse lookup: SF ID -> local ID for branches
-    async fn build_branch_lookup(&self) -> Result<std::collections::HashMap<String, String>, String> {
-        let branches: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM branches WHERE salesforce_id IS NOT NULL",
+    Ok(rows)
+}
+
+/// Write a Salesforce record, in Salesforce field names, over its local
+/// copy. Used to settle a conflict in Salesforce's favour.
+pub async fn apply_remote(conn: &mut SqliteConnection, table_name: &str, record: &Value) -> Result<(), SyncError> {
+    match table_name {
+        "gold_prices" => {
+            let price: SfGoldPrice = from_json(record.clone())?;
+            let existing = find_gold_price(conn, &price).await?;
+            write_gold_price(conn, &price, existing.as_deref()).await?;
+        }
+        "products" => {
+            let product: SfProduct = from_json(record.clone())?;
+            let existing = find_product(conn, &product).await?;
+            write_product(conn, &product, existing.as_deref()).await?;
+        }
+        "inventory" => {
+            let item: SfInventory = from_json(record.clone())?;
+            let existing = find_inventory(conn, &item).await?;
+            let local_item = LocalIds::load(conn).await?.inventory(&item)?;
+            write_inventory(conn, &local_item, item.id.as_deref(), existing.as_deref()).await?;
+        }
+        "customers" => {
+            let customer: SfCustomer = from_json(record.clone())?;
+            let existing = find_customer(conn, &customer).await?;
+            write_customer(conn, &customer, existing.as_deref()).await?;
+        }
+        "transactions" => {
+            let transaction: SfTransaction = from_json(record.clone())?;
+            let existing = find_transaction(conn, &transaction).await?;
+            let local_transaction = LocalIds::load(conn).await?.transaction(&transaction)?;
+            write_transaction(conn, &local_transaction, transaction.id.as_deref(), existing.as_deref()).await?;
+        }
+        _ => return Err(SyncError::Mapping(format!("{} is not pulled from Salesforce", table_name))),
+    }
+    Ok(())
+}
+
+fn to_json<T: serde::Serialize>(record: &T) -> Result<Value, SyncError> {
+    serde_json::to_value(record).map_err(SyncError::from)
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(record: Value) -> Result<T, SyncError> {
+    serde_json::from_value(record).map_err(SyncError::from)
+}
+
+/// Local gold price for the same date, gold type and purity
+async fn find_gold_price(conn: &mut SqliteConnection, sf_price: &SfGoldPrice) -> Result<Option<String>, SyncError> {
+    let existing: Option<(String,)> = sqlx::query_as(
+        "SELECT id FROM gold_prices WHERE date = ? AND gold_type = ? AND purity = ?",
+    )
+    .bind(&sf_price.date)
+    .bind(&sf_price.gold_type)
+    .bind(sf_price.purity)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(existing.map(|(id,)| id))
+}
+
+/// Upsert a pulled gold price, returning its local ID
+async fn write_gold_price(conn: &mut SqliteConnection, sf_price: &SfGoldPrice, existing: Option<&str>) -> Result<String, SyncError> {
+    let local_price = sf_price.from_salesforce();
+
+    if let Some(id) = existing {
+        // Update existing
+        sqlx::query(
+            r#"
+            UPDATE gold_prices
+            SET buy_price = ?, sell_price = ?, source = ?, salesforce_id = ?
+            WHERE id = ?
+            "#,
         )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .bind(local_price.buy_price)
+        .bind(local_price.sell_price)
+        .bind(&local_price.source)
+        .bind(sf_price.id.as_ref())
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
 
-        let mut lookup = std::collections::HashMap::new();
-        for (local_id, sf_id) in branches {
-            if let Some(id) = sf_id {
-                lookup.insert(id, local_id);
-            }
+        Ok(id.to_string())
+    } else {
+        // Insert new
+        sqlx::query(
+            r#"
+            INSERT INTO gold_prices (id, date, gold_type, purity, buy_price, sell_price, source, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&local_price.id)
+        .bind(&local_price.date)
+        .bind(&local_price.gold_type)
+        .bind(local_price.purity)
+        .bind(local_price.buy_price)
+        .bind(local_price.sell_price)
+        .bind(&local_price.source)
+        .bind(sf_price.id.as_ref())
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(local_price.id)
+    }
+}
+
+/// Local product with the same SKU or SF ID
+async fn find_product(conn: &mut SqliteConnection, sf_product: &SfProduct) -> Result<Option<String>, SyncError> {
+    let sf_id = sf_product.id.as_ref();
+    let existing: Option<(String,)> = if let Some(sku) = &sf_product.sku {
+        sqlx::query_as("SELECT id FROM products WHERE sku = ? OR salesforce_id = ?")
+            .bind(sku)
+            .bind(sf_id)
+            .fetch_optional(&mut *conn)
+            .await?
+    } else {
+        sqlx::query_as("SELECT id FROM products WHERE salesforce_id = ?")
+            .bind(sf_id)
+            .fetch_optional(&mut *conn)
+            .await?
+    };
+
+    Ok(existing.map(|(id,)| id))
+}
+
+/// Upsert a pulled product, returning its local ID
+async fn write_product(conn: &mut SqliteConnection, sf_product: &SfProduct, existing: Option<&str>) -> Result<String, SyncError> {
+    let local_product = sf_product.from_salesforce();
+    let sf_id = sf_product.id.as_ref();
+
+    if let Some(id) = existing {
+        // Update existing
+        sqlx::query(
+            r#"
+            UPDATE products
+            SET name = ?, description = ?, gold_type = ?, gold_purity = ?,
+                weight_gram = ?, labor_cost = ?, is_active = ?, salesforce_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&local_product.name)
+        .bind(&local_product.description)
+        .bind(&local_product.gold_type)
+        .bind(local_product.gold_purity)
+        .bind(local_product.weight_gram)
+        .bind(local_product.labor_cost)
+        .bind(local_product.is_active)
+        .bind(sf_id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id.to_string())
+    } else {
+        // Insert new
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+            INSERT INTO products (id, category_id, sku, name, description, gold_type, gold_purity, weight_gram, labor_cost, is_active, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(&local_product.category_id)
+        .bind(&local_product.sku)
+        .bind(&local_product.name)
+        .bind(&local_product.description)
+        .bind(&local_product.gold_type)
+        .bind(local_product.gold_purity)
+        .bind(local_product.weight_gram)
+        .bind(local_product.labor_cost)
+        .bind(local_product.is_active)
+        .bind(sf_id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id)
+    }
+}
+
+/// Reverse lookups (SF ID -> local ID) for the records pulled records link to
+struct LocalIds {
+    products: HashMap<String, String>,
+    branches: HashMap<String, String>,
+    customers: HashMap<String, String>,
+    inventory: HashMap<String, String>,
+}
+
+impl LocalIds {
+    async fn load(conn: &mut SqliteConnection) -> Result<Self, SyncError> {
+        Ok(Self {
+            products: reverse_lookup(conn, "products").await?,
+            branches: reverse_lookup(conn, "branches").await?,
+            customers: reverse_lookup(conn, "customers").await?,
+            inventory: reverse_lookup(conn, "inventory").await?,
+        })
+    }
+
+    /// Map a pulled item to a local one with local product and branch IDs
+    fn inventory(&self, sf_item: &SfInventory) -> Result<Inventory, SyncError> {
+        let mut local_item = sf_item.from_salesforce();
+
+        // Resolve product ID
+        if let Some(product_sf_id) = &sf_item.product_id {
+            local_item.product_id = self
+                .products
+                .get(product_sf_id)
+                .cloned()
+                .ok_or_else(|| SyncError::Mapping(format!("Product {} not found for inventory {}", product_sf_id, sf_item.barcode)))?;
         }
 
-        Ok(lookup)
+        // Resolve branch ID, falling back to the default branch
+        local_item.branch_id = sf_item
+            .branch_id
+            .as_ref()
+            .and_then(|id| self.branches.get(id))
+            .cloned()
+            .unwrap_or_else(|| "default".to_string());
+
+        Ok(local_item)
+    }
+
+    /// Map a pulled transaction to a local one. It must belong to a known
+    /// branch; a customer not pulled yet is left out.
+    fn transaction(&self, sf_transaction: &SfTransaction) -> Result<Transaction, SyncError> {
+        let mut local_transaction = sf_transaction.from_salesforce();
+
+        local_transaction.branch_id = sf_transaction
+            .branch_id
+            .as_ref()
+            .and_then(|id| self.branches.get(id))
+            .cloned()
+            .ok_or_else(|| SyncError::Mapping(format!("Branch not found for transaction {}", sf_transaction.invoice_no)))?;
+        local_transaction.customer_id = sf_transaction
+            .customer_id
+            .as_ref()
+            .and_then(|id| self.customers.get(id))
+            .cloned();
+        local_transaction.user_id = SYNC_USER_ID.to_string();
+
+        Ok(local_transaction)
+    }
+
+    fn transaction_item(&self, sf_item: &SfTransactionItem, transaction_id: &str) -> Result<TransactionItem, SyncError> {
+        let mut local_item = sf_item.from_salesforce();
+
+        local_item.transaction_id = transaction_id.to_string();
+        local_item.inventory_id = self
+            .inventory
+            .get(&sf_item.inventory_id)
+            .cloned()
+            .ok_or_else(|| SyncError::Mapping(format!("Inventory {} not found for transaction item {}", sf_item.inventory_id, sf_item.id.as_deref().unwrap_or_default())))?;
+        // Only a link; the piece may have been sold before this shop synced
+        local_item.origin_inventory_id = sf_item
+            .origin_inventory_id
+            .as_ref()
+            .and_then(|id| self.inventory.get(id))
+            .cloned();
+
+        Ok(local_item)
+    }
+}
+
+/// Build reverse lookup: SF ID -> local ID
+async fn reverse_lookup(conn: &mut SqliteConnection, table_name: &str) -> Result<HashMap<String, String>, SyncError> {
+    let query = format!("SELECT id, salesforce_id FROM {} WHERE salesforce_id IS NOT NULL", table_name);
+    let records: Vec<(String, String)> = sqlx::query_as(&query)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(records.into_iter().map(|(local_id, sf_id)| (sf_id, local_id)).collect())
+}
+
+/// Local inventory item with the same barcode or SF ID
+async fn find_inventory(conn: &mut SqliteConnection, sf_item: &SfInventory) -> Result<Option<String>, SyncError> {
+    let existing: Option<(String,)> = sqlx::query_as(
+        "SELECT id FROM inventory WHERE barcode = ? OR salesforce_id = ?",
+    )
+    .bind(&sf_item.barcode)
+    .bind(sf_item.id.as_ref())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(existing.map(|(id,)| id))
+}
+
+/// Upsert a pulled inventory item, returning its local ID
+async fn write_inventory(
+    conn: &mut SqliteConnection,
+    local_item: &Inventory,
+    sf_id: Option<&str>,
+    existing: Option<&str>,
+) -> Result<String, SyncError> {
+    if let Some(id) = existing {
+        // Update existing (SF wins for status and location)
+        sqlx::query(
+            r#"
+            UPDATE inventory
+            SET status = ?, location = ?, purchase_price = ?, supplier = ?,
+                notes = ?, sold_at = ?, salesforce_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&local_item.status)
+        .bind(&local_item.location)
+        .bind(local_item.purchase_price)
+        .bind(&local_item.supplier)
+        .bind(&local_item.notes)
+        .bind(&local_item.sold_at)
+        .bind(sf_id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id.to_string())
+    } else {
+        // Insert new
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+            INSERT INTO inventory (id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(&local_item.product_id)
+        .bind(&local_item.branch_id)
+        .bind(&local_item.barcode)
+        .bind(&local_item.status)
+        .bind(&local_item.location)
+        .bind(local_item.purchase_price)
+        .bind(&local_item.purchase_date)
+        .bind(&local_item.supplier)
+        .bind(&local_item.notes)
+        .bind(&local_item.sold_at)
+        .bind(sf_id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id)
     }
 }
 
+/// Upsert a pulled branch, matching on SF ID or branch code
+async fn write_branch(conn: &mut SqliteConnection, sf_branch: &SfBranch) -> Result<(), SyncError> {
+    let local_branch = sf_branch.from_salesforce();
+    let existing: Option<(String,)> = sqlx::query_as("SELECT id FROM branches WHERE code = ? OR salesforce_id = ?")
+        .bind(&sf_branch.code)
+        .bind(sf_branch.id.as_ref())
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if let Some((id,)) = existing {
+        sqlx::query(
+            r#"
+            UPDATE branches
+            SET name = ?, address = ?, phone = ?, is_active = ?, salesforce_id = ?, updated_at = datetime('now')
+            WHERE id = ?
+            "#,
+        )
+        .bind(&local_branch.name)
+        .bind(&local_branch.address)
+        .bind(&local_branch.phone)
+        .bind(local_branch.is_active)
+        .bind(sf_branch.id.as_ref())
+        .bind(&id)
+        .execute(&mut *conn)
+        .await?;
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO branches (id, name, code, address, phone, is_active, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&local_branch.id)
+        .bind(&local_branch.name)
+        .bind(&sf_branch.code)
+        .bind(&local_branch.address)
+        .bind(&local_branch.phone)
+        .bind(local_branch.is_active)
+        .bind(sf_branch.id.as_ref())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Local customer with the same SF ID or phone number
+async fn find_customer(conn: &mut SqliteConnection, sf_customer: &SfCustomer) -> Result<Option<String>, SyncError> {
+    let sf_id = sf_customer.id.as_ref();
+    let existing: Option<(String,)> = if let Some(phone) = &sf_customer.phone {
+        sqlx::query_as("SELECT id FROM customers WHERE salesforce_id = ? OR phone = ? ORDER BY salesforce_id = ? DESC")
+            .bind(sf_id)
+            .bind(phone)
+            .bind(sf_id)
+            .fetch_optional(&mut *conn)
+            .await?
+    } else {
+        sqlx::query_as("SELECT id FROM customers WHERE salesforce_id = ?")
+            .bind(sf_id)
+            .fetch_optional(&mut *conn)
+            .await?
+    };
+
+    Ok(existing.map(|(id,)| id))
+}
+
+/// Upsert a pulled customer, returning its local ID
+async fn write_customer(conn: &mut SqliteConnection, sf_customer: &SfCustomer, existing: Option<&str>) -> Result<String, SyncError> {
+    let local_customer: Customer = sf_customer.from_salesforce();
+    let sf_id = sf_customer.id.as_ref();
+
+    if let Some(id) = existing {
+        sqlx::query(
+            r#"
+            UPDATE customers
+            SET name = ?, phone = ?, nik = ?, address = ?, notes = ?, total_transactions = ?, salesforce_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&local_customer.name)
+        .bind(&local_customer.phone)
+        .bind(&local_customer.nik)
+        .bind(&local_customer.address)
+        .bind(&local_customer.notes)
+        .bind(local_customer.total_transactions)
+        .bind(sf_id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id.to_string())
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO customers (id, name, phone, nik, address, notes, total_transactions, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&local_customer.id)
+        .bind(&local_customer.name)
+        .bind(&local_customer.phone)
+        .bind(&local_customer.nik)
+        .bind(&local_customer.address)
+        .bind(&local_customer.notes)
+        .bind(local_customer.total_transactions)
+        .bind(sf_id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(local_customer.id)
+    }
+}
+
+/// Local transaction with the same SF ID or invoice number
+async fn find_transaction(conn: &mut SqliteConnection, sf_transaction: &SfTransaction) -> Result<Option<String>, SyncError> {
+    let existing: Option<(String,)> = sqlx::query_as(
+        "SELECT id FROM transactions WHERE invoice_no = ? OR salesforce_id = ?",
+    )
+    .bind(&sf_transaction.invoice_no)
+    .bind(sf_transaction.id.as_ref())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(existing.map(|(id,)| id))
+}
+
+/// Upsert a pulled transaction, returning its local ID. The branch, user
+/// and creation time of a transaction already here are kept.
+async fn write_transaction(
+    conn: &mut SqliteConnection,
+    local_transaction: &Transaction,
+    sf_id: Option<&str>,
+    existing: Option<&str>,
+) -> Result<String, SyncError> {
+    if let Some(id) = existing {
+        sqlx::query(
+            r#"
+            UPDATE transactions
+            SET customer_id = COALESCE(?, customer_id), subtotal = ?, discount = ?, trade_in_total = ?,
+                total_amount = ?, notes = ?, status = ?, salesforce_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&local_transaction.customer_id)
+        .bind(local_transaction.subtotal)
+        .bind(local_transaction.discount)
+        .bind(local_transaction.trade_in_total)
+        .bind(local_transaction.total_amount)
+        .bind(&local_transaction.notes)
+        .bind(&local_transaction.status)
+        .bind(sf_id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(id.to_string())
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO transactions (id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount, trade_in_total, total_amount, notes, status, salesforce_id, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&local_transaction.id)
+        .bind(&local_transaction.branch_id)
+        .bind(&local_transaction.user_id)
+        .bind(&local_transaction.customer_id)
+        .bind(&local_transaction.invoice_no)
+        .bind(&local_transaction.r#type)
+        .bind(local_transaction.subtotal)
+        .bind(local_transaction.discount)
+        .bind(local_transaction.trade_in_total)
+        .bind(local_transaction.total_amount)
+        .bind(&local_transaction.notes)
+        .bind(&local_transaction.status)
+        .bind(sf_id)
+        .bind(&local_transaction.created_at)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(local_transaction.id.clone())
+    }
+}
+
+/// Upsert a pulled line item, matching on SF ID
+async fn write_transaction_item(conn: &mut SqliteConnection, local_item: &TransactionItem, sf_id: Option<&str>) -> Result<(), SyncError> {
+    let updated = sqlx::query(
+        r#"
+        UPDATE transaction_items
+        SET quantity = ?, unit_price = ?, subtotal = ?, direction = ?, item_condition = ?,
+            origin_inventory_id = ?
+        WHERE salesforce_id = ?
+        "#,
+    )
+    .bind(local_item.quantity)
+    .bind(local_item.unit_price)
+    .bind(local_item.subtotal)
+    .bind(&local_item.direction)
+    .bind(&local_item.item_condition)
+    .bind(&local_item.origin_inventory_id)
+    .bind(sf_id)
+    .execute(&mut *conn)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        sqlx::query(
+            r#"
+            INSERT INTO transaction_items (id, transaction_id, inventory_id, quantity, unit_price, subtotal, price_status,
+                                           direction, item_condition, origin_inventory_id, salesforce_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&local_item.id)
+        .bind(&local_item.transaction_id)
+        .bind(&local_item.inventory_id)
+        .bind(local_item.quantity)
+        .bind(local_item.unit_price)
+        .bind(local_item.subtotal)
+        .bind(&local_item.price_status)
+        .bind(&local_item.direction)
+        .bind(&local_item.item_condition)
+        .bind(&local_item.origin_inventory_id)
+        .bind(sf_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
 /// Result of pull sync operation
 #[derive(Debug, Default)]
 pub struct PullResult {
@@ -376,3 +1060,188 @@ impl PullResult {
         self.errors.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::push;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_transaction_of_other_branch_links_to_local_records() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        for statement in [
+            "INSERT INTO products (id, category_id, name, gold_type, gold_purity, weight_gram, salesforce_id) VALUES ('p1', 'cat-1', 'Cincin', 'LM', 750, 5.0, 'aP1')",
+            "INSERT INTO inventory (id, product_id, branch_id, barcode, status, purchase_price, salesforce_id) VALUES ('i1', 'p1', 'default', 'BC-1', 'sold', 3000000, 'aI1')",
+            "INSERT INTO inventory (id, product_id, branch_id, barcode, status, purchase_price, salesforce_id) VALUES ('i2', 'p1', 'default', 'BC-2', 'reprocess', 1000000, 'aI2')",
+        ] {
+            sqlx::query(statement).execute(&mut *conn).await.unwrap();
+        }
+        let branch: SfBranch = from_json(serde_json::json!({
+            "Id": "aB2", "Name": "Cabang Dua", "Code__c": "CB2", "Is_Active__c": true
+        }))
+        .unwrap();
+        write_branch(&mut conn, &branch).await.unwrap();
+        let customer: SfCustomer = from_json(serde_json::json!({
+            "Id": "aC1", "Name": "Budi", "Phone__c": "0811", "Total_Transactions__c": 3
+        }))
+        .unwrap();
+        write_customer(&mut conn, &customer, None).await.unwrap();
+
+        let sf_transaction: SfTransaction = from_json(serde_json::json!({
+            "Id": "aT1", "Invoice_Number__c": "CB2-0001", "Branch__c": "aB2", "Customer__c": "aC1",
+            "Type__c": "exchange", "Subtotal__c": 3500000, "Discount__c": 0, "Trade_In_Total__c": 1000000,
+            "Total_Amount__c": 2500000, "Status__c": "completed"
+        }))
+        .unwrap();
+        let sf_item: SfTransactionItem = from_json(serde_json::json!({
+            "Id": "aTI1", "Transaction__c": "aT1", "Inventory__c": "aI1",
+            "Quantity__c": 1, "Unit_Price__c": 3500000, "Subtotal__c": 3500000
+        }))
+        .unwrap();
+        let sf_trade_in: SfTransactionItem = from_json(serde_json::json!({
+            "Id": "aTI2", "Transaction__c": "aT1", "Inventory__c": "aI2",
+            "Quantity__c": 1, "Unit_Price__c": 1000000, "Subtotal__c": 1000000,
+            "Direction__c": "in", "Item_Condition__c": "damaged"
+        }))
+        .unwrap();
+
+        let local_ids = LocalIds::load(&mut conn).await.unwrap();
+        let transaction = local_ids.transaction(&sf_transaction).unwrap();
+        assert_eq!(transaction.branch_id, "CB2");
+        assert_eq!(transaction.user_id, SYNC_USER_ID);
+        let id = write_transaction(&mut conn, &transaction, Some("aT1"), None)
+            .await
+            .unwrap();
+        let item = local_ids.transaction_item(&sf_item, &id).unwrap();
+        write_transaction_item(&mut conn, &item, Some("aTI1"))
+            .await
+            .unwrap();
+        // Pulling it again updates instead of duplicating
+        assert_eq!(
+            find_transaction(&mut conn, &sf_transaction).await.unwrap(),
+            Some(id.clone())
+        );
+        write_transaction_item(&mut conn, &item, Some("aTI1"))
+            .await
+            .unwrap();
+        let trade_in = local_ids.transaction_item(&sf_trade_in, &id).unwrap();
+        write_transaction_item(&mut conn, &trade_in, Some("aTI2"))
+            .await
+            .unwrap();
+
+        let history: Vec<(String, String, i64)> = sqlx::query_as(
+            r#"
+            SELECT c.name, t.invoice_no, COUNT(ti.id)
+            FROM transactions t
+            JOIN customers c ON c.id = t.customer_id
+            JOIN transaction_items ti ON ti.transaction_id = t.id
+            GROUP BY t.id
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+        assert_eq!(
+            history,
+            vec![("Budi".to_string(), "CB2-0001".to_string(), 2)]
+        );
+
+        // The exchange goes back to Salesforce as it came: with its trade-in
+        // total, and the traded-in piece as an incoming item
+        let lookups = build_lookups(&mut conn).await.unwrap();
+        let pushed = push::local_record(&mut conn, "transactions", &id, &lookups)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pushed["Type__c"], "exchange");
+        assert_eq!(pushed["Trade_In_Total__c"], 1000000);
+        assert_eq!(pushed["Total_Amount__c"], 2500000);
+        let items: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, direction, item_condition FROM transaction_items ORDER BY direction",
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+        assert_eq!(
+            items
+                .iter()
+                .map(|(_, direction, condition)| (direction.as_str(), condition.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("in", Some("damaged")), ("out", None)]
+        );
+        let pushed = push::local_record(&mut conn, "transaction_items", &items[0].0, &lookups)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pushed["Inventory__c"], "aI2");
+        assert_eq!(pushed["Direction__c"], "in");
+        assert_eq!(pushed["Item_Condition__c"], "damaged");
+
+        // A transaction of a branch not pulled yet is refused
+        let mut unknown = sf_transaction.clone();
+        unknown.branch_id = Some("aB9".to_string());
+        assert!(local_ids.transaction(&unknown).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watermark_restarts_at_failed_records() {
+        let mut watermark = Watermark::default();
+        watermark.pulled(Some("2024-01-02T00:00:00.000+0000"));
+        watermark.pulled(Some("2024-01-04T00:00:00.000+0000"));
+        assert_eq!(
+            watermark
+                .next(Some("2024-01-01T00:00:00.000+0000".to_string()))
+                .as_deref(),
+            Some("2024-01-04T00:00:00.000+0000")
+        );
+
+        let mut watermark = Watermark::default();
+        watermark.pulled(Some("2024-01-02T00:00:00.000+0000"));
+        watermark.failed(Some("2024-01-03T00:00:00.000+0000"));
+        watermark.pulled(Some("2024-01-04T00:00:00.000+0000"));
+        assert_eq!(
+            watermark.next(None).as_deref(),
+            Some("2024-01-03T00:00:00.000+0000")
+        );
+
+        // Nothing changed since the last pull
+        assert_eq!(
+            Watermark::default()
+                .next(Some("2024-01-01T00:00:00.000+0000".to_string()))
+                .as_deref(),
+            Some("2024-01-01T00:00:00.000+0000")
+        );
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::schema::run_migrations(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        for key in ["inventory", "inventory@aB2", "products"] {
+            sqlx::query("INSERT INTO sync_metadata (table_name, pull_watermark) VALUES (?, '2024-01-01T00:00:00.000+0000')")
+                .bind(key)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+        let reset = reset_watermarks(&mut conn, Some("inventory"))
+            .await
+            .unwrap();
+        assert_eq!(reset.len(), 2);
+        let kept: Vec<(String,)> =
+            sqlx::query_as("SELECT table_name FROM sync_metadata WHERE pull_watermark IS NOT NULL")
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(kept, vec![("products".to_string(),)]);
+    }
+}
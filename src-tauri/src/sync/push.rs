@@ -1,8 +1,14 @@
 use super::change_tracker::{ChangeTracker, PendingChange};
-use crate::models::{Customer, GoldPrice, Inventory, Product, Transaction};
+use super::conflicts::{self, ConflictPolicy, NewConflict, PULLED_TABLES};
+use super::deletions::{self, DeleteMode};
+use super::SyncError;
+use crate::models::{Customer, GoldPrice, Inventory, Payment, Product, Transaction, TransactionItem};
 use crate::salesforce::api::SalesforceApi;
 use crate::salesforce::mapper::{SfLookups, ToSalesforce};
-use sqlx::SqlitePool;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 /// Push sync handler - sends local changes to Salesforce
@@ -19,212 +25,400 @@ impl PushSync {
     }
 
     /// Push all pending changes to Salesforce
-    pub async fn push_all(&self) -> Result<PushResult, String> {
+    pub async fn push_all(&self) -> Result<PushResult, SyncError> {
         let mut result = PushResult::default();
 
         // Build lookup tables for SF IDs
-        let lookups = self.build_lookups().await?;
-
-        // Push in dependency order
-        result.merge(self.push_table("products", &lookups).await?);
-        result.merge(self.push_table("inventory", &lookups).await?);
-        result.merge(self.push_table("customers", &lookups).await?);
-        result.merge(self.push_table("gold_prices", &lookups).await?);
-        result.merge(self.push_table("transactions", &lookups).await?);
+        let mut conn = self.pool.acquire().await?;
+        let mut lookups = build_lookups(&mut conn).await?;
+        drop(conn);
+
+        // Push in dependency order; records pushed here are added to the
+        // lookups so the tables after them can link to them
+        result.merge(self.push_table("products", &mut lookups).await?);
+        result.merge(self.push_table("inventory", &mut lookups).await?);
+        result.merge(self.push_table("customers", &mut lookups).await?);
+        result.merge(self.push_table("gold_prices", &mut lookups).await?);
+        result.merge(self.push_table("transactions", &mut lookups).await?);
+        result.merge(self.push_table("transaction_items", &mut lookups).await?);
+        result.merge(self.push_table("payments", &mut lookups).await?);
 
         Ok(result)
     }
 
     /// Push changes for a specific table
-    async fn push_table(&self, table_name: &str, lookups: &SfLookups) -> Result<PushResult, String> {
+    async fn push_table(&self, table_name: &str, lookups: &mut SfLookups) -> Result<PushResult, SyncError> {
         let mut result = PushResult::default();
+        let requests = self.api.request_count();
         let changes = self.tracker.get_pending_changes(table_name).await?;
+        let remote_modstamps = self.remote_modstamps(table_name, &changes).await?;
+        let mut pushed = Vec::new();
+        // Large pushes upsert in one Bulk API job; deletes still go one by one
+        let bulk_field = bulk_external_id(table_name).filter(|_| changes.len() > self.api.bulk_threshold());
+        let mut batch = Vec::new();
 
         for change in changes {
+            if change.action != "delete" && !self.ready_to_push(&change, &remote_modstamps, lookups).await? {
+                continue;
+            }
+            if bulk_field.is_some() && change.action != "delete" {
+                batch.push(change);
+                continue;
+            }
+
             match self.push_change(&change, lookups).await {
-                Ok(sf_id) => {
+                Ok(sf_record) => {
                     // Update local record with SF ID if returned
-                    if let Some(id) = sf_id {
+                    if let Some((id, record)) = sf_record {
                         self.update_salesforce_id(table_name, &change.record_id, &id).await?;
+                        lookups.insert(table_name, &change.record_id, &id);
+                        pushed.push((change.record_id.clone(), id, record));
                     }
-                    self.tracker.mark_synced(&change.id).await?;
+                    self.tracker.mark_synced(&change).await?;
                     result.records_pushed += 1;
                 }
                 Err(e) => {
-                    self.tracker.mark_failed(&change.id, &e).await?;
+                    self.tracker.mark_failed(&change, &e).await?;
                     result.errors.push(format!("{}/{}: {}", table_name, change.record_id, e));
                 }
             }
         }
 
+        if let Some(field) = bulk_field {
+            result.merge(self.push_bulk(table_name, field, batch, lookups, &mut pushed).await?);
+        }
+        self.save_pushed_versions(table_name, pushed).await?;
+        self.update_sync_metadata(table_name, result.records_pushed, requests).await?;
+
         Ok(result)
     }
 
-    /// Push a single change to Salesforce
-    async fn push_change(&self, change: &PendingChange, lookups: &SfLookups) -> Result<Option<String>, String> {
+    /// Update sync metadata after pushing `table_name`, which started when
+    /// `requests` API requests had been sent
+    async fn update_sync_metadata(&self, table_name: &str, records_count: i32, requests: u64) -> Result<(), SyncError> {
+        let requests = (self.api.request_count() - requests) as i64;
+
+        sqlx::query(
+            r#"
+            INSERT INTO sync_metadata (table_name, last_push_at, records_pushed, push_requests)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(table_name) DO UPDATE SET
+                last_push_at = excluded.last_push_at,
+                records_pushed = records_pushed + excluded.records_pushed,
+                push_requests = excluded.push_requests
+            "#,
+        )
+        .bind(table_name)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(records_count)
+        .bind(requests)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Upsert `changes` in one Bulk API job on `external_id_field`, settling
+    /// each change as `push_table` does. A job that fails as a whole fails
+    /// every change in it, to be retried with the next push.
+    async fn push_bulk(
+        &self,
+        table_name: &str,
+        external_id_field: &str,
+        changes: Vec<PendingChange>,
+        lookups: &mut SfLookups,
+        pushed: &mut Vec<(String, String, Value)>,
+    ) -> Result<PushResult, SyncError> {
+        let mut result = PushResult::default();
+        let mut records = Vec::new();
+        // Changes by the external ID they are upserted on
+        let mut batched = HashMap::new();
+
+        let mut unready = Vec::new();
+
+        let mut conn = self.pool.acquire().await?;
+        for change in changes {
+            let record = match local_record(&mut conn, table_name, &change.record_id, lookups).await? {
+                Some(record) => record,
+                None => {
+                    unready.push((change, SyncError::NotFound("Record not found".to_string())));
+                    continue;
+                }
+            };
+            if let Some(field) = missing_link(&record) {
+                unready.push((change, SyncError::Mapping(format!("{} is not in Salesforce yet", field))));
+                continue;
+            }
+            let key = record.get(external_id_field).and_then(Value::as_str).unwrap_or_default().to_string();
+            records.push(record.clone());
+            batched.insert(key, (change, record));
+        }
+        drop(conn);
+
+        for (change, error) in unready {
+            self.fail(&mut result, &change, &error).await?;
+        }
+
+        if records.is_empty() {
+            return Ok(result);
+        }
+        let outcome = match self.api.bulk_upsert(self.table_to_sobject(table_name), external_id_field, &records).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                for (change, _) in batched.into_values() {
+                    self.fail(&mut result, &change, &e).await?;
+                }
+                return Ok(result);
+            }
+        };
+
+        for success in outcome.successful {
+            let Some((change, record)) = success.record.get(external_id_field).and_then(|key| batched.remove(key)) else {
+                continue;
+            };
+            self.update_salesforce_id(table_name, &change.record_id, &success.sf_id).await?;
+            lookups.insert(table_name, &change.record_id, &success.sf_id);
+            pushed.push((change.record_id.clone(), success.sf_id, record));
+            self.tracker.mark_synced(&change).await?;
+            result.records_pushed += 1;
+        }
+        for failure in outcome.failed {
+            if let Some((change, _)) = failure.record.get(external_id_field).and_then(|key| batched.remove(key)) {
+                self.fail(&mut result, &change, &failure.error).await?;
+            }
+        }
+        for (change, _) in batched.into_values() {
+            let error = SyncError::Network("Missing from the bulk job results".to_string());
+            self.fail(&mut result, &change, &error).await?;
+        }
+
+        Ok(result)
+    }
+
+    async fn fail(&self, result: &mut PushResult, change: &PendingChange, error: &SyncError) -> Result<(), SyncError> {
+        self.tracker.mark_failed(change, error).await?;
+        result.errors.push(format!("{}/{}: {}", change.table_name, change.record_id, error));
+        Ok(())
+    }
+
+    /// Salesforce's current SystemModstamp for the records behind `changes`
+    /// that were synced before, keyed by local ID
+    async fn remote_modstamps(&self, table_name: &str, changes: &[PendingChange]) -> Result<HashMap<String, String>, SyncError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut sf_ids = HashMap::new();
+        for change in changes.iter().filter(|c| c.action != "delete") {
+            let versioned = conflicts::version(&mut conn, table_name, &change.record_id).await?.is_some();
+            if let Some(sf_id) = get_salesforce_id(&mut conn, table_name, &change.record_id).await?.filter(|_| versioned) {
+                sf_ids.insert(sf_id, change.record_id.clone());
+            }
+        }
+        drop(conn);
+
+        if sf_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let ids: Vec<String> = sf_ids.keys().cloned().collect();
+        let modstamps = self.api.get_modstamps(self.table_to_sobject(table_name), &ids).await?;
+
+        Ok(modstamps
+            .into_iter()
+            .filter_map(|(sf_id, modstamp)| sf_ids.remove(&sf_id).map(|local_id| (local_id, modstamp)))
+            .collect())
+    }
+
+    /// Check a change against Salesforce's side before pushing it. A record
+    /// edited in Salesforce since the last sync is pushed only under the
+    /// local wins policy; pulled tables leave it for the pull to settle, and
+    /// the rest queue a conflict for the owner.
+    async fn ready_to_push(
+        &self,
+        change: &PendingChange,
+        remote_modstamps: &HashMap<String, String>,
+        lookups: &SfLookups,
+    ) -> Result<bool, SyncError> {
+        let table_name = change.table_name.as_str();
+        let mut conn = self.pool.acquire().await?;
+
+        if conflicts::has_open_conflict(&mut conn, table_name, &change.record_id).await? {
+            return Ok(false);
+        }
+        let Some(version) = conflicts::version(&mut conn, table_name, &change.record_id).await? else {
+            return Ok(true);
+        };
+        let remote_modstamp = remote_modstamps.get(&change.record_id).map(String::as_str);
+        if !version.remote_changed(remote_modstamp) {
+            return Ok(true);
+        }
+
+        if conflicts::policy(&mut conn, table_name).await? == ConflictPolicy::LocalWins {
+            return Ok(true);
+        }
+        if PULLED_TABLES.contains(&table_name) {
+            return Ok(false);
+        }
+
+        let Some(local) = local_record(&mut conn, table_name, &change.record_id, lookups).await? else {
+            return Ok(true);
+        };
+        let Some(sf_id) = get_salesforce_id(&mut conn, table_name, &change.record_id).await? else {
+            return Ok(true);
+        };
+        drop(conn);
+
+        let remote = self.api.get_record(self.table_to_sobject(table_name), &sf_id).await?;
+        let base = version.base_json.as_deref().and_then(|b| serde_json::from_str(b).ok());
+        let fields = conflicts::merge(base.as_ref(), &local, &remote).err().unwrap_or_default();
+
+        let mut conn = self.pool.acquire().await?;
+        conflicts::record_conflict(
+            &mut conn,
+            NewConflict {
+                table: table_name,
+                record_id: &change.record_id,
+                local: &local,
+                remote: &remote,
+                base: base.as_ref(),
+                remote_modstamp,
+                local_changed_at: change.created_at.as_deref(),
+                fields: &fields,
+            },
+        )
+        .await?;
+
+        Ok(false)
+    }
+
+    /// Remember what was pushed as the version both sides now agree on
+    async fn save_pushed_versions(&self, table_name: &str, pushed: Vec<(String, String, Value)>) -> Result<(), SyncError> {
+        if pushed.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<String> = pushed.iter().map(|(_, sf_id, _)| sf_id.clone()).collect();
+        let modstamps = self.api.get_modstamps(self.table_to_sobject(table_name), &ids).await?;
+
+        let mut conn = self.pool.acquire().await?;
+        for (record_id, sf_id, record) in pushed {
+            let modstamp = modstamps.get(&sf_id).map(String::as_str);
+            conflicts::save_version(&mut conn, table_name, &record_id, modstamp, &record).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Push a single change to Salesforce, returning the record's SF ID and
+    /// the fields sent
+    async fn push_change(&self, change: &PendingChange, lookups: &SfLookups) -> Result<Option<(String, Value)>, SyncError> {
         match change.action.as_str() {
             "delete" => {
-                self.handle_delete(&change.table_name, &change.record_id).await?;
+                self.handle_delete(change).await?;
                 Ok(None)
             }
             "insert" | "update" => {
-                self.handle_upsert(&change.table_name, &change.record_id, lookups).await
+                self.handle_upsert(&change.table_name, &change.record_id, lookups).await.map(Some)
             }
-            _ => Err(format!("Unknown action: {}", change.action)),
+            _ => Err(SyncError::Mapping(format!("Unknown action: {}", change.action))),
         }
     }
 
-    /// Handle delete action
-    async fn handle_delete(&self, table_name: &str, record_id: &str) -> Result<(), String> {
-        // Get SF ID from local record
-        let sf_id = self.get_salesforce_id(table_name, record_id).await?;
-
-        if let Some(id) = sf_id {
-            let sobject = self.table_to_sobject(table_name);
-            // Note: For now, we don't actually delete from SF - just mark as inactive
-            // This is safer for data integrity
-            // self.api.client.delete_record(&sobject, &id).await?;
-            log::info!("Would delete {} {} (SF ID: {})", sobject, record_id, id);
+    /// Handle delete action. The local row may be gone by now, so the
+    /// Salesforce ID comes from the payload captured with the delete.
+    async fn handle_delete(&self, change: &PendingChange) -> Result<(), SyncError> {
+        let table_name = change.table_name.as_str();
+        let mut conn = self.pool.acquire().await?;
+        let sf_id = match payload_salesforce_id(change.payload.as_deref()) {
+            Some(id) => Some(id),
+            None => get_salesforce_id(&mut conn, table_name, &change.record_id).await?,
+        };
+        // Never pushed, so there is nothing to remove
+        let Some(sf_id) = sf_id else {
+            return Ok(());
+        };
+        let mode = deletions::mode(&mut conn, table_name).await?;
+        drop(conn);
+
+        let sobject = self.table_to_sobject(table_name);
+        let existing = self.api.get_modstamps(sobject, std::slice::from_ref(&sf_id)).await?;
+        if existing.is_empty() {
+            // Already deleted in Salesforce
+            return Ok(());
         }
 
-        Ok(())
+        match mode {
+            DeleteMode::Deactivate => self.api.deactivate_record(sobject, &sf_id).await,
+            DeleteMode::Delete => self.api.delete_record(sobject, &sf_id).await,
+        }
     }
 
     /// Handle insert/update action
-    async fn handle_upsert(&self, table_name: &str, record_id: &str, lookups: &SfLookups) -> Result<Option<String>, String> {
+    async fn handle_upsert(&self, table_name: &str, record_id: &str, lookups: &SfLookups) -> Result<(String, Value), SyncError> {
+        let mut conn = self.pool.acquire().await?;
         match table_name {
             "products" => {
-                let product = self.get_product(record_id).await?;
+                let product = get_product(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Product not found".to_string()))?;
+                drop(conn);
                 let sf_product = product.to_salesforce(lookups);
                 let result = self.api.upsert_product(&sf_product).await?;
-                Ok(Some(result.id))
+                pushed(result.id, &sf_product)
             }
             "inventory" => {
-                let inventory = self.get_inventory(record_id).await?;
+                let inventory = get_inventory(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Inventory not found".to_string()))?;
+                drop(conn);
                 let sf_inventory = inventory.to_salesforce(lookups);
                 let result = self.api.upsert_inventory(&sf_inventory).await?;
-                Ok(Some(result.id))
+                pushed(result.id, &sf_inventory)
             }
             "customers" => {
-                let customer = self.get_customer(record_id).await?;
+                let customer = get_customer(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Customer not found".to_string()))?;
+                drop(conn);
                 let sf_customer = customer.to_salesforce(lookups);
                 let result = self.api.upsert_customer(&sf_customer).await?;
-                Ok(Some(result.id))
+                pushed(result.id, &sf_customer)
             }
             "gold_prices" => {
-                let price = self.get_gold_price(record_id).await?;
+                let price = get_gold_price(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Gold price not found".to_string()))?;
+                drop(conn);
                 let sf_price = price.to_salesforce(lookups);
                 let result = self.api.upsert_gold_price(&sf_price).await?;
-                Ok(Some(result.id))
+                pushed(result.id, &sf_price)
             }
             "transactions" => {
-                let transaction = self.get_transaction(record_id).await?;
+                let transaction = get_transaction(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Transaction not found".to_string()))?;
+                drop(conn);
                 let sf_transaction = transaction.to_salesforce(lookups);
                 let result = self.api.upsert_transaction(&sf_transaction).await?;
-                Ok(Some(result.id))
-            }
-            _ => Err(format!("Unknown table: {}", table_name)),
-        }
-    }
-
-    /// Build lookup tables from local DB
-    async fn build_lookups(&self) -> Result<SfLookups, String> {
-        let mut lookups = SfLookups::new();
-
-        // Load branches
-        let branches: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM branches WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-        for (local_id, sf_id) in branches {
-            if let Some(id) = sf_id {
-                lookups.branches.insert(local_id, id);
+                pushed(result.id, &sf_transaction)
             }
-        }
-
-        // Load products
-        let products: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM products WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-        for (local_id, sf_id) in products {
-            if let Some(id) = sf_id {
-                lookups.products.insert(local_id, id);
-            }
-        }
-
-        // Load inventory
-        let inventory: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM inventory WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-        for (local_id, sf_id) in inventory {
-            if let Some(id) = sf_id {
-                lookups.inventory.insert(local_id, id);
-            }
-        }
-
-        // Load customers
-        let customers: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM customers WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-        for (local_id, sf_id) in customers {
-            if let Some(id) = sf_id {
-                lookups.customers.insert(local_id, id);
+            "transaction_items" => {
+                let item = get_transaction_item(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Transaction item not found".to_string()))?;
+                drop(conn);
+                require_transaction(lookups, &item.transaction_id)?;
+                if lookups.get_inventory_sf_id(&item.inventory_id).is_none() {
+                    return Err(SyncError::Mapping(format!("Inventory {} is not in Salesforce yet", item.inventory_id)));
+                }
+                let sf_item = item.to_salesforce(lookups);
+                let result = self.api.upsert_transaction_item(&sf_item).await?;
+                pushed(result.id, &sf_item)
             }
-        }
-
-        // Load transactions
-        let transactions: Vec<(String, Option<String>)> = sqlx::query_as(
-            "SELECT id, salesforce_id FROM transactions WHERE salesforce_id IS NOT NULL",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-        for (local_id, sf_id) in transactions {
-            if let Some(id) = sf_id {
-                lookups.transactions.insert(local_id, id);
+            "payments" => {
+                let payment = get_payment(&mut conn, record_id).await?.ok_or_else(|| SyncError::NotFound("Payment not found".to_string()))?;
+                drop(conn);
+                require_transaction(lookups, &payment.transaction_id)?;
+                let sf_payment = payment.to_salesforce(lookups);
+                let result = self.api.upsert_payment(&sf_payment).await?;
+                pushed(result.id, &sf_payment)
             }
+            _ => Err(SyncError::Mapping(format!("Unknown table: {}", table_name))),
         }
-
-        Ok(lookups)
-    }
-
-    /// Get Salesforce ID for a local record
-    async fn get_salesforce_id(&self, table_name: &str, record_id: &str) -> Result<Option<String>, String> {
-        let query = format!("SELECT salesforce_id FROM {} WHERE id = ?", table_name);
-        let result: Option<(Option<String>,)> = sqlx::query_as(&query)
-            .bind(record_id)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| e.to_string())?;
-
-        Ok(result.and_then(|r| r.0))
     }
 
     /// Update Salesforce ID in local record
-    async fn update_salesforce_id(&self, table_name: &str, record_id: &str, sf_id: &str) -> Result<(), String> {
+    async fn update_salesforce_id(&self, table_name: &str, record_id: &str, sf_id: &str) -> Result<(), SyncError> {
         let query = format!("UPDATE {} SET salesforce_id = ? WHERE id = ?", table_name);
         sqlx::query(&query)
             .bind(sf_id)
             .bind(record_id)
             .execute(&self.pool)
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         Ok(())
     }
@@ -239,61 +433,177 @@ impl PushSync {
             "transactions" => "Transaction__c",
             "transaction_items" => "Transaction_Item__c",
             "gold_prices" => "Gold_Price__c",
+            "payments" => "Payment__c",
             _ => "Unknown__c",
         }
     }
+}
 
-    // Data fetching methods
-
-    async fn get_product(&self, id: &str) -> Result<Product, String> {
-        sqlx::query_as::<_, Product>(
-            "SELECT id, category_id, sku, name, description, gold_type, gold_purity, weight_gram, labor_cost, images, is_active, created_at FROM products WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| format!("Product not found: {}", e))
+/// Field each record of a table is upserted on when pushed through the Bulk
+/// API. Tables whose records may lack one are always pushed one by one.
+fn bulk_external_id(table_name: &str) -> Option<&'static str> {
+    match table_name {
+        "inventory" => Some("Barcode__c"),
+        "transactions" => Some("Invoice_Number__c"),
+        "transaction_items" | "payments" => Some("External_Id__c"),
+        _ => None,
     }
+}
 
-    async fn get_inventory(&self, id: &str) -> Result<Inventory, String> {
-        sqlx::query_as::<_, Inventory>(
-            "SELECT id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, created_at FROM inventory WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| format!("Inventory not found: {}", e))
+/// A required lookup left empty because the linked record has not been
+/// pushed, which Salesforce would reject
+fn missing_link(record: &Value) -> Option<&'static str> {
+    ["Transaction__c", "Inventory__c"]
+        .into_iter()
+        .find(|field| record.get(*field).and_then(Value::as_str) == Some(""))
+}
+
+/// `salesforce_id` of a delete's payload
+fn payload_salesforce_id(payload: Option<&str>) -> Option<String> {
+    let payload: Value = serde_json::from_str(payload?).ok()?;
+    payload.get("salesforce_id")?.as_str().map(String::from)
+}
+
+fn pushed<T: Serialize>(sf_id: String, record: &T) -> Result<(String, Value), SyncError> {
+    Ok((sf_id, serde_json::to_value(record)?))
+}
+
+/// A local record as it would be pushed, with Salesforce field names
+pub async fn local_record(
+    conn: &mut SqliteConnection,
+    table_name: &str,
+    record_id: &str,
+    lookups: &SfLookups,
+) -> Result<Option<Value>, SyncError> {
+    fn sf_json<T: ToSalesforce>(record: Option<T>, lookups: &SfLookups) -> Result<Option<Value>, SyncError>
+    where
+        T::SfType: Serialize,
+    {
+        record
+            .map(|r| serde_json::to_value(r.to_salesforce(lookups)).map_err(SyncError::from))
+            .transpose()
     }
 
-    async fn get_customer(&self, id: &str) -> Result<Customer, String> {
-        sqlx::query_as::<_, Customer>(
-            "SELECT id, name, phone, nik, address, notes, total_transactions, created_at FROM customers WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| format!("Customer not found: {}", e))
+    match table_name {
+        "products" => sf_json(get_product(conn, record_id).await?, lookups),
+        "inventory" => sf_json(get_inventory(conn, record_id).await?, lookups),
+        "customers" => sf_json(get_customer(conn, record_id).await?, lookups),
+        "gold_prices" => sf_json(get_gold_price(conn, record_id).await?, lookups),
+        "transactions" => sf_json(get_transaction(conn, record_id).await?, lookups),
+        "transaction_items" => sf_json(get_transaction_item(conn, record_id).await?, lookups),
+        "payments" => sf_json(get_payment(conn, record_id).await?, lookups),
+        _ => Err(SyncError::Mapping(format!("Unknown table: {}", table_name))),
     }
+}
 
-    async fn get_gold_price(&self, id: &str) -> Result<GoldPrice, String> {
-        sqlx::query_as::<_, GoldPrice>(
-            "SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at FROM gold_prices WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| format!("Gold price not found: {}", e))
+/// Build lookup tables from local DB
+pub async fn build_lookups(conn: &mut SqliteConnection) -> Result<SfLookups, SyncError> {
+    let mut lookups = SfLookups::new();
+
+    for table_name in ["branches", "products", "inventory", "customers", "transactions"] {
+        let query = format!("SELECT id, salesforce_id FROM {} WHERE salesforce_id IS NOT NULL", table_name);
+        let records: Vec<(String, String)> = sqlx::query_as(&query)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for (local_id, sf_id) in records {
+            lookups.insert(table_name, &local_id, &sf_id);
+        }
     }
 
-    async fn get_transaction(&self, id: &str) -> Result<Transaction, String> {
-        sqlx::query_as::<_, Transaction>(
-            "SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount, total_amount, notes, status, created_at FROM transactions WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| format!("Transaction not found: {}", e))
+    Ok(lookups)
+}
+
+/// Get Salesforce ID for a local record
+async fn get_salesforce_id(conn: &mut SqliteConnection, table_name: &str, record_id: &str) -> Result<Option<String>, SyncError> {
+    let query = format!("SELECT salesforce_id FROM {} WHERE id = ?", table_name);
+    let result: Option<(Option<String>,)> = sqlx::query_as(&query)
+        .bind(record_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    Ok(result.and_then(|r| r.0))
+}
+
+// Data fetching functions
+
+async fn get_product(conn: &mut SqliteConnection, id: &str) -> Result<Option<Product>, SyncError> {
+    sqlx::query_as::<_, Product>(
+        "SELECT id, category_id, sku, name, description, gold_type, gold_purity, weight_gram, labor_cost, images, is_active, created_at FROM products WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_inventory(conn: &mut SqliteConnection, id: &str) -> Result<Option<Inventory>, SyncError> {
+    sqlx::query_as::<_, Inventory>(
+        "SELECT id, product_id, branch_id, barcode, status, location, purchase_price, purchase_date, supplier, notes, sold_at, created_at FROM inventory WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_customer(conn: &mut SqliteConnection, id: &str) -> Result<Option<Customer>, SyncError> {
+    sqlx::query_as::<_, Customer>(
+        "SELECT id, name, phone, nik, address, notes, total_transactions, created_at FROM customers WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_gold_price(conn: &mut SqliteConnection, id: &str) -> Result<Option<GoldPrice>, SyncError> {
+    sqlx::query_as::<_, GoldPrice>(
+        "SELECT id, date, gold_type, purity, buy_price, sell_price, source, created_at FROM gold_prices WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_transaction(conn: &mut SqliteConnection, id: &str) -> Result<Option<Transaction>, SyncError> {
+    sqlx::query_as::<_, Transaction>(
+        "SELECT id, branch_id, user_id, customer_id, invoice_no, type, subtotal, discount, trade_in_total, total_amount, notes, status, created_at FROM transactions WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_transaction_item(conn: &mut SqliteConnection, id: &str) -> Result<Option<TransactionItem>, SyncError> {
+    sqlx::query_as::<_, TransactionItem>(
+        "SELECT id, transaction_id, inventory_id, quantity, unit_price, subtotal, gold_price_ref, computed_price, price_status, direction, item_condition, origin_inventory_id FROM transaction_items WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+async fn get_payment(conn: &mut SqliteConnection, id: &str) -> Result<Option<Payment>, SyncError> {
+    sqlx::query_as::<_, Payment>(
+        "SELECT id, transaction_id, method, amount, reference_no, bank_name, status, paid_at, created_at FROM payments WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(SyncError::from)
+}
+
+/// Line items and payments are pushed after their transaction; until it has
+/// reached Salesforce the change fails and is retried on the next sync
+fn require_transaction(lookups: &SfLookups, transaction_id: &str) -> Result<(), SyncError> {
+    if lookups.get_transaction_sf_id(transaction_id).is_none() {
+        return Err(SyncError::Mapping(format!("Transaction {} is not in Salesforce yet", transaction_id)));
     }
+    Ok(())
 }
 
 /// Result of push sync operation
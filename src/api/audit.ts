@@ -0,0 +1,23 @@
+/**
+ * Audit Log API - History of sensitive changes (owner only)
+ */
+
+import { tauriInvoke, ApiResponse } from './index';
+import type { AuditLogEntry, AuditLogFilter } from '../types';
+
+/**
+ * Get audit entries, newest first
+ */
+export async function getAuditLog(
+  filter?: AuditLogFilter,
+  limit?: number
+): Promise<ApiResponse<AuditLogEntry[]>> {
+  return tauriInvoke<AuditLogEntry[]>('get_audit_log', { filter, limit });
+}
+
+/**
+ * Export all matching audit entries as CSV text
+ */
+export async function exportAuditLog(filter?: AuditLogFilter): Promise<ApiResponse<string>> {
+  return tauriInvoke<string>('export_audit_log', { filter });
+}
@@ -35,6 +35,13 @@ export async function login(request: LoginRequest): Promise<ApiResponse<LoginRes
   return tauriInvoke<LoginResponse>('login', { request });
 }
 
+/**
+ * End the current session
+ */
+export async function logout(): Promise<ApiResponse<boolean>> {
+  return tauriInvoke<boolean>('logout');
+}
+
 /**
  * Get all users
  */
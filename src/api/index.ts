@@ -13,10 +13,25 @@ export interface ApiResponse<T> {
   success: boolean;
   data?: T;
   error?: string;
+  error_code?: string;
+}
+
+/**
+ * Read the session token saved by the auth provider, if any
+ */
+function getSessionToken(): string | undefined {
+  const storedSession = localStorage.getItem('emaspos_session');
+  if (!storedSession) return undefined;
+  try {
+    return (JSON.parse(storedSession) as { token?: string }).token;
+  } catch {
+    return undefined;
+  }
 }
 
 /**
  * Invoke a Tauri command with proper error handling
+ * Every command except login requires the session token, so it is attached here
  * Falls back to demo mode when not running in Tauri
  */
 export async function tauriInvoke<T>(
@@ -32,7 +47,7 @@ export async function tauriInvoke<T>(
   }
 
   try {
-    const result = await invoke<ApiResponse<T>>(command, args);
+    const result = await invoke<ApiResponse<T>>(command, { token: getSessionToken(), ...args });
     return result;
   } catch (error) {
     console.error(`[Tauri Error] ${command}:`, error);
@@ -62,3 +77,6 @@ export * from './customers';
 export * from './goldPrices';
 export * from './reports';
 export * from './sync';
+export * from './stockOpname';
+export * from './transfers';
+export * from './audit';
@@ -76,13 +76,12 @@ export async function scanBarcode(barcode: string): Promise<ApiResponse<Inventor
 }
 
 /**
- * Add new inventory item
+ * Add new inventory item to the logged-in user's branch
  */
 export async function addInventory(
-  request: CreateInventoryRequest,
-  branchId: string
+  request: CreateInventoryRequest
 ): Promise<ApiResponse<Inventory>> {
-  return tauriInvoke<Inventory>('add_inventory', { request, branchId });
+  return tauriInvoke<Inventory>('add_inventory', { request });
 }
 
 /**
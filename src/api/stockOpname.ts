@@ -0,0 +1,69 @@
+/**
+ * Stock Opname API - Physical stock count sessions
+ */
+
+import { tauriInvoke, ApiResponse } from './index';
+import type { OpnameScanResponse, StockOpnameResult, StockOpnameSession } from '../types';
+
+/**
+ * Open a stock count for a location, or the whole branch when omitted
+ */
+export async function openStockOpname(
+  location?: string,
+  notes?: string
+): Promise<ApiResponse<StockOpnameSession>> {
+  return tauriInvoke<StockOpnameSession>('open_stock_opname', { location, notes });
+}
+
+/**
+ * Record a scanned barcode in an open stock count
+ */
+export async function recordOpnameScan(
+  sessionId: string,
+  barcode: string,
+  location?: string
+): Promise<ApiResponse<OpnameScanResponse>> {
+  return tauriInvoke<OpnameScanResponse>('record_opname_scan', {
+    sessionId,
+    barcode,
+    location,
+  });
+}
+
+/**
+ * Close a stock count and get its reconciliation
+ */
+export async function closeStockOpname(
+  sessionId: string
+): Promise<ApiResponse<StockOpnameResult[]>> {
+  return tauriInvoke<StockOpnameResult[]>('close_stock_opname', { sessionId });
+}
+
+/**
+ * Get recent stock counts of the current branch
+ */
+export async function getStockOpnameSessions(): Promise<ApiResponse<StockOpnameSession[]>> {
+  return tauriInvoke<StockOpnameSession[]>('get_stock_opname_sessions');
+}
+
+/**
+ * Get the reconciliation of a closed stock count
+ */
+export async function getStockOpnameResults(
+  sessionId: string
+): Promise<ApiResponse<StockOpnameResult[]>> {
+  return tauriInvoke<StockOpnameResult[]>('get_stock_opname_results', { sessionId });
+}
+
+/**
+ * Approve a closed stock count, applying the selected results (owner only)
+ */
+export async function approveStockOpname(
+  sessionId: string,
+  adjustResultIds: string[]
+): Promise<ApiResponse<StockOpnameResult[]>> {
+  return tauriInvoke<StockOpnameResult[]>('approve_stock_opname', {
+    sessionId,
+    adjustResultIds,
+  });
+}
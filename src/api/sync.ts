@@ -3,7 +3,20 @@
  */
 
 import { tauriInvoke, ApiResponse } from './index';
-import type { SyncConfig, SaveSyncConfigRequest, SyncStatus, SyncResult } from '../types';
+import type {
+  SyncConfig,
+  SaveSyncConfigRequest,
+  SyncStatus,
+  SyncResult,
+  SecretStatus,
+  SyncConflict,
+  SyncConflictPolicy,
+  SyncConflictPolicyName,
+  SyncDeleteMode,
+  SyncDeleteModeName,
+  SyncLog,
+  SyncMetadata,
+} from '../types';
 
 /**
  * Get current sync configuration
@@ -56,9 +69,139 @@ export async function pullInventoryFromSf(branchSfId?: string): Promise<ApiRespo
   });
 }
 
+/**
+ * Pull customers and transactions from Salesforce
+ */
+export async function pullTransactionsFromSf(branchSfId?: string): Promise<ApiResponse<SyncResult>> {
+  return tauriInvoke<SyncResult>('pull_transactions_from_sf', {
+    branch_sf_id: branchSfId
+  });
+}
+
+/**
+ * Make the next pull fetch every record again, for one table or all of them
+ */
+export async function resetPullWatermarks(tableName?: string): Promise<ApiResponse<SyncMetadata[]>> {
+  return tauriInvoke<SyncMetadata[]>('reset_pull_watermarks', {
+    table_name: tableName
+  });
+}
+
 /**
  * Toggle sync enabled/disabled
  */
 export async function toggleSyncEnabled(enabled: boolean): Promise<ApiResponse<boolean>> {
   return tauriInvoke<boolean>('toggle_sync_enabled', { enabled });
 }
+
+/**
+ * Get how stored Salesforce secrets are protected and whether they are unlocked
+ */
+export async function getSecretStatus(): Promise<ApiResponse<SecretStatus>> {
+  return tauriInvoke<SecretStatus>('get_secret_status');
+}
+
+/**
+ * Unlock passphrase-protected Salesforce secrets for this session
+ */
+export async function unlockSyncSecrets(passphrase: string): Promise<ApiResponse<boolean>> {
+  return tauriInvoke<boolean>('unlock_sync_secrets', { passphrase });
+}
+
+/**
+ * Protect secrets with a passphrase, or go back to the keyfile when omitted
+ */
+export async function setSyncPassphrase(passphrase?: string): Promise<ApiResponse<boolean>> {
+  return tauriInvoke<boolean>('set_sync_passphrase', { passphrase });
+}
+
+/**
+ * Discard stored secrets when the passphrase is lost
+ */
+export async function resetSyncSecrets(): Promise<ApiResponse<boolean>> {
+  return tauriInvoke<boolean>('reset_sync_secrets');
+}
+
+/**
+ * Get sync conflicts (open ones by default)
+ */
+export async function getSyncConflicts(status?: 'open' | 'resolved'): Promise<ApiResponse<SyncConflict[]>> {
+  return tauriInvoke<SyncConflict[]>('get_sync_conflicts', { status });
+}
+
+/**
+ * Resolve a sync conflict by keeping the local record or taking Salesforce's
+ */
+export async function resolveSyncConflict(
+  conflictId: string,
+  resolution: 'local' | 'server'
+): Promise<ApiResponse<SyncConflict>> {
+  return tauriInvoke<SyncConflict>('resolve_sync_conflict', {
+    conflict_id: conflictId,
+    resolution
+  });
+}
+
+/**
+ * Get the conflict policy of each synced table
+ */
+export async function getConflictPolicies(): Promise<ApiResponse<SyncConflictPolicy[]>> {
+  return tauriInvoke<SyncConflictPolicy[]>('get_conflict_policies');
+}
+
+/**
+ * Set how sync settles conflicts on a table
+ */
+export async function setConflictPolicy(
+  tableName: string,
+  policy: SyncConflictPolicyName
+): Promise<ApiResponse<SyncConflictPolicy>> {
+  return tauriInvoke<SyncConflictPolicy>('set_conflict_policy', {
+    table_name: tableName,
+    policy
+  });
+}
+
+/**
+ * Get how local deletes are carried out in Salesforce, per table
+ */
+export async function getDeleteModes(): Promise<ApiResponse<SyncDeleteMode[]>> {
+  return tauriInvoke<SyncDeleteMode[]>('get_delete_modes');
+}
+
+/**
+ * Set whether a table's deletes deactivate or delete the Salesforce record
+ */
+export async function setDeleteMode(
+  tableName: string,
+  mode: SyncDeleteModeName
+): Promise<ApiResponse<SyncDeleteMode>> {
+  return tauriInvoke<SyncDeleteMode>('set_delete_mode', {
+    table_name: tableName,
+    mode
+  });
+}
+
+/**
+ * Get changes that failed every retry
+ */
+export async function getDeadLetters(): Promise<ApiResponse<SyncLog[]>> {
+  return tauriInvoke<SyncLog[]>('get_dead_letters');
+}
+
+/**
+ * Queue a failed change again, optionally correcting the record's fields first
+ */
+export async function retryDeadLetter(
+  id: string,
+  fields?: Record<string, unknown>
+): Promise<ApiResponse<SyncLog>> {
+  return tauriInvoke<SyncLog>('retry_dead_letter', { id, fields });
+}
+
+/**
+ * Give up on a failed change
+ */
+export async function discardDeadLetter(id: string): Promise<ApiResponse<boolean>> {
+  return tauriInvoke<boolean>('discard_dead_letter', { id });
+}
@@ -7,15 +7,39 @@ import type { Transaction, Payment } from '../types';
 
 export interface TransactionItem {
   inventory_id: string;
-  unit_price: number;
+  /** Manual override; the backend prices the item from today's gold price when omitted */
+  unit_price?: number;
   discount: number;
 }
 
+/**
+ * Gold bought from a walk-in customer, priced from today's buy price
+ */
 export interface BuybackItem {
-  gold_type: string;
+  gold_type: 'LM' | 'UBS' | 'Lokal';
+  gold_purity: number;
+  weight_gram: number;
+  /** good: back on sale as is; damaged/scrap: sent for reprocessing */
+  condition: 'good' | 'damaged' | 'scrap';
+  /** Barcode of the piece if this shop originally sold it */
+  barcode?: string;
+  deduction_percent?: number;
+  /** Manual override for the line total */
+  unit_price?: number;
+}
+
+/**
+ * Customer gold handed over on an exchange, valued at today's buy price
+ */
+export interface TradeInItem {
+  gold_type: 'LM' | 'UBS' | 'Lokal';
   gold_purity: number;
   weight_gram: number;
-  unit_price: number;
+  /** Percentage deducted from the gold value for wear or impurities */
+  deduction_percent?: number;
+  category_id?: string;
+  name?: string;
+  notes?: string;
 }
 
 export interface CreateTransactionRequest {
@@ -23,6 +47,7 @@ export interface CreateTransactionRequest {
   customer_id?: string;
   items: TransactionItem[];
   buyback_items?: BuybackItem[];
+  trade_ins?: TradeInItem[];
   subtotal: number;
   discount: number;
   tax: number;
@@ -44,18 +69,12 @@ export interface TransactionFilters {
 }
 
 /**
- * Create a new transaction
+ * Create a new transaction for the logged-in user's branch
  */
 export async function createTransaction(
-  request: CreateTransactionRequest,
-  userId: string,
-  branchId: string
+  request: CreateTransactionRequest
 ): Promise<ApiResponse<Transaction>> {
-  return tauriInvoke<Transaction>('create_transaction', {
-    request,
-    userId,
-    branchId,
-  });
+  return tauriInvoke<Transaction>('create_transaction', { request });
 }
 
 /**
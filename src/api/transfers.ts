@@ -0,0 +1,71 @@
+/**
+ * Stock Transfer API - Moving stock between branches
+ */
+
+import { tauriInvoke, ApiResponse } from './index';
+import type { Branch, StockTransfer, TransferManifest } from '../types';
+
+/**
+ * Get all active branches
+ */
+export async function getBranches(): Promise<ApiResponse<Branch[]>> {
+  return tauriInvoke<Branch[]>('get_branches');
+}
+
+/**
+ * Create a draft transfer from the current branch (owner only)
+ */
+export async function createStockTransfer(
+  toBranchId: string,
+  barcodes: string[],
+  notes?: string
+): Promise<ApiResponse<StockTransfer>> {
+  return tauriInvoke<StockTransfer>('create_stock_transfer', {
+    request: { to_branch_id: toBranchId, barcodes, notes },
+  });
+}
+
+/**
+ * Dispatch a draft transfer, putting its items in transit (owner only)
+ */
+export async function dispatchStockTransfer(
+  transferId: string
+): Promise<ApiResponse<StockTransfer>> {
+  return tauriInvoke<StockTransfer>('dispatch_stock_transfer', { transferId });
+}
+
+/**
+ * Receive an in-transit transfer into the destination branch
+ */
+export async function receiveStockTransfer(
+  transferId: string
+): Promise<ApiResponse<StockTransfer>> {
+  return tauriInvoke<StockTransfer>('receive_stock_transfer', { transferId });
+}
+
+/**
+ * Cancel a draft or in-transit transfer (owner only)
+ */
+export async function cancelStockTransfer(
+  transferId: string
+): Promise<ApiResponse<StockTransfer>> {
+  return tauriInvoke<StockTransfer>('cancel_stock_transfer', { transferId });
+}
+
+/**
+ * Get transfers sent from or to the current branch
+ */
+export async function getStockTransfers(
+  status?: StockTransfer['status']
+): Promise<ApiResponse<StockTransfer[]>> {
+  return tauriInvoke<StockTransfer[]>('get_stock_transfers', { status });
+}
+
+/**
+ * Get the item manifest of a transfer
+ */
+export async function getTransferManifest(
+  transferId: string
+): Promise<ApiResponse<TransferManifest>> {
+  return tauriInvoke<TransferManifest>('get_transfer_manifest', { transferId });
+}
@@ -1,6 +1,6 @@
 import { createContext, useContext, useState, useEffect, ReactNode } from 'react';
 import type { User, Session } from '../types';
-import { login as apiLogin } from '../api/auth';
+import { login as apiLogin, logout as apiLogout } from '../api/auth';
 import { isTauri } from '../lib/utils';
 
 interface AuthContextType {
@@ -93,6 +93,11 @@ export function AuthProvider({ children }: { children: ReactNode }) {
   };
 
   const logout = () => {
+    // Revoke the session server-side; the token is read from storage, so
+    // this must be sent before the stored session is removed
+    if (isTauri()) {
+      void apiLogout();
+    }
     setUser(null);
     setSession(null);
     localStorage.removeItem('emaspos_session');
@@ -132,14 +132,8 @@ export function useAddInventory() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: async ({
-      request,
-      branchId,
-    }: {
-      request: api.CreateInventoryRequest;
-      branchId: string;
-    }) => {
-      const response = await api.addInventory(request, branchId);
+    mutationFn: async ({ request }: { request: api.CreateInventoryRequest }) => {
+      const response = await api.addInventory(request);
       if (!response.success || !response.data) {
         throw new Error(response.error || 'Failed to add inventory');
       }
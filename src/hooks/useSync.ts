@@ -11,10 +11,21 @@ import {
   manualSync,
   pullGoldPricesFromSf,
   pullInventoryFromSf,
+  pullTransactionsFromSf,
   toggleSyncEnabled,
 } from '../api/sync';
+import type { ApiResponse } from '../api/index';
 import type { SaveSyncConfigRequest } from '../types';
 
+/**
+ * Throw a failed sync response, keeping the error code so the UI can show it
+ */
+function throwSyncError(response: ApiResponse<unknown>): never {
+  throw new Error(
+    response.error_code ? `[${response.error_code}] ${response.error}` : response.error
+  );
+}
+
 // Query keys
 export const syncKeys = {
   all: ['sync'] as const,
@@ -30,7 +41,7 @@ export function useSyncConfig() {
     queryKey: syncKeys.config(),
     queryFn: async () => {
       const response = await getSyncConfig();
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     staleTime: 5 * 60 * 1000, // 5 minutes
@@ -45,7 +56,7 @@ export function useSyncStatus() {
     queryKey: syncKeys.status(),
     queryFn: async () => {
       const response = await getSyncStatus();
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     refetchInterval: 30000, // Refresh every 30 seconds
@@ -61,7 +72,7 @@ export function useSaveSyncConfig() {
   return useMutation({
     mutationFn: async (request: SaveSyncConfigRequest) => {
       const response = await saveSyncConfig(request);
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     onSuccess: () => {
@@ -78,7 +89,7 @@ export function useTestSfConnection() {
   return useMutation({
     mutationFn: async () => {
       const response = await testSfConnection();
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
   });
@@ -93,7 +104,7 @@ export function useManualSync() {
   return useMutation({
     mutationFn: async () => {
       const response = await manualSync();
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     onSuccess: () => {
@@ -115,7 +126,7 @@ export function usePullGoldPrices() {
   return useMutation({
     mutationFn: async () => {
       const response = await pullGoldPricesFromSf();
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     onSuccess: () => {
@@ -134,7 +145,7 @@ export function usePullInventory() {
   return useMutation({
     mutationFn: async (branchSfId?: string) => {
       const response = await pullInventoryFromSf(branchSfId);
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     onSuccess: () => {
@@ -144,6 +155,26 @@ export function usePullInventory() {
   });
 }
 
+/**
+ * Hook to pull customers and transactions from Salesforce
+ */
+export function usePullTransactions() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: async (branchSfId?: string) => {
+      const response = await pullTransactionsFromSf(branchSfId);
+      if (!response.success) throwSyncError(response);
+      return response.data;
+    },
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: syncKeys.status() });
+      queryClient.invalidateQueries({ queryKey: ['transactions'] });
+      queryClient.invalidateQueries({ queryKey: ['customers'] });
+    },
+  });
+}
+
 /**
  * Hook to toggle sync enabled/disabled
  */
@@ -153,7 +184,7 @@ export function useToggleSyncEnabled() {
   return useMutation({
     mutationFn: async (enabled: boolean) => {
       const response = await toggleSyncEnabled(enabled);
-      if (!response.success) throw new Error(response.error);
+      if (!response.success) throwSyncError(response);
       return response.data;
     },
     onSuccess: () => {
@@ -38,16 +38,8 @@ export function useCreateTransaction() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: async ({
-      request,
-      userId,
-      branchId,
-    }: {
-      request: api.CreateTransactionRequest;
-      userId: string;
-      branchId: string;
-    }) => {
-      const response = await api.createTransaction(request, userId, branchId);
+    mutationFn: async ({ request }: { request: api.CreateTransactionRequest }) => {
+      const response = await api.createTransaction(request);
       if (!response.success || !response.data) {
         throw new Error(response.error || 'Failed to create transaction');
       }
@@ -121,21 +113,15 @@ export function useCheckout() {
     paymentMethod,
     paymentAmount,
     referenceNo,
-    userId,
-    branchId,
   }: {
     transactionRequest: api.CreateTransactionRequest;
     paymentMethod: 'cash' | 'qris' | 'bank_transfer';
     paymentAmount: number;
     referenceNo?: string;
-    userId: string;
-    branchId: string;
   }) => {
     // Step 1: Create transaction
     const transaction = await createTransaction.mutateAsync({
       request: transactionRequest,
-      userId,
-      branchId,
     });
 
     // Step 2: Process payment
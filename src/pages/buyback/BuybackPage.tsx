@@ -15,6 +15,8 @@ interface BuybackItem {
   goldType: 'LM' | 'UBS' | 'Lokal';
   purity: number;
   weight: number;
+  condition: 'good' | 'damaged' | 'scrap';
+  barcode?: string;
   pricePerGram: number;
   total: number;
 }
@@ -23,6 +25,8 @@ export function BuybackPage() {
   const [goldType, setGoldType] = useState<'LM' | 'UBS' | 'Lokal'>('LM');
   const [purity, setPurity] = useState<number>(750);
   const [weight, setWeight] = useState<string>('');
+  const [condition, setCondition] = useState<'good' | 'damaged' | 'scrap'>('good');
+  const [originBarcode, setOriginBarcode] = useState('');
   const [customerName, setCustomerName] = useState('');
   const [customerPhone, setCustomerPhone] = useState('');
   const [customerNik, setCustomerNik] = useState('');
@@ -50,12 +54,15 @@ export function BuybackPage() {
       goldType,
       purity,
       weight: weightNum,
+      condition,
+      barcode: originBarcode.trim() || undefined,
       pricePerGram,
       total: itemTotal,
     };
 
     setItems([...items, newItem]);
     setWeight('');
+    setOriginBarcode('');
   };
 
   const handleRemoveItem = (index: number) => {
@@ -83,20 +90,19 @@ export function BuybackPage() {
         request: {
           type: 'buyback',
           customer_id: customerId,
-          items: [], // No inventory items for buyback
+          items: [], // Buyback gold is entered by weight, not from stock
           buyback_items: items.map((item) => ({
             gold_type: item.goldType,
             gold_purity: item.purity,
             weight_gram: item.weight,
-            unit_price: item.pricePerGram,
+            condition: item.condition,
+            barcode: item.barcode,
           })),
           subtotal: totalBuyback,
           discount: 0,
           tax: 0,
           total: totalBuyback,
         },
-        userId: user.id,
-        branchId: user.branch_id,
       });
 
       // Process payment
@@ -242,6 +248,25 @@ export function BuybackPage() {
                 </div>
               </div>
 
+              <div className="grid grid-cols-1 md:grid-cols-2 gap-4 mt-4">
+                <Select
+                  label="Kondisi"
+                  value={condition}
+                  onChange={(e) => setCondition(e.target.value as typeof condition)}
+                  options={[
+                    { value: 'good', label: 'Baik (langsung dijual)' },
+                    { value: 'damaged', label: 'Rusak (dilebur/diperbaiki)' },
+                    { value: 'scrap', label: 'Rongsok' },
+                  ]}
+                />
+                <Input
+                  label="Barcode Asal (jika dari toko ini)"
+                  value={originBarcode}
+                  onChange={(e) => setOriginBarcode(e.target.value)}
+                  placeholder="EM-XXX-000000"
+                />
+              </div>
+
               {/* Price Preview */}
               {weightNum > 0 && pricePerGram > 0 && (
                 <div className="mt-4 p-4 bg-blue-50 rounded-lg">
@@ -135,7 +135,6 @@ export function InventoryPage() {
           purchase_price: parseInt(formData.purchasePrice.replace(/\D/g, '')),
           location: formData.location,
         },
-        branchId: user.branch_id,
       });
 
       // Reset form and close modal
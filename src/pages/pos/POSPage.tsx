@@ -126,8 +126,6 @@ export function POSPage() {
         },
         paymentMethod,
         paymentAmount: paymentMethod === 'cash' ? cashReceivedAmount : cart.total,
-        userId: user.id,
-        branchId: user.branch_id,
       });
 
       // Store receipt data
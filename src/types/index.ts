@@ -61,7 +61,7 @@ export interface Inventory {
   product_id: string;
   branch_id: string;
   barcode: string;
-  status: 'available' | 'sold' | 'reserved';
+  status: 'available' | 'sold' | 'reserved' | 'reprocess' | 'returned' | 'missing' | 'in_transit';
   location?: string;
   purchase_price: number;
   purchase_date?: string;
@@ -94,6 +94,7 @@ export interface Transaction {
   subtotal: number;
   discount: number;
   tax: number;
+  trade_in_total: number;
   total: number;
   notes?: string;
   status: 'pending' | 'completed' | 'void';
@@ -114,6 +115,9 @@ export interface TransactionItem {
   unit_price: number;
   subtotal: number;
   gold_price_ref?: number;
+  direction: 'out' | 'in';
+  item_condition?: 'good' | 'damaged' | 'scrap';
+  origin_inventory_id?: string;
   // Joined fields
   inventory?: Inventory;
 }
@@ -135,11 +139,117 @@ export interface SyncLog {
   table_name: string;
   record_id: string;
   action: 'insert' | 'update' | 'delete';
+  payload?: string;
   synced: boolean;
   synced_at?: string;
+  error_message?: string;
+  retry_count: number;
+  next_retry_at?: string;
+  dead_lettered_at?: string;
   created_at: string;
 }
 
+// Stock opname types
+export interface StockOpnameSession {
+  id: string;
+  branch_id: string;
+  location?: string;
+  status: 'open' | 'closed' | 'approved';
+  notes?: string;
+  opened_by: string;
+  opened_at: string;
+  closed_by?: string;
+  closed_at?: string;
+  approved_by?: string;
+  approved_at?: string;
+}
+
+export interface StockOpnameScan {
+  id: string;
+  session_id: string;
+  barcode: string;
+  inventory_id?: string;
+  location?: string;
+  scanned_by: string;
+  scanned_at: string;
+}
+
+export interface OpnameScanResponse {
+  scan: StockOpnameScan;
+  duplicate: boolean;
+  inventory?: Inventory;
+}
+
+export interface StockOpnameResult {
+  id: string;
+  session_id: string;
+  kind: 'missing' | 'unexpected' | 'wrong_location' | 'sold_but_scanned' | 'found';
+  barcode: string;
+  inventory_id?: string;
+  status_before?: string;
+  expected_location?: string;
+  scanned_location?: string;
+  resolution?: 'adjusted' | 'ignored';
+}
+
+// Audit log types
+export interface AuditLogEntry {
+  id: string;
+  actor_id: string;
+  actor_username: string;
+  branch_id: string;
+  action: string;
+  entity_type: string;
+  entity_id: string;
+  before_json?: string;
+  after_json?: string;
+  created_at: string;
+}
+
+export interface AuditLogFilter {
+  entity_type?: string;
+  entity_id?: string;
+  actor_id?: string;
+  action?: string;
+  date_from?: string;
+  date_to?: string;
+}
+
+// Stock transfer types
+export interface StockTransfer {
+  id: string;
+  transfer_no: string;
+  from_branch_id: string;
+  to_branch_id: string;
+  status: 'draft' | 'in_transit' | 'received' | 'cancelled';
+  notes?: string;
+  created_by: string;
+  created_at: string;
+  dispatched_by?: string;
+  dispatched_at?: string;
+  received_by?: string;
+  received_at?: string;
+}
+
+export interface TransferManifestItem {
+  inventory_id: string;
+  barcode: string;
+  product_name: string;
+  gold_type: 'LM' | 'UBS' | 'Lokal';
+  gold_purity: number;
+  weight_gram: number;
+  purchase_price: number;
+}
+
+export interface TransferManifest {
+  transfer: StockTransfer;
+  from_branch_name: string;
+  to_branch_name: string;
+  items: TransferManifestItem[];
+  total_weight: number;
+  total_value: number;
+}
+
 // Cart types for POS
 export interface CartItem {
   inventory: Inventory;
@@ -194,6 +304,7 @@ export interface ApiResponse<T> {
   success: boolean;
   data?: T;
   error?: string;
+  error_code?: string;
 }
 
 // Auth types
@@ -247,31 +358,51 @@ export interface AppSettings {
 }
 
 // Salesforce Sync types
+export type SyncAuthFlow = 'password' | 'jwt_bearer';
+
 export interface SyncConfig {
   id: string;
   sf_client_id?: string;
-  sf_client_secret?: string;
   sf_username?: string;
-  sf_password?: string;
-  sf_security_token?: string;
+  sf_auth_flow: SyncAuthFlow;
+  sf_jwt_audience?: string;
   sf_instance_url?: string;
   is_sandbox: boolean;
   sync_enabled: boolean;
   sync_interval_minutes: number;
+  // Records above which sync uses the Salesforce Bulk API; unset for the default
+  bulk_threshold?: number;
+  // Percent of the daily Salesforce API limit past which optional pulls are skipped
+  api_usage_threshold?: number;
+  has_client_secret: boolean;
+  has_password: boolean;
+  has_security_token: boolean;
+  has_jwt_private_key: boolean;
   created_at: string;
   updated_at?: string;
 }
 
+// Secrets are write-only: leave them out to keep the stored value
 export interface SaveSyncConfigRequest {
   sf_client_id: string;
-  sf_client_secret: string;
+  sf_client_secret?: string;
   sf_username: string;
-  sf_password: string;
-  sf_security_token: string;
+  sf_password?: string;
+  sf_security_token?: string;
+  sf_auth_flow?: SyncAuthFlow;
+  sf_jwt_private_key?: string;
+  sf_jwt_audience?: string;
   sf_instance_url?: string;
   is_sandbox: boolean;
   sync_enabled: boolean;
   sync_interval_minutes: number;
+  bulk_threshold?: number;
+  api_usage_threshold?: number;
+}
+
+export interface SecretStatus {
+  mode: 'keyfile' | 'passphrase';
+  unlocked: boolean;
 }
 
 export interface SyncStatus {
@@ -279,7 +410,16 @@ export interface SyncStatus {
   sync_enabled: boolean;
   last_sync_at?: string;
   pending_changes: number;
+  dead_letters: number;
   error_message?: string;
+  error_code?: string;
+  api_usage?: ApiUsage;
+  api_limit_reached: boolean;
+}
+
+export interface ApiUsage {
+  used: number;
+  limit: number;
 }
 
 export interface SyncResult {
@@ -290,6 +430,41 @@ export interface SyncResult {
   completed_at: string;
 }
 
+export type SyncConflictPolicyName = 'server_wins' | 'local_wins' | 'merge' | 'manual';
+
+/** Record edited both locally and in Salesforce; JSON uses Salesforce field names */
+export interface SyncConflict {
+  id: string;
+  table_name: string;
+  record_id: string;
+  salesforce_id?: string;
+  local_json: string;
+  remote_json: string;
+  base_json?: string;
+  remote_modstamp?: string;
+  local_changed_at?: string;
+  conflicting_fields: string;
+  status: 'open' | 'resolved';
+  resolution?: 'local' | 'server';
+  resolved_by?: string;
+  resolved_at?: string;
+  created_at: string;
+}
+
+export interface SyncConflictPolicy {
+  table_name: string;
+  policy: SyncConflictPolicyName;
+  updated_at?: string;
+}
+
+export type SyncDeleteModeName = 'deactivate' | 'delete';
+
+export interface SyncDeleteMode {
+  table_name: string;
+  mode: SyncDeleteModeName;
+  updated_at?: string;
+}
+
 export interface SyncMetadata {
   table_name: string;
   last_pull_at?: string;
@@ -297,4 +472,7 @@ export interface SyncMetadata {
   last_full_sync_at?: string;
   records_pulled: number;
   records_pushed: number;
+  pull_watermark?: string;
+  pull_requests: number;
+  push_requests: number;
 }